@@ -0,0 +1,36 @@
+/* C API of the cpureport cdylib (libcpureport.so / cpureport.dll) */
+#ifndef CPUREPORT_H
+#define CPUREPORT_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CpureportRun CpureportRun;
+
+/* Start sampling `package` on `device` (NULL for `adb -d`), NULL on invalid arguments */
+CpureportRun *cpureport_start_run(const char *package, const char *device,
+                                  uint64_t interval_ms, uint64_t duration_s);
+
+/* Add a marker event while running, 0 on success */
+int cpureport_mark(CpureportRun *run, const char *label);
+
+/* Stop sampling (blocks until the collectors are done), 0 on success, else the exit code of
+   the command line for the failure (8 collectors cannot start, 10 failed) */
+int cpureport_stop_run(CpureportRun *run);
+
+/* Summary of a stopped run as JSON, NULL while running; free with cpureport_free_string */
+char *cpureport_get_summary_json(const CpureportRun *run);
+
+void cpureport_free_string(char *value);
+
+/* Free the run, stopping it first if needed */
+void cpureport_free_run(CpureportRun *run);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,120 @@
+use crate::events;
+use serde_json::json;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// Where threshold alerts go while the run is going on, the console always gets them
+#[derive(Clone, Debug)]
+pub struct AlertOptions {
+    // samples in a row above the threshold before alerting
+    pub consecutive: usize,
+    // url a JSON message is POSTed to (`text` makes it a Slack/Mattermost incoming webhook)
+    pub webhook: Option<String>,
+    // desktop notification through notify-send, osascript or msg
+    pub desktop: bool,
+}
+
+impl Default for AlertOptions {
+    fn default() -> Self {
+        AlertOptions {
+            consecutive: 3,
+            webhook: None,
+            desktop: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AlertKind {
+    Breach,
+    Recovered,
+}
+
+// Sends the alerts of one run
+#[derive(Clone)]
+pub struct Alerter {
+    options: AlertOptions,
+    pkg: String,
+}
+
+impl Alerter {
+    pub fn new(options: &AlertOptions, pkg: &str) -> Alerter {
+        Alerter {
+            options: options.clone(),
+            pkg: pkg.to_string(),
+        }
+    }
+
+    pub fn consecutive(&self) -> usize {
+        self.options.consecutive.max(1)
+    }
+
+    pub fn fire(&self, kind: AlertKind, metric: &str, value: f64, threshold: f64) {
+        let text = match kind {
+            AlertKind::Breach => format!(
+                "{}: {} {} > {} (连续 {} 个样本)",
+                self.pkg,
+                metric,
+                value,
+                threshold,
+                self.consecutive()
+            ),
+            AlertKind::Recovered => {
+                format!("{}: {} 恢复到 {} <= {}", self.pkg, metric, value, threshold)
+            }
+        };
+        // the bell gets the attention of whoever watches the terminal
+        println!("\x07告警: {}", text);
+
+        if let Some(url) = &self.options.webhook {
+            let body = json!({
+                "text": format!("cpureport {}", text),
+                "package": self.pkg,
+                "metric": metric,
+                "value": value,
+                "threshold": threshold,
+                "recovered": kind == AlertKind::Recovered,
+                "time": events::now_millis(),
+            });
+            let url = url.clone();
+            // never hold up sampling for a slow endpoint
+            thread::spawn(move || {
+                if let Err(e) = ureq::post(&url).send_json(&body) {
+                    println!("告警 webhook 发送失败: {}", e);
+                }
+            });
+        }
+
+        if self.options.desktop {
+            notify_desktop(&text);
+        }
+    }
+}
+
+fn notify_desktop(text: &str) {
+    let mut cmd = if cfg!(target_os = "windows") {
+        let mut win_cmd = Command::new("msg");
+        win_cmd.args(["*", "/TIME:30", text]);
+        win_cmd
+    } else if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification \"{}\" with title \"cpureport\"",
+            text.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let mut mac_cmd = Command::new("osascript");
+        mac_cmd.args(["-e", &script]);
+        mac_cmd
+    } else {
+        let mut linux_cmd = Command::new("notify-send");
+        linux_cmd.args(["cpureport", text]);
+        linux_cmd
+    };
+    let spawned = cmd
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|mut child| thread::spawn(move || child.wait()));
+    if let Err(e) = spawned {
+        println!("桌面通知失败: {}", e);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::sample::Series;
+use crate::stats;
+
+// Finest step of the common axis, in milliseconds
+const MIN_STEP: u64 = 100;
+// Gaps wider than this many of a series' own sampling steps are not bridged by interpolation
+const MAX_GAP_STEPS: f64 = 3.0;
+
+// Series that were sampled at their own times (cpu every interval, memory as fast as
+// `dumpsys meminfo` answers) resampled onto one time axis, so they can be plotted together or
+// correlated row by row
+pub struct Aligned {
+    // unix milliseconds of the rows
+    pub times: Vec<u64>,
+    // one column per series, in display units, none where the series has no value at that time
+    pub columns: Vec<Vec<Option<f64>>>,
+}
+
+// `(time, display value)` of the samples, samples without a time (read from old manifests) are
+// spread over the run
+fn timed(start: u64, end: u64, series: &Series) -> Vec<(u64, f64)> {
+    let values = series.display_values();
+    let span = end.saturating_sub(start) as f64;
+    let step = span / (values.len().max(2) - 1) as f64;
+    let mut points: Vec<(u64, f64)> = series
+        .measured()
+        .zip(values)
+        .enumerate()
+        .map(|(i, (sample, value))| match sample.ts {
+            0 => (start + (i as f64 * step) as u64, value),
+            ts => (ts, value),
+        })
+        .collect();
+    points.sort_by_key(|(time, _)| *time);
+    points
+}
+
+// Median time between the samples of a series
+fn spacing(points: &[(u64, f64)]) -> Option<f64> {
+    let gaps: Vec<f64> = points
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0) as f64)
+        .filter(|gap| *gap > 0.0)
+        .collect();
+    (!gaps.is_empty()).then(|| stats::median(&gaps))
+}
+
+// Value of a series at `time`, linearly interpolated between the samples around it
+fn value_at(points: &[(u64, f64)], max_gap: f64, time: u64) -> Option<f64> {
+    let next = points.partition_point(|(t, _)| *t < time);
+    let (next_time, next_value) = *points.get(next)?;
+    if next_time == time {
+        return Some(next_value);
+    }
+    let (prev_time, prev_value) = *points.get(next.checked_sub(1)?)?;
+    let gap = (next_time - prev_time) as f64;
+    if gap > max_gap {
+        return None;
+    }
+    let fraction = (time - prev_time) as f64 / gap;
+    Some(prev_value + (next_value - prev_value) * fraction)
+}
+
+impl Aligned {
+    // The axis steps at the sampling interval of the most often sampled series, so none of
+    // its samples are lost, and spans the run
+    pub fn new(start: u64, end: u64, series: &[&Series]) -> Aligned {
+        let points: Vec<Vec<(u64, f64)>> = series
+            .iter()
+            .map(|series| timed(start, end, series))
+            .collect();
+        let spacings: Vec<Option<f64>> = points.iter().map(|points| spacing(points)).collect();
+        let step = spacings
+            .iter()
+            .flatten()
+            .copied()
+            .reduce(f64::min)
+            .map_or(MIN_STEP, |step| (step as u64).max(MIN_STEP));
+
+        // From the first to the last sample of any series
+        let first = points
+            .iter()
+            .filter_map(|points| points.first())
+            .map(|p| p.0)
+            .min();
+        let last = points
+            .iter()
+            .filter_map(|points| points.last())
+            .map(|p| p.0)
+            .max();
+        let times: Vec<u64> = match (first, last) {
+            (Some(first), Some(last)) => (first..=last).step_by(step as usize).collect(),
+            _ => Vec::new(),
+        };
+        let columns = points
+            .iter()
+            .zip(&spacings)
+            .map(|(points, spacing)| {
+                let max_gap = spacing.unwrap_or(0.0) * MAX_GAP_STEPS;
+                times
+                    .iter()
+                    .map(|time| value_at(points, max_gap, *time))
+                    .collect()
+            })
+            .collect();
+        Aligned { times, columns }
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    // The columns restricted to the rows every series has a value in, for correlations
+    pub fn complete_columns(&self) -> Vec<Vec<f64>> {
+        let rows: Vec<usize> = (0..self.len())
+            .filter(|row| self.columns.iter().all(|column| column[*row].is_some()))
+            .collect();
+        self.columns
+            .iter()
+            .map(|column| rows.iter().filter_map(|row| column[*row]).collect())
+            .collect()
+    }
+}
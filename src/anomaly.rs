@@ -0,0 +1,294 @@
+use crate::transport::quote;
+use crate::{adb_shell, events, stats};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Dominant frames listed in the anomaly section
+pub const TOP_FRAMES: usize = 5;
+
+// Samples the recent level of a metric is taken from
+const WINDOW: usize = 30;
+// Samples needed before anything counts as a spike
+const WARMUP: usize = 10;
+// Standard deviations above the recent mean that make a spike
+const SPIKE_SIGMAS: f64 = 3.0;
+
+// How sampling reacts to spikes, no reaction if not set
+#[derive(Clone, Debug)]
+pub struct AdaptiveOptions {
+    // milliseconds between samples during a spike
+    pub fast_interval: u64,
+    // seconds the fast rate lasts after the last spike
+    pub hold: u64,
+    // capture the app's thread stacks with `debuggerd -b` on cpu spikes
+    pub stacks: bool,
+}
+
+// A value well above the recent level of the series
+pub struct SpikeDetector {
+    window: VecDeque<f64>,
+    // smallest rise that counts, the calibrated noise floor
+    floor: f64,
+}
+
+impl SpikeDetector {
+    pub fn new(floor: f64) -> SpikeDetector {
+        SpikeDetector {
+            window: VecDeque::with_capacity(WINDOW),
+            floor,
+        }
+    }
+
+    // Whether `value` is a spike, it is added to the window either way. Returns the recent mean.
+    pub fn check(&mut self, value: f64) -> Option<f64> {
+        let recent: Vec<f64> = self.window.iter().copied().collect();
+        if self.window.len() == WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(value);
+        if recent.len() < WARMUP {
+            return None;
+        }
+        let mean = stats::mean(&recent);
+        let rise = (SPIKE_SIGMAS * stats::stddev(&recent)).max(self.floor);
+        (value > mean + rise).then_some(mean)
+    }
+}
+
+// The sampling rate shared by the collectors of a run, raised for a while after a spike
+#[derive(Clone)]
+pub struct Boost {
+    options: Option<AdaptiveOptions>,
+    // unix milliseconds the fast rate lasts until
+    until: Arc<AtomicU64>,
+}
+
+impl Boost {
+    pub fn new(options: Option<AdaptiveOptions>) -> Boost {
+        Boost {
+            options,
+            until: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.options.is_some()
+    }
+
+    pub fn stacks(&self) -> bool {
+        self.options.as_ref().is_some_and(|options| options.stacks)
+    }
+
+    // Sample fast for the next `hold` seconds, returns whether the rate was at base before
+    pub fn trigger(&self) -> bool {
+        let Some(options) = &self.options else {
+            return false;
+        };
+        let now = events::now_millis();
+        let previous = self
+            .until
+            .swap(now + options.hold * 1000, Ordering::Relaxed);
+        previous <= now
+    }
+
+    // Time to sleep before the next sample
+    pub fn interval(&self, base: u64) -> Duration {
+        match &self.options {
+            Some(options) if events::now_millis() < self.until.load(Ordering::Relaxed) => {
+                Duration::from_millis(options.fast_interval.min(base))
+            }
+            _ => Duration::from_millis(base),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match &self.options {
+            Some(options) => format!(
+                "sampling every {}ms for {}s",
+                options.fast_interval, options.hold
+            ),
+            None => String::new(),
+        }
+    }
+}
+
+// Java snapshots taken per sustained spike, and the time between them
+const JAVA_SNAPSHOTS: usize = 3;
+const JAVA_SNAPSHOT_GAP: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StackKind {
+    // `debuggerd -b`
+    Native,
+    // `debuggerd -j`, or `kill -3` traces with root
+    Java,
+}
+
+impl StackKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            StackKind::Native => "native",
+            StackKind::Java => "java",
+        }
+    }
+}
+
+// Thread stacks of the app taken during a spike
+pub struct StackSnapshot {
+    // unix time in milliseconds
+    pub time: u64,
+    pub pid: String,
+    pub kind: StackKind,
+    pub text: String,
+}
+
+// `debuggerd -b <pid>` in the background, it takes a while and sampling has to go on meanwhile
+pub fn capture_stacks(device_cmd: &str, pid: &str) -> JoinHandle<Vec<StackSnapshot>> {
+    let (device_cmd, pid) = (device_cmd.to_string(), pid.to_string());
+    thread::spawn(move || {
+        let time = events::now_millis();
+        let text = adb_shell(&device_cmd, &format!("debuggerd -b {}", pid));
+        vec![StackSnapshot {
+            time,
+            pid,
+            kind: StackKind::Native,
+            text,
+        }]
+    })
+}
+
+// One Java stack dump of the process, `debuggerd -j` (debuggable apps) or else the ANR trace
+// `kill -3` writes (needs root)
+fn java_stacks(device_cmd: &str, pid: &str) -> String {
+    let text = adb_shell(device_cmd, &format!("debuggerd -j {}", pid));
+    if text.contains(" tid=") {
+        return text;
+    }
+    adb_shell(device_cmd, &format!("su -c 'kill -3 {}'", pid));
+    thread::sleep(Duration::from_secs(1));
+    let latest = adb_shell(device_cmd, "su -c 'ls -t /data/anr'");
+    match latest.split_whitespace().next() {
+        Some(name) => adb_shell(
+            device_cmd,
+            &format!("su -c {}", quote(&format!("cat /data/anr/{}", quote(name)))),
+        ),
+        None => String::new(),
+    }
+}
+
+// A few Java stack dumps in a row in the background, what stays on top across them is what
+// keeps the cpu busy
+pub fn capture_java_stacks(device_cmd: &str, pid: &str) -> JoinHandle<Vec<StackSnapshot>> {
+    let (device_cmd, pid) = (device_cmd.to_string(), pid.to_string());
+    thread::spawn(move || {
+        let mut snapshots = Vec::new();
+        for i in 0..JAVA_SNAPSHOTS {
+            if i > 0 {
+                thread::sleep(JAVA_SNAPSHOT_GAP);
+            }
+            let time = events::now_millis();
+            let text = java_stacks(&device_cmd, &pid);
+            if text.contains(" tid=") {
+                snapshots.push(StackSnapshot {
+                    time,
+                    pid: pid.clone(),
+                    kind: StackKind::Java,
+                    text,
+                });
+            }
+        }
+        if snapshots.is_empty() {
+            println!("无法获取 java 堆栈 (需要可调试的应用或 root)");
+        }
+        snapshots
+    })
+}
+
+// A frame and the share of runnable thread samples it was on top of
+pub struct FrameShare {
+    pub frame: String,
+    pub count: usize,
+    pub share: f64,
+}
+
+// The `n` frames most often on top of a runnable thread across the Java snapshots, e.g.
+// `"main" prio=5 tid=1 Runnable` followed by `  at com.example.Foo.bar(Foo.java:42)`
+pub fn dominant_frames(stacks: &[StackSnapshot], n: usize) -> Vec<FrameShare> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let mut samples = 0;
+    for stack in stacks.iter().filter(|stack| stack.kind == StackKind::Java) {
+        let mut runnable = false;
+        let mut top_seen = false;
+        for line in stack.text.lines() {
+            if line.starts_with('"') {
+                runnable = line.contains(" Runnable");
+                top_seen = false;
+                continue;
+            }
+            let Some(frame) = line.trim().strip_prefix("at ") else {
+                continue;
+            };
+            if !runnable || top_seen {
+                continue;
+            }
+            top_seen = true;
+            samples += 1;
+            match counts.iter_mut().find(|(known, _)| known == frame) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((frame.to_string(), 1)),
+            }
+        }
+    }
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts
+        .into_iter()
+        .take(n)
+        .map(|(frame, count)| FrameShare {
+            frame,
+            count,
+            share: count as f64 / samples as f64 * 100.0,
+        })
+        .collect()
+}
+
+pub fn frames_json(frames: &[FrameShare]) -> Value {
+    Value::Array(
+        frames
+            .iter()
+            .map(
+                |frame| json!({ "frame": frame.frame, "count": frame.count, "share": frame.share }),
+            )
+            .collect(),
+    )
+}
+
+pub fn print_dominant_frames(frames: &[FrameShare]) {
+    if frames.is_empty() {
+        return;
+    }
+    println!("cpu 峰值时的热点 java 栈帧:");
+    for frame in frames {
+        println!("  {:5.1}% ({}x) {}", frame.share, frame.count, frame.frame);
+    }
+}
+
+// All snapshots in one text file, separated by headers
+pub fn save_stacks(path: &str, start: u64, stacks: &[StackSnapshot]) -> std::io::Result<()> {
+    let content: String = stacks
+        .iter()
+        .map(|stack| {
+            format!(
+                "----- {} pid {} at +{:.1}s -----\n{}\n",
+                stack.kind.label(),
+                stack.pid,
+                stack.time.saturating_sub(start) as f64 / 1000.0,
+                stack.text.trim_end()
+            )
+        })
+        .collect();
+    std::fs::write(path, content)
+}
@@ -0,0 +1,177 @@
+use crate::adb_shell;
+use crate::events::{Event, EventKind, EventLog, now_millis};
+use crate::now;
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Milliseconds between two looks at the app's ops
+const POLL_INTERVAL: u64 = 2000;
+
+// Ops of the privacy dashboard, the sensors behind them keep the device awake and busy
+const WATCHED: [&str; 8] = [
+    "CAMERA",
+    "RECORD_AUDIO",
+    "FINE_LOCATION",
+    "COARSE_LOCATION",
+    "MONITOR_LOCATION",
+    "MONITOR_HIGH_POWER_LOCATION",
+    "BODY_SENSORS",
+    "ACTIVITY_RECOGNITION",
+];
+
+// Last access of every op the app used since boot (Android 10+):
+//
+// CAMERA: allow; time=+1m2s345ms ago; duration=+3s12ms
+// RECORD_AUDIO: allow; time=+5s ago; duration=running
+pub fn command(pkg: &str) -> String {
+    format!("cmd appops get {}", pkg)
+}
+
+// Milliseconds of an appops duration, `+1h2m3s45ms`
+fn parse_millis(text: &str) -> Option<u64> {
+    let text = text.trim().trim_start_matches('+');
+    let mut total = 0;
+    let mut number = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: u64 = std::mem::take(&mut number).parse().ok()?;
+        let unit = match c {
+            'd' => 86_400_000,
+            'h' => 3_600_000,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                1
+            }
+            'm' => 60_000,
+            's' => 1000,
+            _ => return None,
+        };
+        total += value * unit;
+    }
+    number.is_empty().then_some(total)
+}
+
+// One watched op of `command` output
+#[derive(Debug, PartialEq)]
+struct Access {
+    op: String,
+    // milliseconds since its last access
+    ago: u64,
+    // still going on, like a camera that is open
+    running: bool,
+}
+
+fn parse(output: &str) -> Vec<Access> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (op, rest) = line.trim().split_once(':')?;
+            if !WATCHED.contains(&op) {
+                return None;
+            }
+            let ago = rest
+                .split(';')
+                .find_map(|field| field.trim().strip_prefix("time="))
+                .and_then(|time| parse_millis(time.trim_end_matches("ago")))?;
+            Some(Access {
+                op: op.to_string(),
+                ago,
+                running: rest.contains("running"),
+            })
+        })
+        .collect()
+}
+
+// Record every use of the camera, microphone, location and body sensors while the app is
+// sampled as `appop` events, `CAMERA`, and when one that went on stops, `CAMERA stopped`
+pub fn watch(
+    device_cmd: &str,
+    pkg: &str,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let (device_cmd, command) = (device_cmd.to_string(), command(pkg));
+    thread::spawn(move || {
+        // (op, unix millis of its last access, running) at the last look, the accesses
+        // before the run are not recorded
+        let mut last: Vec<(String, u64, bool)> = Vec::new();
+        let mut first = true;
+        let mut next = now_millis();
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                let read = now_millis();
+                for access in parse(&adb_shell(&device_cmd, &command)) {
+                    let at = read.saturating_sub(access.ago);
+                    let known = last.iter_mut().find(|(op, ..)| *op == access.op);
+                    let (accessed, stopped) = match &known {
+                        // `ago` is read to the millisecond, a second apart is a new access
+                        Some((_, last_at, was_running)) => {
+                            (at > last_at + 1000, *was_running && !access.running)
+                        }
+                        None => (!first, false),
+                    };
+                    if accessed || (first && access.running) {
+                        let detail = if access.running {
+                            format!("{} running", access.op)
+                        } else {
+                            access.op.clone()
+                        };
+                        events.record(EventKind::Appop, detail);
+                    } else if stopped {
+                        events.record(EventKind::Appop, format!("{} stopped", access.op));
+                    }
+                    match known {
+                        Some(known) => *known = (access.op, at, access.running),
+                        None => last.push((access.op, at, access.running)),
+                    }
+                }
+                first = false;
+                next = now_millis() + POLL_INTERVAL;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    })
+}
+
+// Times every op was used in the run, stops not counted
+pub fn counts(events: &[Event]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for event in events.iter().filter(|event| event.kind == EventKind::Appop) {
+        if event.detail.ends_with(" stopped") {
+            continue;
+        }
+        let op = event.detail.split(' ').next().unwrap_or_default();
+        match counts.iter_mut().find(|(other, _)| other == op) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((op.to_string(), 1)),
+        }
+    }
+    counts
+}
+
+pub fn counts_json(counts: &[(String, usize)]) -> Value {
+    let map: Map<String, Value> = counts
+        .iter()
+        .map(|(op, count)| (op.clone(), json!(count)))
+        .collect();
+    Value::Object(map)
+}
+
+pub fn print_counts(counts: &[(String, usize)]) {
+    if counts.is_empty() {
+        return;
+    }
+    let text: Vec<String> = counts
+        .iter()
+        .map(|(op, count)| format!("{} {} 次", op, count))
+        .collect();
+    println!("权限使用: {}", text.join(", "));
+}
@@ -0,0 +1,190 @@
+use crate::adb_argv;
+use crate::connection::Link;
+use crate::events::now_millis;
+use crate::shell::ShellSession;
+use crate::transport;
+use crate::transport::quote;
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+
+// Line in front of the output of every command of a batch
+const SECTION: &str = "===cpureport section";
+
+// A command the collectors ask for, and how often
+#[derive(Debug)]
+struct Entry {
+    line: String,
+    // unix milliseconds it was last asked for, and the time between the last two asks
+    asked: u64,
+    period: Option<u64>,
+    // output of the last batch nobody has taken yet, with the time it was read
+    output: Option<(u64, String)>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    commands: Vec<Entry>,
+    // adb shells run, and the commands they ran
+    batches: usize,
+    batched: usize,
+}
+
+// `--batch`: the reads of one tick in a single `adb shell`. The first collector due runs every
+// command that is due about now and the others take their output from it, instead of each
+// opening a shell of its own. The commands are learned as the collectors ask for them.
+#[derive(Clone, Debug)]
+pub struct ShellBatch {
+    // milliseconds an output is fresh for, half the sampling interval
+    window: u64,
+    state: Arc<Mutex<State>>,
+}
+
+// `output` of a batch run split back into one output per command, by the section lines
+fn split_sections(output: &str, count: usize) -> Vec<String> {
+    let mut sections = vec![String::new(); count];
+    let mut current = None;
+    for line in output.split_inclusive('\n') {
+        if let Some(index) = line.trim_end().strip_prefix(SECTION) {
+            current = index
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index < count);
+        } else if let Some(index) = current {
+            sections[index].push_str(line);
+        }
+    }
+    sections
+}
+
+// The script the device's shell reads from stdin. Every command is quoted and evaluated, so
+// `a; b` and `top -n 1 | grep pkg` run the same as on their own, and one that does not parse
+// leaves the others be.
+fn script(commands: &[&str]) -> String {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(index, command)| format!("echo '{} {}'\neval {}\n", SECTION, index, quote(command)))
+        .collect()
+}
+
+impl ShellBatch {
+    pub fn new(interval: u64) -> Self {
+        ShellBatch {
+            window: (interval / 2).max(1),
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    // The output of `adb shell <command>`, from this tick's batch, run in `session` if there is
+    // one, else in an `adb shell` of its own that tells `link` when the device went away
+    pub fn shell(
+        &self,
+        device_cmd: &str,
+        command: &str,
+        session: Option<&ShellSession>,
+        link: Option<&Link>,
+    ) -> String {
+        let mut state = self.state.lock().unwrap();
+        let now = now_millis();
+        let known = state
+            .commands
+            .iter()
+            .position(|known| known.line == command);
+        let index = match known {
+            Some(index) => {
+                let known = &mut state.commands[index];
+                known.period = Some(now.saturating_sub(known.asked));
+                known.asked = now;
+                let fresh = known
+                    .output
+                    .take()
+                    .filter(|(read, _)| now.saturating_sub(*read) <= self.window);
+                if let Some((_, output)) = fresh {
+                    return output;
+                }
+                index
+            }
+            None => {
+                state.commands.push(Entry {
+                    line: command.to_string(),
+                    asked: now,
+                    period: None,
+                    output: None,
+                });
+                state.commands.len() - 1
+            }
+        };
+
+        // this one, and those whose collectors will ask for them within the window
+        let window = self.window;
+        let due: Vec<usize> = (0..state.commands.len())
+            .filter(|other| {
+                let other_command = &state.commands[*other];
+                *other == index
+                    || other_command
+                        .period
+                        .is_some_and(|period| other_command.asked + period <= now + window)
+            })
+            .collect();
+        let lines: Vec<&str> = due
+            .iter()
+            .map(|other| state.commands[*other].line.as_str())
+            .collect();
+        let script = script(&lines);
+        let output = session
+            .and_then(|session| session.run(device_cmd, &script))
+            .unwrap_or_else(|| run(device_cmd, &script, link));
+        let outputs = split_sections(&output, lines.len());
+        state.batches += 1;
+        state.batched += outputs.len();
+        let read = now_millis();
+        let mut mine = String::new();
+        for (other, output) in due.into_iter().zip(outputs) {
+            if other == index {
+                mine = output;
+            } else {
+                state.commands[other].output = Some((read, output));
+            }
+        }
+        mine
+    }
+
+    // `(adb shells, commands run in them)` so far
+    pub fn counts(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.batches, state.batched)
+    }
+
+    pub fn print(&self) {
+        let (batches, batched) = self.counts();
+        if batches > 0 {
+            println!("批量执行: {} 条命令合并为 {} 批", batched, batches);
+        }
+    }
+}
+
+// `adb shell sh` with `script` on its stdin, nothing needs quoting that way
+fn run(device_cmd: &str, script: &str, link: Option<&Link>) -> String {
+    let child = transport::command(&adb_argv(device_cmd, &["shell", "sh"]))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let Ok(mut child) = child else {
+        return String::new();
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // the shell ends once stdin is closed
+        let _ = stdin.write_all(script.as_bytes());
+        let _ = stdin.write_all(b"exit\n");
+    }
+    let Ok(output) = child.wait_with_output() else {
+        return String::new();
+    };
+    if let Some(link) = link {
+        link.observe(&String::from_utf8_lossy(&output.stderr));
+    }
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
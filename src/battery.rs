@@ -0,0 +1,139 @@
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::{adb_shell, now};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Milliseconds between two looks at the battery during a run
+const POLL_INTERVAL: u64 = 10_000;
+// Seconds between two looks while waiting for the device to charge
+const WAIT_INTERVAL: u64 = 30;
+// `--critical-battery` when only `--min-battery` is given
+pub const DEFAULT_CRITICAL: u32 = 15;
+
+// Current of the battery in microamps, charging or discharging depending on the device
+pub const CURRENT_NOW: &str = "/sys/class/power_supply/battery/current_now";
+
+// `  level: 87` of `dumpsys battery`
+fn field(output: &str, key: &str) -> Option<f64> {
+    output.lines().find_map(|line| {
+        let (name, value) = line.trim().split_once(':')?;
+        if name == key {
+            value.trim().parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+// Battery level in percent of `dumpsys battery` output, `scale` is practically always 100
+pub fn parse_level(output: &str) -> Option<f64> {
+    let level = field(output, "level")?;
+    let scale = field(output, "scale")
+        .filter(|scale| *scale > 0.0)
+        .unwrap_or(100.0);
+    Some(level * 100.0 / scale)
+}
+
+// Battery voltage in millivolts of `dumpsys battery` output
+pub fn parse_voltage(output: &str) -> Option<f64> {
+    field(output, "voltage").filter(|voltage| *voltage > 0.0)
+}
+
+// Battery level in percent from `dumpsys battery`, none if the device does not say
+pub fn level(device_cmd: &str) -> Option<u32> {
+    let output = adb_shell(device_cmd, "dumpsys battery");
+    parse_level(&output).map(|level| level.round() as u32)
+}
+
+// `u0a101` for uid 10101, how `dumpsys batterystats` names app uids
+fn uid_name(uid: u32) -> String {
+    format!(
+        "u{}a{}",
+        uid / 100_000,
+        (uid % 100_000).saturating_sub(10_000)
+    )
+}
+
+// The battery use `dumpsys batterystats` attributes to `uid` since its last `--reset`, from
+// the `Estimated power use (mAh):` section:
+//
+//     UID u0a101: 12.3 fg: 11.9 bg: 0.4 ( cpu=8.10 ...
+//
+// (`Uid u0a101: 12.3 ( cpu=8.10 ...` before API 31). The device only counts while it runs on
+// battery, a charging device attributes nothing.
+pub fn attributed_mah(device_cmd: &str, uid: u32) -> Option<f64> {
+    let output = adb_shell(device_cmd, "dumpsys batterystats");
+    let name = format!("{}:", uid_name(uid));
+    output
+        .lines()
+        .skip_while(|line| !line.contains("Estimated power use (mAh):"))
+        .find_map(|line| {
+            let mut tokens = line.split_whitespace();
+            let kind = tokens.next()?;
+            if !kind.eq_ignore_ascii_case("uid") || tokens.next()? != name {
+                return None;
+            }
+            tokens.next()?.parse().ok()
+        })
+}
+
+// Start attributing afresh, so `attributed_mah` covers the run only
+pub fn reset_stats(device_cmd: &str) {
+    adb_shell(device_cmd, "dumpsys batterystats --reset");
+}
+
+// Before a run: Ok once the battery is at `min` percent. With `wait` it polls until the device
+// has charged that far, else it fails right away.
+pub fn ensure(device_cmd: &str, min: u32, wait: bool) -> Result<(), String> {
+    loop {
+        let Some(current) = level(device_cmd) else {
+            println!("无法读取电量, 不检查电量");
+            return Ok(());
+        };
+        if current >= min {
+            println!("电量: {}%", current);
+            return Ok(());
+        }
+        if !wait {
+            return Err(format!(
+                "Failed to start: battery at {}%, below --min-battery {}%",
+                current, min
+            ));
+        }
+        println!("电量 {}% 低于 {}%, 等待充电...", current, min);
+        thread::sleep(Duration::from_secs(WAIT_INTERVAL));
+    }
+}
+
+// End the run with a `battery low` event once the battery drops below `critical` percent, the
+// samples until then are kept and reported. Returns the level it ended the run at.
+pub fn watch(
+    device_cmd: &str,
+    critical: u32,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<Option<u32>> {
+    let device_cmd = device_cmd.to_string();
+    thread::spawn(move || {
+        let mut next = now_millis();
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                if let Some(current) = level(&device_cmd).filter(|current| *current < critical) {
+                    println!("电量 {}% 低于 {}%, 提前结束采样", current, critical);
+                    events.record(
+                        EventKind::BatteryLow,
+                        format!("{}%, below {}%", current, critical),
+                    );
+                    stop.store(true, Ordering::Relaxed);
+                    return Some(current);
+                }
+                next = now_millis() + POLL_INTERVAL;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        None
+    })
+}
@@ -0,0 +1,98 @@
+use crate::device::DeviceInfo;
+use crate::package::PackageInfo;
+use crate::{RunData, sla, stats};
+use serde_json::{Value, json};
+
+// Single-value metric over the runs, e.g. `timeToInitialDisplayMs`
+fn metric(runs: &[f64]) -> Value {
+    json!({
+        "minimum": stats::min(runs),
+        "maximum": stats::max(runs),
+        "median": stats::median(runs),
+        "runs": runs,
+    })
+}
+
+// Metric sampled many times per run, e.g. `frameDurationCpuMs`
+fn sampled_metric(samples: &[f64]) -> Value {
+    json!({
+        "P50": stats::percentile(samples, 50.0),
+        "P90": stats::percentile(samples, 90.0),
+        "P95": stats::percentile(samples, 95.0),
+        "P99": stats::percentile(samples, 99.0),
+        "runs": [samples],
+    })
+}
+
+// The run in the androidx.benchmark (macrobenchmark) JSON layout, so Android Studio and
+// benchmark dashboards can read it like a `*-benchmarkData.json`.
+// `extra_metrics` are single-value metrics of the scenario, e.g. `timeToInitialDisplayMs`.
+pub fn benchmark_json(
+    name: &str,
+    pkg: &str,
+    package: &PackageInfo,
+    device: &DeviceInfo,
+    data: &RunData,
+    extra_metrics: &[(&str, f64)],
+) -> Value {
+    let cpu = data.cpu.values();
+    let mem_mb = data.mem.display_values();
+    let mut metrics = json!({
+        "cpuAveragePercent": metric(&[stats::mean(&cpu)]),
+        "cpuMaxPercent": metric(&[stats::max(&cpu)]),
+        "memoryAverageMb": metric(&[stats::mean(&mem_mb)]),
+        "memoryMaxMb": metric(&[stats::max(&mem_mb)]),
+    });
+    for (key, value) in extra_metrics {
+        metrics[*key] = metric(&[*value]);
+    }
+    if !data.sla.is_empty() {
+        let passed = if sla::passed(&data.sla) { 1.0 } else { 0.0 };
+        metrics["slaPassed"] = metric(&[passed]);
+    }
+
+    let mut benchmark = json!({
+        "context": {
+            "build": {
+                "brand": device.brand,
+                "device": device.device,
+                "fingerprint": device.fingerprint,
+                "model": device.model,
+                "version": { "sdk": device.sdk },
+            },
+            "cpuCoreCount": device.cpu_cores,
+            "cpuLocked": false,
+            "memTotalBytes": device.mem_total_bytes,
+            "sustainedPerformanceModeEnabled": false,
+        },
+        "benchmarks": [{
+            "name": name,
+            "params": {
+                "package": pkg,
+                "versionName": package.version_name,
+                "versionCode": package.version_code,
+                "instant": package.instant.to_string(),
+                "splits": package.splits.join(","),
+            },
+            "className": "cpureport",
+            "totalRunTimeNs": data.end.saturating_sub(data.start) * 1_000_000,
+            "metrics": metrics,
+            "sampledMetrics": {
+                "cpuPercent": sampled_metric(&cpu),
+                "memoryPssMb": sampled_metric(&mem_mb),
+            },
+            "warmupIterations": 0,
+            "repeatIterations": 1,
+            "thermalThrottleSleepSeconds": 0,
+        }],
+    });
+    // not part of the androidx format, consumers ignore it
+    if !data.sla.is_empty() {
+        benchmark["benchmarks"][0]["sla"] = sla::verdicts_json(&data.sla);
+    }
+    benchmark
+}
+
+pub fn save_benchmark_json(path: &str, benchmark: &Value) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(benchmark)?)
+}
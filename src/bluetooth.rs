@@ -0,0 +1,51 @@
+// The Bluetooth service's state, with the LE scans of every app that scanned since it started
+// (Android 8+):
+//
+// Bluetooth Status
+//   enabled: true
+// ...
+//   com.example.app
+//   LE scans (started/stopped)         : 5 / 4
+pub const COMMAND: &str = "dumpsys bluetooth_manager";
+
+// Scans the app started and stopped since the Bluetooth service started, none if it did not
+// scan. Err if the output is not the Bluetooth service's.
+fn parse(output: &str, pkg: &str) -> Result<Option<(u64, u64)>, String> {
+    if !output.contains("Bluetooth Status") {
+        return Err("no Bluetooth Status in dumpsys bluetooth_manager".to_string());
+    }
+    let mut lines = output.lines().map(str::trim);
+    // the app's name on a line of its own, its stats on the lines after; an app that
+    // scanned from several uids is listed once per uid, its first entry is taken
+    if !lines.any(|line| line == pkg || line.starts_with(&format!("{} ", pkg))) {
+        return Ok(None);
+    }
+    let counts = lines
+        .take(3)
+        .find_map(|line| line.strip_prefix("LE scans (started/stopped)"))
+        .and_then(|counts| {
+            let (started, stopped) = counts.trim_start_matches([' ', ':']).split_once('/')?;
+            Some((started.trim().parse().ok()?, stopped.trim().parse().ok()?))
+        });
+    Ok(counts)
+}
+
+// The counts of the last read, scans started between two reads are told by the difference
+#[derive(Default)]
+pub struct ScanTracker {
+    last: Option<u64>,
+}
+
+impl ScanTracker {
+    // `(scans started since the last read, scans going on)`, none for the first read
+    pub fn update(&mut self, output: &str, pkg: &str) -> Result<Option<(u64, u64)>, String> {
+        let (started, stopped) = parse(output, pkg)?.unwrap_or_default();
+        let ongoing = started.saturating_sub(stopped);
+        // Bluetooth turned off and on starts the counts over
+        let since = self
+            .last
+            .replace(started)
+            .map(|last| started.checked_sub(last).unwrap_or(started));
+        Ok(since.map(|since| (since, ongoing)))
+    }
+}
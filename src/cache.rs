@@ -0,0 +1,163 @@
+use crate::{adb_argv, adb_shell, run_adb_command};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Device state that takes a round of adb calls to find out and stays the same until the
+// device reboots: build properties, capabilities, the pid of the app. Kept per serial in
+// `~/.cache/cpureport/devices/<serial>.json`, so back-to-back runs of a suite skip most of
+// their preflight.
+#[derive(Default, Deserialize, Serialize)]
+struct DeviceState {
+    // `/proc/sys/kernel/random/boot_id`, a new one means everything else is stale
+    boot_id: String,
+    // e.g. `getprop ro.build.version.sdk` -> `34`
+    values: BTreeMap<String, String>,
+}
+
+struct Entry {
+    device_cmd: String,
+    // none if the device has no boot id to check the state against, then nothing is kept
+    path: Option<PathBuf>,
+    state: DeviceState,
+}
+
+static ENTRIES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+// `~/.cache/cpureport/<name>` (`%LOCALAPPDATA%\cpureport\<name>` on Windows)
+pub fn dir(name: &str) -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("cpureport").join(name)
+}
+
+// Probe everything again and keep nothing, for `--no-device-cache`
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+// Serial of the device `device_cmd` points adb at
+pub fn serial(device_cmd: &str) -> String {
+    match device_cmd.strip_prefix("-s ") {
+        Some(serial) => serial.trim().to_string(),
+        None => run_adb_command(&adb_argv(device_cmd, &["get-serialno"]))
+            .trim()
+            .to_string(),
+    }
+}
+
+fn open(device_cmd: &str) -> Entry {
+    let boot_id = adb_shell(device_cmd, "cat /proc/sys/kernel/random/boot_id")
+        .trim()
+        .to_string();
+    let serial = serial(device_cmd);
+    let usable = |name: &str| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+    };
+    if !usable(&boot_id) || !usable(&serial) {
+        return Entry {
+            device_cmd: device_cmd.to_string(),
+            path: None,
+            state: DeviceState::default(),
+        };
+    }
+
+    // `:` of `host:port` serials is not allowed in Windows file names
+    let path = dir("devices").join(format!("{}.json", serial.replace(':', "_")));
+    let saved: Option<DeviceState> = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    let state = match saved {
+        Some(state) if state.boot_id == boot_id => state,
+        saved => {
+            if saved.is_some() {
+                println!("设备重启过, 重新检测设备状态");
+            }
+            DeviceState {
+                boot_id,
+                values: BTreeMap::new(),
+            }
+        }
+    };
+    Entry {
+        device_cmd: device_cmd.to_string(),
+        path: Some(path),
+        state,
+    }
+}
+
+fn save(entry: &Entry) {
+    let Some(path) = &entry.path else {
+        return;
+    };
+    let Ok(content) = serde_json::to_string_pretty(&entry.state) else {
+        return;
+    };
+    // written aside and renamed, a run reading it at the same time never sees half a file
+    let partial = path.with_extension(format!("json.{}", std::process::id()));
+    let saved = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&partial, content))
+        .and_then(|_| std::fs::rename(&partial, path));
+    if let Err(e) = saved {
+        let _ = std::fs::remove_file(&partial);
+        println!("无法保存设备状态缓存 {}: {}", path.display(), e);
+    }
+}
+
+// Apply `f` to the state of the device, loaded on first use
+fn with_entry<T>(device_cmd: &str, f: impl FnOnce(&mut Entry) -> T) -> T {
+    let mut entries = ENTRIES.lock().unwrap_or_else(|e| e.into_inner());
+    let index = match entries
+        .iter()
+        .position(|entry| entry.device_cmd == device_cmd)
+    {
+        Some(index) => index,
+        None => {
+            entries.push(open(device_cmd));
+            entries.len() - 1
+        }
+    };
+    f(&mut entries[index])
+}
+
+// The value of `key` on the device, `probe` finds it out if it is not known since the last
+// reboot. Empty answers are not kept, the device may just not have been ready.
+pub fn cached(device_cmd: &str, key: &str, probe: impl FnOnce() -> String) -> String {
+    if DISABLED.load(Ordering::Relaxed) {
+        return probe();
+    }
+    if let Some(value) = with_entry(device_cmd, |entry| entry.state.values.get(key).cloned()) {
+        return value;
+    }
+    let value = probe();
+    if !value.is_empty() {
+        with_entry(device_cmd, |entry| {
+            entry.state.values.insert(key.to_string(), value.clone());
+            save(entry);
+        });
+    }
+    value
+}
+
+// Drop a value that turned out to be stale, e.g. the pid of an app that was restarted
+pub fn forget(device_cmd: &str, key: &str) {
+    if DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    with_entry(device_cmd, |entry| {
+        if entry.state.values.remove(key).is_some() {
+            save(entry);
+        }
+    });
+}
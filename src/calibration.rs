@@ -0,0 +1,117 @@
+use crate::schema::{self, Kind};
+use crate::{RunData, get_current_time, stats};
+use serde_json::{Value, json};
+
+// Bumped when the calibration layout changes
+pub const CALIBRATION_VERSION: u64 = 1;
+
+// Standard deviations of idle noise a difference has to exceed to count
+pub const NOISE_SIGMAS: f64 = 2.0;
+
+// Spread of a metric while nothing happens on the device
+#[derive(Clone, Copy, Debug)]
+pub struct Noise {
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+impl Noise {
+    pub fn of(values: &[f64]) -> Noise {
+        Noise {
+            mean: stats::mean(values),
+            stddev: stats::stddev(values),
+        }
+    }
+
+    // Differences within this are indistinguishable from noise
+    pub fn tolerance(&self) -> f64 {
+        NOISE_SIGMAS * self.stddev
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "mean": self.mean,
+            "stddev": self.stddev,
+            "variance": self.stddev.powi(2),
+            "tolerance": self.tolerance(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Noise> {
+        Some(Noise {
+            mean: value["mean"].as_f64()?,
+            stddev: value["stddev"].as_f64()?,
+        })
+    }
+}
+
+// Noise floor of the app on one device, measured by `calibrate`, memory in MB
+#[derive(Clone, Debug)]
+pub struct Calibration {
+    pub fingerprint: String,
+    pub package: String,
+    pub cpu: Noise,
+    pub mem: Noise,
+}
+
+impl Calibration {
+    pub fn from_run(fingerprint: &str, pkg: &str, data: &RunData) -> Calibration {
+        Calibration {
+            fingerprint: fingerprint.to_string(),
+            package: pkg.to_string(),
+            cpu: Noise::of(&data.cpu.values()),
+            mem: Noise::of(&data.mem.display_values()),
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "cpu噪声: 均值 {:.2}, 标准差 {:.2}, 容差 ±{:.2}",
+            self.cpu.mean,
+            self.cpu.stddev,
+            self.cpu.tolerance()
+        );
+        println!(
+            "内存噪声: 均值 {:.2}, 标准差 {:.2}, 容差 ±{:.2}(MB)",
+            self.mem.mean,
+            self.mem.stddev,
+            self.mem.tolerance()
+        );
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let calibration = json!({
+            "schema": Kind::Calibration.label(),
+            "version": CALIBRATION_VERSION,
+            "created": get_current_time(),
+            "fingerprint": self.fingerprint,
+            "package": self.package,
+            "metrics": {
+                "cpu": self.cpu.to_json(),
+                "mem": self.mem.to_json(),
+            },
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&calibration)?)
+    }
+
+    // The calibration at `path`, if there is one for this device and package
+    pub fn load(path: &str, fingerprint: &str, pkg: &str) -> Option<Calibration> {
+        let content = std::fs::read_to_string(path).ok()?;
+        let mut value: Value = serde_json::from_str(&content).ok()?;
+        if let Err(e) = schema::upgrade(Kind::Calibration, &mut value) {
+            println!("校准文件{}未使用: {}", path, e);
+            return None;
+        }
+        let calibration = Calibration {
+            fingerprint: value["fingerprint"].as_str()?.to_string(),
+            package: value["package"].as_str()?.to_string(),
+            cpu: Noise::from_json(&value["metrics"]["cpu"])?,
+            mem: Noise::from_json(&value["metrics"]["mem"])?,
+        };
+        if calibration.fingerprint != fingerprint || calibration.package != pkg {
+            println!("校准文件{}不是这台设备/这个应用的, 未使用", path);
+            return None;
+        }
+        Some(calibration)
+    }
+}
@@ -0,0 +1,175 @@
+use crate::adb_shell;
+use crate::events::{Event, EventKind, EventLog, now_millis};
+use crate::now;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Milliseconds between two looks at the camera service
+const POLL_INTERVAL: u64 = 2000;
+
+// The camera service's state with the clients that have a camera open, Android 9 and later:
+//
+// Active Camera Clients:
+// [
+// (Camera ID: 0, Cost: 100, PID: 12345, Score: 0, State: 2User Id: 0, Maximum Cost: 100, Client Package: com.example.app)
+// ]
+//
+// and before that once per device:
+//
+// Device 0 is open. Client instance dump:
+//     Client priority score: 0 state: 2
+//     Client package: com.example.app
+pub const COMMAND: &str = "dumpsys media.camera";
+
+// Ids of the cameras the app has open
+fn parse(output: &str, pkg: &str) -> Vec<String> {
+    let mut open: Vec<String> = Vec::new();
+    let mut device = None;
+    for line in output.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("Device ") {
+            device = rest
+                .strip_suffix(" is open. Client instance dump:")
+                .map(str::to_string);
+            continue;
+        }
+        let Some((_, client)) = line
+            .split_once("Client Package: ")
+            .or_else(|| line.split_once("Client package: "))
+        else {
+            continue;
+        };
+        if client.trim_end_matches(')').trim() != pkg {
+            continue;
+        }
+        let id = line
+            .split_once("Camera ID: ")
+            .and_then(|(_, rest)| rest.split(',').next())
+            .map(str::to_string)
+            .or_else(|| device.take());
+        if let Some(id) = id.filter(|id| !open.contains(id)) {
+            open.push(id);
+        }
+    }
+    open
+}
+
+// Record every camera the app opens and closes while it is sampled as `camera` events,
+// `camera 0 opened` and `camera 0 closed`, a camera open when the run starts is recorded too
+pub fn watch(
+    device_cmd: &str,
+    pkg: &str,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let device_cmd = device_cmd.to_string();
+    let pkg = pkg.to_string();
+    thread::spawn(move || {
+        let mut open: Vec<String> = Vec::new();
+        let mut next = now_millis();
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                let current = parse(&adb_shell(&device_cmd, COMMAND), &pkg);
+                for id in current.iter().filter(|id| !open.contains(id)) {
+                    events.record(EventKind::Camera, format!("camera {} opened", id));
+                }
+                for id in open.iter().filter(|id| !current.contains(id)) {
+                    events.record(EventKind::Camera, format!("camera {} closed", id));
+                }
+                open = current;
+                next = now_millis() + POLL_INTERVAL;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    })
+}
+
+// A camera the app held open, times in unix milliseconds
+#[derive(Debug, PartialEq)]
+pub struct Session {
+    pub camera: String,
+    pub start: u64,
+    pub end: u64,
+    // still open when the run ended, a session that never closes is a leaked camera
+    pub open_at_end: bool,
+}
+
+impl Session {
+    pub fn seconds(&self) -> f64 {
+        self.end.saturating_sub(self.start) as f64 / 1000.0
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "camera": self.camera,
+            "start": self.start,
+            "end": self.end,
+            "seconds": self.seconds(),
+            "open_at_end": self.open_at_end,
+        })
+    }
+}
+
+// The sessions of the `camera` events, in the order they were opened
+pub fn sessions(events: &[Event], end: u64) -> Vec<Session> {
+    let mut sessions: Vec<Session> = Vec::new();
+    for event in events
+        .iter()
+        .filter(|event| event.kind == EventKind::Camera)
+    {
+        let mut words = event.detail.split(' ').skip(1);
+        let (Some(camera), Some(action)) = (words.next(), words.next()) else {
+            continue;
+        };
+        match action {
+            "opened" => sessions.push(Session {
+                camera: camera.to_string(),
+                start: event.time,
+                end,
+                open_at_end: true,
+            }),
+            "closed" => {
+                if let Some(session) = sessions
+                    .iter_mut()
+                    .rev()
+                    .find(|session| session.camera == camera && session.open_at_end)
+                {
+                    session.end = event.time;
+                    session.open_at_end = false;
+                }
+            }
+            _ => {}
+        }
+    }
+    sessions
+}
+
+pub fn sessions_json(sessions: &[Session]) -> Value {
+    json!({
+        "sessions": sessions.iter().map(Session::to_json).collect::<Vec<Value>>(),
+        "seconds": sessions.iter().map(Session::seconds).sum::<f64>(),
+    })
+}
+
+pub fn print_sessions(sessions: &[Session]) {
+    if sessions.is_empty() {
+        return;
+    }
+    let seconds: f64 = sessions.iter().map(Session::seconds).sum();
+    println!("相机: {} 次会话, 共 {:.1} 秒", sessions.len(), seconds);
+    for session in sessions {
+        println!(
+            "  摄像头 {}: {:.1} 秒{}",
+            session.camera,
+            session.seconds(),
+            if session.open_at_end {
+                " (运行结束时仍打开)"
+            } else {
+                ""
+            }
+        );
+    }
+}
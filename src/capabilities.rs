@@ -0,0 +1,157 @@
+use crate::cache;
+use crate::platform::Platform;
+
+// Data sources collectors depend on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    // `top` lists processes with their cpu usage
+    Top,
+    // `ps` lists processes
+    Ps,
+    // `dumpsys meminfo` (or the smaps_rollup fallback) works for the app
+    Meminfo,
+    // `dumpsys gfxinfo <pkg> framestats`
+    Framestats,
+    // `dumpsys thermalservice`
+    ThermalService,
+    // `dumpsys powerstats`
+    PowerStats,
+    Screencap,
+    // `/proc/pressure` (PSI) is readable
+    Pressure,
+    // adb runs as root or `su` is available
+    Root,
+}
+
+impl Capability {
+    pub fn label(self) -> &'static str {
+        match self {
+            Capability::Top => "top",
+            Capability::Ps => "ps",
+            Capability::Meminfo => "meminfo",
+            Capability::Framestats => "framestats",
+            Capability::ThermalService => "thermalservice",
+            Capability::PowerStats => "powerstats",
+            Capability::Screencap => "screencap",
+            Capability::Pressure => "pressure",
+            Capability::Root => "root",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Capability> {
+        ALL.iter()
+            .copied()
+            .find(|capability| capability.label() == label)
+    }
+}
+
+const ALL: [Capability; 9] = [
+    Capability::Top,
+    Capability::Ps,
+    Capability::Meminfo,
+    Capability::Framestats,
+    Capability::ThermalService,
+    Capability::PowerStats,
+    Capability::Screencap,
+    Capability::Pressure,
+    Capability::Root,
+];
+
+// What the device supports, probed once before sampling
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    supported: Vec<Capability>,
+}
+
+impl Capabilities {
+    // The capabilities probed since the last reboot of the device, or probed now
+    pub fn probe(device_cmd: &str, pkg: &str, platform: &Platform) -> Capabilities {
+        // whether meminfo works depends on the app and the quirks
+        let key = format!("capabilities {} {}", pkg, platform.quirk.describe());
+        let labels = cache::cached(device_cmd, key.trim(), || {
+            labels(&Capabilities::probe_device(device_cmd, pkg, platform).supported)
+        });
+        Capabilities {
+            supported: labels
+                .split(", ")
+                .filter_map(Capability::from_label)
+                .collect(),
+        }
+    }
+
+    fn probe_device(device_cmd: &str, pkg: &str, platform: &Platform) -> Capabilities {
+        // Registered services, one per line after a header. Builds without `dumpsys -l` list
+        // nothing, then services are assumed to be there and the other probes decide.
+        let services = platform.shell(device_cmd, "dumpsys -l");
+        let has_service = |name: &str| {
+            !services.contains("Currently running services")
+                || services.lines().any(|line| line.trim() == name)
+        };
+
+        let top = platform.shell(device_cmd, &platform.top_command());
+        let has_top = top.lines().any(|line| {
+            line.split_whitespace()
+                .next()
+                .is_some_and(|pid| pid.parse::<u32>().is_ok())
+        });
+
+        let meminfo = platform.shell(device_cmd, &format!("dumpsys meminfo {}", pkg));
+        let has_meminfo = has_service("meminfo")
+            && (!meminfo.contains("Permission Denial")
+                || platform.quirk.meminfo_fallback == Some(true));
+
+        let screencap = platform.shell(device_cmd, "command -v screencap");
+        let pressure = platform.shell(device_cmd, "cat /proc/pressure/memory");
+        // `adb root`, or an su binary (Magisk style `su -c`)
+        let root = platform.shell(device_cmd, "id").contains("uid=0(")
+            || platform.shell(device_cmd, "su -c id").contains("uid=0(");
+
+        let probes = [
+            (Capability::Top, has_top),
+            (Capability::Ps, !platform.processes(device_cmd).is_empty()),
+            (Capability::Meminfo, has_meminfo),
+            (
+                Capability::Framestats,
+                platform.gfxinfo_framestats && has_service("gfxinfo"),
+            ),
+            (Capability::ThermalService, has_service("thermalservice")),
+            (Capability::PowerStats, has_service("powerstats")),
+            (Capability::Screencap, !screencap.trim().is_empty()),
+            (Capability::Pressure, pressure.contains("avg10=")),
+            (Capability::Root, root),
+        ];
+        Capabilities {
+            supported: probes
+                .into_iter()
+                .filter_map(|(capability, supported)| supported.then_some(capability))
+                .collect(),
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.supported.contains(&capability)
+    }
+
+    // The ones of `required` the device does not support
+    pub fn missing(&self, required: &[Capability]) -> Vec<Capability> {
+        required
+            .iter()
+            .copied()
+            .filter(|capability| !self.has(*capability))
+            .collect()
+    }
+
+    pub fn print(&self) {
+        let labels: Vec<&str> = self.supported.iter().map(|c| c.label()).collect();
+        println!("设备支持: {}", labels.join(", "));
+    }
+}
+
+// "top, ps"
+pub fn labels(capabilities: &[Capability]) -> String {
+    capabilities
+        .iter()
+        .map(|capability| capability.label())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
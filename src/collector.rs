@@ -0,0 +1,106 @@
+use crate::RunData;
+use crate::metrics;
+use crate::sample::{Sample, Unit};
+
+// `--interval` and the like: `500` or `500ms`, `2s`, `1m`, in milliseconds
+pub fn parse_interval(text: &str) -> Result<u64, String> {
+    let (number, scale) = if let Some(number) = text.strip_suffix("ms") {
+        (number, 1)
+    } else if let Some(number) = text.strip_suffix('s') {
+        (number, 1000)
+    } else if let Some(number) = text.strip_suffix('m') {
+        (number, 60_000)
+    } else {
+        (text, 1)
+    };
+    match number.parse::<u64>() {
+        Ok(value) if value > 0 => Ok(value * scale),
+        _ => Err(format!("Failed to parse interval {}", text)),
+    }
+}
+
+// Which collector gets the device first when they queue for it, `High` before `Low`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    // the numbers runs are compared on, never skipped
+    High,
+    Normal,
+    // heavy dumpsys calls, their samples are left out when the collector falls behind
+    Low,
+}
+
+// A source of samples sampled on its own thread until the run ends. Every collector of the
+// catalog is implemented on top of it, more are added to a run with `Run::with_collector`.
+pub trait MetricCollector: Send {
+    // name in `--metrics` and in the partial results, also of its series unless it sends others
+    fn name(&self) -> &'static str;
+
+    // milliseconds between two samples
+    fn interval(&self) -> u64;
+
+    // its place in the queue for the device
+    fn priority(&self) -> Priority {
+        Priority::Normal
+    }
+
+    // The series it sends and their units in report order, the catalog's columns for a
+    // built-in one. Series it sends without declaring them follow the declared ones.
+    fn series(&self) -> Vec<(&'static str, Unit)> {
+        metrics::find(self.name()).map_or(Vec::new(), |metric| metric.columns.to_vec())
+    }
+
+    // Its series whose first sample is not like the others: rates and deltas over the moment
+    // sampling started, and the app's cpu and mem getting going with it. That sample is marked
+    // `warmup`, the reports leave it out unless `--raw`.
+    fn warmup(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    // Whether it reads the device, one that does not neither queues for it nor pauses while it
+    // is disconnected
+    fn reads_device(&self) -> bool {
+        true
+    }
+
+    // One look at the device, the samples it takes (none if there is nothing to sample right
+    // now, like the app not running). `Err` with the reason when it could not read them, the
+    // series has a gap there.
+    fn collect(&mut self) -> Result<Vec<Sample>, String>;
+
+    // Hand what it found besides samples to the run's data once sampling is over
+    fn finish(&mut self, _data: &mut RunData) {}
+}
+
+impl MetricCollector for Box<dyn MetricCollector> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn interval(&self) -> u64 {
+        (**self).interval()
+    }
+
+    fn priority(&self) -> Priority {
+        (**self).priority()
+    }
+
+    fn series(&self) -> Vec<(&'static str, Unit)> {
+        (**self).series()
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        (**self).warmup()
+    }
+
+    fn reads_device(&self) -> bool {
+        (**self).reads_device()
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        (**self).collect()
+    }
+
+    fn finish(&mut self, data: &mut RunData) {
+        (**self).finish(data)
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// How raw data files are compressed, `--compress`
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Compression {
+    // `.gz`, every tool can read it
+    Gzip,
+    // `.zst`, smaller and faster to write, pandas and `zstdcat` read it
+    Zstd,
+}
+
+impl Compression {
+    // Suffix appended to the file name, `samples_<time>.csv.zst`
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+// `path` with the suffix of `compression`, unchanged if none
+pub fn path(path: &str, compression: Option<Compression>) -> String {
+    match compression {
+        Some(compression) => format!("{}.{}", path, compression.suffix()),
+        None => path.to_string(),
+    }
+}
+
+// Write `text` to `path`, compressed as it is written if `compression` is given. `path`
+// already has the suffix, see `path`.
+pub fn write(path: &str, text: &str, compression: Option<Compression>) -> Result<(), String> {
+    let failed = |e: std::io::Error| format!("Failed to save {}: {}", path, e);
+    let file = BufWriter::new(File::create(path).map_err(failed)?);
+    match compression {
+        None => {
+            let mut file = file;
+            file.write_all(text.as_bytes()).map_err(failed)?;
+            file.flush().map_err(failed)
+        }
+        Some(Compression::Gzip) => {
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            encoder.write_all(text.as_bytes()).map_err(failed)?;
+            encoder.finish().map_err(failed)?.flush().map_err(failed)
+        }
+        Some(Compression::Zstd) => {
+            let mut encoder =
+                zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL).map_err(failed)?;
+            encoder.write_all(text.as_bytes()).map_err(failed)?;
+            encoder.finish().map_err(failed)?.flush().map_err(failed)
+        }
+    }
+}
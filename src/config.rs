@@ -0,0 +1,140 @@
+use crate::cache;
+use crate::exec::{self, ExecMetric};
+use crate::quirks::Quirk;
+use crate::score::ScoreModel;
+use crate::shaping::NetworkProfile;
+use crate::theme::Theme;
+use crate::wasm::{self, WasmPlugin};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// `--config` file, YAML (or JSON)
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // per-manufacturer overrides of the built-in quirks
+    #[serde(default)]
+    pub quirks: Vec<Quirk>,
+    // named lists of scenarios, run with `suite <name>`
+    #[serde(default)]
+    pub suites: Vec<Suite>,
+    // logo, colors and footer of the reports
+    #[serde(default)]
+    pub theme: Theme,
+    // weights and ranges combining a run into one 0-100 score
+    #[serde(default)]
+    pub score: ScoreModel,
+    // latency and bandwidth profiles for `--network-profile`, next to the built-in ones
+    #[serde(default)]
+    pub network_profiles: Vec<NetworkProfile>,
+    // what the reports call a metric, `pss_total: App Memory (MB)`
+    #[serde(default)]
+    pub display_names: BTreeMap<String, String>,
+    // metrics of the team's own, read by host commands while sampling
+    #[serde(default)]
+    pub exec: Vec<ExecMetric>,
+    // sandboxed parsers of device commands, for vendor dumps
+    #[serde(default)]
+    pub wasm: Vec<WasmPlugin>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Suite {
+    pub name: String,
+    pub scenarios: Vec<SuiteScenario>,
+}
+
+// One run of a suite: cpureport arguments added to the ones the suite was started with,
+// e.g. `["--launch", "-t", "120"]` or `["upgrade", "--old-apk", "old.apk", "--new-apk", "new.apk"]`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuiteScenario {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl Config {
+    // `source` is a path or an http(s) url
+    pub fn load(source: &str) -> Result<Config, String> {
+        let content = if source.starts_with("http://") || source.starts_with("https://") {
+            fetch_cached(source)?
+        } else {
+            std::fs::read_to_string(source)
+                .map_err(|e| format!("Failed to read config {}: {}", source, e))?
+        };
+        let config: Config = serde_yaml::from_str(&content)
+            .map_err(|e| format!("Failed to parse config {}: {}", source, e))?;
+        config
+            .theme
+            .validate()
+            .map_err(|e| format!("Failed to parse config {}: {}", source, e))?;
+        config
+            .score
+            .validate()
+            .map_err(|e| format!("Failed to parse config {}: {}", source, e))?;
+        exec::validate(&config.exec)
+            .map_err(|e| format!("Failed to parse config {}: {}", source, e))?;
+        wasm::validate(&config.wasm)
+            .map_err(|e| format!("Failed to parse config {}: {}", source, e))?;
+        Ok(config)
+    }
+
+    pub fn suite(&self, name: &str) -> Option<&Suite> {
+        self.suites.iter().find(|suite| suite.name == name)
+    }
+}
+
+// GET `url` revalidating the cached copy (ETag or Last-Modified), the cached copy is used
+// when the server says it is unchanged or cannot be reached
+fn fetch_cached(url: &str) -> Result<String, String> {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let dir = cache::dir("config");
+    let body_path = dir.join(format!("{:016x}", hasher.finish()));
+    // the validators of the cached copy, one `Header: value` per line
+    let validators_path = body_path.with_extension("validators");
+
+    let cached = std::fs::read_to_string(&body_path).ok();
+    let mut request = ureq::get(url);
+    if cached.is_some() {
+        let validators = std::fs::read_to_string(&validators_path).unwrap_or_default();
+        for (header, value) in validators.lines().filter_map(|line| line.split_once(": ")) {
+            let condition = match header {
+                "ETag" => "If-None-Match",
+                _ => "If-Modified-Since",
+            };
+            request = request.header(condition, value);
+        }
+    }
+    match request.call() {
+        Ok(mut response) => {
+            let validators: String = ["ETag", "Last-Modified"]
+                .iter()
+                .filter_map(|header| {
+                    let value = response.headers().get(*header)?.to_str().ok()?;
+                    Some(format!("{}: {}\n", header, value))
+                })
+                .collect();
+            let body = response
+                .body_mut()
+                .read_to_string()
+                .map_err(|e| format!("Failed to read config {}: {}", url, e))?;
+            let _ = std::fs::create_dir_all(&dir);
+            let _ = std::fs::write(&body_path, &body);
+            let _ = std::fs::write(&validators_path, validators);
+            Ok(body)
+        }
+        Err(ureq::Error::StatusCode(304)) if cached.is_some() => Ok(cached.unwrap_or_default()),
+        Err(e) => match cached {
+            Some(cached) => {
+                println!("获取配置失败, 使用缓存 ({}): {}", url, e);
+                Ok(cached)
+            }
+            None => Err(format!("Failed to fetch config {}: {}", url, e)),
+        },
+    }
+}
@@ -0,0 +1,194 @@
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::{adb_argv, now, run_adb_command};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Why collectors have no samples while the device is gone
+pub const REASON: &str = "device disconnected";
+
+// Milliseconds to the first try to get the device back, doubled after every one that fails
+const FIRST_RETRY: u64 = 1000;
+const MAX_RETRY: u64 = 16000;
+
+// The connection of a run to its device, shared by the run's collectors and commands. Every
+// run has its own, runs at the same time, like the two of `paired` or the sessions of an
+// embedding program, are not paused or counted for each other.
+#[derive(Clone, Debug, Default)]
+pub struct Link {
+    // set once adb said the device is gone, until `watch` has it back. Collectors do not read
+    // in between, a glitch of the cable is a gap in their data instead of zeros, or a death of
+    // the app that never happened.
+    lost: Arc<AtomicBool>,
+    // tries to get the device back during the run
+    tries: Arc<AtomicUsize>,
+    // milliseconds its adb commands took since `health` last looked, kept only if the run
+    // samples it so nothing piles up without it
+    latencies: Option<Arc<Mutex<Vec<f64>>>>,
+}
+
+impl Link {
+    // `timed` to keep how long the adb commands took
+    pub fn new(timed: bool) -> Link {
+        Link {
+            latencies: timed.then(Arc::default),
+            ..Link::default()
+        }
+    }
+
+    pub fn lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+
+    pub fn retries(&self) -> usize {
+        self.tries.load(Ordering::Relaxed)
+    }
+
+    // The stderr of an adb command of the run
+    pub fn observe(&self, stderr: &str) {
+        if disconnected(stderr) && !self.lost.swap(true, Ordering::Relaxed) {
+            println!(
+                "设备断开: {}",
+                stderr.lines().next().unwrap_or_default().trim()
+            );
+        }
+    }
+
+    pub fn record_latency(&self, millis: u64) {
+        if let Some(latencies) = &self.latencies {
+            latencies.lock().unwrap().push(millis as f64);
+        }
+    }
+
+    // The latencies since the last call
+    pub fn take_latencies(&self) -> Vec<f64> {
+        self.latencies
+            .as_ref()
+            .map(|latencies| std::mem::take(&mut *latencies.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    // Whether the device answers now, noted for the run's others if it does not
+    pub fn probe(&self, device_cmd: &str) -> bool {
+        let online = run_adb_command(&adb_argv(device_cmd, &["get-state"])).trim() == "device";
+        if !online {
+            self.lost.store(true, Ordering::Relaxed);
+        }
+        online
+    }
+}
+
+// A device over the network, `adb connect`ed for a run and disconnected again when dropped, on
+// every way the run may return
+pub struct RemoteDevice {
+    pub endpoint: String,
+}
+
+impl RemoteDevice {
+    pub fn connect(endpoint: &str) -> Result<RemoteDevice, String> {
+        let result = run_adb_command(&["adb", "connect", endpoint]);
+        println!("{}", result.trim());
+        if !result.contains("connected to") {
+            return Err(format!("cannot connect to {}: {}", endpoint, result.trim()));
+        }
+        Ok(RemoteDevice {
+            endpoint: endpoint.to_string(),
+        })
+    }
+}
+
+impl Drop for RemoteDevice {
+    fn drop(&mut self) {
+        run_adb_command(&["adb", "disconnect", &self.endpoint]);
+    }
+}
+
+// Whether adb's `stderr` says the device went away:
+//
+//   error: device offline
+//   error: device 'R58M12ABCDE' not found
+//   error: no devices/emulators found
+//   error: closed
+//
+// Newer adb prefix them with `adb: `. Errors of commands on the device have no such prefix.
+pub fn disconnected(stderr: &str) -> bool {
+    stderr.lines().any(|line| {
+        let line = line.trim();
+        let Some(message) = ["adb: error: ", "error: ", "adb: "]
+            .iter()
+            .find_map(|prefix| line.strip_prefix(prefix))
+        else {
+            return false;
+        };
+        message == "device offline"
+            || message == "closed"
+            || message.starts_with("device unauthorized")
+            || message.starts_with("no devices")
+            || (message.starts_with("device '") && message.ends_with("' not found"))
+    })
+}
+
+// Wait for the device whenever it went away until `end_time` (unix seconds) or `stop`, trying
+// less often the longer it is gone. A device over the network is connected again, one on usb
+// is picked up by the adb server on its own once it is back. Outages are recorded as
+// `disconnect` events when they start and end.
+pub fn watch(
+    device_cmd: &str,
+    link: Link,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let device_cmd = device_cmd.to_string();
+    // `adb connect` takes the serial of a device over tcp, `host:port`
+    let endpoint = device_cmd
+        .strip_prefix("-s ")
+        .filter(|serial| serial.contains(':'))
+        .map(str::to_string);
+    thread::spawn(move || {
+        let running = || now() < end_time && !stop.load(Ordering::Relaxed);
+        while running() {
+            if !link.lost() {
+                thread::sleep(Duration::from_millis(200));
+                continue;
+            }
+            let since = now_millis();
+            events.record(EventKind::Disconnect, "device disconnected");
+            let (mut retry, mut next, mut tries) = (FIRST_RETRY, since + FIRST_RETRY, 0);
+            let back = loop {
+                if !running() {
+                    break false;
+                }
+                if now_millis() < next {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+                tries += 1;
+                link.tries.fetch_add(1, Ordering::Relaxed);
+                if let Some(endpoint) = &endpoint {
+                    run_adb_command(&["adb", "connect", endpoint]);
+                }
+                if run_adb_command(&adb_argv(&device_cmd, &["get-state"])).trim() == "device" {
+                    break true;
+                }
+                retry = (retry * 2).min(MAX_RETRY);
+                next = now_millis() + retry;
+                println!("设备未恢复, {}ms 后重试", retry);
+            };
+            if !back {
+                println!("设备在运行结束前未恢复");
+                break;
+            }
+            link.lost.store(false, Ordering::Relaxed);
+            events.record(
+                EventKind::Disconnect,
+                format!(
+                    "device back after {:.1}s, {} tries",
+                    now_millis().saturating_sub(since) as f64 / 1000.0,
+                    tries
+                ),
+            );
+        }
+    })
+}
@@ -0,0 +1,110 @@
+use crate::events::{self, EventKind, EventLog};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Control protocol for embedding: JSON commands come in on stdin, one per line
+// (`{"cmd": "start"}`, `stop`, `mark` with an optional `label`, `status`, which replies
+// with the sample counts and the `current` values) and every reply is a JSON line on
+// stdout with an `event` field. A run that fails ends with a `failed` event with the `error`
+// kind, its `message` and the `exitCode`. Other stdout lines are the usual human readable log
+// and should be ignored by the controller.
+pub fn reply(value: Value) {
+    println!("{}", value);
+}
+
+fn parse(line: &str) -> Result<(String, Value), String> {
+    let command: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    let name = command
+        .get("cmd")
+        .and_then(Value::as_str)
+        .ok_or("missing \"cmd\"")?
+        .to_string();
+    Ok((name, command))
+}
+
+// Block until the controller sends `start`, `false` if it stopped or went away before that
+pub fn wait_for_start() -> bool {
+    reply(json!({ "event": "ready" }));
+    let lines = events::stdin_lines().lock().unwrap();
+    while let Ok(line) = lines.recv() {
+        match parse(&line) {
+            Ok((name, _)) if name == "start" => {
+                reply(json!({ "event": "started" }));
+                return true;
+            }
+            Ok((name, _)) if name == "stop" => return false,
+            Ok((name, _)) if name == "status" => {
+                reply(json!({ "event": "status", "running": false }))
+            }
+            Ok((name, _)) => {
+                reply(json!({ "event": "error", "message": format!("{} before start", name) }))
+            }
+            Err(message) => reply(json!({ "event": "error", "message": message })),
+        }
+    }
+    false
+}
+
+// Serve commands during the run until `end_time` (unix seconds) or until asked to stop
+pub fn watch_commands(
+    events: EventLog,
+    metrics: Vec<&'static str>,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let lines = events::stdin_lines().lock().unwrap();
+        while events::now_millis() / 1000 < end_time && !stop.load(Ordering::Relaxed) {
+            let line = match lines.recv_timeout(Duration::from_millis(200)) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => continue,
+                // Without a controller nobody can stop us early, run to the end
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            match parse(&line) {
+                Ok((name, command)) => match name.as_str() {
+                    "mark" => {
+                        let label = command
+                            .get("label")
+                            .and_then(Value::as_str)
+                            .unwrap_or("mark");
+                        events.record(EventKind::Marker, label);
+                        reply(json!({ "event": "marked", "label": label }));
+                    }
+                    "status" => {
+                        let samples: serde_json::Map<String, Value> = metrics
+                            .iter()
+                            .zip(events.sample_counts())
+                            .map(|(metric, count)| (metric.to_string(), json!(count)))
+                            .collect();
+                        let current: serde_json::Map<String, Value> = events
+                            .latest_values()
+                            .into_iter()
+                            .map(|(metric, value)| (metric.to_string(), json!(value)))
+                            .collect();
+                        reply(json!({
+                            "event": "status",
+                            "running": true,
+                            "elapsed": events::now_millis().saturating_sub(events.start()) as f64 / 1000.0,
+                            "samples": samples,
+                            "current": current,
+                        }));
+                    }
+                    "stop" => {
+                        stop.store(true, Ordering::Relaxed);
+                        reply(json!({ "event": "stopping" }));
+                    }
+                    "start" => reply(json!({ "event": "error", "message": "already started" })),
+                    _ => reply(
+                        json!({ "event": "error", "message": format!("unknown command {}", name) }),
+                    ),
+                },
+                Err(message) => reply(json!({ "event": "error", "message": message })),
+            }
+        }
+    })
+}
@@ -0,0 +1,114 @@
+// Jiffies of every core since boot and the frequency it runs at right now:
+//
+// cpu0 2255 34 2290 22625563 6290 127 456 0 0 0
+// ...
+// freq cpu0 1804800
+pub const COMMAND: &str = "cat /proc/stat; for cpu in /sys/devices/system/cpu/cpu[0-9]*; do echo freq ${cpu##*/} $(cat $cpu/cpufreq/scaling_cur_freq); done";
+
+// Cores with series of their own, phones have up to 10, the ones past these are left out
+pub const MAX_CORES: usize = 16;
+
+// Series names of the cores, samples name their metric statically
+pub const USAGE: [&str; MAX_CORES] = [
+    "core0_usage",
+    "core1_usage",
+    "core2_usage",
+    "core3_usage",
+    "core4_usage",
+    "core5_usage",
+    "core6_usage",
+    "core7_usage",
+    "core8_usage",
+    "core9_usage",
+    "core10_usage",
+    "core11_usage",
+    "core12_usage",
+    "core13_usage",
+    "core14_usage",
+    "core15_usage",
+];
+pub const FREQUENCY: [&str; MAX_CORES] = [
+    "core0_freq",
+    "core1_freq",
+    "core2_freq",
+    "core3_freq",
+    "core4_freq",
+    "core5_freq",
+    "core6_freq",
+    "core7_freq",
+    "core8_freq",
+    "core9_freq",
+    "core10_freq",
+    "core11_freq",
+    "core12_freq",
+    "core13_freq",
+    "core14_freq",
+    "core15_freq",
+];
+
+// `(busy, total)` jiffies of the fields after the name of a `/proc/stat` cpu line
+pub fn busy_total<'a>(fields: impl Iterator<Item = &'a str>) -> (u64, u64) {
+    let values: Vec<u64> = fields.filter_map(|value| value.parse().ok()).collect();
+    // user nice system idle iowait irq softirq steal, guest time is in user already
+    let total: u64 = values.iter().take(8).sum();
+    let idle = values.get(3).copied().unwrap_or(0) + values.get(4).copied().unwrap_or(0);
+    (total.saturating_sub(idle), total)
+}
+
+// `(busy, total)` jiffies of every online core, by core number, from the `cpuN` lines
+fn jiffies(output: &str) -> Vec<(usize, u64, u64)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let core: usize = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            let (busy, total) = busy_total(fields);
+            Some((core, busy, total))
+        })
+        .collect()
+}
+
+// MHz every core with cpufreq runs at, by core number, from the `freq cpuN <kHz>` lines
+pub fn frequencies(output: &str) -> Vec<(usize, f64)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.strip_prefix("freq ")?.split_whitespace();
+            let core = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            let khz: f64 = fields.next()?.parse().ok()?;
+            Some((core, khz / 1000.0))
+        })
+        .collect()
+}
+
+// Jiffies of the last read, one look at a core's usage needs two
+#[derive(Default)]
+pub struct CoreTracker {
+    last: Vec<(usize, u64, u64)>,
+}
+
+impl CoreTracker {
+    // Percent every core was busy since the last read, by core number. None for the first read,
+    // and no entry for a core that was offline at either read.
+    pub fn update(&mut self, output: &str) -> Option<Vec<(usize, f64)>> {
+        let current = jiffies(output);
+        let last = std::mem::replace(&mut self.last, current);
+        if last.is_empty() {
+            return None;
+        }
+        Some(
+            self.last
+                .iter()
+                .filter_map(|(core, busy, total)| {
+                    let (_, last_busy, last_total) =
+                        last.iter().find(|(other, _, _)| other == core)?;
+                    let elapsed = total
+                        .checked_sub(*last_total)
+                        .filter(|elapsed| *elapsed > 0)?;
+                    let busy = busy.saturating_sub(*last_busy).min(elapsed);
+                    Some((*core, busy as f64 * 100.0 / elapsed as f64))
+                })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,69 @@
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::now;
+use crate::platform::Platform;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Milliseconds between two looks at the cpuset
+const POLL_INTERVAL: u64 = 1000;
+
+// The cpuset of the app's main process without the leading slash (`top-app`, `foreground`,
+// `background`, `system-background`, `restricted`), none while it is not running
+pub fn cpuset(device_cmd: &str, pkg: &str, platform: &Platform) -> Option<String> {
+    let pids = platform.shell(device_cmd, &format!("pidof {}", pkg));
+    // pidof lists the main process only, the others have `pkg:name`
+    let pid = pids.split_whitespace().next()?;
+    let output = platform.shell(device_cmd, &format!("cat /proc/{}/cpuset", pid));
+    // an error (the process just exited) is more than one word
+    let cpuset = output.trim();
+    if !cpuset.starts_with('/') || cpuset.contains(' ') {
+        return None;
+    }
+    // `/` is the root cpuset, every cpu
+    match cpuset.trim_start_matches('/') {
+        "" => Some("root".to_string()),
+        cpuset => Some(cpuset.to_string()),
+    }
+}
+
+// Record the app's cpuset at the start and every time the system moves it, OEM background
+// restrictions doing so mid-run explain sudden performance cliffs
+pub fn watch(
+    device_cmd: &str,
+    pkg: &str,
+    platform: &Platform,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let (device_cmd, pkg, platform) = (device_cmd.to_string(), pkg.to_string(), platform.clone());
+    thread::spawn(move || {
+        let mut last: Option<String> = None;
+        let mut next = now_millis();
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                let current = cpuset(&device_cmd, &pkg, &platform);
+                if let Some(current) = current.filter(|current| last.as_ref() != Some(current)) {
+                    let detail = match &last {
+                        Some(last) => format!("{} -> {}", last, current),
+                        None => current.clone(),
+                    };
+                    events.record(EventKind::Cpuset, detail);
+                    last = Some(current);
+                }
+                next = now_millis() + POLL_INTERVAL;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    })
+}
+
+pub fn print_shares(shares: &[(String, f64)]) {
+    let text: Vec<String> = shares
+        .iter()
+        .map(|(cpuset, share)| format!("{} {:.1}%", cpuset, share * 100.0))
+        .collect();
+    println!("cpuset: {}", text.join(", "));
+}
@@ -0,0 +1,77 @@
+use crate::{adb_argv, run_adb_command};
+use serde_json::{Value, json};
+
+// Status of a run cut short by a crash loop, its averages say nothing about the app
+pub const INVALID_UNSTABLE: &str = "INVALID-UNSTABLE";
+
+// Crashes shown in the report, and lines of each
+const MAX_EXCERPTS: usize = 3;
+const EXCERPT_LINES: usize = 20;
+
+// The app kept crashing, the run was ended early
+#[derive(Clone, Debug)]
+pub struct CrashLoop {
+    // times the process died or came back with another pid
+    pub crashes: usize,
+    // the limit that was exceeded
+    pub max_restarts: usize,
+    // unix milliseconds the run was ended at
+    pub time: u64,
+    // the app's last crashes in the `crash` log buffer
+    pub excerpts: Vec<String>,
+}
+
+impl CrashLoop {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "status": INVALID_UNSTABLE,
+            "crashes": self.crashes,
+            "maxRestarts": self.max_restarts,
+            "time": self.time,
+            "excerpts": self.excerpts,
+        })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "运行无效 ({}): 应用崩溃/重启 {} 次, 超过 {} 次, 提前结束",
+            INVALID_UNSTABLE, self.crashes, self.max_restarts
+        );
+        for excerpt in &self.excerpts {
+            println!("{}\n", excerpt);
+        }
+    }
+}
+
+// The app's last crashes from `logcat -b crash`, lines are grouped by the pid that logged them
+// (`10-14 05:00:00.000  1234  1234 E AndroidRuntime: FATAL EXCEPTION: main`)
+pub fn crash_excerpts(device_cmd: &str, pkg: &str) -> Vec<String> {
+    let log = run_adb_command(&adb_argv(
+        device_cmd,
+        &["logcat", "-b", "crash", "-d", "-t", "500"],
+    ));
+    let mut groups: Vec<(String, Vec<&str>)> = Vec::new();
+    for line in log.lines() {
+        let Some(pid) = line.split_whitespace().nth(2) else {
+            continue;
+        };
+        match groups.last_mut() {
+            Some((last, lines)) if last == pid => lines.push(line),
+            _ => groups.push((pid.to_string(), vec![line])),
+        }
+    }
+    let mut excerpts: Vec<String> = groups
+        .into_iter()
+        .filter(|(_, lines)| lines.iter().any(|line| line.contains(pkg)))
+        .map(|(_, lines)| {
+            lines
+                .into_iter()
+                .take(EXCERPT_LINES)
+                .collect::<Vec<&str>>()
+                .join("\n")
+        })
+        .collect();
+    let skip = excerpts.len().saturating_sub(MAX_EXCERPTS);
+    excerpts.drain(..skip);
+    excerpts
+}
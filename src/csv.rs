@@ -0,0 +1,184 @@
+use crate::RunData;
+use crate::compress::{self, Compression};
+use crate::events::{self, EventKind};
+use crate::report::{ReportWriter, SeriesTable};
+use crate::sample::{Quality, Sample, Series};
+use crate::screen;
+use crate::sla::{self, Verdict};
+use crate::timestamp;
+use std::fmt::Write;
+
+// `cpu_data_<time>.csv`..., a header row and one row per sample for pandas, R and the like:
+//
+// timestamp_ms,time,elapsed_s,value,unit
+// 1760421462974,2026-10-14 06:17:42.974,1.523,27,%
+//
+// Values are in the collected unit (`%`, `KB`, `mW`, `frames`), runs that watched the screen get
+// a `screen` column and series with samples that are not good a `quality` column (`warmup` for
+// the first sample of `--raw` runs). Unlike the workbooks there are no max and average rows.
+// With `--compress` the files are `.csv.gz` or `.csv.zst`.
+pub struct CsvWriter {
+    pub compression: Option<Compression>,
+}
+
+impl ReportWriter for CsvWriter {
+    fn extension(&self) -> &'static str {
+        match self.compression {
+            None => "csv",
+            Some(Compression::Gzip) => "csv.gz",
+            Some(Compression::Zstd) => "csv.zst",
+        }
+    }
+
+    fn write_series(&self, path: &str, table: &SeriesTable) -> Result<(), String> {
+        let series = table.series;
+        let times: Vec<u64> = series.samples.iter().map(|sample| sample.ts).collect();
+        let screen_states = screen::states_at(&table.data.events, &times);
+        let watched = screen_states.iter().any(Option::is_some);
+        let qualities = series
+            .samples
+            .iter()
+            .any(|sample| sample.quality != Quality::Good);
+        let unit = series.unit.label();
+
+        let mut text = String::from("timestamp_ms,time,elapsed_s,value,unit");
+        if watched {
+            text.push_str(",screen");
+        }
+        if qualities {
+            text.push_str(",quality");
+        }
+        text.push('\n');
+        for (idx, sample) in series.samples.iter().enumerate() {
+            // samples read back without a time (old manifests) have the time columns empty
+            if sample.ts > 0 {
+                let elapsed = sample.ts.saturating_sub(table.data.start) as f64 / 1000.0;
+                let _ = write!(
+                    text,
+                    "{},{},{}",
+                    sample.ts,
+                    timestamp::format(sample.ts),
+                    elapsed
+                );
+            } else {
+                text.push_str(",,");
+            }
+            let _ = write!(text, ",{},{}", sample.value, unit);
+            if watched {
+                let _ = write!(
+                    text,
+                    ",{}",
+                    screen_states[idx].as_deref().unwrap_or_default()
+                );
+            }
+            if qualities {
+                let _ = write!(text, ",{}", sample.quality.label());
+            }
+            text.push('\n');
+        }
+        compress::write(path, &text, self.compression)
+    }
+}
+
+// State-like events a long row is tagged with, as `screen=off;doze=IDLE`
+const TAGS: [(EventKind, &str); 4] = [
+    (EventKind::Screen, "screen"),
+    (EventKind::Cpuset, "cpuset"),
+    (EventKind::Doze, "doze"),
+    (EventKind::Thermal, "thermal"),
+];
+
+// `samples_<time>.csv`, every series in one tidy table for R and pandas pipelines that want the
+// long format, a row per sample in time order:
+//
+// timestamp_ms,time,elapsed_s,metric,value,unit,quality,tags
+// 1760421462974,2026-10-14 06:17:42.974,1.523,cpu,27,%,good,screen=off
+//
+// `tags` holds the screen state, cpuset and doze state the sample was taken in, empty if none
+// of them was watched. `path` has the suffix of `compression` already, see `compress::path`.
+pub fn save_long(
+    path: &str,
+    data: &RunData,
+    compression: Option<Compression>,
+) -> Result<(), String> {
+    let mut rows: Vec<(&Series, &Sample)> = [&data.cpu, &data.mem]
+        .into_iter()
+        .chain(&data.series)
+        .flat_map(|series| series.samples.iter().map(move |sample| (series, sample)))
+        .collect();
+    rows.sort_by_key(|(_, sample)| sample.ts);
+    let times: Vec<u64> = rows.iter().map(|(_, sample)| sample.ts).collect();
+    let states: Vec<(&str, Vec<Option<String>>)> = TAGS
+        .iter()
+        .map(|(kind, name)| (*name, events::states_at(&data.events, *kind, &times)))
+        .collect();
+
+    let mut text = String::from("timestamp_ms,time,elapsed_s,metric,value,unit,quality,tags\n");
+    for (idx, (series, sample)) in rows.iter().enumerate() {
+        if sample.ts > 0 {
+            let elapsed = sample.ts.saturating_sub(data.start) as f64 / 1000.0;
+            let _ = write!(
+                text,
+                "{},{},{}",
+                sample.ts,
+                timestamp::format(sample.ts),
+                elapsed
+            );
+        } else {
+            text.push_str(",,");
+        }
+        let tags: Vec<String> = states
+            .iter()
+            .filter_map(|(name, states)| {
+                states[idx]
+                    .as_ref()
+                    .map(|state| format!("{}={}", name, state))
+            })
+            .collect();
+        let _ = writeln!(
+            text,
+            ",{},{},{},{},{}",
+            series.name,
+            sample.value,
+            series.unit.label(),
+            sample.quality.label(),
+            tags.join(";")
+        );
+    }
+    compress::write(path, &text, compression)
+}
+
+// `sla_<time>.csv` next to the csv tables, the verdict of every `--sla` condition and the run's
+// as the last row, the columns of the workbook's SLA sheet:
+//
+// condition,actual,unit,result,note
+// cpu_p95 < 35%,41.2,%,FAIL,
+// SLA,,,FAIL,
+pub fn save_sla(path: &str, verdicts: &[Verdict]) -> Result<(), String> {
+    let result = |passed| if passed { "PASS" } else { "FAIL" };
+    let mut text = String::from("condition,actual,unit,result,note\n");
+    for verdict in verdicts {
+        let _ = writeln!(
+            text,
+            "{},{},{},{},{}",
+            field(&verdict.condition.text()),
+            verdict
+                .actual
+                .map_or(String::new(), |actual| actual.to_string()),
+            verdict.condition.unit,
+            result(verdict.passed),
+            field(verdict.note.as_deref().unwrap_or_default())
+        );
+    }
+    let _ = writeln!(text, "SLA,,,{},", result(sla::passed(verdicts)));
+    compress::write(path, &text, None)
+}
+
+// `text` as one csv field, quoted if it has a comma, quote or line break
+fn field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
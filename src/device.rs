@@ -0,0 +1,109 @@
+use crate::error::Error;
+use crate::{adb_argv, adb_shell, cache, transport};
+
+// Read-only (`ro.`) properties are set at boot and come from the device state cache
+pub fn getprop(device_cmd: &str, name: &str) -> String {
+    let read = || {
+        adb_shell(device_cmd, &format!("getprop {}", name))
+            .trim()
+            .to_string()
+    };
+    if name.starts_with("ro.") {
+        cache::cached(device_cmd, &format!("getprop {}", name), read)
+    } else {
+        read()
+    }
+}
+
+// That adb runs, the device is there and online, and `pkg` is installed, before anything is
+// set up on it
+pub fn check(device_cmd: &str, pkg: Option<&str>) -> Result<(), Error> {
+    let version = transport::command(&["adb", "version"])
+        .output()
+        .map_err(|e| Error::AdbMissing(e.to_string()))?;
+    // the shell of the `--adb-host` runs adb there and cannot find it
+    if version.status.code() == Some(127) {
+        return Err(Error::AdbMissing(format!(
+            "not on the PATH of {}",
+            transport::adb_host().unwrap_or("this host")
+        )));
+    }
+
+    let state = transport::command(&adb_argv(device_cmd, &["get-state"]))
+        .output()
+        .map_err(|e| Error::AdbMissing(e.to_string()))?;
+    let stdout = String::from_utf8_lossy(&state.stdout).trim().to_string();
+    if stdout != "device" {
+        // "error: device offline", "error: device 'X' not found", "error: no devices found"
+        let stderr = String::from_utf8_lossy(&state.stderr);
+        let message = stderr
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_start_matches("error: ")
+            .to_string();
+        return Err(if !stdout.is_empty() {
+            // booted into recovery, the bootloader or sideload
+            Error::DeviceUnavailable(format!("device is in {}", stdout))
+        } else if message.contains("offline") || message.contains("unauthorized") {
+            Error::DeviceUnavailable(message)
+        } else {
+            Error::DeviceNotFound(if message.is_empty() {
+                format!("adb {} get-state failed", device_cmd)
+            } else {
+                message
+            })
+        });
+    }
+
+    if let Some(pkg) = pkg
+        && !adb_shell(device_cmd, &format!("pm path {}", pkg)).contains("package:")
+    {
+        return Err(Error::PackageNotInstalled(pkg.to_string()));
+    }
+    Ok(())
+}
+
+// Build properties and hardware of the device under test
+pub struct DeviceInfo {
+    pub brand: String,
+    pub device: String,
+    pub model: String,
+    pub manufacturer: String,
+    pub fingerprint: String,
+    pub sdk: u32,
+    pub cpu_cores: u32,
+    pub mem_total_bytes: u64,
+}
+
+impl DeviceInfo {
+    pub fn probe(device_cmd: &str) -> DeviceInfo {
+        let cores = cache::cached(device_cmd, "nproc", || {
+            adb_shell(device_cmd, "nproc").trim().to_string()
+        });
+        let mem_total_kb = cache::cached(device_cmd, "mem_total_kb", || {
+            let meminfo = adb_shell(device_cmd, "cat /proc/meminfo");
+            // "MemTotal:        7849564 kB"
+            meminfo
+                .lines()
+                .find(|line| line.starts_with("MemTotal:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .unwrap_or_default()
+                .to_string()
+        });
+
+        DeviceInfo {
+            brand: getprop(device_cmd, "ro.product.brand"),
+            device: getprop(device_cmd, "ro.product.device"),
+            model: getprop(device_cmd, "ro.product.model"),
+            manufacturer: getprop(device_cmd, "ro.product.manufacturer"),
+            fingerprint: getprop(device_cmd, "ro.build.fingerprint"),
+            sdk: getprop(device_cmd, "ro.build.version.sdk")
+                .parse()
+                .unwrap_or(0),
+            cpu_cores: cores.trim().parse().unwrap_or(0),
+            mem_total_bytes: mem_total_kb.parse().unwrap_or(0) * 1024,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use serde_json::{Value, json};
+
+// Storage I/O of the app's main process since it started, quoted for the device's shell. Other
+// apps' io is only readable as the app (debuggable builds) or root:
+//
+// rchar: 4185631
+// wchar: 250996
+// read_bytes: 1622016
+// write_bytes: 122880
+pub fn command(pkg: &str) -> String {
+    format!(
+        "pid=$(pidof {}); cat /proc/$pid/io 2>/dev/null || run-as {} cat /proc/$pid/io",
+        pkg, pkg
+    )
+}
+
+// `(read_bytes, write_bytes)` of `command` output, the bytes that went to or came from the
+// storage device, page cache hits left out
+fn parse(output: &str) -> Option<(u64, u64)> {
+    let counter = |key: &str| {
+        output.lines().find_map(|line| {
+            line.trim()
+                .strip_prefix(key)?
+                .trim_start_matches(':')
+                .trim()
+                .parse()
+                .ok()
+        })
+    };
+    Some((counter("read_bytes")?, counter("write_bytes")?))
+}
+
+// What the app read from and wrote to storage over the run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiskIo {
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+impl DiskIo {
+    pub fn to_json(&self) -> Value {
+        json!({ "read_bytes": self.read_bytes, "write_bytes": self.write_bytes })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "磁盘: 读取 {:.1} KB, 写入 {:.1} KB",
+            self.read_bytes as f64 / 1024.0,
+            self.write_bytes as f64 / 1024.0
+        );
+    }
+}
+
+// The counters of the last read, with its time
+#[derive(Default)]
+pub struct IoTracker {
+    last: Option<(u64, u64, u64)>,
+    pub total: DiskIo,
+}
+
+impl IoTracker {
+    // KB/s read and written since the last read at `now` (unix milliseconds), none for the
+    // first read. Err if the app's io was not readable.
+    pub fn update(&mut self, output: &str, now: u64) -> Result<Option<(f64, f64)>, String> {
+        let (read, write) = parse(output).ok_or("no read_bytes in /proc/<pid>/io")?;
+        let Some((last_read, last_write, since)) = self.last.replace((read, write, now)) else {
+            return Ok(None);
+        };
+        // a restarted app counts from 0 again
+        let delta = |current: u64, last: u64| current.checked_sub(last).unwrap_or(current);
+        let (read, write) = (delta(read, last_read), delta(write, last_write));
+        self.total.read_bytes += read;
+        self.total.write_bytes += write;
+        let seconds = now.saturating_sub(since).max(1) as f64 / 1000.0;
+        Ok(Some((
+            read as f64 / 1024.0 / seconds,
+            write as f64 / 1024.0 / seconds,
+        )))
+    }
+}
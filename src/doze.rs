@@ -0,0 +1,154 @@
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::package::PackageInfo;
+use crate::sample::Series;
+use crate::traffic;
+use crate::{adb_shell, now};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// `dumpsys deviceidle` state the device lets apps run jobs, syncs and alarms in
+const MAINTENANCE: &str = "IDLE_MAINTENANCE";
+
+// A stretch the device spent in the maintenance state, with what the app's uid sent and
+// received meanwhile
+#[derive(Clone, Debug)]
+pub struct DozeWindow {
+    // unix time in milliseconds
+    pub start: u64,
+    pub end: u64,
+    // bytes over all networks, none if `dumpsys netstats` did not say
+    pub rx_bytes: Option<u64>,
+    pub tx_bytes: Option<u64>,
+}
+
+impl DozeWindow {
+    // Average and peak of the samples of `series` taken in the window, none without any
+    pub fn average_max(&self, series: &Series) -> Option<(f64, f64)> {
+        let values: Vec<f64> = series
+            .measured()
+            .filter(|sample| sample.ts >= self.start && sample.ts <= self.end)
+            .map(|sample| sample.value)
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        let max = values.iter().copied().fold(f64::MIN, f64::max);
+        Some((values.iter().sum::<f64>() / values.len() as f64, max))
+    }
+
+    pub fn to_json(&self, cpu: &Series) -> Value {
+        let cpu = self.average_max(cpu);
+        json!({
+            "start": self.start,
+            "end": self.end,
+            "duration_s": self.end.saturating_sub(self.start) as f64 / 1000.0,
+            "cpu_average": cpu.map(|(average, _)| average),
+            "cpu_max": cpu.map(|(_, max)| max),
+            "rx_bytes": self.rx_bytes,
+            "tx_bytes": self.tx_bytes,
+        })
+    }
+}
+
+pub fn windows_json(windows: &[DozeWindow], cpu: &Series) -> Value {
+    Value::Array(windows.iter().map(|window| window.to_json(cpu)).collect())
+}
+
+pub fn print_windows(windows: &[DozeWindow], cpu: &Series) {
+    for (idx, window) in windows.iter().enumerate() {
+        let seconds = window.end.saturating_sub(window.start) as f64 / 1000.0;
+        let cpu = window
+            .average_max(cpu)
+            .map_or("无样本".to_string(), |(average, max)| {
+                format!("cpu均值 {:.1}, 峰值 {:.1}", average, max)
+            });
+        let traffic = match (window.rx_bytes, window.tx_bytes) {
+            (Some(rx), Some(tx)) => format!(", 接收 {} B, 发送 {} B", rx, tx),
+            _ => String::new(),
+        };
+        println!("维护窗口 {}: {:.1}s, {}{}", idx + 1, seconds, cpu, traffic);
+    }
+}
+
+// Move deep doze on by one state, `Stepped to deep: IDLE_MAINTENANCE`
+fn step(device_cmd: &str) -> Option<String> {
+    let output = adb_shell(device_cmd, "dumpsys deviceidle step");
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Stepped to deep:"))
+        .map(|state| state.trim().to_string())
+}
+
+// Unplug the battery and force the device into deep idle, then step it on every `every` seconds
+// so it goes through maintenance windows and back into idle. Every state is a `doze` event, the
+// stretches in IDLE_MAINTENANCE are returned with the app's traffic in them. Doze and the
+// battery are given back to the device once the run is over.
+pub fn cycle(
+    device_cmd: &str,
+    pkg: &str,
+    every: u64,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<Vec<DozeWindow>> {
+    let (device_cmd, pkg) = (device_cmd.to_string(), pkg.to_string());
+    thread::spawn(move || {
+        let shell = |command: &str| adb_shell(&device_cmd, command);
+        let uid = PackageInfo::resolve(&device_cmd, &pkg).uid;
+        let traffic = || uid.and_then(|uid| traffic::netstats_bytes(&device_cmd, uid));
+
+        shell("dumpsys battery unplug");
+        shell("dumpsys deviceidle force-idle");
+        println!("设备进入 doze");
+        events.record(EventKind::Doze, "IDLE".to_string());
+        let mut last = "IDLE".to_string();
+
+        let mut windows = Vec::new();
+        // start of the open window and the traffic counters then
+        let mut open: Option<(u64, Option<(u64, u64)>)> = None;
+        let mut close = |open: &mut Option<(u64, Option<(u64, u64)>)>| {
+            if let Some((start, before)) = open.take() {
+                let after = traffic();
+                let delta = before.zip(after).map(|(before, after)| {
+                    (
+                        after.0.saturating_sub(before.0),
+                        after.1.saturating_sub(before.1),
+                    )
+                });
+                windows.push(DozeWindow {
+                    start,
+                    end: now_millis(),
+                    rx_bytes: delta.map(|(rx, _)| rx),
+                    tx_bytes: delta.map(|(_, tx)| tx),
+                });
+            }
+        };
+
+        let mut next = now_millis() + every * 1000;
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                if let Some(current) = step(&device_cmd).filter(|current| *current != last) {
+                    println!("doze: {}", current);
+                    if last == MAINTENANCE {
+                        close(&mut open);
+                    }
+                    if current == MAINTENANCE {
+                        open = Some((now_millis(), traffic()));
+                    }
+                    events.record(EventKind::Doze, format!("{} -> {}", last, current));
+                    last = current;
+                }
+                next = now_millis() + every * 1000;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        close(&mut open);
+
+        shell("dumpsys deviceidle unforce");
+        shell("dumpsys battery reset");
+        windows
+    })
+}
@@ -0,0 +1,131 @@
+use crate::RunData;
+use crate::events::EventKind;
+use crate::sample::{Sample, Series, Unit};
+use serde_json::{Value, json};
+
+// Series the energy metrics are derived from, a run has them when both the power and the
+// frame collectors ran
+pub const POWER: &str = "power";
+pub const FRAMES: &str = "frames";
+
+// Milliwatt milliseconds in a milliwatt hour
+const MW_MS_PER_MWH: f64 = 3_600_000.0;
+
+// Energy and frames over a part of the run
+#[derive(Clone, Debug)]
+pub struct Efficiency {
+    // marker that starts the part, none for the whole run
+    pub step: Option<String>,
+    // unix milliseconds
+    pub start: u64,
+    pub end: u64,
+    pub energy_mwh: f64,
+    pub frames: f64,
+}
+
+impl Efficiency {
+    // mWh per frame, none without frames
+    pub fn per_frame(&self) -> Option<f64> {
+        (self.frames > 0.0).then(|| self.energy_mwh / self.frames)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({
+            "start": self.start,
+            "end": self.end,
+            "energy_mwh": self.energy_mwh,
+            "frames": self.frames,
+            "mwh_per_frame": self.per_frame(),
+        });
+        if let Some(step) = &self.step {
+            value["step"] = json!(step);
+        }
+        value
+    }
+}
+
+fn timed(series: &Series) -> Vec<&Sample> {
+    let mut samples: Vec<&Sample> = series.measured().filter(|s| s.ts > 0).collect();
+    samples.sort_by_key(|sample| sample.ts);
+    samples
+}
+
+// Power integrated over `start..end` with the trapezoid rule, segments are cut at the bounds
+fn energy_mwh(power: &[&Sample], start: u64, end: u64) -> f64 {
+    let mut energy = 0.0;
+    for pair in power.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let (from, to) = (a.ts.max(start), b.ts.min(end));
+        if from >= to {
+            continue;
+        }
+        let at =
+            |time: u64| a.value + (b.value - a.value) * (time - a.ts) as f64 / (b.ts - a.ts) as f64;
+        energy += (at(from) + at(to)) / 2.0 * (to - from) as f64;
+    }
+    energy / MW_MS_PER_MWH
+}
+
+// Frames rendered in `start..end`, each sample counts the frames since the one before it
+fn frames_in(frames: &[&Sample], start: u64, end: u64) -> f64 {
+    frames
+        .iter()
+        .filter(|sample| sample.ts > start && sample.ts <= end)
+        .map(|sample| sample.value)
+        .sum()
+}
+
+fn series<'a>(data: &'a RunData, name: &str, unit: Unit) -> Option<&'a Series> {
+    data.series
+        .iter()
+        .find(|series| series.name == name && series.unit == unit && !series.is_empty())
+}
+
+// Energy per frame of the whole run, then of every interaction the markers split it into,
+// none unless the run has both power and frames
+pub fn efficiency(data: &RunData) -> Option<Vec<Efficiency>> {
+    let power = timed(series(data, POWER, Unit::Milliwatts)?);
+    let frames = timed(series(data, FRAMES, Unit::Frames)?);
+    let part = |step: Option<String>, start: u64, end: u64| Efficiency {
+        step,
+        start,
+        end,
+        energy_mwh: energy_mwh(&power, start, end),
+        frames: frames_in(&frames, start, end),
+    };
+
+    let mut parts = vec![part(None, data.start, data.end)];
+    let markers: Vec<_> = data
+        .events
+        .iter()
+        .filter(|event| event.kind == EventKind::Marker)
+        .collect();
+    for (idx, marker) in markers.iter().enumerate() {
+        let end = markers.get(idx + 1).map_or(data.end, |next| next.time);
+        parts.push(part(Some(marker.detail.clone()), marker.time, end));
+    }
+    Some(parts)
+}
+
+pub fn efficiency_json(parts: &[Efficiency]) -> Value {
+    let mut value = parts.first().map_or(json!({}), Efficiency::to_json);
+    value["steps"] = Value::Array(parts.iter().skip(1).map(Efficiency::to_json).collect());
+    value
+}
+
+pub fn print_efficiency(parts: &[Efficiency]) {
+    for part in parts {
+        let per_frame = part
+            .per_frame()
+            .map_or("-".to_string(), |per_frame| format!("{:.6} mWh", per_frame));
+        println!(
+            "能耗{}: {:.3} mWh, {} 帧, 每帧 {}",
+            part.step
+                .as_ref()
+                .map_or(String::new(), |step| format!(" [{}]", step)),
+            part.energy_mwh,
+            part.frames,
+            per_frame
+        );
+    }
+}
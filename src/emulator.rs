@@ -0,0 +1,138 @@
+use crate::run_adb_command;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// An avd to boot, temporary ones are deleted when dropped
+pub struct Avd {
+    name: String,
+    temporary: bool,
+}
+
+// A headless emulator process, killed when dropped
+pub struct Emulator {
+    child: Child,
+    pub serial: String,
+    // Dropped after the emulator so a temporary avd is deleted once it is no longer in use
+    _avd: Avd,
+}
+
+impl Avd {
+    pub fn existing(name: &str) -> Self {
+        Avd {
+            name: name.to_string(),
+            temporary: false,
+        }
+    }
+}
+
+impl Drop for Avd {
+    fn drop(&mut self) {
+        if self.temporary {
+            println!("删除临时avd: {}", self.name);
+            let _ = Command::new("avdmanager")
+                .args(["delete", "avd", "-n", &self.name])
+                .output();
+        }
+    }
+}
+
+// Create a throwaway avd from an installed system image via `avdmanager`
+pub fn create_avd(system_image: &str) -> Result<Avd, String> {
+    let name = format!("cpureport_{}", std::process::id());
+    println!("创建临时avd: {} ({})", name, system_image);
+
+    let mut child = Command::new("avdmanager")
+        .args(["create", "avd", "-n", &name, "-k", system_image, "--force"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to execute avdmanager: {}", e))?;
+    // Answer "no" to the custom hardware profile prompt
+    if let Some(stdin) = child.stdin.as_mut() {
+        let _ = stdin.write_all(b"no\n");
+    }
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("avdmanager failed to create {}: {}", name, status));
+    }
+
+    Ok(Avd {
+        name,
+        temporary: true,
+    })
+}
+
+impl Emulator {
+    // Start the emulator without a window and wait until Android reports boot completed
+    pub fn boot(avd: Avd, port: u16, timeout_secs: u64) -> Result<Emulator, String> {
+        println!("启动模拟器: {} (port {})", avd.name, port);
+        let child = Command::new("emulator")
+            .args(["-avd", &avd.name, "-port", &port.to_string()])
+            .args(["-no-window", "-no-audio", "-no-boot-anim", "-no-snapshot"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to execute emulator: {}", e))?;
+
+        let mut emulator = Emulator {
+            child,
+            serial: format!("emulator-{}", port),
+            _avd: avd,
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+        loop {
+            if let Ok(Some(status)) = emulator.child.try_wait() {
+                return Err(format!("emulator exited during boot: {}", status));
+            }
+            let booted = run_adb_command(&[
+                "adb",
+                "-s",
+                &emulator.serial,
+                "shell",
+                "getprop",
+                "sys.boot_completed",
+            ]);
+            if booted.trim() == "1" {
+                break;
+            }
+            if Instant::now() > deadline {
+                return Err(format!("emulator did not boot within {}s", timeout_secs));
+            }
+            thread::sleep(Duration::from_secs(2));
+        }
+
+        // Dismiss the lock screen so launched activities are actually resumed
+        run_adb_command(&[
+            "adb",
+            "-s",
+            &emulator.serial,
+            "shell",
+            "input",
+            "keyevent",
+            "82",
+        ]);
+        println!("模拟器已启动: {}", emulator.serial);
+        Ok(emulator)
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        println!("关闭模拟器: {}", self.serial);
+        run_adb_command(&["adb", "-s", &self.serial, "emu", "kill"]);
+
+        // Give the emulator a chance to shut down cleanly before killing it
+        let deadline = Instant::now() + Duration::from_secs(15);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Display;
+
+// Why a cpureport run failed. Each has an exit code of its own, so a CI job can tell a device
+// that went missing from a regression. The samples taken before a failure are written first.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    // the run finished, but failed its SLA or ended in a crash loop
+    #[error("{0}")]
+    Checks(String),
+    // an option value that does not parse, like clap's own errors
+    #[error("{0}")]
+    Usage(String),
+    #[error("adb not found: {0}")]
+    AdbMissing(String),
+    // no device, not this one, or more than one without `-s`
+    #[error("device not found: {0}")]
+    DeviceNotFound(String),
+    // offline, unauthorized
+    #[error("device not available: {0}")]
+    DeviceUnavailable(String),
+    #[error("package {0} is not installed")]
+    PackageNotInstalled(String),
+    #[error("package {0} was not running, nothing was sampled")]
+    PackageNotRunning(String),
+    // a `--strict` run, with every problem found
+    #[error("collectors cannot start: {0}")]
+    Collectors(String),
+    // some reports were not written, the others were
+    #[error("reports not written: {0}")]
+    Output(String),
+    #[error("{context}: {reason}")]
+    Failed { context: String, reason: String },
+}
+
+impl Error {
+    pub fn failed(context: &str, reason: impl Display) -> Error {
+        Error::Failed {
+            context: context.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+
+    // 101 means a bug
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Checks(_) => 1,
+            Error::Usage(_) => 2,
+            Error::AdbMissing(_) => 3,
+            Error::DeviceNotFound(_) => 4,
+            Error::DeviceUnavailable(_) => 5,
+            Error::PackageNotInstalled(_) => 6,
+            Error::PackageNotRunning(_) => 7,
+            Error::Collectors(_) => 8,
+            Error::Output(_) => 9,
+            Error::Failed { .. } => 10,
+        }
+    }
+
+    // What the controller of a `--controlled` run gets as the `error` of the `failed` event
+    pub fn label(&self) -> &'static str {
+        match self {
+            Error::Checks(_) => "checks",
+            Error::Usage(_) => "usage",
+            Error::AdbMissing(_) => "adb_missing",
+            Error::DeviceNotFound(_) => "device_not_found",
+            Error::DeviceUnavailable(_) => "device_unavailable",
+            Error::PackageNotInstalled(_) => "package_not_installed",
+            Error::PackageNotRunning(_) => "package_not_running",
+            Error::Collectors(_) => "collectors",
+            Error::Output(_) => "output",
+            Error::Failed { .. } => "failed",
+        }
+    }
+}
+
+// `Error::Failed` from the errors of the steps of a run, with what was being done
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T, Error>;
+
+    // `Error::Usage` instead, for the values of the command line
+    fn usage(self, context: &str) -> Result<T, Error>;
+}
+
+impl<T, E: Display> Context<T> for Result<T, E> {
+    fn context(self, context: &str) -> Result<T, Error> {
+        self.map_err(|e| Error::failed(context, e))
+    }
+
+    fn usage(self, context: &str) -> Result<T, Error> {
+        self.map_err(|e| Error::Usage(format!("{}: {}", context, e)))
+    }
+}
@@ -0,0 +1,406 @@
+use crate::alert::{AlertKind, Alerter};
+use crate::theme::Theme;
+use crate::timestamp;
+use crate::writer::SampleCounts;
+use rust_xlsxwriter::{RowNum, Workbook, Worksheet, XlsxError};
+use serde_json::{Map, Value, json};
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Threshold,
+    Marker,
+    ProcessDied,
+    Restart,
+    // a value well above the recent level, sampled faster for a while
+    Spike,
+    // the app crashed too often, the run was ended
+    CrashLoop,
+    // the display was rotated
+    Rotation,
+    // the app went into or out of split-screen, picture-in-picture or freeform
+    WindowMode,
+    // the app's main process is in another cpuset, the first one is recorded too
+    Cpuset,
+    // the battery fell below the critical level, the run was ended
+    BatteryLow,
+    // the screen turned on, off or started dozing, the first state is recorded too
+    Screen,
+    // a radio was switched by `--network`
+    Network,
+    // the device was stepped into another doze state by the `doze` mode, the first one is
+    // recorded too
+    Doze,
+    // the device's thermal status changed, anything above `none` is throttling, the first
+    // status is recorded too
+    Thermal,
+    // the app used the camera, microphone, location or body sensors
+    Appop,
+    // the app crashed or did not respond, from logcat
+    Crash,
+    Anr,
+    // the app opened or closed a camera, a camera open at the start is recorded too
+    Camera,
+    // a marker the run sent to logcat and the trace buffer to be lined up with other tools
+    Sync,
+    // adb lost the device, or got it back
+    Disconnect,
+}
+
+impl EventKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EventKind::Threshold => "threshold",
+            EventKind::Marker => "marker",
+            EventKind::ProcessDied => "process died",
+            EventKind::Restart => "restart",
+            EventKind::Spike => "spike",
+            EventKind::CrashLoop => "crash loop",
+            EventKind::Rotation => "rotation",
+            EventKind::WindowMode => "window mode",
+            EventKind::Cpuset => "cpuset",
+            EventKind::BatteryLow => "battery low",
+            EventKind::Screen => "screen",
+            EventKind::Network => "network",
+            EventKind::Doze => "doze",
+            EventKind::Thermal => "thermal",
+            EventKind::Appop => "appop",
+            EventKind::Crash => "crash",
+            EventKind::Anr => "anr",
+            EventKind::Camera => "camera",
+            EventKind::Sync => "sync",
+            EventKind::Disconnect => "disconnect",
+        }
+    }
+}
+
+pub struct Event {
+    // unix time in milliseconds
+    pub time: u64,
+    pub kind: EventKind,
+    pub detail: String,
+    // number of samples each series held when the event happened
+    pub positions: Vec<usize>,
+}
+
+// Timeline shared by all collectors of a run
+#[derive(Clone)]
+pub struct EventLog {
+    start: u64,
+    events: Arc<Mutex<Vec<Event>>>,
+    counts: SampleCounts,
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+impl EventLog {
+    // `counts` are the series the events are positioned against, in sheet order
+    pub fn new(counts: SampleCounts) -> Self {
+        EventLog {
+            start: now_millis(),
+            events: Arc::new(Mutex::new(Vec::new())),
+            counts,
+        }
+    }
+
+    pub fn record(&self, kind: EventKind, detail: impl Into<String>) {
+        let detail = detail.into();
+        println!("EVENT [{}]: {}", kind.label(), detail);
+        let positions = self.counts.get();
+        self.events.lock().unwrap().push(Event {
+            time: now_millis(),
+            kind,
+            detail,
+            positions,
+        });
+    }
+
+    // `(time, kind, detail)` of the last `count` events so far, oldest first
+    pub fn recent(&self, count: usize) -> Vec<(u64, EventKind, String)> {
+        let mut events: Vec<(u64, EventKind, String)> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| (event.time, event.kind, event.detail.clone()))
+            .collect();
+        events.sort_by_key(|(time, _, _)| *time);
+        events.split_off(events.len().saturating_sub(count))
+    }
+
+    // All events in chronological order
+    pub fn take(&self) -> Vec<Event> {
+        let mut events = std::mem::take(&mut *self.events.lock().unwrap());
+        events.sort_by_key(|event| event.time);
+        events
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    // Number of samples collected so far in each series
+    pub fn sample_counts(&self) -> Vec<usize> {
+        self.counts.get()
+    }
+
+    // `(metric, value)` of the latest sample of each series, in the unit reports show it in
+    pub fn latest_values(&self) -> Vec<(&'static str, f64)> {
+        self.counts.latest()
+    }
+}
+
+// `(time, new state)` of the events of `kind`
+pub fn changes(events: &[Event], kind: EventKind) -> Vec<(u64, &str)> {
+    events
+        .iter()
+        .filter(|event| event.kind == kind)
+        .map(|event| {
+            let state = event.detail.rsplit(" -> ").next().unwrap_or_default();
+            (event.time, state)
+        })
+        .collect()
+}
+
+// The state at every time of `times` from the events of `kind`, none before the first one
+pub fn states_at(events: &[Event], kind: EventKind, times: &[u64]) -> Vec<Option<String>> {
+    let changes = changes(events, kind);
+    times
+        .iter()
+        .map(|time| {
+            changes
+                .iter()
+                .take_while(|(at, _)| at <= time)
+                .last()
+                .map(|(_, state)| state.to_string())
+        })
+        .collect()
+}
+
+// Share of the run from the first event of `kind` to `end` spent in each state, for events whose
+// detail is the first state and then `old -> new` at every change (`cpuset`, `screen`)
+pub fn shares(events: &[Event], kind: EventKind, end: u64) -> Vec<(String, f64)> {
+    let changes = changes(events, kind);
+    let Some((first, _)) = changes.first() else {
+        return Vec::new();
+    };
+    let span = end.saturating_sub(*first).max(1) as f64;
+    let mut shares: Vec<(String, f64)> = Vec::new();
+    for (idx, (time, state)) in changes.iter().enumerate() {
+        let until = changes.get(idx + 1).map_or(end, |(next, _)| *next);
+        let share = until.saturating_sub(*time) as f64 / span;
+        match shares.iter_mut().find(|(name, _)| name == state) {
+            Some((_, total)) => *total += share,
+            None => shares.push((state.to_string(), share)),
+        }
+    }
+    shares
+}
+
+pub fn shares_json(shares: &[(String, f64)]) -> Value {
+    let map: Map<String, Value> = shares
+        .iter()
+        .map(|(state, share)| (state.clone(), json!(share)))
+        .collect();
+    Value::Object(map)
+}
+
+// Lines typed on stdin, read by a single thread for the whole process
+pub fn stdin_lines() -> &'static Mutex<Receiver<String>> {
+    static LINES: OnceLock<Mutex<Receiver<String>>> = OnceLock::new();
+    LINES.get_or_init(|| {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
+}
+
+// Record a marker for every line entered on stdin until `end_time` (unix seconds) or `stop`
+pub fn watch_markers(events: EventLog, end_time: u64, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+    println!("输入文字并回车可添加标记 (press Enter to add a marker)");
+    thread::spawn(move || {
+        let lines = stdin_lines().lock().unwrap();
+        while now_millis() / 1000 < end_time && !stop.load(Ordering::Relaxed) {
+            match lines.recv_timeout(Duration::from_millis(200)) {
+                Ok(line) => {
+                    let label = line.trim();
+                    events.record(
+                        EventKind::Marker,
+                        if label.is_empty() { "mark" } else { label },
+                    );
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                // stdin is closed (e.g. on CI), no markers can come anymore
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    })
+}
+
+// Turns "above threshold" samples into one event when entering and one when leaving the breach,
+// and alerts once the breach has lasted the alerter's number of samples
+pub struct ThresholdWatch {
+    metric: &'static str,
+    threshold: Option<f64>,
+    // how far below the threshold a breach ends, so noise around it is a single breach
+    hysteresis: f64,
+    breached: bool,
+    // samples in a row above the threshold
+    above: usize,
+    alerter: Option<Alerter>,
+    alerted: bool,
+}
+
+impl ThresholdWatch {
+    pub fn new(metric: &'static str, threshold: Option<f64>, hysteresis: f64) -> Self {
+        ThresholdWatch {
+            metric,
+            threshold,
+            hysteresis,
+            breached: false,
+            above: 0,
+            alerter: None,
+            alerted: false,
+        }
+    }
+
+    pub fn with_alerts(self, alerter: Alerter) -> Self {
+        ThresholdWatch {
+            alerter: Some(alerter),
+            ..self
+        }
+    }
+
+    // Samples in a row above the threshold so far
+    pub fn above(&self) -> usize {
+        self.above
+    }
+
+    pub fn check(&mut self, value: f64, events: &EventLog) {
+        let Some(threshold) = self.threshold else {
+            return;
+        };
+        self.above = if value > threshold { self.above + 1 } else { 0 };
+        if value > threshold && !self.breached {
+            self.breached = true;
+            events.record(
+                EventKind::Threshold,
+                format!("{} {} > {}", self.metric, value, threshold),
+            );
+        } else if value <= threshold - self.hysteresis && self.breached {
+            self.breached = false;
+            events.record(
+                EventKind::Threshold,
+                format!("{} back to {} <= {}", self.metric, value, threshold),
+            );
+        }
+
+        let Some(alerter) = &self.alerter else {
+            return;
+        };
+        if self.breached && !self.alerted && self.above >= alerter.consecutive() {
+            self.alerted = true;
+            alerter.fire(AlertKind::Breach, self.metric, value, threshold);
+        } else if !self.breached && self.alerted {
+            self.alerted = false;
+            alerter.fire(AlertKind::Recovered, self.metric, value, threshold);
+        }
+    }
+}
+
+// A data sheet the timeline links into, in the workbook `file` or in the timeline's own if none
+pub struct LinkedSheet<'a> {
+    pub title: &'a str,
+    pub file: Option<&'a str>,
+    pub sheet: &'a str,
+    pub column: &'a str,
+}
+
+// Write the timeline, each event linking to the sample taken right before it in each data sheet.
+// `dropped` is the number of samples at the start of each series that are not in its sheet, the
+// warm-up one unless the run was `--raw`, hence that many rows less. The data sheets have a
+// header row, hence one more.
+pub fn save_timeline(
+    path: &str,
+    start: u64,
+    events: &[Event],
+    sheets: &[LinkedSheet],
+    dropped: usize,
+    theme: &Theme,
+) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Events")?;
+    theme.brand(sheet)?;
+    write_timeline(sheet, start, events, sheets, dropped, theme)?;
+    workbook.save(path)
+}
+
+// The timeline into a sheet that is already named and branded
+pub fn write_timeline(
+    sheet: &mut Worksheet,
+    start: u64,
+    events: &[Event],
+    sheets: &[LinkedSheet],
+    dropped: usize,
+    theme: &Theme,
+) -> Result<(), XlsxError> {
+    let header = theme.header_format();
+    sheet.write_row_with_format(0, 0, ["Time", "Offset (s)", "Event", "Detail"], &header)?;
+    for (col, linked) in sheets.iter().enumerate() {
+        sheet.write_with_format(0, 4 + col as u16, linked.title, &header)?;
+    }
+
+    for (idx, event) in events.iter().enumerate() {
+        let row = idx as RowNum + 1;
+        sheet.write(row, 0, timestamp::format(event.time))?;
+        sheet.write(row, 1, event.time.saturating_sub(start) as f64 / 1000.0)?;
+        sheet.write(row, 2, event.kind.label())?;
+        sheet.write(row, 3, &event.detail)?;
+
+        for (col, linked) in sheets.iter().enumerate() {
+            let Some(&count) = event.positions.get(col) else {
+                continue;
+            };
+            if count == 0 {
+                continue;
+            }
+            // an event before the first sample in the sheet links to that one
+            let sample_row = count.saturating_sub(dropped).max(1) + 1;
+            let target = format!(
+                "'{}'!{}{}",
+                linked.sheet.replace('\'', "''"),
+                linked.column,
+                sample_row
+            );
+            let url = match linked.file {
+                Some(file) => format!("file:///{}#{}", file, target),
+                None => format!("internal:{}", target),
+            };
+            sheet.write_url_with_text(
+                row,
+                4 + col as u16,
+                url.as_str(),
+                format!("row {}", sample_row),
+            )?;
+        }
+    }
+    Ok(())
+}
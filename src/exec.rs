@@ -0,0 +1,184 @@
+use crate::collector::MetricCollector;
+use crate::metrics;
+use crate::sample::{Sample, Unit};
+use crate::transport;
+use serde::Deserialize;
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Milliseconds a command may take before the sample is given up, if its interval is shorter
+const MIN_TIMEOUT: u64 = 5000;
+
+// A metric of the config's `exec` list, a host command run every `interval` that prints the
+// value, e.g. a team's own counter read with `adb shell`:
+//
+// exec:
+//   - name: db_size
+//     command: adb shell run-as com.example.app stat -c %s databases/main.db
+//     interval: 5000
+//     unit: count
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecMetric {
+    // name of its series
+    pub name: String,
+    // run with `sh -c` (`cmd /C` on Windows), `ANDROID_SERIAL` and `CPUREPORT_PACKAGE` set
+    pub command: String,
+    // milliseconds between two runs, the run's interval if not given
+    #[serde(default)]
+    pub interval: Option<u64>,
+    // unit label of its values (`%`, `KB`, `ms`, `count`...), `count` if not given
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+impl ExecMetric {
+    pub fn unit(&self) -> Unit {
+        self.unit
+            .as_deref()
+            .and_then(Unit::from_label)
+            .unwrap_or(Unit::Count)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || self.name.contains(char::is_whitespace) {
+            return Err(format!("exec metric name '{}' is not a name", self.name));
+        }
+        if metrics::is_builtin(&self.name) {
+            return Err(format!("exec metric {} is a built-in metric", self.name));
+        }
+        if let Some(unit) = self
+            .unit
+            .as_deref()
+            .filter(|unit| Unit::from_label(unit).is_none())
+        {
+            return Err(format!(
+                "exec metric {} has an unknown unit {}",
+                self.name, unit
+            ));
+        }
+        if self.interval == Some(0) {
+            return Err(format!("exec metric {} has an interval of 0", self.name));
+        }
+        Ok(())
+    }
+}
+
+// Every metric is one name and one series
+pub fn validate(metrics: &[ExecMetric]) -> Result<(), String> {
+    for (index, metric) in metrics.iter().enumerate() {
+        metric.validate()?;
+        if metrics[..index]
+            .iter()
+            .any(|other| other.name == metric.name)
+        {
+            return Err(format!("exec metric {} is listed twice", metric.name));
+        }
+    }
+    Ok(())
+}
+
+// The value of a command's output, one number and nothing else
+fn parse(output: &str) -> Result<f64, String> {
+    let output = output.trim();
+    output
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+        .ok_or_else(|| format!("printed '{}' instead of a number", output))
+}
+
+// Wait for `child` until `timeout`, killed if it takes longer
+fn wait(mut child: Child, timeout: Duration) -> Result<String, String> {
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let mut output = String::new();
+                if let Some(mut stdout) = child.stdout.take() {
+                    let _ = stdout.read_to_string(&mut output);
+                }
+                return if status.success() {
+                    Ok(output)
+                } else {
+                    Err(format!("failed: {}", status))
+                };
+            }
+            Ok(None) if started.elapsed() < timeout => thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}ms", timeout.as_millis()));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+// Runs the command of an `ExecMetric`, its series is named after it
+pub struct ExecCollector {
+    // the series' name lives as long as the samples, which outlive the run
+    name: &'static str,
+    command: String,
+    interval: u64,
+    unit: Unit,
+    serial: Option<String>,
+    pkg: String,
+}
+
+impl ExecCollector {
+    // `interval` is the run's, for metrics not given their own
+    pub fn new(metric: &ExecMetric, device_cmd: &str, pkg: &str, interval: u64) -> ExecCollector {
+        ExecCollector {
+            name: Box::leak(metric.name.clone().into_boxed_str()),
+            command: metric.command.clone(),
+            interval: metric.interval.unwrap_or(interval),
+            unit: metric.unit(),
+            serial: device_cmd.strip_prefix("-s ").map(str::to_string),
+            pkg: pkg.to_string(),
+        }
+    }
+
+    fn run(&self) -> Result<String, String> {
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut win_cmd = Command::new("cmd");
+            win_cmd.arg("/C");
+            win_cmd
+        } else {
+            let mut sh_cmd = Command::new("sh");
+            sh_cmd.arg("-c");
+            sh_cmd
+        };
+        cmd.arg(&self.command)
+            .env("CPUREPORT_PACKAGE", &self.pkg)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        if let Some(host) = transport::adb_host() {
+            cmd.env("CPUREPORT_ADB_HOST", host);
+        }
+        if let Some(serial) = &self.serial {
+            cmd.env("ANDROID_SERIAL", serial);
+        }
+        let child = cmd.spawn().map_err(|e| e.to_string())?;
+        wait(child, Duration::from_millis(self.interval.max(MIN_TIMEOUT)))
+    }
+}
+
+impl MetricCollector for ExecCollector {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.run().map_err(|e| format!("{}: {}", self.command, e))?;
+        let value = parse(&output).map_err(|e| format!("{} {}", self.command, e))?;
+        Ok(vec![Sample::new(self.name, value, self.unit)])
+    }
+}
@@ -0,0 +1,184 @@
+use crate::events;
+use crate::theme::Theme;
+use rust_xlsxwriter::{RowNum, Workbook, XlsxError};
+use serde_json::{Value, json};
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+// A stretch of the run a collector could not sample, the other collectors carried on
+#[derive(Clone, Debug)]
+pub struct CollectorFailure {
+    pub collector: String,
+    // unix milliseconds, `end` is none if it lasted until the run was over
+    pub start: u64,
+    pub end: Option<u64>,
+    pub reason: String,
+    // samples lost in the stretch, none if the collector stopped altogether
+    pub attempts: Option<usize>,
+}
+
+impl CollectorFailure {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "collector": self.collector,
+            "start": self.start,
+            "end": self.end,
+            "reason": self.reason,
+            "attempts": self.attempts,
+        })
+    }
+
+    // "+3.0s - +12.5s", relative to the start of the run
+    pub fn range_text(&self, run_start: u64) -> String {
+        let offset = |time: u64| format!("+{:.1}s", time.saturating_sub(run_start) as f64 / 1000.0);
+        format!(
+            "{} - {}",
+            offset(self.start),
+            self.end.map_or("end".to_string(), offset)
+        )
+    }
+}
+
+// Failures of all collectors of a run
+#[derive(Clone, Default)]
+pub struct FailureLog {
+    failures: Arc<Mutex<Vec<CollectorFailure>>>,
+}
+
+// The message a thread panicked with
+pub fn panic_reason(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|reason| reason.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panicked".to_string())
+}
+
+impl FailureLog {
+    pub fn record(&self, failure: CollectorFailure) {
+        self.failures.lock().unwrap().push(failure);
+    }
+
+    // A collector thread that panicked at about `since`
+    pub fn record_panic(&self, collector: &str, since: u64, panic: &(dyn Any + Send)) {
+        let reason = panic_reason(panic);
+        println!("采集失败: {} 停止 ({})", collector, reason);
+        self.record(CollectorFailure {
+            collector: collector.to_string(),
+            start: since,
+            end: None,
+            reason,
+            attempts: None,
+        });
+    }
+
+    // Sorted by start
+    pub fn take(&self) -> Vec<CollectorFailure> {
+        let mut failures = std::mem::take(&mut *self.failures.lock().unwrap());
+        failures.sort_by_key(|failure| failure.start);
+        failures
+    }
+}
+
+// Turns one collector's failed attempts in a row into a single failure
+pub struct FailureTracker {
+    collector: &'static str,
+    log: FailureLog,
+    open: Option<CollectorFailure>,
+}
+
+impl FailureTracker {
+    pub fn new(collector: &'static str, log: &FailureLog) -> FailureTracker {
+        FailureTracker {
+            collector,
+            log: log.clone(),
+            open: None,
+        }
+    }
+
+    pub fn failed(&mut self, reason: &str) {
+        match &mut self.open {
+            Some(failure) => failure.attempts = failure.attempts.map(|attempts| attempts + 1),
+            None => {
+                println!("采集失败: {} ({})", self.collector, reason);
+                self.open = Some(CollectorFailure {
+                    collector: self.collector.to_string(),
+                    start: events::now_millis(),
+                    end: None,
+                    reason: reason.to_string(),
+                    attempts: Some(1),
+                });
+            }
+        }
+    }
+
+    pub fn succeeded(&mut self) {
+        if let Some(mut failure) = self.open.take() {
+            println!("采集恢复: {}", self.collector);
+            failure.end = Some(events::now_millis());
+            self.log.record(failure);
+        }
+    }
+}
+
+impl Drop for FailureTracker {
+    // A stretch still going on when the collector stops lasted until the end
+    fn drop(&mut self) {
+        if let Some(failure) = self.open.take() {
+            self.log.record(failure);
+        }
+    }
+}
+
+pub fn failures_json(failures: &[CollectorFailure]) -> Value {
+    Value::Array(failures.iter().map(CollectorFailure::to_json).collect())
+}
+
+pub fn print_failures(run_start: u64, failures: &[CollectorFailure]) {
+    if failures.is_empty() {
+        return;
+    }
+    println!("部分结果, 以下时间段缺少数据:");
+    for failure in failures {
+        println!(
+            "  {} {}: {}",
+            failure.collector,
+            failure.range_text(run_start),
+            failure.reason
+        );
+    }
+}
+
+// One row per failure, with the collector, the affected time range and the reason
+pub fn save_failures(
+    path: &str,
+    run_start: u64,
+    failures: &[CollectorFailure],
+    theme: &Theme,
+) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Failures")?;
+    theme.brand(sheet)?;
+    sheet.write_row_with_format(
+        0,
+        0,
+        ["Collector", "From (s)", "To (s)", "Lost Samples", "Reason"],
+        &theme.header_format(),
+    )?;
+    let offset = |time: u64| time.saturating_sub(run_start) as f64 / 1000.0;
+    for (idx, failure) in failures.iter().enumerate() {
+        let row = idx as RowNum + 1;
+        sheet.write(row, 0, &failure.collector)?;
+        sheet.write(row, 1, offset(failure.start))?;
+        match failure.end {
+            Some(end) => sheet.write(row, 2, offset(end))?,
+            None => sheet.write(row, 2, "end")?,
+        };
+        if let Some(attempts) = failure.attempts {
+            sheet.write(row, 3, attempts as f64)?;
+        }
+        sheet.write(row, 4, &failure.reason)?;
+    }
+    workbook.save(path)
+}
@@ -0,0 +1,127 @@
+// C API for device-lab controllers linking against the cdylib, see `include/cpureport.h`
+use crate::report::summary_json;
+use crate::session::SamplingSession;
+use crate::{CollectOptions, RunData};
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::ptr;
+
+pub enum CpureportRun {
+    Running(SamplingSession),
+    Stopped(Box<RunData>),
+}
+
+unsafe fn to_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        None
+    } else {
+        unsafe { CStr::from_ptr(value) }.to_str().ok()
+    }
+}
+
+/// Start sampling `package` on `device` (NULL for `adb -d`), returns NULL on invalid arguments.
+///
+/// # Safety
+/// `package` and `device` must be NULL or valid NUL terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cpureport_start_run(
+    package: *const c_char,
+    device: *const c_char,
+    interval_ms: u64,
+    duration_s: u64,
+) -> *mut CpureportRun {
+    let Some(pkg) = (unsafe { to_str(package) }) else {
+        return ptr::null_mut();
+    };
+    let device = unsafe { to_str(device) }.unwrap_or_default();
+    let options = CollectOptions {
+        duration: duration_s,
+        interval: interval_ms,
+        ..Default::default()
+    };
+    let session = SamplingSession::start(pkg, device, &options);
+    Box::into_raw(Box::new(CpureportRun::Running(session)))
+}
+
+/// Add a marker event to a running run, returns 0 on success.
+///
+/// # Safety
+/// `run` must come from `cpureport_start_run`, `label` must be a valid NUL terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cpureport_mark(run: *mut CpureportRun, label: *const c_char) -> c_int {
+    let (Some(run), Some(label)) = (unsafe { run.as_ref() }, unsafe { to_str(label) }) else {
+        return -1;
+    };
+    match run {
+        CpureportRun::Running(session) => {
+            session.mark(label);
+            0
+        }
+        CpureportRun::Stopped(_) => -1,
+    }
+}
+
+/// Stop sampling, blocks until the collectors are done. Returns 0 on success, else the exit
+/// code of the command line for the failure (8 collectors cannot start, 10 failed) and the run
+/// has an empty summary.
+///
+/// # Safety
+/// `run` must come from `cpureport_start_run` and not be freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cpureport_stop_run(run: *mut CpureportRun) -> c_int {
+    let Some(run) = (unsafe { run.as_mut() }) else {
+        return -1;
+    };
+    // Park an empty result while the session is moved out to be stopped
+    let placeholder = CpureportRun::Stopped(Box::default());
+    match std::mem::replace(run, placeholder) {
+        CpureportRun::Running(session) => match session.stop() {
+            Ok(data) => {
+                *run = CpureportRun::Stopped(Box::new(data));
+                0
+            }
+            Err(e) => e.exit_code(),
+        },
+        stopped => {
+            *run = stopped;
+            -1
+        }
+    }
+}
+
+/// Summary of a stopped run as JSON, NULL while still running. Free with `cpureport_free_string`.
+///
+/// # Safety
+/// `run` must come from `cpureport_start_run` and not be freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cpureport_get_summary_json(run: *const CpureportRun) -> *mut c_char {
+    match unsafe { run.as_ref() } {
+        Some(CpureportRun::Stopped(data)) => CString::new(summary_json(data).to_string())
+            .map(CString::into_raw)
+            .unwrap_or(ptr::null_mut()),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `value` must be NULL or come from `cpureport_get_summary_json`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cpureport_free_string(value: *mut c_char) {
+    if !value.is_null() {
+        drop(unsafe { CString::from_raw(value) });
+    }
+}
+
+/// Free a run, stopping it first if it is still sampling.
+///
+/// # Safety
+/// `run` must be NULL or come from `cpureport_start_run`, and is invalid afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cpureport_free_run(run: *mut CpureportRun) {
+    if run.is_null() {
+        return;
+    }
+    if let CpureportRun::Running(session) = *unsafe { Box::from_raw(run) } {
+        // nobody asks for the result any more
+        let _ = session.stop();
+    }
+}
@@ -0,0 +1,118 @@
+// Frame timings of `dumpsys gfxinfo <pkg> framestats`, a csv block per window of the app with
+// the last 120 frames in nanoseconds of the monotonic clock:
+//
+// ---PROFILEDATA---
+// Flags,FrameTimelineVsyncId,IntendedVsync,Vsync,...,FrameCompleted,...
+// 0,0,10218340761537,10218357428203,...,10218374960574,...
+// ---PROFILEDATA---
+//
+// Rows with other flags than 0 were not drawn normally (the first frame of a window, a resize)
+// and are left out.
+
+// Vsync period assumed when a dump has too few frames to tell, 60 Hz
+const DEFAULT_FRAME_BUDGET_NS: u64 = 16_666_667;
+
+// `(intended vsync, duration)` of every normally drawn frame, in nanoseconds
+fn frames(output: &str) -> Vec<(u64, u64)> {
+    let mut frames = Vec::new();
+    let mut columns: Option<(usize, usize)> = None;
+    for line in output.lines().map(str::trim) {
+        if line == "---PROFILEDATA---" {
+            columns = None;
+        } else if line.starts_with("Flags,") {
+            let names: Vec<&str> = line.split(',').collect();
+            let index = |name: &str| names.iter().position(|column| *column == name);
+            columns = index("IntendedVsync").zip(index("FrameCompleted"));
+        } else if let Some((intended, completed)) = columns {
+            let values: Vec<u64> = line
+                .split(',')
+                .map_while(|value| value.trim().parse().ok())
+                .collect();
+            if values.first() != Some(&0) || values.len() <= intended.max(completed) {
+                continue;
+            }
+            let (intended, completed) = (values[intended], values[completed]);
+            if intended > 0 && completed > intended {
+                frames.push((intended, completed - intended));
+            }
+        }
+    }
+    frames.sort_unstable();
+    frames
+}
+
+// The shortest gap between two intended vsyncs, the device's refresh period (8.3ms at 120 Hz)
+fn frame_budget(frames: &[(u64, u64)]) -> u64 {
+    frames
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].0)
+        .filter(|gap| *gap > 1_000_000)
+        .min()
+        .unwrap_or(DEFAULT_FRAME_BUDGET_NS)
+}
+
+// `p`th percentile of sorted `values`, nearest rank
+fn percentile(values: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+// The frames drawn since the last sample
+pub struct FrameStats {
+    pub frames: usize,
+    pub fps: f64,
+    // percent of the frames that took longer than a vsync period
+    pub jank: f64,
+    // frame times in milliseconds, none without frames
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+}
+
+// Frames seen so far, a dump repeats most of the frames of the one before
+#[derive(Default)]
+pub struct FrameTracker {
+    // intended vsync of the newest frame counted
+    last_vsync: Option<u64>,
+    // unix milliseconds of the last dump
+    last_time: u64,
+}
+
+impl FrameTracker {
+    // The frames of `output` newer than the last dump's, none for the first dump, its frames
+    // were drawn before sampling started
+    pub fn update(&mut self, output: &str, now: u64) -> Option<FrameStats> {
+        let frames = frames(output);
+        let budget = frame_budget(&frames);
+        let newest = frames.last().map(|(vsync, _)| *vsync);
+        let since = self.last_time;
+        self.last_time = now;
+        let Some(last_vsync) = self.last_vsync else {
+            // a window that has not drawn yet has no frames, every frame it draws is new
+            self.last_vsync = Some(newest.unwrap_or_default());
+            return None;
+        };
+        self.last_vsync = Some(newest.unwrap_or(last_vsync).max(last_vsync));
+
+        let mut durations: Vec<f64> = frames
+            .iter()
+            .filter(|(vsync, _)| *vsync > last_vsync)
+            .map(|(_, duration)| *duration as f64 / 1_000_000.0)
+            .collect();
+        durations.sort_unstable_by(f64::total_cmp);
+        let janky = durations
+            .iter()
+            .filter(|duration| **duration > budget as f64 / 1_000_000.0)
+            .count();
+        let seconds = now.saturating_sub(since).max(1) as f64 / 1000.0;
+        Some(FrameStats {
+            frames: durations.len(),
+            fps: durations.len() as f64 / seconds,
+            jank: match durations.len() {
+                0 => 0.0,
+                count => janky as f64 * 100.0 / count as f64,
+            },
+            p90: (!durations.is_empty()).then(|| percentile(&durations, 90.0)),
+            p99: (!durations.is_empty()).then(|| percentile(&durations, 99.0)),
+        })
+    }
+}
@@ -0,0 +1,267 @@
+use crate::align::Aligned;
+use crate::device::DeviceInfo;
+use crate::package::PackageInfo;
+use crate::sample::Series;
+use crate::{RunData, get_current_time, sla, stats};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rsa::RsaPrivateKey;
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::sha2::Sha256;
+use rsa::signature::{SignatureEncoding, Signer};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+const SHEETS_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+// Tab the run summaries are appended to
+pub const RUNS_TAB: &str = "Runs";
+
+const RUNS_HEADER: [&str; 12] = [
+    "Time",
+    "Package",
+    "Version",
+    "Device",
+    "SDK",
+    "Duration (s)",
+    "Cpu avg (%)",
+    "Cpu max (%)",
+    "Mem avg (MB)",
+    "Mem max (MB)",
+    "Score",
+    "SLA",
+];
+
+// The JSON key file of a Google Cloud service account, the spreadsheet has to be shared with
+// its `client_email`
+#[derive(Deserialize)]
+pub struct ServiceAccount {
+    client_email: String,
+    private_key: String,
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+}
+
+fn default_token_uri() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+impl ServiceAccount {
+    pub fn load(path: &str) -> Result<ServiceAccount, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read service account {}: {}", path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse service account {}: {}", path, e))
+    }
+
+    // OAuth access token for the Sheets API, from a JWT signed with the account's key
+    fn access_token(&self) -> Result<String, String> {
+        let now = chrono::Utc::now().timestamp();
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "RS256", "typ": "JWT" }).to_string());
+        let claims = URL_SAFE_NO_PAD.encode(
+            json!({
+                "iss": self.client_email,
+                "scope": SCOPE,
+                "aud": self.token_uri,
+                "iat": now,
+                "exp": now + 3600,
+            })
+            .to_string(),
+        );
+        let key = RsaPrivateKey::from_pkcs8_pem(&self.private_key)
+            .map_err(|e| format!("Failed to read service account key: {}", e))?;
+        let unsigned = format!("{}.{}", header, claims);
+        let signature = SigningKey::<Sha256>::new(key).sign(unsigned.as_bytes());
+        let assertion = format!(
+            "{}.{}",
+            unsigned,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        );
+
+        let mut response = ureq::post(&self.token_uri)
+            .send_form([
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion.as_str()),
+            ])
+            .map_err(|e| format!("Failed to get Google access token: {}", e))?;
+        let body: Value = response
+            .body_mut()
+            .read_json()
+            .map_err(|e| format!("Failed to get Google access token: {}", e))?;
+        body.get("access_token")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| "Failed to get Google access token: no access_token".to_string())
+    }
+}
+
+// A spreadsheet written with a service account's token
+pub struct Spreadsheet {
+    id: String,
+    token: String,
+}
+
+impl Spreadsheet {
+    pub fn open(id: &str, account: &ServiceAccount) -> Result<Spreadsheet, String> {
+        Ok(Spreadsheet {
+            id: id.to_string(),
+            token: account.access_token()?,
+        })
+    }
+
+    fn auth(&self) -> String {
+        format!("Bearer {}", self.token)
+    }
+
+    fn tabs(&self) -> Result<Vec<String>, String> {
+        let mut response = ureq::get(format!(
+            "{}/{}?fields=sheets.properties.title",
+            SHEETS_URL, self.id
+        ))
+        .header("Authorization", self.auth())
+        .call()
+        .map_err(|e| format!("Failed to read spreadsheet {}: {}", self.id, e))?;
+        let body: Value = response
+            .body_mut()
+            .read_json()
+            .map_err(|e| format!("Failed to read spreadsheet {}: {}", self.id, e))?;
+        Ok(body["sheets"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(|sheet| sheet["properties"]["title"].as_str())
+            .map(str::to_string)
+            .collect())
+    }
+
+    // Add the tab `title` with `header` as its first row unless it exists already
+    fn ensure_tab(&self, title: &str, header: Vec<Value>) -> Result<(), String> {
+        if self.tabs()?.iter().any(|tab| tab == title) {
+            return Ok(());
+        }
+        ureq::post(format!("{}/{}:batchUpdate", SHEETS_URL, self.id))
+            .header("Authorization", self.auth())
+            .send_json(
+                json!({ "requests": [{ "addSheet": { "properties": { "title": title } } }] }),
+            )
+            .map_err(|e| format!("Failed to add tab {}: {}", title, e))?;
+        self.append(title, vec![header])
+    }
+
+    // Rows after the last filled one of the tab
+    fn append(&self, tab: &str, rows: Vec<Vec<Value>>) -> Result<(), String> {
+        ureq::post(format!(
+            "{}/{}/values/{}!A1:append?valueInputOption=RAW&insertDataOption=INSERT_ROWS",
+            SHEETS_URL,
+            self.id,
+            encode_tab(tab)
+        ))
+        .header("Authorization", self.auth())
+        .send_json(json!({ "values": rows }))
+        .map_err(|e| format!("Failed to append to tab {}: {}", tab, e))?;
+        Ok(())
+    }
+}
+
+// `'Cpu data'`, quoted for A1 notation and escaped for the url path
+fn encode_tab(tab: &str) -> String {
+    let quoted = format!("'{}'", tab.replace('\'', "''"));
+    quoted
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn number(value: f64) -> Value {
+    if value.is_finite() {
+        json!(value)
+    } else {
+        json!("")
+    }
+}
+
+// One row of the `Runs` tab
+fn summary_row(
+    pkg: &str,
+    package: &PackageInfo,
+    device: &DeviceInfo,
+    data: &RunData,
+) -> Vec<Value> {
+    let cpu = data.cpu.values();
+    let mem = data.mem.display_values();
+    vec![
+        json!(get_current_time()),
+        json!(pkg),
+        json!(package.version_name),
+        json!(device.model),
+        json!(device.sdk),
+        json!(data.end.saturating_sub(data.start) / 1000),
+        number(stats::mean(&cpu)),
+        number(stats::max(&cpu)),
+        number(stats::mean(&mem)),
+        number(stats::max(&mem)),
+        data.score
+            .as_ref()
+            .map_or(json!(""), |score| number(score.value)),
+        match data.sla.is_empty() {
+            true => json!(""),
+            false if sla::passed(&data.sla) => json!("PASS"),
+            false => json!("FAIL"),
+        },
+    ]
+}
+
+// Append the run's summary to the `Runs` tab, and with `series` its samples on the common time
+// axis to a tab of their own. Returns the name of that tab.
+pub fn export(
+    spreadsheet: &Spreadsheet,
+    pkg: &str,
+    package: &PackageInfo,
+    device: &DeviceInfo,
+    data: &RunData,
+    series: bool,
+) -> Result<Option<String>, String> {
+    let header = RUNS_HEADER.iter().map(|title| json!(title)).collect();
+    spreadsheet.ensure_tab(RUNS_TAB, header)?;
+    spreadsheet.append(RUNS_TAB, vec![summary_row(pkg, package, device, data)])?;
+    if !series {
+        return Ok(None);
+    }
+
+    let mut all: Vec<&Series> = vec![&data.cpu, &data.mem];
+    all.extend(&data.series);
+    let aligned = Aligned::new(data.start, data.end, &all);
+    let tab = format!("series_{}", get_current_time());
+    let mut header = vec![json!("Time (s)")];
+    header.extend(all.iter().map(|series| {
+        json!(format!(
+            "{} ({})",
+            series.title(),
+            series.unit.display_label()
+        ))
+    }));
+    spreadsheet.ensure_tab(&tab, header)?;
+    let rows: Vec<Vec<Value>> = aligned
+        .times
+        .iter()
+        .enumerate()
+        .map(|(idx, time)| {
+            let mut row = vec![json!(time.saturating_sub(data.start) as f64 / 1000.0)];
+            row.extend(
+                aligned
+                    .columns
+                    .iter()
+                    .map(|values| values[idx].map_or(json!(""), |value| json!(value))),
+            );
+            row
+        })
+        .collect();
+    spreadsheet.append(&tab, rows)?;
+    Ok(Some(tab))
+}
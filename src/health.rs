@@ -0,0 +1,67 @@
+use crate::connection::Link;
+use crate::sample::{Sample, Unit};
+use crate::stats;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Milliseconds between two samples of the counters, the latency percentiles are of the adb
+// commands in between
+pub const INTERVAL: u64 = 5000;
+
+// Series of the `health` collector
+pub const SAMPLES: &str = "health_samples";
+pub const FAILURES: &str = "health_failures";
+pub const RETRIES: &str = "health_retries";
+pub const LATENCY: [(&str, f64); 3] = [("adb_p50", 50.0), ("adb_p90", 90.0), ("adb_p99", 99.0)];
+
+// What the collectors of a run took and missed, counted since it started
+#[derive(Clone, Default)]
+pub struct HealthCounters {
+    samples: Arc<AtomicUsize>,
+    failures: Arc<AtomicUsize>,
+}
+
+impl HealthCounters {
+    pub fn sampled(&self, count: usize) {
+        self.samples.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn failed(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// The counters of the sampling so far as samples of their own series, so every exporter and
+// report has them next to the app's: the samples taken and the attempts that failed, the tries
+// to get a disconnected device back, and the 50th, 90th and 99th percentile of how long adb
+// commands took since the samples before.
+pub fn sample(counters: &HealthCounters, link: &Link) -> Vec<Sample> {
+    let taken = counters.samples.load(Ordering::Relaxed);
+    let failed = counters.failures.load(Ordering::Relaxed);
+    let retries = link.retries();
+    let mut samples = vec![
+        Sample::new(SAMPLES, taken as f64, Unit::Count),
+        Sample::new(FAILURES, failed as f64, Unit::Count),
+        Sample::new(RETRIES, retries as f64, Unit::Count),
+    ];
+    let latencies = link.take_latencies();
+    if latencies.is_empty() {
+        println!(
+            "采集状态: {} 个样本, {} 次失败, {} 次重连",
+            taken, failed, retries
+        );
+        return samples;
+    }
+    let percentiles: Vec<f64> = LATENCY
+        .iter()
+        .map(|(_, p)| stats::percentile(&latencies, *p))
+        .collect();
+    for ((name, _), value) in LATENCY.iter().zip(&percentiles) {
+        samples.push(Sample::new(name, *value, Unit::Milliseconds));
+    }
+    println!(
+        "采集状态: {} 个样本, {} 次失败, {} 次重连, adb p50 {:.0}ms p90 {:.0}ms p99 {:.0}ms",
+        taken, failed, retries, percentiles[0], percentiles[1], percentiles[2]
+    );
+    samples
+}
@@ -0,0 +1,476 @@
+use crate::align::Aligned;
+use crate::anomaly;
+use crate::crashloop;
+use crate::sample::Series;
+use crate::theme::Theme;
+use crate::{RunData, sla, stats};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{Value, json};
+use std::fmt::Write;
+
+const CHART_WIDTH: f64 = 960.0;
+const CHART_HEIGHT: f64 = 200.0;
+// Width thumbnails are shown at, they are captured at `screenshot::THUMBNAIL_WIDTH`
+const THUMBNAIL_WIDTH: f64 = 80.0;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// x of a unix millisecond time on the charts
+fn time_x(data: &RunData, time: u64) -> f64 {
+    let span = data.end.saturating_sub(data.start).max(1) as f64;
+    (time.saturating_sub(data.start) as f64 / span * CHART_WIDTH).min(CHART_WIDTH)
+}
+
+// Opening tag of a chart, `series` are the `{name, unit, points: [[x, seconds, value]]}` the
+// page script shows under the cursor. The viewBox is narrowed to zoom in, the lines keep their
+// width and the labels are outside the svg so nothing gets stretched.
+fn svg_open(title: &str, labels: &str, series: &[Value]) -> String {
+    format!(
+        "<h2>{}</h2>\n<div class=\"axis\">{}</div>\n<svg class=\"chart\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" preserveAspectRatio=\"none\" data-series=\"{}\">\n",
+        escape(title),
+        labels,
+        escape(&Value::Array(series.to_vec()).to_string()),
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT
+    )
+}
+
+// `[x, seconds into the run, value]` of a point for the page script
+fn point_json(data: &RunData, x: f64, time: u64, value: f64) -> Value {
+    let seconds = (time > 0).then(|| time.saturating_sub(data.start) as f64 / 1000.0);
+    json!([(x * 10.0).round() / 10.0, seconds, value])
+}
+
+// An svg line chart of a series at the times it was sampled, with events and screenshots as
+// vertical lines. Samples without a time (read from old manifests) are spread over the run.
+fn chart(data: &RunData, title: &str, series: &Series) -> String {
+    let values = series.display_values();
+    let top = stats::max(&values).max(1.0);
+    let step = CHART_WIDTH / (values.len().max(2) - 1) as f64;
+    let mut hover = Vec::new();
+    let points: Vec<String> = series
+        .measured()
+        .zip(&values)
+        .enumerate()
+        .map(|(i, (sample, value))| {
+            let x = match sample.ts {
+                0 => i as f64 * step,
+                ts => time_x(data, ts),
+            };
+            hover.push(point_json(data, x, sample.ts, *value));
+            format!("{:.1},{:.1}", x, CHART_HEIGHT - value / top * CHART_HEIGHT)
+        })
+        .collect();
+
+    let unit = series.unit.display_label();
+    let mut svg = svg_open(
+        title,
+        &format!("<span>{:.1} {}</span>", top, escape(unit)),
+        &[json!({ "name": title, "unit": unit, "points": hover })],
+    );
+    svg.push_str("<rect class=\"frame\" width=\"100%\" height=\"100%\"/>\n");
+    for screenshot in &data.screenshots {
+        let x = time_x(data, screenshot.time);
+        let _ = writeln!(
+            svg,
+            "<line class=\"shot\" x1=\"{x:.1}\" x2=\"{x:.1}\" y1=\"0\" y2=\"{}\"/>",
+            CHART_HEIGHT
+        );
+    }
+    for event in &data.events {
+        let x = time_x(data, event.time);
+        let _ = writeln!(
+            svg,
+            "<line class=\"event\" x1=\"{x:.1}\" x2=\"{x:.1}\" y1=\"0\" y2=\"{}\"><title>{}: {}</title></line>",
+            CHART_HEIGHT,
+            event.kind.label(),
+            escape(&event.detail)
+        );
+    }
+    let _ = writeln!(svg, "<polyline points=\"{}\"/>\n</svg>", points.join(" "));
+    svg
+}
+
+// Cpu and memory on a common time axis, each scaled to its own peak (the left and right labels)
+fn combined_chart(data: &RunData) -> String {
+    let aligned = Aligned::new(data.start, data.end, &[&data.cpu, &data.mem]);
+    let mut tops = Vec::new();
+    let mut hover = Vec::new();
+    let mut polylines = String::new();
+    for ((column, class), (name, series)) in
+        aligned.columns.iter().zip(["", " class=\"second\""]).zip([
+            (data.cpu.title_or("Cpu"), &data.cpu),
+            (data.mem.title_or("Memory"), &data.mem),
+        ])
+    {
+        let top = stats::max(&column.iter().flatten().copied().collect::<Vec<f64>>()).max(1.0);
+        tops.push(top);
+        // A gap in a series ends its line, the next samples start a new one
+        let mut lines: Vec<Vec<String>> = vec![Vec::new()];
+        let mut points = Vec::new();
+        for (time, value) in aligned.times.iter().zip(column) {
+            match value {
+                Some(value) => {
+                    let x = time_x(data, *time);
+                    points.push(point_json(data, x, *time, *value));
+                    lines.last_mut().unwrap().push(format!(
+                        "{:.1},{:.1}",
+                        x,
+                        CHART_HEIGHT - value / top * CHART_HEIGHT
+                    ))
+                }
+                None if !lines.last().unwrap().is_empty() => lines.push(Vec::new()),
+                None => {}
+            }
+        }
+        for line in lines.iter().filter(|line| !line.is_empty()) {
+            let _ = writeln!(
+                polylines,
+                "<polyline{} points=\"{}\"/>",
+                class,
+                line.join(" ")
+            );
+        }
+        hover.push(json!({
+            "name": name,
+            "unit": series.unit.display_label(),
+            "points": points,
+        }));
+    }
+    let labels = format!(
+        "<span>{:.1} {}</span><span class=\"second\">{:.1} {}</span>",
+        tops.first().copied().unwrap_or(1.0),
+        escape(data.cpu.unit.display_label()),
+        tops.get(1).copied().unwrap_or(1.0),
+        escape(data.mem.unit.display_label())
+    );
+    let mut svg = svg_open("Cpu / Memory", &labels, &hover);
+    svg.push_str("<rect class=\"frame\" width=\"100%\" height=\"100%\"/>\n");
+    svg.push_str(&polylines);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Average (with its 95% interval), peak and sample count of every series, in display units
+fn summary_table(data: &RunData) -> String {
+    let mut table = String::from(
+        "<table class=\"summary\">\n<tr><th>Metric</th><th>Average</th><th>Max</th><th>Samples</th></tr>\n",
+    );
+    for series in [&data.cpu, &data.mem].into_iter().chain(&data.series) {
+        let values = series.display_values();
+        let unit = escape(series.unit.display_label());
+        let _ = writeln!(
+            table,
+            "<tr><td>{}</td><td>{:.2} {}{}</td><td>{:.2} {}</td><td>{}</td></tr>",
+            escape(&series.title()),
+            stats::mean(&values),
+            unit,
+            escape(&stats::ci95_text(&values)),
+            stats::max(&values),
+            unit,
+            values.len()
+        );
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+// Hovering a chart shows the values under the cursor, on every chart at once. Dragging across
+// one zooms all of them into that stretch of the run, a double click zooms out again.
+const SCRIPT: &str = r#"<script>
+(function () {
+  const charts = Array.from(document.querySelectorAll('svg.chart'));
+  const tip = document.getElementById('tip');
+  const width = WIDTH, height = HEIGHT;
+  let view = [0, width], drag = null;
+  charts.forEach(function (svg) {
+    svg.series = JSON.parse(svg.dataset.series);
+    svg.cursor = document.createElementNS('http://www.w3.org/2000/svg', 'line');
+    svg.cursor.setAttribute('class', 'cursor');
+    svg.cursor.setAttribute('y1', 0);
+    svg.cursor.setAttribute('y2', height);
+    svg.appendChild(svg.cursor);
+  });
+  function chartX(svg, e) {
+    const box = svg.getBoundingClientRect();
+    return view[0] + (e.clientX - box.left) / box.width * (view[1] - view[0]);
+  }
+  function nearest(points, x) {
+    let best = null;
+    points.forEach(function (p) {
+      if (best === null || Math.abs(p[0] - x) < Math.abs(best[0] - x)) { best = p; }
+    });
+    return best;
+  }
+  function zoom(a, b) {
+    view = [a, b];
+    charts.forEach(function (svg) {
+      svg.setAttribute('viewBox', a + ' 0 ' + (b - a) + ' ' + height);
+    });
+  }
+  charts.forEach(function (svg) {
+    svg.addEventListener('mousemove', function (e) {
+      const x = chartX(svg, e);
+      charts.forEach(function (other) {
+        other.cursor.setAttribute('x1', x);
+        other.cursor.setAttribute('x2', x);
+      });
+      const lines = svg.series.map(function (s) {
+        const p = nearest(s.points, x);
+        if (p === null) { return ''; }
+        const at = p[1] === null ? '' : p[1].toFixed(1) + 's ';
+        return at + s.name + ': ' + (+p[2].toFixed(2)) + ' ' + s.unit;
+      }).filter(function (line) { return line !== ''; });
+      tip.textContent = lines.join('\n');
+      tip.style.display = lines.length ? 'block' : 'none';
+      tip.style.left = (e.pageX + 12) + 'px';
+      tip.style.top = (e.pageY + 12) + 'px';
+    });
+    svg.addEventListener('mouseleave', function () { tip.style.display = 'none'; });
+    svg.addEventListener('mousedown', function (e) { drag = chartX(svg, e); e.preventDefault(); });
+    svg.addEventListener('mouseup', function (e) {
+      const x = chartX(svg, e);
+      if (drag !== null && Math.abs(x - drag) > (view[1] - view[0]) / 100) {
+        zoom(Math.min(drag, x), Math.max(drag, x));
+      }
+      drag = null;
+    });
+    svg.addEventListener('dblclick', function () { zoom(0, width); });
+  });
+})();
+</script>
+"#;
+
+// Thumbnails along the time axis, under the charts
+fn screenshot_strip(data: &RunData) -> String {
+    let mut strip = String::from("<div class=\"strip\">\n");
+    for screenshot in &data.screenshots {
+        let left = (time_x(data, screenshot.time) - THUMBNAIL_WIDTH / 2.0)
+            .clamp(0.0, CHART_WIDTH - THUMBNAIL_WIDTH);
+        let offset = screenshot.time.saturating_sub(data.start) as f64 / 1000.0;
+        let _ = writeln!(
+            strip,
+            "<img style=\"left:{:.1}px\" title=\"{:.1}s\" src=\"data:image/jpeg;base64,{}\"/>",
+            left,
+            offset,
+            STANDARD.encode(&screenshot.jpeg)
+        );
+    }
+    strip.push_str("</div>\n");
+    strip
+}
+
+// A self-contained html page with the curves of the run, its events and screenshots
+pub fn html_report(data: &RunData, theme: &Theme) -> String {
+    let mut charts = vec![
+        chart(data, &data.cpu.title_or("Cpu"), &data.cpu),
+        chart(data, &data.mem.title_or("Memory"), &data.mem),
+        combined_chart(data),
+    ];
+    for series in &data.series {
+        charts.push(chart(data, &series.title(), series));
+    }
+
+    let mut events = String::new();
+    if !data.events.is_empty() {
+        events.push_str(
+            "<h2>Events</h2>\n<table>\n<tr><th>Offset (s)</th><th>Event</th><th>Detail</th></tr>\n",
+        );
+        for event in &data.events {
+            let _ = writeln!(
+                events,
+                "<tr><td>{:.1}</td><td>{}</td><td>{}</td></tr>",
+                event.time.saturating_sub(data.start) as f64 / 1000.0,
+                event.kind.label(),
+                escape(&event.detail)
+            );
+        }
+        events.push_str("</table>\n");
+    }
+
+    let score = data.score.as_ref().map_or(String::new(), |score| {
+        let parts: Vec<String> = score
+            .parts
+            .iter()
+            .map(|part| {
+                format!(
+                    "{} {}",
+                    escape(&part.metric),
+                    part.points
+                        .map_or("-".to_string(), |points| format!("{:.0}", points))
+                )
+            })
+            .collect();
+        format!(
+            "<h2 class=\"score\">Score: {:.1} / 100</h2>\n<p>{}</p>\n",
+            score.value,
+            parts.join(", ")
+        )
+    });
+    let mut sla_table = String::new();
+    if !data.sla.is_empty() {
+        let _ = writeln!(
+            sla_table,
+            "<h2>SLA: {}</h2>\n<table>\n<tr><th>Condition</th><th>Actual</th><th>Result</th></tr>",
+            if sla::passed(&data.sla) {
+                "PASS"
+            } else {
+                "FAIL"
+            }
+        );
+        for verdict in &data.sla {
+            let actual = verdict.actual.map_or("-".to_string(), |actual| {
+                format!("{:.2}{}", actual, verdict.condition.unit)
+            });
+            let (class, result) = match (verdict.passed, verdict.informational) {
+                (true, _) => ("pass", "PASS"),
+                (false, true) => ("noisy", "FAIL (noisy)"),
+                (false, false) => ("fail", "FAIL"),
+            };
+            let note = verdict
+                .note
+                .as_ref()
+                .map_or(String::new(), |note| format!(" ({})", note));
+            let _ = writeln!(
+                sla_table,
+                "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}{}</td></tr>",
+                escape(&verdict.condition.text()),
+                escape(&actual),
+                class,
+                result,
+                escape(&note)
+            );
+        }
+        sla_table.push_str("</table>\n");
+    }
+
+    // A crash loop makes every number below meaningless, say so first
+    let mut crash_banner = String::new();
+    if let Some(crash_loop) = &data.crash_loop {
+        let _ = writeln!(
+            crash_banner,
+            "<h2 class=\"fail\">{}</h2>\n<p>The app crashed or restarted {} times (more than {}), the run was ended at {:.1}s.</p>",
+            crashloop::INVALID_UNSTABLE,
+            crash_loop.crashes,
+            crash_loop.max_restarts,
+            crash_loop.time.saturating_sub(data.start) as f64 / 1000.0
+        );
+        for excerpt in &crash_loop.excerpts {
+            let _ = writeln!(crash_banner, "<pre>{}</pre>", escape(excerpt));
+        }
+    }
+
+    // Collectors that could not sample part of the run, their curves have gaps there
+    let mut failure_table = String::new();
+    if !data.failures.is_empty() {
+        failure_table.push_str(
+            "<h2>Partial results</h2>\n<table>\n<tr><th>Collector</th><th>Missing</th><th>Reason</th></tr>\n",
+        );
+        for failure in &data.failures {
+            let _ = writeln!(
+                failure_table,
+                "<tr><td class=\"fail\">{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&failure.collector),
+                failure.range_text(data.start),
+                escape(&failure.reason)
+            );
+        }
+        failure_table.push_str("</table>\n");
+    }
+
+    // What the app ran while the cpu was high
+    let mut anomalies = String::new();
+    let frames = anomaly::dominant_frames(&data.stacks, anomaly::TOP_FRAMES);
+    if !frames.is_empty() {
+        anomalies.push_str(
+            "<h2>Anomalies</h2>\n<p>Frames on top of runnable threads during sustained cpu spikes</p>\n<table>\n<tr><th>Share</th><th>Samples</th><th>Frame</th></tr>\n",
+        );
+        for frame in &frames {
+            let _ = writeln!(
+                anomalies,
+                "<tr><td>{:.1}%</td><td>{}</td><td><code>{}</code></td></tr>",
+                frame.share,
+                frame.count,
+                escape(&frame.frame)
+            );
+        }
+        anomalies.push_str("</table>\n");
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>cpureport</title>
+<style>
+body {{ font-family: sans-serif; margin: 24px; }}
+svg {{ display: block; }}
+.frame {{ fill: #fafafa; stroke: #ddd; }}
+h1, h2 {{ color: {primary}; }}
+.logo {{ max-height: 48px; float: right; }}
+footer {{ margin-top: 32px; color: #666; font-size: small; }}
+svg.chart {{ cursor: crosshair; }}
+svg line, svg rect {{ vector-effect: non-scaling-stroke; }}
+polyline {{ fill: none; stroke: {primary}; stroke-width: 1.5; vector-effect: non-scaling-stroke; }}
+polyline.second {{ stroke: {accent}; }}
+.axis {{ display: flex; justify-content: space-between; width: {width}px; font-size: small; }}
+.axis .second {{ color: {accent}; }}
+.event {{ stroke: {accent}; stroke-dasharray: 4 3; }}
+.shot {{ stroke: #bbb; }}
+.cursor {{ stroke: #888; }}
+#tip {{ position: absolute; display: none; white-space: pre; background: #fff; border: 1px solid #ccc; padding: 4px 8px; font-size: small; pointer-events: none; }}
+.hint {{ color: #888; font-size: small; }}
+.strip {{ position: relative; width: {width}px; height: 180px; margin-top: 8px; }}
+.strip img {{ position: absolute; top: 0; width: {thumbnail}px; border: 1px solid #ccc; }}
+.strip img:hover {{ width: 240px; z-index: 1; }}
+td, th {{ padding: 2px 12px; text-align: left; }}
+.pass {{ color: {pass}; }}
+.fail {{ color: {fail}; font-weight: bold; }}
+.score {{ color: {primary}; font-size: 2em; }}
+.noisy {{ color: #888; }}
+</style>
+</head>
+<body>
+{logo}<h1>cpureport</h1>
+{crash}{score}{summary}{sla}{failures}<p class="hint">Drag across a chart to zoom in, double click to zoom out</p>
+{charts}{strip}{anomalies}{events}{footer}<div id="tip"></div>
+{script}</body>
+</html>
+"#,
+        primary = escape(theme.primary()),
+        accent = escape(theme.accent()),
+        pass = escape(theme.pass()),
+        fail = escape(theme.fail()),
+        logo = theme.logo_data_url().map_or(String::new(), |url| {
+            format!("<img class=\"logo\" src=\"{}\"/>\n", url)
+        }),
+        footer = theme.footer.as_ref().map_or(String::new(), |footer| {
+            format!("<footer>{}</footer>\n", escape(footer))
+        }),
+        width = CHART_WIDTH,
+        thumbnail = THUMBNAIL_WIDTH,
+        summary = summary_table(data),
+        script = SCRIPT
+            .replace("WIDTH", &CHART_WIDTH.to_string())
+            .replace("HEIGHT", &CHART_HEIGHT.to_string()),
+        score = score,
+        sla = sla_table,
+        failures = failure_table,
+        crash = crash_banner,
+        anomalies = anomalies,
+        charts = charts.join(""),
+        strip = screenshot_strip(data),
+        events = events,
+    )
+}
+
+pub fn save_html(path: &str, data: &RunData, theme: &Theme) -> std::io::Result<()> {
+    std::fs::write(path, html_report(data, theme))
+}
@@ -0,0 +1,124 @@
+use crate::{adb_shell, scenario};
+
+// An accessibility service enabled for the run, the previous secure settings are put back on drop
+pub struct AccessibilityService {
+    device_cmd: String,
+    previous_services: String,
+    previous_enabled: String,
+}
+
+fn get_secure(device_cmd: &str, key: &str) -> String {
+    adb_shell(device_cmd, &format!("settings get secure {}", key))
+        .trim()
+        .to_string()
+}
+
+fn put_secure(device_cmd: &str, key: &str, value: &str) {
+    if value.is_empty() || value == "null" {
+        adb_shell(device_cmd, &format!("settings delete secure {}", key));
+    } else {
+        adb_shell(
+            device_cmd,
+            &format!("settings put secure {} {}", key, value),
+        );
+    }
+}
+
+impl AccessibilityService {
+    // `component` is the service's `pkg/.Service`
+    pub fn enable(device_cmd: &str, component: &str) -> Result<AccessibilityService, String> {
+        let previous_services = get_secure(device_cmd, "enabled_accessibility_services");
+        let previous_enabled = get_secure(device_cmd, "accessibility_enabled");
+
+        let services = if previous_services.is_empty() || previous_services == "null" {
+            component.to_string()
+        } else if previous_services
+            .split(':')
+            .any(|service| service == component)
+        {
+            previous_services.clone()
+        } else {
+            format!("{}:{}", previous_services, component)
+        };
+        println!("启用无障碍服务: {}", component);
+        put_secure(device_cmd, "enabled_accessibility_services", &services);
+        put_secure(device_cmd, "accessibility_enabled", "1");
+
+        let enabled = get_secure(device_cmd, "enabled_accessibility_services");
+        let service = AccessibilityService {
+            device_cmd: device_cmd.to_string(),
+            previous_services,
+            previous_enabled,
+        };
+        if enabled.split(':').any(|service| service == component) {
+            Ok(service)
+        } else {
+            Err(format!(
+                "Failed to enable accessibility service {}",
+                component
+            ))
+        }
+    }
+}
+
+impl Drop for AccessibilityService {
+    fn drop(&mut self) {
+        put_secure(
+            &self.device_cmd,
+            "enabled_accessibility_services",
+            &self.previous_services,
+        );
+        put_secure(
+            &self.device_cmd,
+            "accessibility_enabled",
+            &self.previous_enabled,
+        );
+    }
+}
+
+// An app drawing over other apps, allowed `SYSTEM_ALERT_WINDOW` and started for the run,
+// it is stopped and its previous app op mode restored on drop
+pub struct OverlayApp {
+    device_cmd: String,
+    pkg: String,
+    previous_mode: String,
+}
+
+impl OverlayApp {
+    pub fn enable(device_cmd: &str, pkg: &str) -> OverlayApp {
+        // e.g. `SYSTEM_ALERT_WINDOW: allow; time=+1d2h ago`
+        let previous_mode = adb_shell(
+            device_cmd,
+            &format!("appops get {} SYSTEM_ALERT_WINDOW", pkg),
+        )
+        .split(':')
+        .nth(1)
+        .and_then(|mode| mode.split(';').next())
+        .map_or("default".to_string(), |mode| mode.trim().to_string());
+
+        println!("启用悬浮窗应用: {}", pkg);
+        adb_shell(
+            device_cmd,
+            &format!("appops set {} SYSTEM_ALERT_WINDOW allow", pkg),
+        );
+        scenario::launch_app(device_cmd, pkg);
+        OverlayApp {
+            device_cmd: device_cmd.to_string(),
+            pkg: pkg.to_string(),
+            previous_mode,
+        }
+    }
+}
+
+impl Drop for OverlayApp {
+    fn drop(&mut self) {
+        adb_shell(&self.device_cmd, &format!("am force-stop {}", self.pkg));
+        adb_shell(
+            &self.device_cmd,
+            &format!(
+                "appops set {} SYSTEM_ALERT_WINDOW {}",
+                self.pkg, self.previous_mode
+            ),
+        );
+    }
+}
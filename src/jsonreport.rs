@@ -0,0 +1,85 @@
+use crate::RunData;
+use crate::device::DeviceInfo;
+use crate::package::PackageInfo;
+use crate::report::summary_json;
+use crate::sample::Series;
+use crate::schema::Kind;
+use crate::timestamp;
+use serde_json::{Value, json};
+
+// Bumped when a field changes meaning or goes away, new fields keep it
+pub const JSON_REPORT_VERSION: u64 = 1;
+
+fn series_json(series: &Series, start: u64) -> Value {
+    let samples: Vec<Value> = series
+        .samples
+        .iter()
+        .map(|sample| {
+            json!({
+                "ts": sample.ts,
+                "elapsed_s": sample.ts.saturating_sub(start) as f64 / 1000.0,
+                "value": sample.value,
+                "quality": sample.quality.label(),
+            })
+        })
+        .collect();
+    json!({
+        "name": series.name,
+        "unit": series.unit.label(),
+        "samples": samples,
+    })
+}
+
+// `--format json`: the whole run in one document for CI jobs, what was measured on which device,
+// every sample with its time in the collected unit, and the statistics of `summary_json`
+// (memory in MB there)
+pub fn report_json(pkg: &str, package: &PackageInfo, device: &DeviceInfo, data: &RunData) -> Value {
+    let series: Vec<Value> = [&data.cpu, &data.mem]
+        .into_iter()
+        .chain(&data.series)
+        .map(|series| series_json(series, data.start))
+        .collect();
+    let events: Vec<Value> = data
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "time": event.time,
+                "elapsed_s": event.time.saturating_sub(data.start) as f64 / 1000.0,
+                "kind": event.kind.label(),
+                "detail": event.detail,
+            })
+        })
+        .collect();
+    json!({
+        "schema": Kind::Report.label(),
+        "version": JSON_REPORT_VERSION,
+        "cpureport": env!("CARGO_PKG_VERSION"),
+        "package": {
+            "name": pkg,
+            "version_name": package.version_name,
+            "version_code": package.version_code,
+        },
+        "device": {
+            "model": device.model,
+            "manufacturer": device.manufacturer,
+            "fingerprint": device.fingerprint,
+            "sdk": device.sdk,
+            "cpu_cores": device.cpu_cores,
+            "mem_total_bytes": device.mem_total_bytes,
+        },
+        "run": {
+            "start": data.start,
+            "start_time": timestamp::format(data.start),
+            "end": data.end,
+            "duration_s": data.end.saturating_sub(data.start) as f64 / 1000.0,
+        },
+        "series": series,
+        "events": events,
+        "statistics": summary_json(data),
+    })
+}
+
+pub fn save_report_json(path: &str, report: &Value) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(report)?)
+}
@@ -0,0 +1,82 @@
+use crate::sample::Series;
+use serde_json::{Value, json};
+
+// Series of the open file descriptors and the threads of the app's main process
+pub const FDS: &str = "fd_count";
+pub const THREADS: &str = "thread_count";
+
+// Samples a series needs before its growth counts as a trend
+const MIN_SAMPLES: usize = 3;
+
+// Open file descriptors and threads of the app's main process. Other apps' fd directories are only listable as the app (debuggable builds) or root:
+//
+// fds 142
+// Threads:	87
+pub fn command(pkg: &str) -> String {
+    format!(
+        "pid=$(pidof {}); n=$(ls /proc/$pid/fd 2>/dev/null | wc -l); [ $n -gt 0 ] || n=$(run-as {} ls /proc/$pid/fd 2>/dev/null | wc -l); echo fds $n; grep Threads: /proc/$pid/status",
+        pkg, pkg
+    )
+}
+
+// `(fds, threads)` of `command` output, no fds if the directory was not readable. Err if the
+// app is not running.
+pub fn parse(output: &str) -> Result<(Option<u64>, u64), String> {
+    let threads = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Threads:")?.trim().parse().ok())
+        .ok_or("no Threads in /proc/<pid>/status")?;
+    // a running process has stdin, stdout and stderr at least, none listed is no permission
+    let fds = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("fds ")?.trim().parse().ok())
+        .filter(|fds| *fds > 0);
+    Ok((fds, threads))
+}
+
+// A count that went up every sample or stayed, and ended above where it started
+#[derive(Debug, PartialEq)]
+pub struct Growth {
+    pub name: String,
+    pub first: f64,
+    pub last: f64,
+}
+
+pub fn growth(series: &[Series]) -> Vec<Growth> {
+    series
+        .iter()
+        .filter(|series| series.name == FDS || series.name == THREADS)
+        .filter_map(|series| {
+            let values = series.values();
+            if values.len() < MIN_SAMPLES || values.windows(2).any(|pair| pair[1] < pair[0]) {
+                return None;
+            }
+            let (first, last) = (values[0], values[values.len() - 1]);
+            (last > first).then(|| Growth {
+                name: series.name.clone(),
+                first,
+                last,
+            })
+        })
+        .collect()
+}
+
+pub fn growth_json(growth: &[Growth]) -> Value {
+    Value::Array(
+        growth
+            .iter()
+            .map(|growth| {
+                json!({ "series": growth.name, "first": growth.first, "last": growth.last })
+            })
+            .collect(),
+    )
+}
+
+pub fn print_growth(growth: &[Growth]) {
+    for growth in growth {
+        println!(
+            "警告: {} 持续增长 ({} -> {}), 没有回落, 可能泄漏",
+            growth.name, growth.first, growth.last
+        );
+    }
+}
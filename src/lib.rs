@@ -0,0 +1,2294 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use alert::{AlertOptions, Alerter};
+use anomaly::{AdaptiveOptions, Boost, SpikeDetector, StackSnapshot};
+use batch::ShellBatch;
+use bluetooth::ScanTracker;
+use calibration::Calibration;
+use capabilities::{Capabilities, Capability};
+use collector::{MetricCollector, Priority};
+use connection::Link;
+use cores::CoreTracker;
+use crashloop::CrashLoop;
+use diskio::{DiskIo, IoTracker};
+use doze::DozeWindow;
+use error::Error;
+use events::{Event, EventKind, EventLog, ThresholdWatch};
+use exec::{ExecCollector, ExecMetric};
+use failures::{CollectorFailure, FailureLog, FailureTracker};
+use gfxinfo::FrameTracker;
+use health::HealthCounters;
+use multiwindow::MultiWindow;
+use network::Toggle;
+use overhead::{Overhead, Throttle};
+use package::PackageInfo;
+use pipe::SamplePipe;
+use platform::Platform;
+use quirks::Quirk;
+use sample::{Quality, Sample, Series, Unit};
+use schedule::{Lag, Scheduler};
+use score::Score;
+use screen::ScreenMode;
+use screenshot::Screenshot;
+use shaping::Shaping;
+use shell::ShellSession;
+use sla::Verdict;
+use snapshot::Snapshot;
+use statsd::StatsdExporter;
+use system::SystemTracker;
+use threads::{ThreadTracker, ThreadUsage};
+use traffic::Traffic;
+use wasm::WasmPlugin;
+use webui::WebUi;
+use webview::WebviewMode;
+use writer::{SampleCounts, SampleSender, SampleStore};
+
+pub mod alert;
+pub mod align;
+pub mod anomaly;
+pub mod appops;
+pub mod batch;
+pub mod battery;
+pub mod benchmark;
+pub mod bluetooth;
+pub mod cache;
+pub mod calibration;
+pub mod camera;
+pub mod capabilities;
+pub mod collector;
+pub mod compress;
+pub mod config;
+pub mod connection;
+pub mod control;
+pub mod cores;
+pub mod cpuset;
+pub mod crashloop;
+pub mod csv;
+pub mod device;
+pub mod diskio;
+pub mod doze;
+pub mod efficiency;
+pub mod emulator;
+pub mod error;
+pub mod events;
+pub mod exec;
+pub mod failures;
+pub mod ffi;
+pub mod gfxinfo;
+pub mod gsheet;
+pub mod health;
+pub mod html;
+pub mod impact;
+pub mod jsonreport;
+pub mod leaks;
+pub mod locale;
+pub mod lock;
+pub mod logcat;
+pub mod manifest;
+pub mod meminfo;
+pub mod metrics;
+pub mod multiwindow;
+pub mod network;
+pub mod overhead;
+pub mod package;
+pub mod pipe;
+pub mod plan;
+pub mod platform;
+pub mod pressure;
+#[cfg(feature = "python")]
+mod python;
+pub mod quirks;
+pub mod report;
+pub mod sample;
+pub mod scenario;
+pub mod schedule;
+pub mod schema;
+pub mod score;
+pub mod screen;
+pub mod screenshot;
+pub mod sensors;
+pub mod session;
+pub mod shaping;
+pub mod shell;
+pub mod sla;
+pub mod snapshot;
+pub mod startup;
+pub mod stats;
+pub mod statsd;
+pub mod stf;
+pub mod sweep;
+pub mod sync;
+pub mod system;
+pub mod theme;
+pub mod thermal;
+pub mod threads;
+pub mod timestamp;
+pub mod traffic;
+pub mod transport;
+pub mod tray;
+pub mod video;
+pub mod wasm;
+pub mod webui;
+pub mod webview;
+pub mod window;
+pub mod wm;
+pub mod workbook;
+pub mod writer;
+
+pub use report::Report;
+pub use session::{Collector, SamplingSession};
+
+// Function to get the current time as a formatted string
+pub fn get_current_time() -> String {
+    timestamp::file_time()
+}
+
+// `adb`, the options picking the device of `device_cmd` (`-s <serial>`, `-d`, or none) and
+// `args`, e.g. `adb_argv("-s emulator-5554", &["get-state"])`
+pub fn adb_argv<'a>(device_cmd: &'a str, args: &[&'a str]) -> Vec<&'a str> {
+    std::iter::once("adb")
+        .chain(device_cmd.split_whitespace())
+        .chain(args.iter().copied())
+        .collect()
+}
+
+// `adb shell` of `command`, one command line the device's shell parses, so values put in it
+// need `transport::quote` unless they are plain words
+pub fn adb_shell(device_cmd: &str, command: &str) -> String {
+    run_adb_command(&adb_argv(device_cmd, &["shell", command]))
+}
+
+// Function to run adb commands and capture the output, on the `--adb-host` if there is one
+pub fn run_adb_command(argv: &[&str]) -> String {
+    run_adb_command_on(argv, None)
+}
+
+// `run_adb_command` for a run, its `link` notes when adb says the device went away
+pub fn run_adb_command_on(argv: &[&str], link: Option<&Link>) -> String {
+    let started = Instant::now();
+    match transport::command(argv).output() {
+        Ok(output) => {
+            if let Some(link) = link {
+                link.record_latency(started.elapsed().as_millis() as u64);
+                link.observe(&String::from_utf8_lossy(&output.stderr));
+            }
+            String::from_utf8_lossy(&output.stdout).to_string()
+        }
+        // adb went away during the run, its reads come back empty and show up as failures
+        Err(e) => {
+            static REPORTED: AtomicBool = AtomicBool::new(false);
+            if !REPORTED.swap(true, Ordering::Relaxed) {
+                println!("无法执行 adb: {}", e);
+            }
+            String::new()
+        }
+    }
+}
+
+// State every collector thread of a run shares
+#[derive(Clone)]
+struct CollectContext {
+    device: String,
+    pkg: String,
+    interval: u64,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+    events: EventLog,
+    samples: SampleSender,
+    webview: Option<WebviewMode>,
+    calibration: Option<Calibration>,
+    platform: Platform,
+    alerter: Alerter,
+    boost: Boost,
+    java_stacks: bool,
+    failures: FailureLog,
+    max_restarts: Option<usize>,
+    // sample the main process alone instead of all the app's processes
+    main_process_only: bool,
+    throttle: Throttle,
+    scheduler: Scheduler,
+    health: HealthCounters,
+    link: Link,
+}
+
+impl CollectContext {
+    // Whether sampling should go on
+    fn running(&self) -> bool {
+        now() < self.end_time && !self.stop.load(Ordering::Relaxed)
+    }
+
+    // Milliseconds from one sample to the next at `interval`, less after spikes and more while
+    // over the overhead budget
+    fn period(&self, interval: u64) -> u64 {
+        let period = self.throttle.interval(self.boost.interval(interval));
+        (period.as_millis() as u64).max(1)
+    }
+}
+
+// Sample `collector` until the run ends, handing it back for what it found besides samples.
+// Samples are due every period from the start, a collector waits for its turn on the device
+// and one that is behind by more than a period takes the next sample late, or with a low
+// priority leaves out the ones it missed.
+fn run_collector<C: MetricCollector>(ctx: &CollectContext, mut collector: C) -> C {
+    let (name, priority) = (collector.name(), collector.priority());
+    let device = collector.reads_device();
+    let mut warming = collector.warmup();
+    let mut failure = FailureTracker::new(name, &ctx.failures);
+    let mut due = events::now_millis();
+    while ctx.running() {
+        let turn = device.then(|| ctx.scheduler.turn(priority, due));
+        // the overhead budget and spikes are about the device's time
+        let period = if device {
+            ctx.period(collector.interval())
+        } else {
+            collector.interval()
+        };
+        let behind = events::now_millis().saturating_sub(due);
+        if behind > period {
+            if priority == Priority::Low {
+                let missed = behind / period;
+                ctx.scheduler.record_skipped(name, missed as usize);
+                due += missed * period;
+                continue;
+            }
+            // the next samples are due a period apart from this one
+            ctx.scheduler.record_late(name);
+            due = events::now_millis();
+        }
+        let collected = if device && ctx.link.lost() {
+            // every sample due until the device is back is one lost
+            Err(connection::REASON.to_string())
+        } else {
+            match collector.collect() {
+                // read while the device went away, they may be empty outputs taken for zeros
+                Ok(_) | Err(_) if device && ctx.link.lost() => Err(connection::REASON.to_string()),
+                collected => collected,
+            }
+        };
+        match collected {
+            Ok(samples) => {
+                failure.succeeded();
+                if device {
+                    ctx.health.sampled(samples.len());
+                }
+                for sample in samples {
+                    match warming.iter().position(|series| *series == sample.metric) {
+                        Some(index) => {
+                            warming.swap_remove(index);
+                            ctx.samples.send(sample.with_quality(Quality::Warmup));
+                        }
+                        None => ctx.samples.send(sample),
+                    }
+                }
+            }
+            Err(reason) => {
+                failure.failed(&reason);
+                if device {
+                    ctx.health.failed();
+                }
+            }
+        }
+        drop(turn);
+        due += period;
+        thread::sleep(Duration::from_millis(
+            due.saturating_sub(events::now_millis()),
+        ));
+    }
+    collector
+}
+
+// cpu usage of the app from top, watches for spikes, restarts and crash loops on the way
+struct CpuCollector {
+    ctx: CollectContext,
+    interval: u64,
+    watch: ThresholdWatch,
+    spikes: SpikeDetector,
+    // captured on spikes
+    stacks: Vec<thread::JoinHandle<Vec<StackSnapshot>>>,
+    last_pid: Option<String>,
+    alive: bool,
+    // names of the app's processes seen running, the main one first
+    processes: Vec<String>,
+    crashes: usize,
+    // crashes counted when they passed `max_restarts` and ended the run
+    crash_loop: Option<usize>,
+}
+
+impl CpuCollector {
+    fn new(ctx: &CollectContext, threshold: Option<f64>, interval: u64) -> CpuCollector {
+        let hysteresis = ctx.calibration.as_ref().map_or(0.0, |c| c.cpu.tolerance());
+        CpuCollector {
+            ctx: ctx.clone(),
+            interval,
+            watch: ThresholdWatch::new("cpu", threshold, hysteresis)
+                .with_alerts(ctx.alerter.clone()),
+            spikes: SpikeDetector::new(hysteresis),
+            stacks: Vec::new(),
+            last_pid: None,
+            alive: true,
+            processes: Vec::new(),
+            crashes: 0,
+            crash_loop: None,
+        }
+    }
+}
+
+impl MetricCollector for CpuCollector {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::High
+    }
+
+    fn series(&self) -> Vec<(&'static str, Unit)> {
+        vec![("cpu", Unit::Percent), ("webview_cpu", Unit::Percent)]
+    }
+
+    // top's first usage is usually abnormally high
+    fn warmup(&self) -> Vec<&'static str> {
+        vec!["cpu", "webview_cpu"]
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let ctx = &self.ctx;
+        let (device, pkg, events) = (&ctx.device, &ctx.pkg, &ctx.events);
+        let mut samples = Vec::new();
+        let top_result = ctx.platform.top_lines(device, pkg);
+        let (process_lines, renderer_lines) = match ctx.webview {
+            Some(_) => webview::split_top_lines(&top_result),
+            None => (top_result.lines().collect(), Vec::new()),
+        };
+        let main_line = package::main_process_line(pkg, &process_lines);
+        if let Some(cpu_line) = main_line {
+            let app_lines = if ctx.main_process_only {
+                vec![cpu_line]
+            } else {
+                package::app_process_lines(pkg, &process_lines)
+            };
+            for name in app_lines
+                .iter()
+                .filter_map(|line| line.split_whitespace().last())
+            {
+                if self.processes.iter().any(|seen| seen == name) {
+                    continue;
+                }
+                if name == pkg {
+                    self.processes.insert(0, name.to_string());
+                } else {
+                    self.processes.push(name.to_string());
+                }
+            }
+            let mut cpu_value: f64 = app_lines
+                .iter()
+                .map(|line| ctx.platform.parse_top_cpu(line))
+                .sum();
+            if let Some(mode) = ctx.webview {
+                let renderer_cpu: f64 = renderer_lines
+                    .iter()
+                    .map(|line| ctx.platform.parse_top_cpu(line))
+                    .sum();
+                cpu_value += renderer_cpu;
+                if mode == WebviewMode::Breakout {
+                    println!("WEBVIEW CPU: {}", renderer_cpu);
+                    samples.push(Sample::new("webview_cpu", renderer_cpu, Unit::Percent));
+                }
+            }
+            if app_lines.len() > 1 {
+                println!("CPU: {} ({} 个进程)", cpu_value, app_lines.len());
+            } else {
+                println!("CPU: {}", cpu_value);
+            }
+            samples.push(Sample::new("cpu", cpu_value, Unit::Percent));
+            self.watch.check(cpu_value, events);
+
+            // The first column of top is the pid, a new one means the app was restarted
+            let pid = cpu_line.split_whitespace().next().map(str::to_string);
+            // A sustained breach, once per breach as the count only passes this value once
+            if ctx.java_stacks
+                && self.watch.above() == ctx.alerter.consecutive()
+                && let Some(pid) = &pid
+            {
+                println!("cpu 持续超过阈值, 采集 java 堆栈");
+                self.stacks.push(anomaly::capture_java_stacks(device, pid));
+            }
+            if ctx.boost.enabled()
+                && let Some(mean) = self.spikes.check(cpu_value)
+                && ctx.boost.trigger()
+            {
+                events.record(
+                    EventKind::Spike,
+                    format!(
+                        "cpu {} (recent {:.1}), {}",
+                        cpu_value,
+                        mean,
+                        ctx.boost.description()
+                    ),
+                );
+                if ctx.boost.stacks()
+                    && let Some(pid) = &pid
+                {
+                    self.stacks.push(anomaly::capture_stacks(device, pid));
+                }
+            }
+            if self.last_pid.is_some() && pid != self.last_pid {
+                // a death seen before the restart was counted already
+                if self.alive {
+                    self.crashes += 1;
+                }
+                events.record(
+                    EventKind::Restart,
+                    format!(
+                        "pid {} -> {}",
+                        self.last_pid.take().unwrap_or_default(),
+                        pid.clone().unwrap_or_default()
+                    ),
+                );
+            }
+            self.last_pid = pid;
+            self.alive = true;
+        } else if self.alive && self.last_pid.is_some() && !ctx.link.lost() {
+            self.alive = false;
+            events.record(EventKind::ProcessDied, format!("{} is not running", pkg));
+            self.crashes += 1;
+        }
+        // Sampling on would average crash loops into a misleading low usage
+        if let Some(max_restarts) = ctx.max_restarts
+            && self.crashes > max_restarts
+            && self.crash_loop.is_none()
+        {
+            events.record(
+                EventKind::CrashLoop,
+                format!("{} crashes, more than {}", self.crashes, max_restarts),
+            );
+            self.crash_loop = Some(self.crashes);
+            ctx.stop.store(true, Ordering::Relaxed);
+        }
+        Ok(samples)
+    }
+
+    fn finish(&mut self, data: &mut RunData) {
+        data.stacks = self
+            .stacks
+            .drain(..)
+            .filter_map(|capture| capture.join().ok())
+            .flatten()
+            .collect();
+        data.processes = std::mem::take(&mut self.processes);
+        if data.processes.len() > 1 {
+            println!("采样的应用进程: {}", data.processes.join(", "));
+        }
+        data.crash_loop = self.crash_loop.map(|crashes| CrashLoop {
+            crashes,
+            max_restarts: self.ctx.max_restarts.unwrap_or_default(),
+            time: events::now_millis(),
+            excerpts: crashloop::crash_excerpts(&self.ctx.device, &self.ctx.pkg),
+        });
+    }
+}
+
+// TOTAL PSS of the app from dumpsys meminfo
+struct MemCollector {
+    ctx: CollectContext,
+    interval: u64,
+    // the sections of the main process's PSS too
+    breakdown: bool,
+    watch: ThresholdWatch,
+    spikes: SpikeDetector,
+}
+
+impl MemCollector {
+    fn new(
+        ctx: &CollectContext,
+        threshold: Option<f64>,
+        interval: u64,
+        breakdown: bool,
+    ) -> MemCollector {
+        let hysteresis = ctx.calibration.as_ref().map_or(0.0, |c| c.mem.tolerance());
+        MemCollector {
+            ctx: ctx.clone(),
+            interval,
+            breakdown,
+            watch: ThresholdWatch::new("mem(MB)", threshold, hysteresis)
+                .with_alerts(ctx.alerter.clone()),
+            spikes: SpikeDetector::new(hysteresis),
+        }
+    }
+}
+
+impl MetricCollector for MemCollector {
+    fn name(&self) -> &'static str {
+        "mem"
+    }
+
+    fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    fn series(&self) -> Vec<(&'static str, Unit)> {
+        let mut series = vec![("mem", Unit::Kilobytes), ("webview_mem", Unit::Kilobytes)];
+        // Android 6 and later have all of them
+        series.extend(
+            meminfo::SECTIONS
+                .iter()
+                .map(|(_, name)| (*name, Unit::Kilobytes)),
+        );
+        series
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        self.series().into_iter().map(|(name, _)| name).collect()
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let ctx = &self.ctx;
+        let (device, pkg, events) = (&ctx.device, &ctx.pkg, &ctx.events);
+        let mut samples = Vec::new();
+        // Renderers are separate processes, their PSS is dumped per pid
+        let (renderer_pss, renderer_quality) = match ctx.webview {
+            Some(mode) => {
+                let (mut pss, mut quality) = (0.0, Quality::Good);
+                for pid in webview::renderer_pids(device, pkg, &ctx.platform) {
+                    match ctx.platform.total_pss(device, pkg, Some(&pid)) {
+                        Some((renderer, renderer_quality)) => {
+                            pss += renderer;
+                            quality = quality.worst(renderer_quality);
+                        }
+                        // the renderer may just have exited
+                        None => quality = quality.worst(Quality::Partial),
+                    }
+                }
+                if mode == WebviewMode::Breakout {
+                    println!("WEBVIEW MEM: {}", pss);
+                    samples.push(
+                        Sample::new("webview_mem", pss, Unit::Kilobytes).with_quality(quality),
+                    );
+                }
+                (pss, quality)
+            }
+            None => (0.0, Quality::Good),
+        };
+
+        let Some((pss, quality, sections)) = ctx.platform.meminfo(device, pkg, None) else {
+            return Err(format!("no PSS for {} in dumpsys meminfo", pkg));
+        };
+        // `dumpsys meminfo <pkg>` is the main process, the others are dumped per pid
+        let (mut others, mut others_quality) = (0.0, Quality::Good);
+        let mut count = 1;
+        if !ctx.main_process_only {
+            for process in ctx
+                .platform
+                .processes(device)
+                .into_iter()
+                .filter(|process| {
+                    process.name != *pkg
+                        && package::is_app_process(&process.name, pkg)
+                        && !webview::is_renderer(&process.name)
+                })
+            {
+                match ctx.platform.total_pss(device, pkg, Some(&process.pid)) {
+                    Some((process_pss, process_quality)) => {
+                        others += process_pss;
+                        others_quality = others_quality.worst(process_quality);
+                        count += 1;
+                    }
+                    // it may just have exited
+                    None => others_quality = others_quality.worst(Quality::Partial),
+                }
+            }
+        }
+        if self.breakdown {
+            samples.extend(
+                sections
+                    .into_iter()
+                    .map(|(series, pss)| Sample::new(series, pss, Unit::Kilobytes)),
+            );
+        }
+        let pss_memory = pss + others + renderer_pss;
+        if count > 1 {
+            println!("MEM: {} ({} 个进程)", pss_memory, count);
+        } else {
+            println!("MEM: {}", pss_memory);
+        }
+        samples.push(
+            Sample::new("mem", pss_memory, Unit::Kilobytes)
+                .with_quality(quality.worst(others_quality).worst(renderer_quality)),
+        );
+        self.watch.check(pss_memory / 1024.0, events);
+        if ctx.boost.enabled()
+            && let Some(mean) = self.spikes.check(pss_memory / 1024.0)
+            && ctx.boost.trigger()
+        {
+            events.record(
+                EventKind::Spike,
+                format!(
+                    "mem(MB) {:.1} (recent {:.1}), {}",
+                    pss_memory / 1024.0,
+                    mean,
+                    ctx.boost.description()
+                ),
+            );
+        }
+        Ok(samples)
+    }
+}
+
+// The device's cpu and memory pressure
+struct PressureCollector {
+    ctx: CollectContext,
+}
+
+impl MetricCollector for PressureCollector {
+    fn name(&self) -> &'static str {
+        "pressure"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        // optional, it is the first to go when sampling costs too much
+        if self.ctx.throttle.shedding() {
+            return Ok(Vec::new());
+        }
+        let output = self.ctx.platform.shell(&self.ctx.device, pressure::COMMAND);
+        let (cpu, mem) = pressure::parse(&output).ok_or("no avg10 in /proc/pressure")?;
+        println!("PSI: cpu {} mem {}", cpu, mem);
+        Ok(vec![
+            Sample::new("psi_cpu", cpu, Unit::Percent),
+            Sample::new("psi_mem", mem, Unit::Percent),
+        ])
+    }
+}
+
+// Load of the whole device, the headroom the app had
+struct SystemCollector {
+    ctx: CollectContext,
+    tracker: SystemTracker,
+}
+
+impl MetricCollector for SystemCollector {
+    fn name(&self) -> &'static str {
+        "system"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        vec![system::SERIES]
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, system::COMMAND);
+        let Some(load) = self.tracker.update(&output)? else {
+            return Ok(Vec::new());
+        };
+        println!("SYSTEM CPU: {:.1}", load);
+        Ok(vec![Sample::new(system::SERIES, load, Unit::Percent)])
+    }
+}
+
+// Bluetooth LE scans the app started since the sample before, and the ones going on
+struct BluetoothCollector {
+    ctx: CollectContext,
+    tracker: ScanTracker,
+}
+
+impl MetricCollector for BluetoothCollector {
+    fn name(&self) -> &'static str {
+        "bluetooth"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        vec!["bt_scans"]
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self
+            .ctx
+            .platform
+            .shell(&self.ctx.device, bluetooth::COMMAND);
+        let Some((started, ongoing)) = self.tracker.update(&output, &self.ctx.pkg)? else {
+            return Ok(Vec::new());
+        };
+        println!("BLUETOOTH: {} scans started, {} ongoing", started, ongoing);
+        Ok(vec![
+            Sample::new("bt_scans", started as f64, Unit::Count),
+            Sample::new("bt_scanning", ongoing as f64, Unit::Count),
+        ])
+    }
+}
+
+// Sensor listeners the app holds, and the sensors they are registered for
+struct SensorCollector {
+    ctx: CollectContext,
+    // connections are told apart by the app's uid
+    uid: Option<u32>,
+}
+
+impl MetricCollector for SensorCollector {
+    fn name(&self) -> &'static str {
+        "sensors"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, sensors::COMMAND);
+        let (listeners, registrations) = sensors::parse(&output, &self.ctx.pkg, self.uid)?;
+        println!(
+            "SENSORS: {} listeners, {} sensors",
+            listeners, registrations
+        );
+        Ok(vec![
+            Sample::new("sensor_listeners", listeners as f64, Unit::Count),
+            Sample::new("sensor_registrations", registrations as f64, Unit::Count),
+        ])
+    }
+}
+
+// Open file descriptors and threads of the app, counts that only grow are a leak
+struct LeakCollector {
+    ctx: CollectContext,
+    command: String,
+}
+
+impl MetricCollector for LeakCollector {
+    fn name(&self) -> &'static str {
+        "leaks"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, &self.command);
+        let (fds, threads) = leaks::parse(&output)?;
+        println!(
+            "FDS: {} THREADS: {}",
+            fds.map_or("-".to_string(), |fds| fds.to_string()),
+            threads
+        );
+        let mut samples = vec![Sample::new(leaks::THREADS, threads as f64, Unit::Count)];
+        samples.extend(fds.map(|fds| Sample::new(leaks::FDS, fds as f64, Unit::Count)));
+        Ok(samples)
+    }
+}
+
+// cpu of every thread of the app's main process
+struct ThreadCollector {
+    ctx: CollectContext,
+    command: String,
+    tracker: ThreadTracker,
+    // how many of the busiest are reported
+    busiest: usize,
+}
+
+impl MetricCollector for ThreadCollector {
+    fn name(&self) -> &'static str {
+        "threads"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, &self.command);
+        if !self.tracker.update(&output) {
+            return Err("no threads of the app in top -H".to_string());
+        }
+        Ok(Vec::new())
+    }
+
+    fn finish(&mut self, data: &mut RunData) {
+        data.threads = self.tracker.busiest(self.busiest);
+        threads::print_threads(&data.threads);
+    }
+}
+
+// Frame rate and jank of the app
+struct FrameCollector {
+    ctx: CollectContext,
+    tracker: FrameTracker,
+}
+
+impl MetricCollector for FrameCollector {
+    fn name(&self) -> &'static str {
+        "frames"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        vec!["frames", "fps", "jank", "frame_p90", "frame_p99"]
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let command = self
+            .ctx
+            .platform
+            .gfxinfo_command(&self.ctx.pkg)
+            .ok_or("no framestats in dumpsys gfxinfo")?;
+        let output = self.ctx.platform.shell(&self.ctx.device, &command);
+        let Some(stats) = self.tracker.update(&output, events::now_millis()) else {
+            return Ok(Vec::new());
+        };
+        println!(
+            "FPS: {:.1} jank {:.1}% ({} frames)",
+            stats.fps, stats.jank, stats.frames
+        );
+        let mut samples = vec![
+            Sample::new("frames", stats.frames as f64, Unit::Frames),
+            Sample::new("fps", stats.fps, Unit::FramesPerSecond),
+            Sample::new("jank", stats.jank, Unit::Percent),
+        ];
+        samples.extend(
+            stats
+                .p90
+                .map(|p90| Sample::new("frame_p90", p90, Unit::Milliseconds)),
+        );
+        samples.extend(
+            stats
+                .p99
+                .map(|p99| Sample::new("frame_p99", p99, Unit::Milliseconds)),
+        );
+        Ok(samples)
+    }
+}
+
+// Series a plugin may send, their names are leaked to live as long as the samples
+#[cfg(feature = "wasm")]
+const MAX_PLUGIN_SERIES: usize = 64;
+
+// The series names of a run's exec metrics and plugins so far, each with what sends it
+#[cfg(feature = "wasm")]
+type SeriesOwners = Arc<std::sync::Mutex<Vec<(String, String)>>>;
+
+// A `wasm` plugin of the config, the series it sends are named by it
+#[cfg(feature = "wasm")]
+struct WasmCollector {
+    ctx: CollectContext,
+    name: &'static str,
+    command: String,
+    interval: u64,
+    plugin: wasm::Plugin,
+    // the names of its series so far
+    series: Vec<&'static str>,
+    owners: SeriesOwners,
+}
+
+#[cfg(feature = "wasm")]
+impl WasmCollector {
+    fn load(
+        ctx: &CollectContext,
+        plugin: &WasmPlugin,
+        owners: &SeriesOwners,
+    ) -> Result<WasmCollector, String> {
+        Ok(WasmCollector {
+            ctx: ctx.clone(),
+            name: Box::leak(plugin.name.clone().into_boxed_str()),
+            command: plugin.command(&ctx.pkg),
+            interval: plugin.interval.unwrap_or(ctx.interval),
+            plugin: wasm::Plugin::load(&plugin.plugin)?,
+            series: Vec::new(),
+            owners: owners.clone(),
+        })
+    }
+
+    // A series of its own, not one of the built-in collectors', an exec metric's or another
+    // plugin's, and no more than `MAX_PLUGIN_SERIES`
+    fn series(&mut self, name: &str) -> Result<&'static str, String> {
+        if let Some(series) = self.series.iter().find(|series| **series == name) {
+            return Ok(series);
+        }
+        if self.series.len() >= MAX_PLUGIN_SERIES {
+            return Err(format!(
+                "plugin sent {}, more than {} series",
+                name, MAX_PLUGIN_SERIES
+            ));
+        }
+        if metrics::is_builtin(name) {
+            return Err(format!("plugin sent {}, a built-in series", name));
+        }
+        let mut owners = self.owners.lock().unwrap();
+        if let Some((_, owner)) = owners
+            .iter()
+            .find(|(series, owner)| series == name && owner != self.name)
+        {
+            return Err(format!("plugin sent {}, a series of {}", name, owner));
+        }
+        owners.push((name.to_string(), self.name.to_string()));
+        let series: &'static str = Box::leak(name.to_string().into_boxed_str());
+        self.series.push(series);
+        Ok(series)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl MetricCollector for WasmCollector {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, &self.command);
+        let parsed = self.plugin.collect(&output)?;
+        let mut samples = Vec::new();
+        for (name, value, unit) in parsed {
+            samples.push(Sample::new(self.series(&name)?, value, unit));
+        }
+        Ok(samples)
+    }
+}
+
+// Usage and frequency of every core of the device
+struct CoreCollector {
+    ctx: CollectContext,
+    tracker: CoreTracker,
+}
+
+impl MetricCollector for CoreCollector {
+    fn name(&self) -> &'static str {
+        "cores"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        cores::USAGE.to_vec()
+    }
+
+    // core by core, the usage of each next to its frequency
+    fn series(&self) -> Vec<(&'static str, Unit)> {
+        cores::USAGE
+            .iter()
+            .zip(cores::FREQUENCY)
+            .flat_map(|(usage, frequency)| [(*usage, Unit::Percent), (frequency, Unit::Megahertz)])
+            .collect()
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, cores::COMMAND);
+        let usage = self.tracker.update(&output);
+        let frequencies = cores::frequencies(&output);
+        if usage.as_ref().is_some_and(Vec::is_empty) || (usage.is_none() && frequencies.is_empty())
+        {
+            return Err("no cores in /proc/stat".to_string());
+        }
+        let mut samples = Vec::new();
+        for (core, percent) in usage.unwrap_or_default() {
+            if let Some(name) = cores::USAGE.get(core) {
+                samples.push(Sample::new(name, percent, Unit::Percent));
+            }
+        }
+        for (core, mhz) in frequencies {
+            if let Some(name) = cores::FREQUENCY.get(core) {
+                samples.push(Sample::new(name, mhz, Unit::Megahertz));
+            }
+        }
+        Ok(samples)
+    }
+}
+
+// Temperatures of the device, and its throttling status as `thermal` events
+struct ThermalCollector {
+    ctx: CollectContext,
+    // read from the thermal service, else from the kernel's thermal zones
+    service: bool,
+    status: Option<u32>,
+}
+
+impl MetricCollector for ThermalCollector {
+    fn name(&self) -> &'static str {
+        "thermal"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let reading = if self.service {
+            let output = self.ctx.platform.shell(&self.ctx.device, thermal::COMMAND);
+            thermal::parse_service(&output).ok_or("no status in dumpsys thermalservice")?
+        } else {
+            let output = self
+                .ctx
+                .platform
+                .shell(&self.ctx.device, thermal::SYSFS_COMMAND);
+            thermal::parse_sysfs(&output)
+        };
+        if let Some(status) = reading.status.filter(|status| Some(*status) != self.status) {
+            let label = thermal::status_label(status);
+            match self.status {
+                None => self.ctx.events.record(EventKind::Thermal, label),
+                Some(last) => self.ctx.events.record(
+                    EventKind::Thermal,
+                    format!("{} -> {}", thermal::status_label(last), label),
+                ),
+            }
+            self.status = Some(status);
+        }
+        if reading.temperatures.is_empty() {
+            return Err("no temperatures of the cpu, gpu, battery or skin".to_string());
+        }
+        let mut samples = Vec::new();
+        samples.extend(
+            reading
+                .status
+                .map(|status| Sample::new("thermal_status", status as f64, Unit::Level)),
+        );
+        samples.extend(
+            reading
+                .temperatures
+                .into_iter()
+                .map(|(name, celsius)| Sample::new(name, celsius, Unit::Celsius)),
+        );
+        Ok(samples)
+    }
+}
+
+// Bytes the app's uid received and sent, in total and per second since the sample before
+struct TrafficCollector {
+    ctx: CollectContext,
+    uid: u32,
+    // whether the kernel's qtaguid counters are there, else `dumpsys netstats` is read
+    qtaguid: Option<bool>,
+    // counters since boot at the first sample, and at the last one with its time
+    first: Option<(u64, u64)>,
+    last: Option<(u64, u64, u64)>,
+    total: Traffic,
+}
+
+impl TrafficCollector {
+    fn new(ctx: &CollectContext, uid: u32) -> Self {
+        TrafficCollector {
+            ctx: ctx.clone(),
+            uid,
+            qtaguid: None,
+            first: None,
+            last: None,
+            total: Traffic::default(),
+        }
+    }
+
+    fn counters(&mut self) -> Option<(u64, u64)> {
+        if self.qtaguid != Some(false) {
+            let output = self
+                .ctx
+                .platform
+                .shell(&self.ctx.device, &format!("cat {}", traffic::QTAGUID));
+            let bytes = traffic::parse_qtaguid(&output, self.uid);
+            if self.qtaguid.is_none() {
+                self.qtaguid = Some(bytes.is_some());
+            }
+            if bytes.is_some() {
+                return bytes;
+            }
+        }
+        let output = self
+            .ctx
+            .platform
+            .shell(&self.ctx.device, traffic::NETSTATS_COMMAND);
+        // an app that has not used the network yet has no entry
+        traffic::parse_netstats(&output, self.uid)
+            .or_else(|| output.contains("ident=").then_some((0, 0)))
+    }
+}
+
+impl MetricCollector for TrafficCollector {
+    fn name(&self) -> &'static str {
+        "traffic"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        vec!["rx_rate", "tx_rate"]
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let (rx, tx) = self
+            .counters()
+            .ok_or("no traffic counters in qtaguid or dumpsys netstats")?;
+        let now = events::now_millis();
+        let (first_rx, first_tx) = *self.first.get_or_insert((rx, tx));
+        self.total = Traffic {
+            rx_bytes: rx.saturating_sub(first_rx),
+            tx_bytes: tx.saturating_sub(first_tx),
+        };
+        let mut samples = vec![
+            Sample::new(
+                "rx_total",
+                self.total.rx_bytes as f64 / 1024.0,
+                Unit::Kilobytes,
+            ),
+            Sample::new(
+                "tx_total",
+                self.total.tx_bytes as f64 / 1024.0,
+                Unit::Kilobytes,
+            ),
+        ];
+        if let Some((last_rx, last_tx, since)) = self.last {
+            let seconds = now.saturating_sub(since).max(1) as f64 / 1000.0;
+            // the counters start over when the device reboots
+            let rx_rate = rx.saturating_sub(last_rx) as f64 / 1024.0 / seconds;
+            let tx_rate = tx.saturating_sub(last_tx) as f64 / 1024.0 / seconds;
+            println!("流量: 接收 {:.1} KB/s, 发送 {:.1} KB/s", rx_rate, tx_rate);
+            samples.push(Sample::new("rx_rate", rx_rate, Unit::KilobytesPerSecond));
+            samples.push(Sample::new("tx_rate", tx_rate, Unit::KilobytesPerSecond));
+        }
+        self.last = Some((rx, tx, now));
+        Ok(samples)
+    }
+
+    fn finish(&mut self, data: &mut RunData) {
+        self.total.print();
+        data.traffic = Some(self.total);
+    }
+}
+
+// Bytes the app read from and wrote to storage, in total and per second since the sample before
+struct IoCollector {
+    ctx: CollectContext,
+    command: String,
+    tracker: IoTracker,
+}
+
+impl MetricCollector for IoCollector {
+    fn name(&self) -> &'static str {
+        "io"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn warmup(&self) -> Vec<&'static str> {
+        vec!["io_read_rate", "io_write_rate"]
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, &self.command);
+        let rates = self.tracker.update(&output, events::now_millis())?;
+        let total = self.tracker.total;
+        let mut samples = vec![
+            Sample::new(
+                "io_read_total",
+                total.read_bytes as f64 / 1024.0,
+                Unit::Kilobytes,
+            ),
+            Sample::new(
+                "io_write_total",
+                total.write_bytes as f64 / 1024.0,
+                Unit::Kilobytes,
+            ),
+        ];
+        if let Some((read_rate, write_rate)) = rates {
+            println!(
+                "磁盘: 读取 {:.1} KB/s, 写入 {:.1} KB/s",
+                read_rate, write_rate
+            );
+            samples.push(Sample::new(
+                "io_read_rate",
+                read_rate,
+                Unit::KilobytesPerSecond,
+            ));
+            samples.push(Sample::new(
+                "io_write_rate",
+                write_rate,
+                Unit::KilobytesPerSecond,
+            ));
+        }
+        Ok(samples)
+    }
+
+    fn finish(&mut self, data: &mut RunData) {
+        self.tracker.total.print();
+        data.io = Some(self.tracker.total);
+    }
+}
+
+// Battery level, voltage and current, and the power drawn from them
+struct BatteryCollector {
+    ctx: CollectContext,
+    // batterystats attributes the run's battery use to it, reset when sampling starts
+    uid: Option<u32>,
+}
+
+impl BatteryCollector {
+    fn new(ctx: &CollectContext) -> Self {
+        let uid = PackageInfo::resolve(&ctx.device, &ctx.pkg).uid;
+        if uid.is_some() {
+            battery::reset_stats(&ctx.device);
+        }
+        BatteryCollector {
+            ctx: ctx.clone(),
+            uid,
+        }
+    }
+}
+
+impl MetricCollector for BatteryCollector {
+    fn name(&self) -> &'static str {
+        "battery"
+    }
+
+    fn interval(&self) -> u64 {
+        self.ctx.interval
+    }
+
+    fn priority(&self) -> Priority {
+        Priority::Low
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        let output = self.ctx.platform.shell(&self.ctx.device, "dumpsys battery");
+        let level = battery::parse_level(&output).ok_or("no level in dumpsys battery")?;
+        let mut samples = vec![Sample::new("battery_level", level, Unit::Percent)];
+        let voltage = battery::parse_voltage(&output);
+        // the sign tells charging from discharging, and devices disagree on which is which
+        let current = self
+            .ctx
+            .platform
+            .shell(&self.ctx.device, &format!("cat {}", battery::CURRENT_NOW))
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|microamps| microamps.abs() / 1000.0);
+        samples.extend(voltage.map(|voltage| Sample::new("voltage", voltage, Unit::Millivolts)));
+        samples.extend(current.map(|current| Sample::new("current", current, Unit::Milliamps)));
+        if let Some((voltage, current)) = voltage.zip(current) {
+            let power = voltage * current / 1000.0;
+            println!("电池: {}% {:.0}mA {:.0}mW", level, current, power);
+            samples.push(Sample::new("power", power, Unit::Milliwatts));
+        }
+        Ok(samples)
+    }
+
+    fn finish(&mut self, data: &mut RunData) {
+        data.battery_mah = self
+            .uid
+            .and_then(|uid| battery::attributed_mah(&self.ctx.device, uid));
+        if let Some(mah) = data.battery_mah {
+            println!("耗电: {:.2} mAh", mah);
+        }
+    }
+}
+
+// Counters of the sampling itself, it reads nothing from the device
+struct HealthCollector {
+    ctx: CollectContext,
+    // its first turn is when sampling starts, with nothing counted yet
+    started: bool,
+}
+
+impl MetricCollector for HealthCollector {
+    fn name(&self) -> &'static str {
+        "health"
+    }
+
+    fn interval(&self) -> u64 {
+        health::INTERVAL
+    }
+
+    fn reads_device(&self) -> bool {
+        false
+    }
+
+    fn collect(&mut self) -> Result<Vec<Sample>, String> {
+        if !std::mem::replace(&mut self.started, true) {
+            return Ok(Vec::new());
+        }
+        Ok(health::sample(&self.ctx.health, &self.ctx.link))
+    }
+}
+
+// The collector `name` of the catalog for `ctx`, none for the ones that are part of cpu or
+// mem (`webview`, `mem_breakdown`) and the ones the app cannot be sampled for
+fn builtin_collector(
+    ctx: &CollectContext,
+    name: &str,
+    options: &CollectOptions,
+    chosen: &[&str],
+    capabilities: &Capabilities,
+) -> Option<Box<dyn MetricCollector>> {
+    let (device, pkg) = (&ctx.device, &ctx.pkg);
+    let collector: Box<dyn MetricCollector> = match name {
+        "cpu" => Box::new(CpuCollector::new(
+            ctx,
+            options.cpu_threshold,
+            options.interval_of("cpu"),
+        )),
+        "mem" => Box::new(MemCollector::new(
+            ctx,
+            options.mem_threshold,
+            options.interval_of("mem"),
+            chosen.contains(&"mem_breakdown"),
+        )),
+        "system" => Box::new(SystemCollector {
+            ctx: ctx.clone(),
+            tracker: SystemTracker::default(),
+        }),
+        "threads" => Box::new(ThreadCollector {
+            ctx: ctx.clone(),
+            command: threads::command(pkg),
+            tracker: ThreadTracker::new(options.raw),
+            busiest: options.threads.unwrap_or_default(),
+        }),
+        "leaks" => Box::new(LeakCollector {
+            ctx: ctx.clone(),
+            command: leaks::command(pkg),
+        }),
+        "pressure" => Box::new(PressureCollector { ctx: ctx.clone() }),
+        "frames" => Box::new(FrameCollector {
+            ctx: ctx.clone(),
+            tracker: FrameTracker::default(),
+        }),
+        "battery" => Box::new(BatteryCollector::new(ctx)),
+        "thermal" => Box::new(ThermalCollector {
+            ctx: ctx.clone(),
+            service: capabilities.has(Capability::ThermalService),
+            status: None,
+        }),
+        "traffic" => {
+            let Some(uid) = PackageInfo::resolve(device, pkg).uid else {
+                println!("无法读取应用uid, 不采集流量");
+                return None;
+            };
+            Box::new(TrafficCollector::new(ctx, uid))
+        }
+        "io" => Box::new(IoCollector {
+            ctx: ctx.clone(),
+            command: diskio::command(pkg),
+            tracker: IoTracker::default(),
+        }),
+        "cores" => Box::new(CoreCollector {
+            ctx: ctx.clone(),
+            tracker: CoreTracker::default(),
+        }),
+        "bluetooth" => Box::new(BluetoothCollector {
+            ctx: ctx.clone(),
+            tracker: ScanTracker::default(),
+        }),
+        "sensors" => Box::new(SensorCollector {
+            ctx: ctx.clone(),
+            uid: PackageInfo::resolve(device, pkg).uid,
+        }),
+        "health" => Box::new(HealthCollector {
+            ctx: ctx.clone(),
+            started: false,
+        }),
+        _ => return None,
+    };
+    Some(collector)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// What stdin is read for while sampling
+#[derive(Clone, Copy, PartialEq)]
+pub enum StdinCommands {
+    // every line is a marker
+    Markers,
+    // JSON control protocol, see `control`
+    Control,
+    // stdin belongs to the embedding program
+    Ignore,
+}
+
+// How to sample a run
+#[derive(Clone)]
+pub struct CollectOptions {
+    pub duration: u64,
+    pub interval: u64,
+    // milliseconds between cpu and between memory samples, `interval` if not set
+    pub cpu_interval: Option<u64>,
+    pub mem_interval: Option<u64>,
+    pub cpu_threshold: Option<f64>,
+    pub mem_threshold: Option<f64>,
+    pub pipe: Option<SamplePipe>,
+    // every sample is also sent there as a gauge
+    pub statsd: Option<StatsdExporter>,
+    // the live page showing the samples, with buttons for markers and stopping the run
+    pub web_ui: Option<WebUi>,
+    pub stdin: StdinCommands,
+    pub webview: Option<WebviewMode>,
+    // seconds between screenshot thumbnails, none are taken if not set
+    pub screenshot_every: Option<u64>,
+    // seconds between raw snapshots (meminfo, status, smaps_rollup, top), none are taken if not set
+    pub snapshot_every: Option<u64>,
+    // noise floor from `calibrate`, threshold breaches end only once back below it
+    pub calibration: Option<Calibration>,
+    // the config file's overrides of the built-in manufacturer quirks
+    pub quirks: Vec<Quirk>,
+    // the config file's names of metrics in the reports
+    pub display_names: BTreeMap<String, String>,
+    // the config file's metrics read by host commands
+    pub exec: Vec<ExecMetric>,
+    // the config file's plugins parsing device commands, run with the `wasm` feature
+    pub wasm: Vec<WasmPlugin>,
+    // how threshold breaches are announced while sampling
+    pub alerts: AlertOptions,
+    // sample faster for a while after a spike
+    pub adaptive: Option<AdaptiveOptions>,
+    // capture Java stacks once cpu stays above the threshold for `alerts.consecutive` samples
+    pub java_stacks: bool,
+    // end the run as INVALID-UNSTABLE once the app crashed or restarted more often than this
+    pub max_restarts: Option<usize>,
+    // record display rotations and windowing mode changes of the app as events
+    pub window_events: bool,
+    // record the cpuset (top-app, foreground, background...) of the app as events
+    pub cpuset: bool,
+    // record the app's use of the camera, microphone, location and body sensors as events
+    pub appops: bool,
+    // record the cameras the app opens and closes as events
+    pub camera: bool,
+    // record the app's crashes, ANRs and process deaths from logcat as events
+    pub logcat: bool,
+    // write a marker to logcat and the trace buffer, and record it as an event, when sampling
+    // starts and ends, to line the run up with traces and captures of other tools
+    pub sync_marker: bool,
+    // record the screen state (on, off, doze) as events and next to every sample
+    pub screen_events: bool,
+    // keep the screen on or turn it off for the run, put back afterwards
+    pub screen: Option<ScreenMode>,
+    // put the app into split-screen or picture-in-picture during the run
+    pub multi_window: Option<MultiWindow>,
+    // switch airplane mode, wifi or mobile data at points of the run, put back afterwards
+    pub network: Vec<Toggle>,
+    // latency, bandwidth and loss the run is sampled under, taken away afterwards
+    pub shaping: Option<Shaping>,
+    // seconds between two steps through the doze states, the device is not put into doze if
+    // not set
+    pub doze: Option<u64>,
+    // collectors of the catalog that are off unless turned on (`pressure`, `frames`, `battery`,
+    // `thermal`, `traffic`, `cores`, `system`, `bluetooth`, `sensors`, `leaks`, `io`,
+    // `health`), sampled on top of `metrics`
+    pub enabled: Vec<String>,
+    // cpu and mem of the main process alone, not summed over the app's processes
+    pub main_process_only: bool,
+    // names of the collectors to run (`cpu`, `mem`, `pressure`, `frames`, `battery`,
+    // `thermal`, `traffic`, `cores`, `system`, `bluetooth`, `sensors`, `leaks`, `io`), the
+    // default ones if none
+    pub metrics: Option<Vec<String>>,
+    // end the run early once the battery is below this percent
+    pub critical_battery: Option<u32>,
+    // collectors that take their turn on the device at once, the others queue by priority
+    pub adb_slots: usize,
+    // run the shell reads of a tick in one `adb shell`
+    pub batch: bool,
+    // write the shell reads to long-lived `adb shell` sessions instead of one `adb shell` each
+    pub shell_session: bool,
+    // keep the warm-up samples the collectors marked instead of dropping them
+    pub raw: bool,
+    // seed of the random input of the run, recorded with it so it can be repeated
+    pub seed: Option<u64>,
+    // random events monkey sends to the app while it is sampled, generated from `seed`
+    pub monkey: Option<u32>,
+    // split the app's PSS into java heap, native heap, code, graphics and stack
+    pub mem_breakdown: bool,
+    // sample the cpu of the app's threads and report this many of the busiest
+    pub threads: Option<usize>,
+    // percent of the device's cpu the sampling may cost, intervals are stretched to stay below
+    pub overhead_budget: Option<f64>,
+    // fail before sampling if a collector cannot be started or take a valid first sample,
+    // instead of leaving it out
+    pub strict: bool,
+}
+
+impl Default for CollectOptions {
+    fn default() -> Self {
+        CollectOptions {
+            duration: 60,
+            interval: 1000,
+            cpu_interval: None,
+            mem_interval: None,
+            cpu_threshold: None,
+            mem_threshold: None,
+            pipe: None,
+            statsd: None,
+            web_ui: None,
+            stdin: StdinCommands::Ignore,
+            webview: None,
+            screenshot_every: None,
+            snapshot_every: None,
+            calibration: None,
+            quirks: Vec::new(),
+            display_names: BTreeMap::new(),
+            exec: Vec::new(),
+            wasm: Vec::new(),
+            alerts: AlertOptions::default(),
+            adaptive: None,
+            java_stacks: false,
+            window_events: false,
+            cpuset: false,
+            appops: false,
+            camera: false,
+            logcat: false,
+            sync_marker: false,
+            screen_events: false,
+            screen: None,
+            multi_window: None,
+            network: Vec::new(),
+            shaping: None,
+            doze: None,
+            enabled: Vec::new(),
+            main_process_only: false,
+            metrics: None,
+            strict: false,
+            adb_slots: schedule::DEFAULT_SLOTS,
+            batch: false,
+            shell_session: true,
+            raw: false,
+            seed: None,
+            monkey: None,
+            mem_breakdown: false,
+            threads: None,
+            overhead_budget: None,
+            critical_battery: None,
+            max_restarts: None,
+        }
+    }
+}
+
+impl CollectOptions {
+    // Milliseconds between the samples of the series `metric`
+    pub fn interval_of(&self, metric: &str) -> u64 {
+        match metric {
+            "cpu" | "webview_cpu" => self.cpu_interval.unwrap_or(self.interval),
+            "mem" | "webview_mem" => self.mem_interval.unwrap_or(self.interval),
+            health::SAMPLES | health::FAILURES | health::RETRIES => health::INTERVAL,
+            _ if health::LATENCY.iter().any(|(name, _)| *name == metric) => health::INTERVAL,
+            _ if meminfo::SECTIONS.iter().any(|(_, name)| *name == metric) => {
+                self.mem_interval.unwrap_or(self.interval)
+            }
+            _ => self.interval,
+        }
+    }
+
+    // Whether the collector `name` runs: the ones in `metrics` if given, else those of the
+    // catalog on by default, and those in `enabled` either way
+    pub fn samples(&self, name: &str) -> bool {
+        let listed = match &self.metrics {
+            Some(metrics) => metrics.iter().any(|metric| metric == name),
+            None => metrics::find(name).is_none_or(|metric| metric.enabled_by.is_none()),
+        };
+        listed || self.enabled.iter().any(|enabled| enabled == name)
+    }
+}
+
+// Samples and events of one run, times in unix milliseconds
+pub struct RunData {
+    pub start: u64,
+    pub end: u64,
+    pub cpu: Series,
+    pub mem: Series,
+    // additional series next to cpu and mem
+    pub series: Vec<Series>,
+    pub events: Vec<Event>,
+    // the first sample of cpu and mem was left out as warm-up, not in `--raw` runs. Positions of
+    // events count it.
+    pub warmup_dropped: bool,
+    pub screenshots: Vec<Screenshot>,
+    // raw device output taken every `snapshot_every` seconds
+    pub snapshots: Vec<Snapshot>,
+    // thread stacks captured on cpu spikes
+    pub stacks: Vec<StackSnapshot>,
+    // outcome of the `--sla` conditions, evaluated once the run is over
+    pub sla: Vec<Verdict>,
+    // the config's scoring model applied once the run is over
+    pub score: Option<Score>,
+    // stretches a collector could not sample, the series have gaps there
+    pub failures: Vec<CollectorFailure>,
+    // set if the run was ended by a crash loop, it is INVALID-UNSTABLE then
+    pub crash_loop: Option<CrashLoop>,
+    // cost of the sampling on the device, measured with an overhead budget
+    pub overhead: Option<Overhead>,
+    // battery level the run was ended early at, it holds the samples until then
+    pub battery_low: Option<u32>,
+    // the network profile the run was sampled under
+    pub shaping: Option<Shaping>,
+    // battery use batterystats attributed to the app over the run, sampled with `battery`
+    pub battery_mah: Option<f64>,
+    // maintenance windows of a `doze` run
+    pub doze: Vec<DozeWindow>,
+    // bytes the app received and sent over the run, sampled with `traffic`
+    pub traffic: Option<Traffic>,
+    // bytes the app read from and wrote to storage over the run, sampled with `io`
+    pub io: Option<DiskIo>,
+    // collectors that fell behind waiting for the device, by name
+    pub lags: BTreeMap<String, Lag>,
+    // seed of the run's random input, see `CollectOptions::seed`
+    pub seed: Option<u64>,
+    // busiest threads of the app, sampled with `threads`
+    pub threads: Vec<ThreadUsage>,
+    // the app's processes seen running while cpu was sampled, the main one first
+    pub processes: Vec<String>,
+}
+
+impl Default for RunData {
+    fn default() -> Self {
+        RunData {
+            start: 0,
+            end: 0,
+            cpu: Series::new("cpu", Unit::Percent),
+            mem: Series::new("mem", Unit::Kilobytes),
+            series: Vec::new(),
+            events: Vec::new(),
+            warmup_dropped: false,
+            screenshots: Vec::new(),
+            snapshots: Vec::new(),
+            stacks: Vec::new(),
+            sla: Vec::new(),
+            score: None,
+            failures: Vec::new(),
+            crash_loop: None,
+            overhead: None,
+            battery_low: None,
+            shaping: None,
+            battery_mah: None,
+            doze: Vec::new(),
+            traffic: None,
+            io: None,
+            lags: BTreeMap::new(),
+            seed: None,
+            threads: Vec::new(),
+            processes: Vec::new(),
+        }
+    }
+}
+
+// A run that has not finished collecting yet, can be stopped early and annotated from outside
+pub struct Run {
+    options: CollectOptions,
+    // collectors added by the embedding program, sampled next to the built-in ones
+    collectors: Vec<Box<dyn MetricCollector>>,
+    counts: SampleCounts,
+    events: EventLog,
+    stop: Arc<AtomicBool>,
+}
+
+impl Run {
+    pub fn new(options: &CollectOptions) -> Run {
+        // Events are positioned against the cpu and mem sheets
+        let counts = SampleCounts::new(&["cpu", "mem"]);
+        Run {
+            options: options.clone(),
+            collectors: Vec::new(),
+            events: EventLog::new(counts.clone()),
+            counts,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Sample `collector` too, its series go to the reports after the built-in ones
+    pub fn with_collector(mut self, collector: Box<dyn MetricCollector>) -> Run {
+        self.collectors.push(collector);
+        self
+    }
+
+    // Set to end the run before its duration is over
+    pub fn stopper(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    pub fn events(&self) -> EventLog {
+        self.events.clone()
+    }
+
+    // Collect CPU and memory samples in parallel until `duration` seconds have passed, a `strict`
+    // run whose collectors cannot start is an error
+    pub fn collect(self, pkg: &str, device_cmd: &str) -> Result<RunData, Error> {
+        self.try_collect(pkg, device_cmd).map_err(Error::Collectors)
+    }
+
+    // `collect`, `Err` with every problem found if `strict` is set and a collector cannot start
+    pub fn try_collect(self, pkg: &str, device_cmd: &str) -> Result<RunData, String> {
+        if !package::valid_name(pkg) {
+            return Err(format!("{} is not an application id", pkg));
+        }
+        let options = &self.options;
+        let platform = Platform::detect(device_cmd, &options.quirks);
+        platform.print();
+
+        // Collectors the device cannot feed are left out instead of recording zeros
+        let capabilities = Capabilities::probe(device_cmd, pkg, &platform);
+        capabilities.print();
+        let mut problems = Vec::new();
+        let mut supported = |name: &str| {
+            let required = metrics::find(name).map_or(&[][..], |metric| metric.requires);
+            let missing = capabilities.missing(required);
+            if !missing.is_empty() && options.strict {
+                problems.push(format!(
+                    "{}: device lacks {}",
+                    name,
+                    capabilities::labels(&missing)
+                ));
+            } else if !missing.is_empty() {
+                println!(
+                    "设备不支持 {} (缺少 {}), 停用该采集",
+                    name,
+                    capabilities::labels(&missing)
+                );
+            }
+            missing.is_empty()
+        };
+        // The collectors of the catalog the run samples, in its order
+        let mut chosen: Vec<&'static str> = Vec::new();
+        for metric in &metrics::CATALOG {
+            let on = match metric.name {
+                "mem_breakdown" => options.mem_breakdown && chosen.contains(&"mem"),
+                "webview" => options.webview.is_some(),
+                "threads" => options.threads.is_some(),
+                name => options.samples(name),
+            };
+            if on && supported(metric.name) {
+                chosen.push(metric.name);
+            }
+        }
+        let webview = options.webview.filter(|_| chosen.contains(&"webview"));
+        let screenshot_every = options.screenshot_every.filter(|_| {
+            let supported = capabilities.has(Capability::Screencap);
+            if !supported && options.strict {
+                problems.push("screenshots: device lacks screencap".to_string());
+            } else if !supported {
+                println!("设备不支持 screencap, 不截图");
+            }
+            supported
+        });
+        if options.strict {
+            problems.extend(first_sample_problems(
+                device_cmd,
+                pkg,
+                &platform,
+                chosen.contains(&"cpu"),
+                chosen.contains(&"mem"),
+            ));
+            if !problems.is_empty() {
+                return Err(problems.join("; "));
+            }
+        }
+
+        if let Some(shaping) = &options.shaping {
+            // a command that did go through is taken back too
+            if let Err(e) = shaping.apply(device_cmd) {
+                shaping.restore(device_cmd);
+                return Err(e);
+            }
+        }
+        let screen_control = options.screen.map(|mode| screen::apply(device_cmd, mode));
+        let interval = options.interval;
+        let end_time = now() + options.duration;
+
+        println!("测试间隔为: {}(milliseconds)", interval);
+        if options.cpu_interval.is_some() || options.mem_interval.is_some() {
+            println!(
+                "cpu 间隔: {}ms, 内存间隔: {}ms",
+                options.interval_of("cpu"),
+                options.interval_of("mem")
+            );
+        }
+        println!("测试时长为: {}(seconds)", options.duration);
+        println!("结束时间为: {}(timestamp)", end_time);
+
+        let events = &self.events;
+        let stop = self.stopper();
+
+        // Collectors hand their samples to a single writer, which stores and exports them
+        if let Some(web_ui) = &options.web_ui {
+            web_ui.attach(events.clone(), stop.clone(), end_time);
+        }
+        let (samples, writer) = writer::spawn(
+            self.counts.clone(),
+            options.pipe.clone(),
+            options.statsd.clone(),
+            options.web_ui.clone(),
+        );
+        // The preflight above ran its commands one by one
+        let batch = options.batch.then(|| ShellBatch::new(options.interval));
+        // a run starts with a device the preflight found
+        let link = Link::new(chosen.contains(&"health"));
+        let session = options
+            .shell_session
+            .then(|| ShellSession::new(link.clone()));
+        let platform = Platform {
+            batch: batch.clone(),
+            session: session.clone(),
+            link: Some(link.clone()),
+            ..platform
+        };
+        let ctx = CollectContext {
+            device: device_cmd.to_string(),
+            pkg: pkg.to_string(),
+            interval,
+            end_time,
+            stop: stop.clone(),
+            events: events.clone(),
+            samples,
+            webview,
+            calibration: options.calibration.clone(),
+            platform,
+            alerter: Alerter::new(&options.alerts, pkg),
+            boost: Boost::new(options.adaptive.clone()),
+            java_stacks: options.java_stacks,
+            failures: FailureLog::default(),
+            max_restarts: options.max_restarts,
+            main_process_only: options.main_process_only,
+            throttle: Throttle::default(),
+            scheduler: Scheduler::new(options.adb_slots),
+            health: HealthCounters::default(),
+            link: link.clone(),
+        };
+        let failures = ctx.failures.clone();
+        let scheduler = ctx.scheduler.clone();
+        let overhead_thread = options.overhead_budget.map(|budget| {
+            overhead::govern(
+                device_cmd,
+                budget,
+                ctx.throttle.clone(),
+                end_time,
+                stop.clone(),
+            )
+        });
+        let connection_thread =
+            connection::watch(device_cmd, link, events.clone(), end_time, stop.clone());
+        let battery_thread = options.critical_battery.map(|critical| {
+            battery::watch(device_cmd, critical, events.clone(), end_time, stop.clone())
+        });
+        let snapshot_thread = options.snapshot_every.map(|every| {
+            snapshot::watch(
+                device_cmd,
+                pkg,
+                &ctx.platform,
+                every,
+                end_time,
+                stop.clone(),
+            )
+        });
+        let appops_thread = options
+            .appops
+            .then(|| appops::watch(device_cmd, pkg, events.clone(), end_time, stop.clone()));
+        let logcat_thread = options
+            .logcat
+            .then(|| logcat::watch(device_cmd, pkg, events.clone(), end_time, stop.clone()));
+        let camera_thread = options
+            .camera
+            .then(|| camera::watch(device_cmd, pkg, events.clone(), end_time, stop.clone()));
+        let cpuset_thread = options.cpuset.then(|| {
+            cpuset::watch(
+                device_cmd,
+                pkg,
+                &ctx.platform,
+                events.clone(),
+                end_time,
+                stop.clone(),
+            )
+        });
+        let network_thread = (!options.network.is_empty()).then(|| {
+            network::schedule(
+                device_cmd,
+                &ctx.platform,
+                &options.network,
+                events.clone(),
+                end_time,
+                stop.clone(),
+            )
+        });
+        let screen_thread = options
+            .screen_events
+            .then(|| screen::watch(device_cmd, events.clone(), end_time, stop.clone()));
+        let doze_thread = options.doze.map(|every| {
+            doze::cycle(
+                device_cmd,
+                pkg,
+                every,
+                events.clone(),
+                end_time,
+                stop.clone(),
+            )
+        });
+        let multi_window_thread = options.multi_window.as_ref().map(|multi_window| {
+            multiwindow::schedule(
+                device_cmd,
+                pkg,
+                &ctx.platform,
+                multi_window,
+                events.clone(),
+                end_time,
+                stop.clone(),
+            )
+        });
+
+        let exec_collectors = options.exec.iter().map(|metric| {
+            Box::new(ExecCollector::new(metric, device_cmd, pkg, interval))
+                as Box<dyn MetricCollector>
+        });
+        // a plugin's name, like an exec metric's, is a series of its own if it sends one so
+        #[cfg(feature = "wasm")]
+        let owners: SeriesOwners = Arc::new(std::sync::Mutex::new(
+            options
+                .exec
+                .iter()
+                .map(|metric| (metric.name.clone(), format!("exec metric {}", metric.name)))
+                .chain(
+                    options
+                        .wasm
+                        .iter()
+                        .map(|plugin| (plugin.name.clone(), plugin.name.clone())),
+                )
+                .collect(),
+        ));
+        #[cfg(feature = "wasm")]
+        let exec_collectors =
+            exec_collectors.chain(options.wasm.iter().filter_map(
+                |plugin| match WasmCollector::load(&ctx, plugin, &owners) {
+                    Ok(collector) => Some(Box::new(collector) as Box<dyn MetricCollector>),
+                    Err(e) => {
+                        println!("不加载 wasm 插件 {}: {}", plugin.name, e);
+                        None
+                    }
+                },
+            ));
+        // The built-in ones, then those of the embedding program and of the config
+        let collectors: Vec<Box<dyn MetricCollector>> = chosen
+            .iter()
+            .filter_map(|name| builtin_collector(&ctx, name, options, &chosen, &capabilities))
+            .chain(self.collectors)
+            .chain(exec_collectors)
+            .collect();
+        let declared: Vec<(&'static str, Vec<(&'static str, Unit)>)> = collectors
+            .iter()
+            .map(|collector| (collector.name(), collector.series()))
+            .collect();
+        let collector_threads: Vec<_> = collectors
+            .into_iter()
+            .map(|collector| {
+                let ctx = ctx.clone();
+                let name = collector.name();
+                (name, thread::spawn(move || run_collector(&ctx, collector)))
+            })
+            .collect();
+        // The writer finishes once the collectors' senders are gone
+        drop(ctx);
+
+        let monkey_thread = options.monkey.map(|monkey| {
+            scenario::start_monkey(device_cmd, pkg, options.seed.unwrap_or_default(), monkey)
+        });
+
+        let screenshot_thread = screenshot_every
+            .map(|every| screenshot::watch(device_cmd, every, end_time, stop.clone()));
+
+        if options.sync_marker {
+            sync::emit(device_cmd, events, "start");
+        }
+
+        let window_thread = options
+            .window_events
+            .then(|| window::watch(device_cmd, pkg, events.clone(), end_time, stop.clone()));
+
+        let stdin_thread = match options.stdin {
+            StdinCommands::Control => Some(control::watch_commands(
+                events.clone(),
+                vec!["cpu", "mem"],
+                end_time,
+                stop.clone(),
+            )),
+            StdinCommands::Markers => Some(events::watch_markers(
+                events.clone(),
+                end_time,
+                stop.clone(),
+            )),
+            StdinCommands::Ignore => None,
+        };
+
+        // Wait for the collectors to finish, one that panicked leaves its series short and the
+        // others still make a report
+        let mut finished = Vec::new();
+        let mut panics = Vec::new();
+        for (name, thread) in collector_threads {
+            match thread.join() {
+                Ok(collector) => finished.push(collector),
+                Err(panic) => panics.push((name, panic)),
+            }
+        }
+        // the collectors are done once the run is over, random input ends with them
+        if let Some(monkey) = monkey_thread {
+            scenario::stop_monkey(device_cmd, monkey);
+        }
+        // their samples senders go with them, the writer finishes once the last is gone
+        let mut found = RunData::default();
+        for mut collector in finished {
+            collector.finish(&mut found);
+        }
+        if options.sync_marker {
+            sync::emit(device_cmd, events, "end");
+        }
+        // Wake up the stdin watcher in case the run ended on time
+        stop.store(true, Ordering::Relaxed);
+        if let Some(Err(panic)) = stdin_thread.map(|thread| thread.join()) {
+            failures.record_panic("stdin", events.start(), panic.as_ref());
+        }
+        // It puts the app back to fullscreen once the run is over
+        if let Some(Err(panic)) = multi_window_thread.map(|thread| thread.join()) {
+            failures.record_panic("multi-window", events.start(), panic.as_ref());
+        }
+        if let Some(Err(panic)) = appops_thread.map(|thread| thread.join()) {
+            failures.record_panic("appops", events.start(), panic.as_ref());
+        }
+        if let Some(Err(panic)) = camera_thread.map(|thread| thread.join()) {
+            failures.record_panic("camera", events.start(), panic.as_ref());
+        }
+        if let Some(Err(panic)) = logcat_thread.map(|thread| thread.join()) {
+            failures.record_panic("logcat", events.start(), panic.as_ref());
+        }
+        if let Some(Err(panic)) = cpuset_thread.map(|thread| thread.join()) {
+            failures.record_panic("cpuset", events.start(), panic.as_ref());
+        }
+        if let Some(Err(panic)) = window_thread.map(|thread| thread.join()) {
+            failures.record_panic("window", events.start(), panic.as_ref());
+        }
+        // It puts the radios back once the run is over
+        if let Some(Err(panic)) = network_thread.map(|thread| thread.join()) {
+            failures.record_panic("network", events.start(), panic.as_ref());
+        }
+        // It gives doze and the battery back to the device once the run is over
+        let doze = match doze_thread.map(|thread| thread.join()) {
+            Some(Ok(windows)) => windows,
+            Some(Err(panic)) => {
+                failures.record_panic("doze", events.start(), panic.as_ref());
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        if let Some(Err(panic)) = screen_thread.map(|thread| thread.join()) {
+            failures.record_panic("screen", events.start(), panic.as_ref());
+        }
+        if let Err(panic) = connection_thread.join() {
+            failures.record_panic("connection", events.start(), panic.as_ref());
+        }
+        if let Some(screen_control) = screen_control {
+            screen_control.restore(device_cmd);
+        }
+        if let Some(shaping) = &options.shaping {
+            shaping.restore(device_cmd);
+        }
+        let overhead = match overhead_thread.map(|thread| thread.join()) {
+            Some(Ok(overhead)) => overhead,
+            Some(Err(panic)) => {
+                failures.record_panic("overhead", events.start(), panic.as_ref());
+                None
+            }
+            None => None,
+        };
+        let battery_low = match battery_thread.map(|thread| thread.join()) {
+            Some(Ok(level)) => level,
+            Some(Err(panic)) => {
+                failures.record_panic("battery", events.start(), panic.as_ref());
+                None
+            }
+            None => None,
+        };
+        let snapshots = match snapshot_thread.map(|thread| thread.join()) {
+            Some(Ok(snapshots)) => snapshots,
+            Some(Err(panic)) => {
+                failures.record_panic("snapshots", events.start(), panic.as_ref());
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        let screenshots = match screenshot_thread.map(|thread| thread.join()) {
+            Some(Ok(screenshots)) => screenshots,
+            Some(Err(panic)) => {
+                failures.record_panic("screenshots", events.start(), panic.as_ref());
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+
+        // The collectors are done and have dropped their senders, the writer drains the rest
+        let mut store = writer.join().unwrap_or_else(|panic| {
+            failures.record_panic("writer", events.start(), panic.as_ref());
+            SampleStore::default()
+        });
+        // 通常执行脚本第一个数据异常的高，移除采集器标记的预热数据; --raw 保留, 统计时排除
+        if !options.raw {
+            store.drop_warmup();
+        }
+        let mut cpu = store.take("cpu", Unit::Percent);
+        let mut mem = store.take("mem", Unit::Kilobytes);
+        // what the collectors declared in their order, a device has only some of them
+        let mut series: Vec<Series> = declared
+            .iter()
+            .flat_map(|(_, series)| series)
+            .map(|(name, unit)| store.take(name, *unit))
+            .filter(|taken| !taken.samples.is_empty())
+            .collect();
+        series.extend(store.take_rest());
+        for series in [&mut cpu, &mut mem].into_iter().chain(&mut series) {
+            series.display_name = options.display_names.get(&series.name).cloned();
+        }
+        for (collector, panic) in panics {
+            // it stopped after its last sample
+            let first = declared
+                .iter()
+                .find(|(name, _)| *name == collector)
+                .and_then(|(_, series)| series.first())
+                .map(|(name, _)| *name);
+            let since = [&cpu, &mem]
+                .into_iter()
+                .chain(&series)
+                .find(|series| Some(series.name.as_str()) == first)
+                .and_then(|series| series.samples.last())
+                .map_or(events.start(), |sample| sample.ts);
+            failures.record_panic(collector, since, panic.as_ref());
+        }
+        let failures = failures.take();
+        failures::print_failures(events.start(), &failures);
+        let lags: BTreeMap<String, Lag> = scheduler
+            .lags()
+            .into_iter()
+            .map(|(collector, lag)| (collector.to_string(), lag))
+            .collect();
+        schedule::print_lags(&lags);
+        if let Some(session) = &session {
+            session.close();
+            session.print();
+        }
+        if let Some(batch) = &batch {
+            batch.print();
+        }
+        if let Some(crash_loop) = &found.crash_loop {
+            crash_loop.print();
+        }
+        if let Some(overhead) = &overhead {
+            overhead.print();
+        }
+
+        Ok(RunData {
+            start: events.start(),
+            end: events::now_millis(),
+            cpu,
+            mem,
+            series,
+            events: events.take(),
+            warmup_dropped: !options.raw,
+            screenshots,
+            snapshots,
+            failures,
+            overhead,
+            battery_low,
+            shaping: options.shaping.clone(),
+            doze,
+            lags,
+            seed: options.seed,
+            ..found
+        })
+    }
+}
+
+// Why the cpu and mem collectors could not take a valid sample right now, for `strict` runs
+fn first_sample_problems(
+    device_cmd: &str,
+    pkg: &str,
+    platform: &Platform,
+    cpu: bool,
+    mem: bool,
+) -> Vec<String> {
+    if !platform
+        .shell(device_cmd, &format!("pm path {}", pkg))
+        .contains("package:")
+    {
+        return vec![format!("package {} not found", pkg)];
+    }
+    let mut problems = Vec::new();
+    if cpu {
+        let top_result = platform.top_lines(device_cmd, pkg);
+        let lines: Vec<&str> = top_result.lines().collect();
+        if package::main_process_line(pkg, &lines).is_none() {
+            problems.push(format!("cpu: {} is not running", pkg));
+        }
+    }
+    if mem && platform.total_pss(device_cmd, pkg, None).is_none() {
+        let meminfo = platform.shell(device_cmd, &format!("dumpsys meminfo {}", pkg));
+        problems.push(if meminfo.contains("Permission Denial") {
+            "mem: dumpsys meminfo denied".to_string()
+        } else {
+            format!("mem: no TOTAL PSS for {}", pkg)
+        });
+    }
+    problems
+}
+
+// Collect a whole run with `options`
+pub fn collect(pkg: &str, device_cmd: &str, options: &CollectOptions) -> Result<RunData, Error> {
+    Run::new(options).collect(pkg, device_cmd)
+}
+
+// `collect`, with the problems of a `strict` run that cannot start as they are
+pub fn try_collect(
+    pkg: &str,
+    device_cmd: &str,
+    options: &CollectOptions,
+) -> Result<RunData, String> {
+    Run::new(options).try_collect(pkg, device_cmd)
+}
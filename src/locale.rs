@@ -0,0 +1,134 @@
+use crate::adb_shell;
+use crate::device::getprop;
+use crate::transport::quote;
+
+// The app's locale overridden for a run (per-app languages, API 33+), put back on drop.
+// Pseudo-locales (`en-XA`, `ar-XB`) only show if the app was built with them.
+pub struct AppLocale {
+    device_cmd: String,
+    pkg: String,
+    previous: String,
+}
+
+fn set_app_locales(device_cmd: &str, pkg: &str, locales: &str) -> String {
+    adb_shell(
+        device_cmd,
+        &format!(
+            "cmd locale set-app-locales {} --locales {}",
+            pkg,
+            quote(locales)
+        ),
+    )
+}
+
+impl AppLocale {
+    pub fn apply(device_cmd: &str, pkg: &str, locale: &str) -> Result<AppLocale, String> {
+        let sdk: u32 = getprop(device_cmd, "ro.build.version.sdk")
+            .parse()
+            .unwrap_or(0);
+        if sdk < 33 {
+            return Err(format!(
+                "Failed to set locale {}: per-app languages need API 33, the device has {}",
+                locale, sdk
+            ));
+        }
+        // e.g. `Locales for com.example.app for user 0 are [ar-XB]`
+        let previous = adb_shell(device_cmd, &format!("cmd locale get-app-locales {}", pkg));
+        let previous = previous
+            .rsplit_once('[')
+            .and_then(|(_, locales)| locales.split_once(']'))
+            .map_or(String::new(), |(locales, _)| locales.replace(' ', ""));
+
+        println!("应用语言: {}", locale);
+        let output = set_app_locales(device_cmd, pkg, locale);
+        if !output.trim().is_empty() {
+            return Err(format!(
+                "Failed to set locale {}: {}",
+                locale,
+                output.trim()
+            ));
+        }
+        Ok(AppLocale {
+            device_cmd: device_cmd.to_string(),
+            pkg: pkg.to_string(),
+            previous,
+        })
+    }
+}
+
+impl Drop for AppLocale {
+    fn drop(&mut self) {
+        set_app_locales(&self.device_cmd, &self.pkg, &self.previous);
+    }
+}
+
+// The system font scale set for a run, the previous one is put back on drop
+pub struct FontScale {
+    device_cmd: String,
+    previous: String,
+}
+
+impl FontScale {
+    pub fn apply(device_cmd: &str, scale: f64) -> FontScale {
+        let previous = adb_shell(device_cmd, "settings get system font_scale")
+            .trim()
+            .to_string();
+        println!("字体缩放: {}", scale);
+        adb_shell(
+            device_cmd,
+            &format!("settings put system font_scale {}", scale),
+        );
+        FontScale {
+            device_cmd: device_cmd.to_string(),
+            previous,
+        }
+    }
+}
+
+impl Drop for FontScale {
+    fn drop(&mut self) {
+        if self.previous.is_empty() || self.previous == "null" {
+            adb_shell(&self.device_cmd, "settings delete system font_scale");
+        } else {
+            adb_shell(
+                &self.device_cmd,
+                &format!("settings put system font_scale {}", self.previous),
+            );
+        }
+    }
+}
+
+// Every combination of the locales and font scales, a missing list keeps the device's setting
+pub fn configurations(
+    locales: &[String],
+    font_scales: &[f64],
+) -> Vec<(Option<String>, Option<f64>)> {
+    let locales: Vec<Option<String>> = match locales {
+        [] => vec![None],
+        locales => locales.iter().cloned().map(Some).collect(),
+    };
+    let font_scales: Vec<Option<f64>> = match font_scales {
+        [] => vec![None],
+        scales => scales.iter().copied().map(Some).collect(),
+    };
+    locales
+        .iter()
+        .flat_map(|locale| {
+            font_scales
+                .iter()
+                .map(move |scale| (locale.clone(), *scale))
+        })
+        .collect()
+}
+
+// `ar-XB_font1.3`, also the name of the directory its reports go to
+pub fn label(locale: Option<&str>, font_scale: Option<f64>) -> String {
+    let mut parts = Vec::new();
+    if let Some(locale) = locale {
+        parts.push(locale.to_string());
+    }
+    if let Some(scale) = font_scale {
+        parts.push(format!("font{}", scale));
+    }
+    parts.join("_")
+}
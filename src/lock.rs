@@ -0,0 +1,333 @@
+use crate::cache;
+use crate::events::now_millis;
+use crate::transport::quote;
+use crate::{adb_shell, timestamp};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Seconds between two looks at a lock held by another run
+const WAIT_INTERVAL: u64 = 2;
+
+// The lock on the device, a directory as `mkdir` either makes it or fails, with an `owner` file
+const DEVICE_LOCK: &str = "/data/local/tmp/cpureport.lock";
+
+// Seconds between two touches of the device lock's owner, and without one after which the lock
+// is taken as left behind by a run whose host is gone
+const HEARTBEAT: u64 = 30;
+const STALE: u64 = 120;
+
+// The device to itself for one run, two cpureport processes sampling the same device would
+// see each other's adb shells in every number. Held as `~/.cache/cpureport/locks/<serial>.lock`
+// with the pid and start of its owner, and removed on drop. Runs on other hosts sharing the
+// device over one adb server see the lock on the device that goes with it.
+pub struct DeviceLock {
+    path: PathBuf,
+    device: Option<OnDevice>,
+}
+
+// `DEVICE_LOCK` while it is held, touched every `HEARTBEAT` so others can tell it is
+struct OnDevice {
+    device_cmd: String,
+    // `<host> <pid> <unix millis>`
+    owner: String,
+    stop: Arc<AtomicBool>,
+    heartbeat: Option<JoinHandle<()>>,
+}
+
+// `<pid> <unix millis>` of the run holding a lock
+struct Owner {
+    pid: u32,
+    since: u64,
+}
+
+// The text of the lock at `path`, empty if there is none, and its owner if it parses
+fn read_owner(path: &PathBuf) -> (String, Option<Owner>) {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    let mut fields = text.split_whitespace();
+    let owner = (|| {
+        Some(Owner {
+            pid: fields.next()?.parse().ok()?,
+            since: fields.next()?.parse().ok()?,
+        })
+    })();
+    (text, owner)
+}
+
+// Remove the lock at `path` if it still holds `seen`. It is moved out of the way first, as that
+// either takes it or fails, and put back if another run took it over in between.
+fn remove_stale(path: &PathBuf, seen: &str) {
+    let mut moved = path.clone().into_os_string();
+    moved.push(format!(".{}.stale", std::process::id()));
+    let moved = PathBuf::from(moved);
+    if std::fs::rename(path, &moved).is_err() {
+        return;
+    }
+    if std::fs::read_to_string(&moved).unwrap_or_default() != seen {
+        // fails only if yet another run made one since, that one holds the device then
+        let _ = std::fs::hard_link(&moved, path);
+    }
+    let _ = std::fs::remove_file(&moved);
+}
+
+// Whether the process `pid` is still running, a run that was killed leaves its lock behind
+fn alive(pid: u32) -> bool {
+    let output = if cfg!(target_os = "windows") {
+        std::process::Command::new("tasklist")
+            .args(["/NH", "/FI", &format!("PID eq {}", pid)])
+            .output()
+    } else {
+        std::process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .output()
+    };
+    match output {
+        Ok(output) if cfg!(target_os = "windows") => {
+            String::from_utf8_lossy(&output.stdout).contains(&pid.to_string())
+        }
+        Ok(output) => output.status.success(),
+        // nothing to tell with, better wait than sample alongside
+        Err(_) => true,
+    }
+}
+
+// The name of this host in the owner of a lock on the device, without anything special to the
+// device's shell
+fn host_name() -> String {
+    let name = std::process::Command::new("hostname")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || "._-".contains(*c))
+        .collect();
+    if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name
+    }
+}
+
+// The owner of the lock on the device as written, and its host, pid and start with the seconds
+// since it was last touched, by the device's clock so the hosts' do not have to agree. The
+// latter are none while it is being written or if it is broken.
+fn read_device_owner(device_cmd: &str) -> (String, Option<(String, Owner, u64)>) {
+    let output = adb_shell(
+        device_cmd,
+        &format!(
+            "cat {}/owner 2>/dev/null; echo; echo $(( $(date +%s) - $(stat -c %Y {}/owner) ))",
+            DEVICE_LOCK, DEVICE_LOCK
+        ),
+    );
+    // the `echo` in between is for an owner without a newline at its end
+    let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+    let text = lines.next().unwrap_or_default().to_string();
+    let parsed = (|| {
+        let mut fields = text.split_whitespace();
+        let host = fields.next()?.to_string();
+        let owner = Owner {
+            pid: fields.next()?.parse().ok()?,
+            since: fields.next()?.parse().ok()?,
+        };
+        let age = lines.next()?.trim().parse().ok()?;
+        Some((host, owner, age))
+    })();
+    (text, parsed)
+}
+
+// Remove the lock on the device if its owner is still `seen`, in the one shell command so a run
+// that took it over since keeps it
+fn remove_device_lock(device_cmd: &str, seen: &str) {
+    adb_shell(
+        device_cmd,
+        &format!(
+            "[ \"$(cat {}/owner 2>/dev/null)\" = {} ] && rm -rf {}",
+            DEVICE_LOCK,
+            quote(seen),
+            DEVICE_LOCK
+        ),
+    );
+}
+
+impl OnDevice {
+    // Take the lock on the device the way `DeviceLock::acquire` takes the one on the host. A
+    // lock of another host is left behind once it was not touched for `STALE`, one of this
+    // host once its pid is gone. None if the device takes no lock.
+    fn acquire(device_cmd: &str, serial: &str, wait: bool) -> Result<Option<OnDevice>, String> {
+        let host = host_name();
+        let owner = format!("{} {} {}", host, std::process::id(), now_millis());
+        let mut waiting = false;
+        let mut unreadable = 0;
+        let mut removed_unreadable = false;
+        loop {
+            let output = adb_shell(
+                device_cmd,
+                &format!(
+                    "mkdir {} 2>/dev/null && echo {} > {}/owner && echo locked",
+                    DEVICE_LOCK, owner, DEVICE_LOCK
+                ),
+            );
+            if output.trim() == "locked" {
+                let stop = Arc::new(AtomicBool::new(false));
+                let heartbeat = {
+                    let device_cmd = device_cmd.to_string();
+                    let stop = stop.clone();
+                    thread::spawn(move || {
+                        let mut touched = Instant::now();
+                        while !stop.load(Ordering::Relaxed) {
+                            thread::sleep(Duration::from_millis(200));
+                            if touched.elapsed() >= Duration::from_secs(HEARTBEAT) {
+                                adb_shell(&device_cmd, &format!("touch {}/owner", DEVICE_LOCK));
+                                touched = Instant::now();
+                            }
+                        }
+                    })
+                };
+                return Ok(Some(OnDevice {
+                    device_cmd: device_cmd.to_string(),
+                    owner,
+                    stop,
+                    heartbeat: Some(heartbeat),
+                }));
+            }
+            let (seen, parsed) = read_device_owner(device_cmd);
+            match parsed {
+                Some((other, owner, age)) if age < STALE && (other != host || alive(owner.pid)) => {
+                    if !wait {
+                        return Err(format!(
+                            "{} is being sampled by cpureport pid {} on {} since {}, wait for it \
+                             or pass --wait-for-device",
+                            serial,
+                            owner.pid,
+                            other,
+                            timestamp::format(owner.since)
+                        ));
+                    }
+                    if !waiting {
+                        println!(
+                            "设备 {} 正被 {} 的 pid {} 使用, 等待...",
+                            serial, other, owner.pid
+                        );
+                        waiting = true;
+                    }
+                    thread::sleep(Duration::from_secs(WAIT_INTERVAL));
+                }
+                Some((other, owner, _)) => {
+                    println!("移除 {} 的 pid {} 留在设备上的锁", other, owner.pid);
+                    remove_device_lock(device_cmd, &seen);
+                }
+                None if unreadable < 10 => {
+                    unreadable += 1;
+                    thread::sleep(Duration::from_millis(100));
+                }
+                // a device whose shell cannot make the lock is only locked on the host
+                None if removed_unreadable => {
+                    println!("无法在设备上加锁, 只锁定本机");
+                    return Ok(None);
+                }
+                None => {
+                    println!("移除设备上无法读取的锁 {}", DEVICE_LOCK);
+                    remove_device_lock(device_cmd, &seen);
+                    unreadable = 0;
+                    removed_unreadable = true;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for OnDevice {
+    // Removed only if it is still this run's, one taken over as stale meanwhile is the other's
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let _ = heartbeat.join();
+        }
+        remove_device_lock(&self.device_cmd, &self.owner);
+    }
+}
+
+impl DeviceLock {
+    // Take the lock of the device `device_cmd` points adb at. If another run holds it, fail
+    // naming that run, or with `wait` queue until it is done. None for a device without a
+    // serial, it is not locked then.
+    pub fn acquire(device_cmd: &str, wait: bool) -> Result<Option<DeviceLock>, String> {
+        let serial = cache::serial(device_cmd);
+        if serial.is_empty() {
+            println!("无法读取设备序列号, 不加锁");
+            return Ok(None);
+        }
+        let mut lock = DeviceLock::acquire_host(&serial, wait)?;
+        // the one on the host is removed again if this fails
+        lock.device = OnDevice::acquire(device_cmd, &serial, wait)?;
+        Ok(Some(lock))
+    }
+
+    fn acquire_host(serial: &str, wait: bool) -> Result<DeviceLock, String> {
+        let dir = cache::dir("locks");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        // `:` of `host:port` serials is not allowed in Windows file names
+        let path = dir.join(format!("{}.lock", serial.replace(':', "_")));
+
+        let mut waiting = false;
+        let mut unreadable = 0;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{} {}", std::process::id(), now_millis())
+                        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                    return Ok(DeviceLock { path, device: None });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(format!("Failed to create {}: {}", path.display(), e)),
+            }
+            let (seen, owner) = read_owner(&path);
+            match owner {
+                Some(owner) if alive(owner.pid) => {
+                    if !wait {
+                        return Err(format!(
+                            "{} is being sampled by cpureport pid {} since {}, wait for it or \
+                             pass --wait-for-device",
+                            serial,
+                            owner.pid,
+                            timestamp::format(owner.since)
+                        ));
+                    }
+                    if !waiting {
+                        println!("设备 {} 正被 pid {} 使用, 等待...", serial, owner.pid);
+                        waiting = true;
+                    }
+                    thread::sleep(Duration::from_secs(WAIT_INTERVAL));
+                }
+                // left by a run that did not finish
+                Some(owner) => {
+                    println!("移除 pid {} 留下的设备锁", owner.pid);
+                    remove_stale(&path, &seen);
+                }
+                // being written right now, or broken if it stays so for a second
+                None if unreadable < 10 => {
+                    unreadable += 1;
+                    thread::sleep(Duration::from_millis(100));
+                }
+                None => {
+                    println!("移除无法读取的设备锁 {}", path.display());
+                    remove_stale(&path, &seen);
+                    unreadable = 0;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for DeviceLock {
+    fn drop(&mut self) {
+        self.device.take();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::events::{Event, EventKind, EventLog, now_millis};
+use crate::package::is_app_process;
+use crate::transport;
+use crate::{adb_argv, now};
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Crashes from the crash buffer and the activity manager's ANRs and process deaths from the
+// system buffer, followed as they are logged, with unix timestamps:
+//
+//   1760421462.974 12345 12345 E AndroidRuntime: FATAL EXCEPTION: main
+//   1760421462.974 12345 12345 E AndroidRuntime: Process: com.example.app, PID: 12345
+//   1760421470.101  1000  1042 E ActivityManager: ANR in com.example.app (com.example.app/.MainActivity)
+//   1760421475.311  1000  1060 I ActivityManager: Process com.example.app (pid 12345) has died: fg  TOP
+pub const ARGS: [&str; 10] = [
+    "logcat",
+    "-v",
+    "epoch",
+    "-T",
+    "1",
+    "-b",
+    "crash,system",
+    "AndroidRuntime:E",
+    "ActivityManager:I",
+    "*:S",
+];
+
+// A crash or ANR whose lines are still coming
+enum Pending {
+    // thread of `FATAL EXCEPTION`, the process and exception once they are logged
+    Crash {
+        thread: String,
+        process: Option<String>,
+        exception: Option<String>,
+    },
+    Anr {
+        process: String,
+    },
+}
+
+// Events of the app in `command` output, fed line by line
+pub struct LogcatParser {
+    pkg: String,
+    // unix seconds the run started, `-T 1` logs one line from before
+    start: f64,
+    pending: Option<Pending>,
+}
+
+impl LogcatParser {
+    pub fn new(pkg: &str, start: u64) -> Self {
+        LogcatParser {
+            pkg: pkg.to_string(),
+            start: start as f64 / 1000.0,
+            pending: None,
+        }
+    }
+
+    // The event `line` completes, if any
+    pub fn feed(&mut self, line: &str) -> Option<(EventKind, String)> {
+        let (head, message) = line.split_once(": ")?;
+        let mut fields = head.split_whitespace();
+        let time: f64 = fields.next()?.parse().ok()?;
+        if time < self.start {
+            return None;
+        }
+        let tag = fields.last()?;
+        let message = message.trim();
+        match tag {
+            "AndroidRuntime" => self.crash_line(message),
+            "ActivityManager" => self.activity_line(message),
+            _ => None,
+        }
+    }
+
+    fn crash_line(&mut self, message: &str) -> Option<(EventKind, String)> {
+        if let Some(thread) = message.strip_prefix("FATAL EXCEPTION: ") {
+            self.pending = Some(Pending::Crash {
+                thread: thread.to_string(),
+                process: None,
+                exception: None,
+            });
+            return None;
+        }
+        let Some(Pending::Crash {
+            thread,
+            process,
+            exception,
+        }) = &mut self.pending
+        else {
+            return None;
+        };
+        if let Some(rest) = message.strip_prefix("Process: ") {
+            *process = rest.split(',').next().map(str::to_string);
+            return None;
+        }
+        let exception = match exception {
+            Some(exception) => exception.clone(),
+            None => {
+                *exception = Some(message.to_string());
+                return None;
+            }
+        };
+        // the first frame tells where it was thrown
+        let frame = message.strip_prefix("at ")?;
+        let crashed = process
+            .as_deref()
+            .is_some_and(|process| is_app_process(process, &self.pkg));
+        let detail = format!("{} in {} at {}", exception, thread, frame);
+        self.pending = None;
+        crashed.then_some((EventKind::Crash, detail))
+    }
+
+    fn activity_line(&mut self, message: &str) -> Option<(EventKind, String)> {
+        if let Some(rest) = message.strip_prefix("ANR in ") {
+            let process = rest.split_whitespace().next().unwrap_or_default();
+            self.pending = is_app_process(process, &self.pkg).then(|| Pending::Anr {
+                process: process.to_string(),
+            });
+            return None;
+        }
+        if let Some(reason) = message.strip_prefix("Reason: ") {
+            let Some(Pending::Anr { process }) = self.pending.take() else {
+                return None;
+            };
+            return Some((EventKind::Anr, format!("ANR in {}: {}", process, reason)));
+        }
+        // `Process com.example.app (pid 12345) has died: fg  TOP`
+        if let Some(rest) = message.strip_prefix("Process ") {
+            let process = rest.split_whitespace().next()?;
+            if is_app_process(process, &self.pkg) && rest.contains(" has died") {
+                let detail = rest.split_whitespace().collect::<Vec<&str>>().join(" ");
+                return Some((EventKind::ProcessDied, format!("logcat: {}", detail)));
+            }
+        }
+        None
+    }
+}
+
+// Follow `command` while the app is sampled and record its crashes (`crash`), ANRs (`anr`) and
+// the deaths of its processes (`process died`) as events, at the time they are logged
+pub fn watch(
+    device_cmd: &str,
+    pkg: &str,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let device_cmd = device_cmd.to_string();
+    let mut parser = LogcatParser::new(pkg, now_millis());
+    thread::spawn(move || {
+        let child = transport::command(&adb_argv(&device_cmd, &ARGS))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                println!("无法读取logcat: {}", e);
+                return;
+            }
+        };
+        let stdout = child.stdout.take();
+        let reader = thread::spawn(move || {
+            let Some(stdout) = stdout else {
+                return;
+            };
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if let Some((kind, detail)) = parser.feed(&line) {
+                    events.record(kind, detail);
+                }
+            }
+        });
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(200));
+        }
+        // logcat follows forever, its output ends with it and so does the reader, which is not
+        // waited for in case adb outlives the shell it was started from
+        let _ = child.kill();
+        let _ = child.wait();
+        drop(reader);
+    })
+}
+
+// `(crashes, ANRs)` of the app logged during the run
+pub fn counts(events: &[Event]) -> (usize, usize) {
+    let count = |kind: EventKind| events.iter().filter(|event| event.kind == kind).count();
+    (count(EventKind::Crash), count(EventKind::Anr))
+}
+
+pub fn counts_json(events: &[Event]) -> Option<Value> {
+    let (crashes, anrs) = counts(events);
+    (crashes + anrs > 0).then(|| json!({ "crashes": crashes, "anrs": anrs }))
+}
+
+pub fn print_counts(events: &[Event]) {
+    let (crashes, anrs) = counts(events);
+    if crashes + anrs > 0 {
+        println!("logcat: 崩溃 {} 次, ANR {} 次", crashes, anrs);
+    }
+}
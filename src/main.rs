@@ -1,10 +1,43 @@
-use chrono::Local;
-use clap::Parser;
-use rust_xlsxwriter::{RowNum, Workbook};
-use std::process::Command;
-use std::sync::{Arc, Mutex};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use cpureport::alert::AlertOptions;
+use cpureport::anomaly::{self, AdaptiveOptions};
+use cpureport::cache;
+use cpureport::calibration::Calibration;
+use cpureport::compress::{self, Compression};
+use cpureport::config::{Config, Suite};
+use cpureport::connection::RemoteDevice;
+use cpureport::csv::{self, CsvWriter};
+use cpureport::device::{self, DeviceInfo};
+use cpureport::error::{Context, Error};
+use cpureport::locale::{self, AppLocale, FontScale};
+use cpureport::manifest::{self, ClockSettings, Manifest};
+use cpureport::multiwindow::{MultiWindow, MultiWindowMode};
+use cpureport::network::Toggle;
+use cpureport::package::{self, PackageInfo};
+use cpureport::pipe::SamplePipe;
+use cpureport::plan::Plan;
+use cpureport::report::{ReportFormat, Saved};
+use cpureport::screen::ScreenMode;
+use cpureport::shaping::{self, Shaping, ShapingBackend};
+use cpureport::sla::Sla;
+use cpureport::startup::{self, LaunchKind};
+use cpureport::statsd::{StatsdExporter, StatsdFlavor};
+use cpureport::sweep::{self, SweepRun};
+use cpureport::theme::Theme;
+use cpureport::transport;
+use cpureport::webui::WebUi;
+use cpureport::webview::WebviewMode;
+use cpureport::wm::{ScreenConfig, ScreenOverride};
+use cpureport::{
+    CollectOptions, RunData, StdinCommands, battery, benchmark, collect, collector, control,
+    emulator, failures, get_current_time, gsheet, html, impact, jsonreport, lock, metrics,
+    overhead, report, scenario, schedule, schema, sla, snapshot, stf, timestamp, tray, video,
+    workbook,
+};
+use std::any::Any;
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
 /// Args
 #[derive(Parser, Debug)]
@@ -14,119 +47,1197 @@ struct Args {
     #[arg(short, long)]
     device: Option<String>,
 
-    /// app's package to test
+    /// remote adb endpoint (host:port) to `adb connect` and test against
+    #[arg(long, conflicts_with_all = ["device", "stf_serial"])]
+    connect: Option<String>,
+
+    /// STF/OpenSTF server url, used together with `--stf-serial`
+    #[arg(long, requires = "stf_serial")]
+    stf_url: Option<String>,
+
+    /// STF access token
+    #[arg(long, env = "STF_TOKEN", hide_env_values = true)]
+    stf_token: Option<String>,
+
+    /// serial of the farm device to reserve, it is released after the run
+    #[arg(long, requires = "stf_url", conflicts_with = "device")]
+    stf_serial: Option<String>,
+
+    /// boot this avd headless for the run, and kill it afterwards
+    #[arg(long, conflicts_with_all = ["device", "connect", "stf_serial"])]
+    avd: Option<String>,
+
+    /// create a temporary avd from this system image (e.g. `system-images;android-34;google_apis;x86_64`) and boot it
+    #[arg(long, conflicts_with_all = ["avd", "device", "connect", "stf_serial"])]
+    system_image: Option<String>,
+
+    /// console port of the booted emulator, the device will be `emulator-[port]`
+    #[arg(long, default_value_t = 5554)]
+    emulator_port: u16,
+
+    /// seconds to wait for the emulator to finish booting
+    #[arg(long, default_value_t = 300)]
+    boot_timeout: u64,
+
+    /// apk to install (`adb install -r -g`) before the test
+    #[arg(long)]
+    apk: Option<String>,
+
+    /// launch the app before sampling
+    #[arg(long)]
+    launch: bool,
+
+    /// send this many random events to the app with monkey while it is sampled, generated from `--seed`
+    #[arg(long, value_name = "EVENTS")]
+    monkey: Option<u32>,
+
+    /// seed of the run's random input, for `--monkey` and scripts (as `CPUREPORT_SEED`); a random one if not given, recorded in the manifest and reused by `replay`
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// print what the run would collect and execute, with sample counts and output size, without running it
+    #[arg(long)]
+    plan: bool,
+
+    /// run adb on this machine over SSH (`user@host`), where the device is attached
+    #[arg(long, value_name = "USER@HOST")]
+    adb_host: Option<String>,
+
+    /// write file names and report times in UTC instead of the host's time zone
+    #[arg(long)]
+    utc: bool,
+
+    /// how report times are written, `iso8601` (`2026-01-31T14:05:09.120+08:00`, file names `20260131T140509+0800`) or a strftime pattern; `2026-01-31 14:05:09.120` if not set
+    #[arg(long, value_name = "FORMAT")]
+    timestamp_format: Option<String>,
+
+    /// end the run early and mark it INVALID-UNSTABLE once the app crashed or restarted more than this many times; exits with 1
+    #[arg(long, value_name = "N")]
+    max_restarts: Option<usize>,
+
+    /// do not start unless the battery is at least at this percent
+    #[arg(long, value_name = "PERCENT")]
+    min_battery: Option<u32>,
+
+    /// wait for the device to charge up to `--min-battery` instead of failing
+    #[arg(long, requires = "min_battery")]
+    wait_for_battery: bool,
+
+    /// wait for another cpureport run on the same device to finish instead of failing
+    #[arg(long, conflicts_with = "no_device_lock")]
+    wait_for_device: bool,
+
+    /// sample the device even if another cpureport run holds its lock
+    #[arg(long)]
+    no_device_lock: bool,
+
+    /// end the run early, keeping and reporting the samples so far, once the battery drops below this percent; 15 with `--min-battery`
+    #[arg(long, value_name = "PERCENT")]
+    critical_battery: Option<u32>,
+
+    /// read everything the collectors need in a tick (top, meminfo, /proc, dumpsys) in one `adb shell` with a section per command, instead of one per command
+    #[arg(long)]
+    batch: bool,
+
+    /// spawn an `adb shell` for every read, instead of writing them to long-lived shell sessions
+    #[arg(long)]
+    no_shell_session: bool,
+
+    /// keep every sample as read, the first one of cpu, mem and the rates too (marked `warmup` in the csv and json reports), the statistics still leave it out
+    #[arg(long)]
+    raw: bool,
+
+    /// collectors that may use adb at the same time, the others wait with cpu first and heavy dumpsys collectors last, which leave out samples they fall behind on
+    #[arg(long, value_name = "N", default_value_t = schedule::DEFAULT_SLOTS, value_parser = clap::value_parser!(usize))]
+    adb_slots: usize,
+
+    /// keep the on-device cpu cost of sampling below this share of the device (`2%`), stretching the intervals and pausing optional collectors while above it
+    #[arg(long, value_name = "PERCENT")]
+    overhead_budget: Option<String>,
+
+    /// abort before sampling if a collector cannot start or take a valid first sample
+    #[arg(long)]
+    strict: bool,
+
+    /// probe the device again instead of using the state cached since its last reboot
+    #[arg(long)]
+    no_device_cache: bool,
+
+    /// app's package to test, required unless replaying a manifest or listing metrics
     #[arg(short, long)]
-    package: String,
+    package: Option<String>,
 
     /// test time (seconds, default)
     #[arg(short, long)]
     time: Option<u64>,
 
-    /// test interval (millisecond)
+    /// test interval, milliseconds or `500ms`, `2s`
     #[arg(short, long)]
-    interval: Option<u64>,
+    interval: Option<String>,
+
+    /// interval of the cpu samples (`500ms`, `2s`), the test interval if not given
+    #[arg(long, value_name = "INTERVAL")]
+    cpu_interval: Option<String>,
+
+    /// interval of the memory samples (`500ms`, `5s`), the test interval if not given
+    #[arg(long, value_name = "INTERVAL")]
+    mem_interval: Option<String>,
+
+    /// cpu usage (%) above which a threshold event is recorded
+    #[arg(long)]
+    cpu_threshold: Option<f64>,
+
+    /// memory (MB) above which a threshold event is recorded
+    #[arg(long)]
+    mem_threshold: Option<f64>,
+
+    /// samples in a row above `--cpu-threshold`/`--mem-threshold` before alerting
+    #[arg(long, default_value_t = 3)]
+    alert_after: usize,
+
+    /// also POST alerts as JSON to this url (Slack/Mattermost incoming webhooks work as is)
+    #[arg(long)]
+    alert_webhook: Option<String>,
+
+    /// also show alerts as desktop notifications
+    #[arg(long)]
+    alert_desktop: bool,
+
+    /// on a spike (a value well above the recent level), sample every [milliseconds] for a while
+    #[arg(long)]
+    adaptive: Option<u64>,
+
+    /// seconds the faster sampling lasts after the last spike
+    #[arg(long, default_value_t = 10, requires = "adaptive")]
+    adaptive_hold: u64,
+
+    /// capture the app's thread stacks (`debuggerd -b`) on cpu spikes into `stacks_[time].txt`
+    #[arg(long, requires = "adaptive")]
+    spike_stacks: bool,
+
+    /// capture Java stacks (`debuggerd -j`, or `kill -3` with root) when cpu stays above `--cpu-threshold` for `--alert-after` samples, and list the dominant frames
+    #[arg(long, requires = "cpu_threshold")]
+    java_stacks: bool,
+
+    /// stream every sample as a JSON line to the stdin of this command
+    #[arg(long)]
+    pipe_to: Option<String>,
+
+    /// send every sample as a gauge to this StatsD/DogStatsD agent (`host:port`, usually 8125)
+    #[arg(long, value_name = "HOST:PORT")]
+    statsd: Option<String>,
+
+    /// how the device and package tags are sent to `--statsd`
+    #[arg(long, value_enum, default_value_t = StatsdFlavor::Dogstatsd, requires = "statsd")]
+    statsd_flavor: StatsdFlavor,
+
+    /// serve a live page on this port with the curves, current values and events of the run, and buttons to add a marker or stop it
+    #[arg(long, value_name = "PORT")]
+    web_ui: Option<u16>,
+
+    /// serve `--web-ui` on every interface instead of this host's alone, for a browser on another machine; anyone who can reach the port sees the run
+    #[arg(long, requires = "web_ui")]
+    web_ui_all_interfaces: bool,
+
+    /// wait for JSON commands on stdin (start/stop/mark/status), replies are JSON lines on stdout
+    #[arg(long)]
+    controlled: bool,
+
+    /// stay in the system tray with the app's current cpu and memory, and menu items to start a run with the other options, add a marker, stop it and quit (`tray` feature)
+    #[arg(long, conflicts_with_all = ["controlled", "plan"])]
+    tray: bool,
+
+    /// include the app's WebView renderer (`:sandboxed_process`) processes in cpu and memory
+    #[arg(long, value_enum)]
+    webview: Option<WebviewMode>,
+
+    /// take a screenshot thumbnail every [seconds] for the html report
+    #[arg(long)]
+    screenshot_every: Option<u64>,
+
+    /// record display rotations and split-screen/picture-in-picture changes of the app (`dumpsys window`, `dumpsys activity`) as timeline events
+    #[arg(long)]
+    window_events: bool,
+
+    /// record the cpuset (`top-app`, `foreground`, `background`...) of the app's main process over time as events
+    #[arg(long)]
+    cpuset: bool,
+
+    /// record the app's use of the camera, microphone, location and body sensors (`cmd appops get`) as timeline events, the sensors explain many power and cpu anomalies
+    #[arg(long)]
+    appops: bool,
+
+    /// record the cameras the app opens and closes (`dumpsys media.camera`) as timeline events, and the sessions with their durations, a camera left open drains the battery and heats the device
+    #[arg(long)]
+    camera: bool,
+
+    /// follow logcat while sampling and record the app's crashes (with their exception and first frame), ANRs (with their reason) and process deaths as timeline events
+    #[arg(long)]
+    logcat: bool,
+
+    /// write a `cpureport-sync start` and `end` marker to logcat (tag `cpureport`) and the kernel trace marker when sampling starts and ends, and record them as `sync` events, to time-align the run with Perfetto traces and network captures
+    #[arg(long)]
+    sync_marker: bool,
+
+    /// record the screen state (`on`, `off`, `doze`, from `dumpsys power`) as timeline events and next to every sample
+    #[arg(long)]
+    screen_state: bool,
+
+    /// `on` wakes the screen and keeps it on for the run (`svc power stayon true`), `off` turns it off when sampling starts; both are put back afterwards and imply --screen-state
+    #[arg(long, value_enum)]
+    screen_mode: Option<ScreenMode>,
+
+    /// put the app into split-screen or picture-in-picture during the run, recorded as markers
+    #[arg(long, value_enum)]
+    multi_window: Option<MultiWindowMode>,
+
+    /// seconds into the run the app is put into `--multi-window`
+    #[arg(long, default_value_t = 5, requires = "multi_window")]
+    multi_window_after: u64,
+
+    /// seconds the app stays in `--multi-window` before going back to fullscreen, until the end of the run if not set
+    #[arg(long, requires = "multi_window")]
+    multi_window_hold: Option<u64>,
+
+    /// switch a radio during the run, `airplane:on@10`, `wifi:off@30s`, `data:on@2m` (repeatable); each switch is a timeline event and every radio is put back afterwards
+    #[arg(long, value_name = "RADIO:on|off@TIME")]
+    network: Vec<String>,
+
+    /// sample under a latency/bandwidth profile, built in (`2g`, `edge`, `3g`, `lte`, `lossy`) or from `network_profiles` of the config; recorded in the summary and manifest
+    #[arg(long, value_name = "PROFILE")]
+    network_profile: Option<String>,
+
+    /// how the profile is put in place: `tc` netem on rooted devices, the `emulator` console, or a throttling `proxy` on the host set as the device's http proxy; the emulator console on emulators and tc elsewhere if not set
+    #[arg(long, value_enum, requires = "network_profile")]
+    shaping: Option<ShapingBackend>,
+
+    /// device interface `--shaping tc` shapes
+    #[arg(long, default_value = "wlan0", requires = "network_profile")]
+    shaping_interface: String,
+
+    /// `host:port` of the throttling proxy for `--shaping proxy`, implies it
+    #[arg(
+        long,
+        value_name = "HOST:PORT",
+        requires = "network_profile",
+        required_if_eq("shaping", "proxy")
+    )]
+    http_proxy: Option<String>,
+
+    /// collectors to run, `cpu,mem` (the default), `cpu`, `mem,pressure`, `cpu,mem,frames`...
+    #[arg(long, value_delimiter = ',', value_name = "METRICS")]
+    metrics: Option<Vec<String>>,
+
+    /// sample the device's cpu and memory pressure (`/proc/pressure`, API 29+) next to the app
+    #[arg(long)]
+    pressure: bool,
+
+    /// sample the app's frame rate, janky frame share and 90th/99th percentile frame time (`dumpsys gfxinfo framestats`)
+    #[arg(long)]
+    frames: bool,
+
+    /// sample the battery level, voltage, current and power (`dumpsys battery`, `current_now`) and report the mAh `dumpsys batterystats` attributes to the app over the run
+    #[arg(long)]
+    battery: bool,
+
+    /// sample the device's thermal status and cpu, gpu, battery and skin temperatures (`dumpsys thermalservice`, `/sys/class/thermal` without it) and report how long it throttled
+    #[arg(long)]
+    thermal: bool,
+
+    /// record the java heap, native heap, code, graphics and stack of the app's PSS (the `App Summary` of `dumpsys meminfo`) as series of their own, to tell bitmap leaks from native allocations
+    #[arg(long)]
+    mem_breakdown: bool,
+
+    /// sample the cpu of the app's threads (`top -H`) and list the busiest N (10 if not given) with their average and peak on a `Threads` worksheet
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10")]
+    threads: Option<usize>,
+
+    /// sample the cpu load of the whole device (`/proc/stat`) next to the app's, as a `System` series showing the headroom the app had
+    #[arg(long)]
+    system: bool,
+
+    /// sample the open file descriptors (`/proc/<pid>/fd`, debuggable builds or root) and threads (`/proc/<pid>/status`) of the app, and warn when either only grows
+    #[arg(long)]
+    leaks: bool,
+
+    /// sample the Bluetooth LE scans the app starts and has going on (`dumpsys bluetooth_manager`), to catch scanning that never stops in the background
+    #[arg(long)]
+    bluetooth: bool,
+
+    /// sample the sensor listeners the app holds and the sensors they listen to (`dumpsys sensorservice`), to catch listeners left registered
+    #[arg(long)]
+    sensors: bool,
+
+    /// sample how busy every core is and the frequency it runs at (`/proc/stat`, `scaling_cur_freq`), to see which cluster the work lands on
+    #[arg(long)]
+    cores: bool,
+
+    /// sample the bytes the app receives and sends per second and in total (`/proc/net/xt_qtaguid/stats`, `dumpsys netstats` from Android 10)
+    #[arg(long)]
+    traffic: bool,
+
+    /// sample the bytes the app reads from and writes to storage per second and in total (`/proc/<pid>/io`, debuggable builds or root)
+    #[arg(long)]
+    io: bool,
+
+    /// sample cpureport's own health every 5s as series next to the app's, in every report and exporter: samples taken and failed attempts so far, tries to reconnect the device, and the p50/p90/p99 of adb command latency
+    #[arg(long)]
+    health: bool,
+
+    /// cpu and mem of the app's main process alone, instead of summed over all its processes (`:remote`, `:push`...)
+    #[arg(long)]
+    main_process_only: bool,
+
+    /// save raw snapshots (meminfo, /proc status and smaps_rollup, top) every [interval] (`300`, `30s`, `5m`, `1h`) into `snapshots_[time]/`
+    #[arg(long, value_name = "INTERVAL")]
+    snapshot_every: Option<String>,
+
+    /// record the screen during the run and render the cpu/memory curves under it into `video_[time].mp4` (needs ffmpeg)
+    #[arg(long)]
+    video: bool,
+
+    /// YAML (or JSON) config file or http(s) url of one (cached), with `quirks` and `suites`
+    #[arg(long)]
+    config: Option<String>,
+
+    /// YAML (or JSON) file with the `conditions` the run has to meet, e.g. `cpu_p95 < 35% AND mem_max < 400MB`; exits with 1 if one is not met
+    #[arg(long)]
+    sla: Option<String>,
+
+    /// Google Sheets spreadsheet id to append the run's summary to (tab `Runs`), shared with the service account
+    #[arg(long, value_name = "SPREADSHEET_ID", requires = "gsheet_credentials")]
+    gsheet: Option<String>,
+
+    /// JSON key file of the Google Cloud service account writing to `--gsheet`
+    #[arg(long, env = "GOOGLE_APPLICATION_CREDENTIALS", value_name = "PATH")]
+    gsheet_credentials: Option<String>,
+
+    /// also write the samples to a tab `series_[time]` of `--gsheet`
+    #[arg(long, requires = "gsheet")]
+    gsheet_series: bool,
+
+    /// metric whose SLA conditions and comparison differences are informational only and never fail the run, e.g. `jank` or `cpuMaxDeltaPercent`; repeatable, adds to the SLA file's `noisy`
+    #[arg(long, value_name = "METRIC")]
+    noisy: Vec<String>,
+
+    /// noise floor written by `calibrate`, comparisons and threshold events use it when it matches the device and app
+    #[arg(long, default_value = "cpureport_calibration.json")]
+    calibration: String,
+
+    /// output formats, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "xlsx")]
+    format: Vec<ReportFormat>,
+
+    /// compress the raw sample files of `--format csv` and `csv-long` as they are written (`.csv.gz`, `.csv.zst`), multi-hour runs of many collectors otherwise take hundreds of MB
+    #[arg(long, value_enum, value_name = "CODEC")]
+    compress: Option<Compression>,
+
+    #[command(subcommand)]
+    mode: Option<Mode>,
+}
+
+/// Scenarios, without one the app is just sampled as it runs
+#[derive(Subcommand, Debug)]
+enum Mode {
+    /// install the old apk, populate data, upgrade to the new apk and measure its first launch
+    Upgrade {
+        /// apk of the previous version
+        #[arg(long)]
+        old_apk: String,
+
+        /// apk of the version under test, installed over the old one
+        #[arg(long)]
+        new_apk: String,
+
+        /// host script generating data with the old version (`ANDROID_SERIAL` and `CPUREPORT_PACKAGE` are set)
+        #[arg(long)]
+        data_script: Option<String>,
+
+        /// logcat text the new version prints once its data migration is done
+        #[arg(long)]
+        migration_marker: Option<String>,
+    },
+    /// measure the app cold launched without and then with an accessibility service or overlay app enabled, and report the delta
+    #[command(group = clap::ArgGroup::new("helper").required(true))]
+    Impact {
+        /// accessibility service component (`pkg/.Service`) to enable
+        #[arg(long, group = "helper")]
+        accessibility_service: Option<String>,
+
+        /// app drawing over other apps, it is allowed `SYSTEM_ALERT_WINDOW` and started
+        #[arg(long, group = "helper")]
+        overlay_app: Option<String>,
+    },
+    /// sample the idle app to measure the noise floor of every metric, and save it to `--calibration`
+    Calibrate {
+        /// minutes to sample, leave the device alone meanwhile
+        #[arg(long, default_value_t = 5)]
+        minutes: u64,
+    },
+    /// put the device into doze with the screen off and step it through idle and maintenance windows,
+    /// reporting the app's cpu and network traffic in every maintenance window
+    Doze {
+        /// seconds between two steps (`dumpsys deviceidle step`)
+        #[arg(long, default_value_t = 30)]
+        step_every: u64,
+    },
+    /// measure the app in the foreground while another app works in the background, sampling both and the device's cpu/memory pressure;
+    /// the background app's reports go to `background/`
+    Paired {
+        /// package of the app working in the background, launched before the app under test
+        #[arg(long)]
+        background: String,
+
+        /// host script starting the background work once the background app is up (`ANDROID_SERIAL` is set, `CPUREPORT_PACKAGE` is the background app)
+        #[arg(long)]
+        background_script: Option<String>,
+    },
+    /// cold launch and measure the app once per simulated screen (`wm size`, `wm density`), reset afterwards, and compare the runs;
+    /// the first screen's reports are the run's, the others go to `screen_[screen]/`
+    Screens {
+        /// `WIDTHxHEIGHT`, `@DENSITY` or `WIDTHxHEIGHT@DENSITY`, e.g. `--screen 1080x2400@420 --screen 720x1280@320`
+        #[arg(long = "screen", required = true)]
+        screens: Vec<String>,
+    },
+    /// cold launch and measure the app once per combination of app locale (API 33+) and font scale, restored afterwards, and compare the runs;
+    /// the first configuration's reports are the run's, the others go to `config_[configuration]/`
+    #[command(group = clap::ArgGroup::new("configurations").required(true).multiple(true))]
+    Sweep {
+        /// app locale, e.g. `--locale en-US --locale ar-XB --locale en-XA`
+        #[arg(long = "locale", group = "configurations")]
+        locales: Vec<String>,
+
+        /// system font scale, e.g. `--font-scale 1 --font-scale 1.3 --font-scale 2`
+        #[arg(long = "font-scale", group = "configurations")]
+        font_scales: Vec<f64>,
+    },
+    /// launch the app repeatedly with `am start -W` and report its time to initial display (min/avg/p90)
+    /// and cpu/memory while it starts, each launch sampled for `-t` seconds; the first launch's reports are the run's
+    Startup {
+        /// number of measured launches
+        #[arg(long, default_value_t = 10)]
+        launches: usize,
+
+        /// cold force-stops the app before every launch, warm leaves it with back so the process stays
+        #[arg(long, value_enum, default_value = "cold")]
+        kind: LaunchKind,
+    },
+    /// run every scenario of a suite from `--config`, each as its own cpureport run with the scenario's arguments added
+    Suite {
+        /// name of the suite
+        name: String,
+    },
+    /// what the tool can measure
+    Metrics {
+        #[command(subcommand)]
+        action: MetricsAction,
+    },
+    /// run the scenario recorded in a `manifest_[time].json` again and compare it with the recorded run,
+    /// device options given here replace the recorded ones
+    Replay {
+        /// manifest of the run to replay
+        manifest: String,
+    },
+    /// rewrite json reports, manifests, sweeps, startups and calibrations of older cpureport versions in the current layout, in place;
+    /// directories are searched for their `.json` files
+    Migrate {
+        /// files or directories of results
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// only print what would be upgraded
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
-// Function to get the current time as a formatted string
-fn get_current_time() -> String {
-    Local::now().format("%Y%m%d_%H%M%S").to_string()
+#[derive(Subcommand, Debug)]
+enum MetricsAction {
+    /// print every collector with the device capabilities it needs, its default interval and output columns
+    List {
+        /// print a JSON array instead of a table
+        #[arg(long)]
+        json: bool,
+    },
 }
 
-// Function to run adb commands and capture the output
-fn run_adb_command(command: &str) -> String {
-    let mut cmd = if cfg!(target_os = "windows") {
-        let mut win_cmd = Command::new("cmd");
-        win_cmd.arg("/C");
-        win_cmd
-    } else {
-        let mut sh_cmd = Command::new("sh");
-        sh_cmd.arg("-c");
-        sh_cmd
-    };
+// What a `collect` on a thread of its own that panicked gives instead of its run
+fn panicked(panic: Box<dyn Any + Send>) -> Result<RunData, Error> {
+    Err(Error::failed(
+        "Failed to collect",
+        failures::panic_reason(panic.as_ref()),
+    ))
+}
 
-    let output = cmd
-        .arg(command)
-        .output()
-        .expect("Failed to execute adb command");
-    String::from_utf8_lossy(&output.stdout).to_string()
+// The reports of another run of the scenario into `dir`, only its failures count for the run
+fn save_run(dir: &str, data: &RunData, theme: &Theme, saved: &mut Saved) {
+    match std::fs::create_dir_all(dir) {
+        Ok(()) => saved
+            .failed
+            .extend(report::save_reports(dir, data, theme).failed),
+        Err(e) => {
+            saved.record(dir.to_string(), Err(e));
+        }
+    }
 }
 
-// Function to collect CPU data
-fn get_cpu_data(
-    cpu_list: Arc<Mutex<Vec<f64>>>,
-    interval: u64,
-    device: &str,
-    end_time: u64,
+// One configuration of `screens`, the screen is back as it was once it is sampled
+fn sample_screen(
+    device_cmd: &str,
     pkg: &str,
-) {
-    let interval_millis = Duration::from_millis(interval);
-
-    while now() < end_time {
-        let top_result =
-            run_adb_command(&format!("adb {} shell top -b -n 1 | grep {}", device, pkg));
-        if let Some(cpu_line) = top_result.lines().next() {
-            let cpu_value: f64 = cpu_line
-                .split_whitespace()
-                .nth(8)
-                .unwrap_or("0")
-                .replace("%", "")
-                .parse()
-                .unwrap_or(0.0);
-            println!("CPU: {}", cpu_value);
-            cpu_list.lock().unwrap().push(cpu_value);
-        }
-        thread::sleep(interval_millis);
-    }
-    cpu_list.lock().unwrap().remove(0); // Remove the first anomalous value
+    screen: &ScreenConfig,
+    options: &CollectOptions,
+) -> Result<RunData, Error> {
+    let _screen = ScreenOverride::apply(device_cmd, screen).context("Failed to override screen")?;
+    scenario::cold_launch(device_cmd, pkg);
+    collect(pkg, device_cmd, options)
 }
 
-// Function to collect memory data
-fn get_mem_data(
-    mem_list: Arc<Mutex<Vec<f64>>>,
-    interval: u64,
-    device: &str,
-    end_time: u64,
+// One configuration of `sweep`, the app's locale and the font scale are back as they were once
+// it is sampled
+fn sample_configuration(
+    device_cmd: &str,
     pkg: &str,
-) {
-    let interval_millis = Duration::from_millis(interval);
-
-    while now() < end_time {
-        let mem_result = run_adb_command(&format!("adb {} shell dumpsys meminfo {}", device, pkg));
-        mem_result.lines().for_each(|line| {
-            if line.contains("TOTAL PSS:") {
-                // println!("{}", line);
-                let pss_memory = line
-                    .split_whitespace()
-                    .collect::<Vec<&str>>()
-                    .get(2)
-                    .unwrap_or(&"0")
-                    .parse()
-                    .unwrap_or(0.0);
-                println!("MEM: {}", pss_memory);
-                mem_list.lock().unwrap().push(pss_memory);
+    app_locale: Option<&str>,
+    font_scale: Option<f64>,
+    options: &CollectOptions,
+) -> Result<RunData, Error> {
+    let _locale = app_locale
+        .map(|app_locale| AppLocale::apply(device_cmd, pkg, app_locale))
+        .transpose()
+        .context("Failed to set app locale")?;
+    let _font_scale = font_scale.map(|font_scale| FontScale::apply(device_cmd, font_scale));
+    scenario::cold_launch(device_cmd, pkg);
+    collect(pkg, device_cmd, options)
+}
+
+// The comparison of the configurations of `sweep` or `screens` and the reports of each under
+// `{prefix}_{label}`, but the first, whose are the main ones. If the configuration after the
+// last of `runs` `failed`, the ones sampled before are written all the same, the first's
+// reports with the others as the run ends with the error.
+fn save_sweep_runs(
+    mode: &str,
+    f_path: &str,
+    prefix: &str,
+    runs: &[SweepRun],
+    failed: Option<Error>,
+    theme: &Theme,
+    saved: &mut Saved,
+) -> Result<(), Error> {
+    if runs.is_empty() {
+        return failed.map_or(Ok(()), Err);
+    }
+    let first = if failed.is_some() {
+        println!("采集失败, 保存已完成的 {} 个配置", runs.len());
+        0
+    } else {
+        1
+    };
+    sweep::print_sweep(mode, runs);
+    for run in &runs[first..] {
+        let path = format!("{}/{}_{}", f_path, prefix, run.label);
+        save_run(&path, &run.data, theme, saved);
+    }
+    let sweep_path = format!("{}/sweep_{}.json", f_path, get_current_time());
+    let result = sweep::save_sweep(&sweep_path, mode, runs);
+    if saved.record(sweep_path.clone(), result) {
+        println!("对比结果: {}", sweep_path);
+    }
+    failed.map_or(Ok(()), Err)
+}
+
+// Run the scenarios one after another, each with `base_argv` and its own arguments
+fn run_suite(suite: &Suite, base_argv: &[String]) -> Result<bool, Error> {
+    let exe = std::env::current_exe().context("Failed to locate cpureport")?;
+    let mut results = Vec::new();
+    for scenario in &suite.scenarios {
+        println!("场景: {} ({})", scenario.name, scenario.args.join(" "));
+        let status = std::process::Command::new(&exe)
+            .args(base_argv)
+            .args(&scenario.args)
+            .status();
+        results.push((&scenario.name, status));
+    }
+
+    println!("套件 {}:", suite.name);
+    let mut success = true;
+    for (name, status) in results {
+        match status {
+            Ok(status) if status.success() => println!("  {}: 成功", name),
+            Ok(status) => {
+                success = false;
+                println!("  {}: 失败 ({})", name, status);
             }
-        });
-        thread::sleep(interval_millis);
+            Err(e) => {
+                success = false;
+                println!("  {}: 未能启动 ({})", name, e);
+            }
+        }
+    }
+    Ok(success)
+}
+
+// `--plan`: the scenario's own commands around the sampling of each run
+fn print_plan(args: &Args, pkg: &str, options: &CollectOptions) -> Result<(), Error> {
+    let device_cmd = match &args.device {
+        Some(device) => format!("-s {}", device),
+        None if args.connect.is_some() || args.stf_serial.is_some() || args.avd.is_some() => {
+            "-s <device>".to_string()
+        }
+        None => "-d".to_string(),
+    };
+    let adb = |command: String| format!("adb {} {}", device_cmd, command);
+    let launch = adb(format!(
+        "shell monkey -p {} -c android.intent.category.LAUNCHER 1",
+        pkg
+    ));
+    let cold_launch = [
+        adb(format!("shell am force-stop {}", pkg)),
+        adb(format!("shell am start -W -n <{} launcher>", pkg)),
+    ];
+    let install = |apk: &str| adb(format!("install -r -g {}", apk));
+
+    let mut setup = Vec::new();
+    let (mode, runs, duration) = match &args.mode {
+        Some(Mode::Upgrade {
+            old_apk,
+            new_apk,
+            data_script,
+            ..
+        }) => {
+            setup.push(adb(format!("uninstall {}", pkg)));
+            setup.push(install(old_apk));
+            if let Some(script) = data_script {
+                setup.push(format!("{}    (本机脚本)", script));
+            }
+            setup.push(install(new_apk));
+            setup.push(adb(format!("shell am start -W -n <{} launcher>", pkg)));
+            ("upgrade", 1, options.duration)
+        }
+        Some(Mode::Impact {
+            accessibility_service,
+            overlay_app,
+        }) => {
+            setup.extend(args.apk.as_deref().map(install));
+            setup.extend(cold_launch.clone());
+            if let Some(component) = accessibility_service {
+                setup.push(adb(format!(
+                    "shell settings put secure enabled_accessibility_services {}",
+                    component
+                )));
+            }
+            if let Some(overlay) = overlay_app {
+                setup.push(adb(format!(
+                    "shell appops set {} SYSTEM_ALERT_WINDOW allow",
+                    overlay
+                )));
+            }
+            setup.extend(cold_launch.clone());
+            ("impact", 2, options.duration)
+        }
+        Some(Mode::Paired {
+            background,
+            background_script,
+        }) => {
+            setup.extend(args.apk.as_deref().map(install));
+            setup.push(adb(format!(
+                "shell monkey -p {} -c android.intent.category.LAUNCHER 1",
+                background
+            )));
+            if let Some(script) = background_script {
+                setup.push(format!("{}    (本机脚本)", script));
+            }
+            setup.push(launch.clone());
+            ("paired", 1, options.duration)
+        }
+        Some(Mode::Screens { screens }) => {
+            setup.extend(args.apk.as_deref().map(install));
+            for screen in screens {
+                let config = ScreenConfig::parse(screen).usage("Failed to parse screen")?;
+                if let Some((width, height)) = config.size {
+                    setup.push(adb(format!("shell wm size {}x{}", width, height)));
+                }
+                if let Some(density) = config.density {
+                    setup.push(adb(format!("shell wm density {}", density)));
+                }
+                setup.extend(cold_launch.clone());
+                setup.push(format!("采样 {}    (每个屏幕)", config.label()));
+            }
+            setup.push(adb("shell wm size reset".to_string()));
+            setup.push(adb("shell wm density reset".to_string()));
+            ("screens", screens.len() as u64, options.duration)
+        }
+        Some(Mode::Sweep {
+            locales,
+            font_scales,
+        }) => {
+            setup.extend(args.apk.as_deref().map(install));
+            let configurations = locale::configurations(locales, font_scales);
+            for (app_locale, font_scale) in &configurations {
+                if let Some(app_locale) = app_locale {
+                    setup.push(adb(format!(
+                        "shell cmd locale set-app-locales {} --locales \"{}\"",
+                        pkg, app_locale
+                    )));
+                }
+                if let Some(font_scale) = font_scale {
+                    setup.push(adb(format!(
+                        "shell settings put system font_scale {}",
+                        font_scale
+                    )));
+                }
+                setup.extend(cold_launch.clone());
+                setup.push(format!(
+                    "采样 {}    (每个配置)",
+                    locale::label(app_locale.as_deref(), *font_scale)
+                ));
+            }
+            ("sweep", configurations.len() as u64, options.duration)
+        }
+        Some(Mode::Startup { launches, kind }) => {
+            setup.extend(args.apk.as_deref().map(install));
+            if *kind == LaunchKind::Warm {
+                setup.push(adb(format!("shell am start -W -n <{} launcher>", pkg)));
+            }
+            for _ in 0..*launches {
+                setup.push(match kind {
+                    LaunchKind::Cold => adb(format!("shell am force-stop {}", pkg)),
+                    LaunchKind::Warm => adb("shell input keyevent 4".to_string()),
+                });
+                setup.push(adb(format!("shell am start -W -n <{} launcher>", pkg)));
+            }
+            setup.push(format!("采样 {} 秒    (每次启动)", options.duration));
+            ("startup", *launches as u64, options.duration)
+        }
+        Some(Mode::Calibrate { minutes }) => {
+            if args.launch {
+                setup.push(launch.clone());
+            }
+            ("calibrate", 1, minutes * 60)
+        }
+        Some(Mode::Doze { .. }) => {
+            if args.launch {
+                setup.push(launch.clone());
+            }
+            ("doze", 1, options.duration)
+        }
+        _ => {
+            setup.extend(args.apk.as_deref().map(install));
+            if args.launch {
+                setup.push(launch.clone());
+            }
+            ("sampling", 1, options.duration)
+        }
+    };
+    if args.video {
+        setup.push(adb(
+            "shell screenrecord --time-limit <seconds> /sdcard/cpureport_screenrecord.mp4"
+                .to_string(),
+        ));
+    }
+
+    let mut run_options = CollectOptions {
+        duration,
+        ..options.clone()
+    };
+    if mode == "paired" {
+        run_options.enabled.push("pressure".to_string());
+    }
+    if let Some(Mode::Doze { step_every }) = &args.mode {
+        run_options = doze_options(&run_options, *step_every);
     }
+    let mut plan = Plan::new(mode, pkg, &device_cmd, &run_options, runs);
+    plan.commands.splice(0..0, setup);
+    plan.formats = args.format.clone();
+    plan.compression = args.compress;
+    plan.video = args.video;
+    plan.print();
+    Ok(())
+}
 
-    // 通常执行脚本第一个数据异常的高，移除第一个数据
-    mem_list.lock().unwrap().remove(0);
+// Doze only starts with the screen off, it is turned off for the run and watched
+fn doze_options(options: &CollectOptions, step_every: u64) -> CollectOptions {
+    CollectOptions {
+        doze: Some(step_every),
+        screen: Some(ScreenMode::Off),
+        screen_events: true,
+        // input would wake the device
+        monkey: None,
+        ..options.clone()
+    }
 }
 
-fn now() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+// A device chosen for `replay` replaces the one the manifest was recorded with
+fn replace_device(replayed: &mut Args, args: &mut Args) {
+    if args.device.is_some()
+        || args.connect.is_some()
+        || args.stf_serial.is_some()
+        || args.avd.is_some()
+        || args.system_image.is_some()
+    {
+        replayed.device = args.device.take();
+        replayed.connect = args.connect.take();
+        replayed.stf_url = args.stf_url.take();
+        replayed.stf_token = args.stf_token.take();
+        replayed.stf_serial = args.stf_serial.take();
+        replayed.avd = args.avd.take();
+        replayed.system_image = args.system_image.take();
+        replayed.emulator_port = args.emulator_port;
+        replayed.boot_timeout = args.boot_timeout;
+    }
 }
 
 // Main function
 fn main() {
     let args = Args::parse();
-    let pkg = args.package;
-    let device = args.device.unwrap_or("".to_string());
-    let duration = args.time.unwrap_or(60);
-    let interval = args.interval.unwrap_or(1000);
+    let controlled = args.controlled;
+    if let Err(e) = run(args) {
+        if controlled {
+            control::reply(serde_json::json!({
+                "event": "failed",
+                "error": e.label(),
+                "message": e.to_string(),
+                "exitCode": e.exit_code(),
+            }));
+        }
+        eprintln!("{}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+// A whole cpureport invocation, the device and farm leases are released when it returns
+fn run(mut args: Args) -> Result<(), Error> {
+    if let Some(Mode::Metrics {
+        action: MetricsAction::List { json },
+    }) = &args.mode
+    {
+        metrics::print_catalog(*json);
+        return Ok(());
+    }
+    if let Some(Mode::Migrate { paths, dry_run }) = &args.mode {
+        schema::migrate(paths, *dry_run).context("Failed to migrate")?;
+        return Ok(());
+    }
+
+    // Replaying runs the recorded command line instead, the manifest keeps the original one
+    let replay = match &args.mode {
+        Some(Mode::Replay { manifest }) => {
+            Some(Manifest::load(manifest).context("Failed to load manifest")?)
+        }
+        _ => None,
+    };
+    // A seed given to `replay` tries other random input on the recorded scenario
+    let seed_override = args.seed;
+    let (args, recorded_argv) = match &replay {
+        Some(manifest) => {
+            let replay_argv = manifest
+                .replay_argv()
+                .context("Failed to prepare replay scripts")?;
+            let mut replayed =
+                Args::try_parse_from(std::iter::once("cpureport".to_string()).chain(replay_argv))
+                    .unwrap_or_else(|e| e.exit());
+            if matches!(replayed.mode, Some(Mode::Replay { .. })) {
+                return Err(Error::failed(
+                    "Failed to replay",
+                    "the manifest records another replay",
+                ));
+            }
+            replace_device(&mut replayed, &mut args);
+            (replayed, manifest.argv.clone())
+        }
+        None => (args, std::env::args().skip(1).collect::<Vec<String>>()),
+    };
+    let scripts: Vec<(String, String)> = match (&replay, &args.mode) {
+        (Some(manifest), _) => manifest.scripts.clone(),
+        (
+            None,
+            Some(Mode::Upgrade {
+                data_script: Some(script),
+                ..
+            })
+            | Some(Mode::Paired {
+                background_script: Some(script),
+                ..
+            }),
+        ) => vec![(
+            script.clone(),
+            std::fs::read_to_string(script).context("Failed to read data script")?,
+        )],
+        _ => Vec::new(),
+    };
+
+    let Some(pkg) = args.package.clone() else {
+        Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the following required argument was not provided: --package <PACKAGE>",
+            )
+            .exit()
+    };
+    if !package::valid_name(&pkg) {
+        Args::command()
+            .error(
+                ErrorKind::ValueValidation,
+                format!(
+                    "invalid value '{}' for '--package <PACKAGE>': not an application id",
+                    pkg
+                ),
+            )
+            .exit()
+    }
+
+    let config = args
+        .config
+        .as_deref()
+        .map(Config::load)
+        .transpose()
+        .context("Failed to load config")?
+        .unwrap_or_default();
+    let mut sla = args
+        .sla
+        .as_deref()
+        .map(Sla::load)
+        .transpose()
+        .context("Failed to load SLA")?;
+    let mut noisy = args.noisy.clone();
+    if let Some(sla) = &mut sla {
+        noisy.extend(sla.noisy.iter().cloned());
+        sla.noisy = noisy.clone();
+    }
+
+    if args.no_device_cache {
+        cache::disable();
+    }
+    if let Some(host) = &args.adb_host {
+        transport::set_adb_host(host);
+    }
+    timestamp::configure(args.utc, args.timestamp_format.as_deref())
+        .usage("Failed to parse --timestamp-format")?;
+
+    // A suite runs its scenarios as separate cpureport runs, they set up the device themselves
+    if let Some(Mode::Suite { name }) = &args.mode {
+        let suite = config
+            .suite(name)
+            .ok_or_else(|| Error::Usage(format!("Failed to find suite {} in the config", name)))?;
+        let tail = ["suite".to_string(), name.clone()];
+        let base_argv = recorded_argv.strip_suffix(&tail).ok_or_else(|| {
+            Error::Usage("Failed to run suite: its name has to be the last argument".to_string())
+        })?;
+        if !run_suite(suite, base_argv)? {
+            return Err(Error::Checks(format!("suite {} failed", name)));
+        }
+        return Ok(());
+    }
+
+    // The tray starts every run as a separate cpureport too, whenever the tester asks for one
+    if args.tray {
+        let argv = recorded_argv
+            .iter()
+            .filter(|arg| *arg != "--tray")
+            .cloned()
+            .collect();
+        tray::run(&pkg, argv).context("Failed to start tray")?;
+        return Ok(());
+    }
+
+    // The recorded one when replaying, so the replay gets the same random input
+    let seed = seed_override
+        .or_else(|| replay.as_ref().and_then(Manifest::seed))
+        .or(args.seed)
+        .unwrap_or_else(scenario::random_seed);
+    let mut options = CollectOptions {
+        duration: args.time.unwrap_or(60),
+        interval: args
+            .interval
+            .as_deref()
+            .map(collector::parse_interval)
+            .transpose()
+            .usage("Failed to parse --interval")?
+            .unwrap_or(1000),
+        cpu_interval: args
+            .cpu_interval
+            .as_deref()
+            .map(collector::parse_interval)
+            .transpose()
+            .usage("Failed to parse --cpu-interval")?,
+        mem_interval: args
+            .mem_interval
+            .as_deref()
+            .map(collector::parse_interval)
+            .transpose()
+            .usage("Failed to parse --mem-interval")?,
+        cpu_threshold: args.cpu_threshold,
+        mem_threshold: args.mem_threshold,
+        stdin: if args.controlled {
+            StdinCommands::Control
+        } else {
+            StdinCommands::Markers
+        },
+        webview: args.webview,
+        screenshot_every: args.screenshot_every,
+        snapshot_every: args
+            .snapshot_every
+            .as_deref()
+            .map(snapshot::parse_every)
+            .transpose()
+            .usage("Failed to parse --snapshot-every")?,
+        calibration: None,
+        quirks: config.quirks.clone(),
+        display_names: config.display_names.clone(),
+        exec: config.exec.clone(),
+        wasm: config.wasm.clone(),
+        alerts: AlertOptions {
+            consecutive: args.alert_after,
+            webhook: args.alert_webhook.clone(),
+            desktop: args.alert_desktop,
+        },
+        adaptive: args.adaptive.map(|fast_interval| AdaptiveOptions {
+            fast_interval,
+            hold: args.adaptive_hold,
+            stacks: args.spike_stacks,
+        }),
+        java_stacks: args.java_stacks,
+        window_events: args.window_events,
+        enabled: [
+            ("pressure", args.pressure),
+            ("frames", args.frames),
+            ("battery", args.battery),
+            ("thermal", args.thermal),
+            ("traffic", args.traffic),
+            ("cores", args.cores),
+            ("system", args.system),
+            ("bluetooth", args.bluetooth),
+            ("sensors", args.sensors),
+            ("leaks", args.leaks),
+            ("io", args.io),
+            ("health", args.health),
+        ]
+        .into_iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| name.to_string())
+        .collect(),
+        main_process_only: args.main_process_only,
+        metrics: args
+            .metrics
+            .as_deref()
+            .map(metrics::parse_selection)
+            .transpose()
+            .usage("Failed to parse --metrics")?,
+        cpuset: args.cpuset,
+        appops: args.appops,
+        camera: args.camera,
+        logcat: args.logcat,
+        sync_marker: args.sync_marker,
+        screen_events: args.screen_state || args.screen_mode.is_some(),
+        screen: args.screen_mode,
+        multi_window: args.multi_window.map(|mode| MultiWindow {
+            mode,
+            after: args.multi_window_after,
+            hold: args.multi_window_hold,
+        }),
+        network: args
+            .network
+            .iter()
+            .map(|toggle| Toggle::parse(toggle))
+            .collect::<Result<_, _>>()
+            .usage("Failed to parse network toggle")?,
+        shaping: args
+            .network_profile
+            .as_ref()
+            .map(|name| -> Result<Shaping, Error> {
+                let emulator = args.avd.is_some()
+                    || args
+                        .device
+                        .as_deref()
+                        .is_some_and(|device| device.starts_with("emulator-"));
+                Ok(Shaping {
+                    profile: shaping::find(name, &config.network_profiles)
+                        .usage("Failed to find network profile")?,
+                    backend: args.shaping.unwrap_or(match args.http_proxy {
+                        Some(_) => ShapingBackend::Proxy,
+                        None => Shaping::default_backend(emulator),
+                    }),
+                    interface: args.shaping_interface.clone(),
+                    proxy: args.http_proxy.clone(),
+                })
+            })
+            .transpose()?,
+        doze: None,
+        strict: args.strict,
+        critical_battery: args
+            .critical_battery
+            .or(args.min_battery.map(|_| battery::DEFAULT_CRITICAL)),
+        overhead_budget: args
+            .overhead_budget
+            .as_deref()
+            .map(overhead::parse_budget)
+            .transpose()
+            .usage("Failed to parse --overhead-budget")?,
+        max_restarts: args.max_restarts,
+        adb_slots: args.adb_slots,
+        batch: args.batch,
+        shell_session: !args.no_shell_session,
+        raw: args.raw,
+        seed: Some(seed),
+        monkey: args.monkey,
+        mem_breakdown: args.mem_breakdown,
+        threads: args.threads,
+        statsd: None,
+        pipe: None,
+        web_ui: None,
+    };
+
+    if args.plan {
+        return print_plan(&args, &pkg, &options);
+    }
+
+    let mut device = args.device.unwrap_or("".to_string());
+
+    // Reserve the farm device first, the lease releases it when the run returns
+    let stf_lease = args
+        .stf_serial
+        .as_ref()
+        .map(|serial| {
+            let client = stf::StfClient::new(
+                args.stf_url.as_deref().unwrap_or_default(),
+                args.stf_token.as_deref().unwrap_or_default(),
+            );
+            client.acquire(serial)
+        })
+        .transpose()
+        .context("Failed to acquire STF device")?;
+
+    // Disconnected when the run returns, before the lease is released
+    let remote = args
+        .connect
+        .or_else(|| stf_lease.as_ref().map(|lease| lease.remote_url.clone()))
+        .map(|endpoint| RemoteDevice::connect(&endpoint))
+        .transpose()
+        .map_err(Error::DeviceNotFound)?;
+    if let Some(remote) = &remote {
+        device = remote.endpoint.clone();
+    }
+
+    // The emulator is killed (and a temporary avd deleted) when the run returns
+    let emulator = if args.avd.is_some() || args.system_image.is_some() {
+        let avd = match &args.system_image {
+            Some(image) => emulator::create_avd(image).context("Failed to create avd")?,
+            None => emulator::Avd::existing(args.avd.as_deref().unwrap_or_default()),
+        };
+        let emulator = emulator::Emulator::boot(avd, args.emulator_port, args.boot_timeout)
+            .context("Failed to boot emulator")?;
+        device = emulator.serial.clone();
+        Some(emulator)
+    } else {
+        None
+    };
+
+    options.pipe = args
+        .pipe_to
+        .as_deref()
+        .map(SamplePipe::spawn)
+        .transpose()
+        .context("Failed to start pipe-to command")?;
 
     println!("测试包名为: {}", pkg);
 
@@ -138,125 +1249,581 @@ fn main() {
         format!("-s {}", device)
     };
 
-    let end_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-        + duration;
+    // adb, the device and the app are checked before anything is changed on the device, the app
+    // only if the run does not install it
+    let installs = args.apk.is_some() || matches!(args.mode, Some(Mode::Upgrade { .. }));
+    device::check(&device_cmd, (!installs).then_some(pkg.as_str()))?;
 
-    println!("测试间隔为: {}(milliseconds)", interval);
-    println!("测试时长为: {}(seconds)", duration);
-    println!("结束时间为: {}(timestamp)", end_time);
+    // Held until the run returns, another run on the device fails or waits meanwhile
+    let _device_lock = if args.no_device_lock {
+        None
+    } else {
+        lock::DeviceLock::acquire(&device_cmd, args.wait_for_device)
+            .context("Failed to lock device")?
+    };
 
+    if let Some(address) = &args.statsd {
+        let serial = cache::serial(&device_cmd);
+        let tags = [("device", serial.as_str()), ("package", pkg.as_str())];
+        options.statsd = Some(
+            StatsdExporter::connect(address, args.statsd_flavor, &tags)
+                .context("Failed to start statsd exporter")?,
+        );
+    }
+
+    if let Some(port) = args.web_ui {
+        options.web_ui = Some(
+            WebUi::serve(port, &pkg, args.web_ui_all_interfaces)
+                .context("Failed to start web ui")?,
+        );
+    }
 
     let f_path = ".";
 
-    let cpu_list = Arc::new(Mutex::new(Vec::new()));
-    let mem_list = Arc::new(Mutex::new(Vec::new()));
+    let fingerprint = device::getprop(&device_cmd, "ro.build.fingerprint");
+    if !matches!(args.mode, Some(Mode::Calibrate { .. })) {
+        options.calibration = Calibration::load(&args.calibration, &fingerprint, &pkg);
+        if options.calibration.is_some() {
+            println!("使用校准文件: {}", args.calibration);
+        }
+    }
 
-    // Spawn threads for CPU and memory data collection
-    let cpu_thread = {
-        let cpu_list = Arc::clone(&cpu_list);
-        let pkg = pkg.clone();
-        let device_cmd = device_cmd.clone();
-        thread::spawn(move || get_cpu_data(cpu_list, interval, &device_cmd, end_time, &pkg))
-    };
+    if let Some(min) = args.min_battery {
+        battery::ensure(&device_cmd, min, args.wait_for_battery)
+            .context("Failed to check battery")?;
+    }
 
-    let mem_thread = {
-        let mem_list = Arc::clone(&mem_list);
-        let pkg = pkg.clone();
-        let device_cmd = device_cmd.clone();
-        thread::spawn(move || get_mem_data(mem_list, interval, &device_cmd, end_time, &pkg))
-    };
+    if args.controlled && !control::wait_for_start() {
+        println!("Stopped before start");
+        return Ok(());
+    }
+
+    let recording = args
+        .video
+        .then(|| video::Recording::start(&device_cmd, options.duration))
+        .transpose()
+        .context("Failed to record screen")?;
+
+    // Reports that could not be written do not keep the others from it
+    let mut saved = Saved::default();
+    let (name, mut data, launch_metrics) = match &args.mode {
+        Some(Mode::Upgrade {
+            old_apk,
+            new_apk,
+            data_script,
+            migration_marker,
+        }) => {
+            scenario::prepare_upgrade(
+                &device_cmd,
+                &device,
+                &pkg,
+                old_apk,
+                data_script.as_deref(),
+                new_apk,
+                options.seed,
+            )
+            .context("Failed to prepare upgrade")?;
+
+            // Sample from just before the first launch of the new version
+            let collector = {
+                let pkg = pkg.clone();
+                let device_cmd = device_cmd.clone();
+                let options = options.clone();
+                thread::spawn(move || collect(&pkg, &device_cmd, &options))
+            };
+            let launch = scenario::first_launch(
+                &device_cmd,
+                &pkg,
+                migration_marker.as_deref(),
+                Duration::from_secs(options.duration),
+            );
+            let data = collector.join().unwrap_or_else(panicked)?;
+
+            let millis =
+                |value: Option<u64>| value.map_or("unknown".to_string(), |v| v.to_string());
+            println!(
+                "升级后首次启动耗时: {}(milliseconds)",
+                millis(launch.total_time)
+            );
+            println!(
+                "数据迁移耗时: {}(milliseconds)",
+                millis(launch.migration_time)
+            );
+
+            let mut launch_metrics = Vec::new();
+            if let Some(total_time) = launch.total_time {
+                launch_metrics.push(("timeToInitialDisplayMs", total_time as f64));
+            }
+            if let Some(migration_time) = launch.migration_time {
+                launch_metrics.push(("migrationTimeMs", migration_time as f64));
+            }
+            ("upgrade", data, launch_metrics)
+        }
+        Some(Mode::Impact {
+            accessibility_service,
+            overlay_app,
+        }) => {
+            if let Some(apk) = &args.apk {
+                scenario::install(&device_cmd, apk).context("Failed to install apk")?;
+            }
+
+            println!("基准测试 (baseline)");
+            scenario::cold_launch(&device_cmd, &pkg);
+            let baseline = collect(&pkg, &device_cmd, &options)?;
+            let baseline_path = format!("{}/baseline", f_path);
+            save_run(&baseline_path, &baseline, &config.theme, &mut saved);
+
+            let data = {
+                let _service = accessibility_service
+                    .as_ref()
+                    .map(|component| impact::AccessibilityService::enable(&device_cmd, component))
+                    .transpose()
+                    .context("Failed to enable accessibility service")?;
+                let _overlay = overlay_app
+                    .as_ref()
+                    .map(|overlay| impact::OverlayApp::enable(&device_cmd, overlay));
+                scenario::cold_launch(&device_cmd, &pkg);
+                collect(&pkg, &device_cmd, &options)
+                    .inspect_err(|_| println!("基准测试结果已保存: {}", baseline_path))?
+            };
+
+            let delta_metrics =
+                report::print_comparison(&baseline, &data, options.calibration.as_ref(), &noisy);
+            ("impact", data, delta_metrics)
+        }
+        Some(Mode::Paired {
+            background,
+            background_script,
+        }) => {
+            if let Some(apk) = &args.apk {
+                scenario::install(&device_cmd, apk).context("Failed to install apk")?;
+            }
+            println!("后台应用: {}", background);
+            scenario::launch_app(&device_cmd, background);
+            if let Some(script) = background_script {
+                scenario::run_host_script(script, &device, background, options.seed)
+                    .context("Failed to run background script")?;
+            }
+            // The app under test comes to the foreground over it
+            scenario::launch_app(&device_cmd, &pkg);
 
-    // Wait for threads to finish
-    cpu_thread.join().unwrap();
-    mem_thread.join().unwrap();
+            // Only the app under test reads stdin and feeds the exporters
+            let background_options = CollectOptions {
+                stdin: StdinCommands::Ignore,
+                pipe: None,
+                statsd: None,
+                web_ui: None,
+                screenshot_every: None,
+                window_events: false,
+                multi_window: None,
+                network: Vec::new(),
+                shaping: None,
+                doze: None,
+                screen_events: false,
+                screen: None,
+                // the optional collectors measure the app under test
+                enabled: Vec::new(),
+                // the commands measure the app under test
+                exec: Vec::new(),
+                wasm: Vec::new(),
+                monkey: None,
+                ..options.clone()
+            };
+            let background_collector = {
+                let background = background.clone();
+                let device_cmd = device_cmd.clone();
+                thread::spawn(move || collect(&background, &device_cmd, &background_options))
+            };
+            let mut paired_options = options.clone();
+            paired_options.enabled.push("pressure".to_string());
+            let data = collect(&pkg, &device_cmd, &paired_options);
+            // the background app's run is kept even if the one under test failed
+            let background_data = background_collector.join().unwrap_or_else(panicked)?;
 
-    let current_time = get_current_time();
+            println!("后台应用 {}:", background);
+            let background_path = format!("{}/background", f_path);
+            save_run(
+                &background_path,
+                &background_data,
+                &config.theme,
+                &mut saved,
+            );
+            let data = data?;
+            ("paired", data, Vec::new())
+        }
+        Some(Mode::Screens { screens }) => {
+            let configs: Vec<ScreenConfig> = screens
+                .iter()
+                .map(|screen| ScreenConfig::parse(screen))
+                .collect::<Result<_, _>>()
+                .usage("Failed to parse screen")?;
+            if let Some(apk) = &args.apk {
+                scenario::install(&device_cmd, apk).context("Failed to install apk")?;
+            }
 
-    println!("current time is: {}", current_time);
+            let (mut runs, mut failed) = (Vec::new(), None);
+            for screen in &configs {
+                match sample_screen(&device_cmd, &pkg, screen, &options) {
+                    Ok(data) => runs.push(SweepRun {
+                        label: screen.label(),
+                        data,
+                    }),
+                    Err(e) => {
+                        failed = Some(e);
+                        break;
+                    }
+                }
+            }
+            save_sweep_runs(
+                "screens",
+                f_path,
+                "screen",
+                &runs,
+                failed,
+                &config.theme,
+                &mut saved,
+            )?;
+            ("screens", runs.remove(0).data, Vec::new())
+        }
+        Some(Mode::Sweep {
+            locales,
+            font_scales,
+        }) => {
+            if let Some(apk) = &args.apk {
+                scenario::install(&device_cmd, apk).context("Failed to install apk")?;
+            }
 
-    // Save results to Excel files
-    let cpu_file_path = format!("{}/cpu_data_{}.xlsx", f_path, current_time);
-    let mem_file_path = format!("{}/mem_data_{}.xlsx", f_path, current_time);
+            let (mut runs, mut failed) = (Vec::new(), None);
+            for (app_locale, font_scale) in locale::configurations(locales, font_scales) {
+                let label = locale::label(app_locale.as_deref(), font_scale);
+                println!("配置: {}", label);
+                match sample_configuration(
+                    &device_cmd,
+                    &pkg,
+                    app_locale.as_deref(),
+                    font_scale,
+                    &options,
+                ) {
+                    Ok(data) => runs.push(SweepRun { label, data }),
+                    Err(e) => {
+                        failed = Some(e);
+                        break;
+                    }
+                }
+            }
+            save_sweep_runs(
+                "sweep",
+                f_path,
+                "config",
+                &runs,
+                failed,
+                &config.theme,
+                &mut saved,
+            )?;
+            ("sweep", runs.remove(0).data, Vec::new())
+        }
+        Some(Mode::Startup { launches, kind }) => {
+            if let Some(apk) = &args.apk {
+                scenario::install(&device_cmd, apk).context("Failed to install apk")?;
+            }
+            let component = scenario::launcher_component(&device_cmd, &pkg).ok_or_else(|| {
+                Error::failed("Failed to resolve launcher activity", "none found")
+            })?;
+            // a warm launch starts from a running process
+            if *kind == LaunchKind::Warm {
+                startup::launch(&device_cmd, &component).context("Failed to launch app")?;
+            }
 
-    let cpu_data = cpu_list.lock().unwrap();
-    let mem_data = mem_list.lock().unwrap();
+            let (mut runs, mut failed) = (Vec::new(), None);
+            for index in 0..*launches {
+                println!("启动 {}/{} ({})", index + 1, launches, kind.label());
+                startup::prepare(&device_cmd, &pkg, *kind);
+                // Sample from just before the launch
+                let collector = {
+                    let pkg = pkg.clone();
+                    let device_cmd = device_cmd.clone();
+                    let options = options.clone();
+                    thread::spawn(move || collect(&pkg, &device_cmd, &options))
+                };
+                let launched = startup::launch(&device_cmd, &component);
+                let data = match collector.join().unwrap_or_else(panicked) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        failed = Some(e);
+                        break;
+                    }
+                };
+                match launched {
+                    Ok((ttid, state)) => runs.push(startup::Launch { ttid, state, data }),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            if runs.is_empty() {
+                return Err(failed.unwrap_or_else(|| {
+                    Error::failed("Failed to measure startup", "no launch succeeded")
+                }));
+            }
+            startup::print_startup(*kind, &runs);
+            let startup_path = format!("{}/startup_{}.json", f_path, get_current_time());
+            let result = startup::save_startup(&startup_path, *kind, &runs);
+            if saved.record(startup_path.clone(), result) {
+                println!("启动结果: {}", startup_path);
+            }
+            // the launches before the one that failed are in it
+            if let Some(e) = failed {
+                return Err(e);
+            }
 
-    let cpu_sum = cpu_data.iter().sum::<f64>();
+            let (_, avg, p90) = startup::ttid(&runs);
+            let launch_metrics = vec![
+                ("timeToInitialDisplayMs", avg),
+                ("timeToInitialDisplayP90Ms", p90),
+            ];
+            ("startup", runs.remove(0).data, launch_metrics)
+        }
+        Some(Mode::Calibrate { minutes }) => {
+            println!("校准: 保持设备和应用空闲{}分钟", minutes);
+            if args.launch {
+                scenario::launch_app(&device_cmd, &pkg);
+            }
+            let calibrate_options = CollectOptions {
+                duration: minutes * 60,
+                // the noise of the app left alone
+                monkey: None,
+                ..options.clone()
+            };
+            let data = collect(&pkg, &device_cmd, &calibrate_options)?;
 
-    let cpu_average: f64 = cpu_sum / cpu_data.len() as f64;
-    let cpu_max = cpu_data
-        .iter()
-        .max_by(|a, b| a.total_cmp(b))
-        .unwrap_or(&0.0);
+            let calibration = Calibration::from_run(&fingerprint, &pkg, &data);
+            calibration.print();
+            let result = calibration.save(&args.calibration);
+            if saved.record(args.calibration.clone(), result) {
+                println!("校准文件: {}", args.calibration);
+            }
+            ("calibrate", data, Vec::new())
+        }
+        Some(Mode::Doze { step_every }) => {
+            if args.launch {
+                scenario::launch_app(&device_cmd, &pkg);
+            }
+            let data = collect(&pkg, &device_cmd, &doze_options(&options, *step_every))?;
+            ("doze", data, Vec::new())
+        }
+        Some(Mode::Replay { .. }) => unreachable!("replay runs the recorded mode"),
+        Some(Mode::Suite { .. }) => unreachable!("suites run their scenarios as separate runs"),
+        Some(Mode::Metrics { .. }) => unreachable!("metrics are listed without a run"),
+        Some(Mode::Migrate { .. }) => unreachable!("results are migrated without a run"),
+        None => {
+            if let Some(apk) = &args.apk {
+                scenario::install(&device_cmd, apk).context("Failed to install apk")?;
+            }
 
-    let mem_sum = mem_data.iter().sum::<f64>();
-    let mem_average: f64 = mem_sum / (mem_data.len() as f64 * 1024.0);
-    let mem_max = mem_data
-        .iter()
-        .max_by(|a, b| a.total_cmp(b))
-        .unwrap_or(&0.0)
-        / 1024.0;
+            if args.launch {
+                scenario::launch_app(&device_cmd, &pkg);
+            }
 
-    println!("cpu均值: {}", cpu_average);
-    println!("cpu峰值: {}", cpu_max);
-    println!("内存均值: {}", mem_average);
-    println!("内存峰值: {}", mem_max);
+            let data = collect(&pkg, &device_cmd, &options)?;
+            ("sampling", data, Vec::new())
+        }
+    };
 
-    // Save CPU data
-    {
-        let mut workbook = Workbook::new();
-        let sheet = workbook.add_worksheet();
-        sheet.set_name("Cpu Data").unwrap();
-        cpu_data.iter().enumerate().for_each(|(idx, cpu)| {
-            sheet.write(idx as RowNum, 1, cpu.to_string()).unwrap();
-        });
-        sheet
-            .write_row(
-                cpu_data.len() as RowNum,
-                0,
-                ["Cpu Max", cpu_max.to_string().as_str()],
-            )
-            .unwrap();
-        sheet
-            .write_row(
-                cpu_data.len() as RowNum + 1,
-                0,
-                ["Cpu Average", cpu_average.to_string().as_str()],
-            )
-            .unwrap();
+    if let Some(sla) = &sla {
+        data.sla = sla.evaluate(&data, &launch_metrics);
+        sla::print_verdicts(&data.sla);
+    }
+    data.score = config.score.score(&data, &launch_metrics);
+    if let Some(score) = &data.score {
+        score.print();
+    }
+
+    let package_info = PackageInfo::resolve(&device_cmd, &pkg);
+    package_info.print();
+    let device_info = DeviceInfo::probe(&device_cmd);
+    let clock = ClockSettings::probe(&device_cmd);
 
-        workbook.save(&cpu_file_path).unwrap();
+    if let Some(manifest) = &replay {
+        manifest.check_environment(&device_info, &package_info, &clock);
+        println!("回放对比 (记录 -> 回放)");
+        report::print_comparison(
+            &manifest.recorded_run(),
+            &data,
+            options.calibration.as_ref(),
+            &noisy,
+        );
     }
 
-    // Save Memory Data
-    {
-        let mut workbook = Workbook::new();
-        let sheet = workbook.add_worksheet();
-        sheet.set_name("Memory Data").unwrap();
-        mem_data.iter().enumerate().for_each(|(idx, memory)| {
-            sheet.write(idx as RowNum, 1, memory.to_string()).unwrap();
+    if let Some(recording) = recording {
+        let current_time = get_current_time();
+        let raw_path = format!("{}/screenrecord_{}.mp4", f_path, current_time);
+        let strip_path = format!("{}/graph_{}.png", f_path, current_time);
+        let video_path = format!("{}/video_{}.mp4", f_path, current_time);
+        let rendered = recording.finish(&raw_path).and_then(|recording_start| {
+            video::render_strip(&data, &strip_path)?;
+            let offset = data.start.saturating_sub(recording_start);
+            video::composite(&raw_path, &strip_path, &data, offset, &video_path)
         });
+        match rendered {
+            Ok(()) => {
+                let _ = std::fs::remove_file(&raw_path);
+                let _ = std::fs::remove_file(&strip_path);
+                println!("视频: {}", video_path);
+                saved.files.push(video_path);
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+    for format in &args.format {
+        match format {
+            ReportFormat::Xlsx => saved.extend(report::save_reports(f_path, &data, &config.theme)),
+            ReportFormat::Macrobenchmark => {
+                let path = format!(
+                    "{}/{}-{}-benchmarkData.json",
+                    f_path,
+                    pkg,
+                    get_current_time()
+                );
+                let json = benchmark::benchmark_json(
+                    name,
+                    &pkg,
+                    &package_info,
+                    &device_info,
+                    &data,
+                    &launch_metrics,
+                );
+                let result = benchmark::save_benchmark_json(&path, &json);
+                saved.record(path, result);
+            }
+            ReportFormat::Csv => saved.extend(report::save_tables(
+                f_path,
+                &get_current_time(),
+                &data,
+                &CsvWriter {
+                    compression: args.compress,
+                },
+            )),
+            ReportFormat::CsvLong => {
+                let path = compress::path(
+                    &format!("{}/samples_{}.csv", f_path, get_current_time()),
+                    args.compress,
+                );
+                let result = csv::save_long(&path, &data, args.compress);
+                saved.record(path, result);
+            }
+            ReportFormat::Json => {
+                let path = format!("{}/report_{}.json", f_path, get_current_time());
+                let json = jsonreport::report_json(&pkg, &package_info, &device_info, &data);
+                let result = jsonreport::save_report_json(&path, &json);
+                saved.record(path, result);
+            }
+            ReportFormat::Workbook => {
+                let path = format!("{}/report_{}.xlsx", f_path, get_current_time());
+                let result = workbook::save_combined(&path, &data, &config.theme);
+                saved.record(path, result);
+            }
+            ReportFormat::Html => {
+                let path = format!("{}/report_{}.html", f_path, get_current_time());
+                let result = html::save_html(&path, &data, &config.theme);
+                saved.record(path, result);
+            }
+        }
+    }
 
-        sheet
-            .write_row(
-                mem_data.len() as RowNum,
-                0,
-                ["Mem Max", mem_max.to_string().as_str()],
-            )
-            .unwrap();
-        sheet
-            .write_row(
-                mem_data.len() as RowNum + 1,
-                0,
-                ["Mem Average", mem_average.to_string().as_str()],
-            )
-            .unwrap();
+    // the other formats have the verdicts of `--sla` inside, the csv ones in a table next to them
+    if !data.sla.is_empty()
+        && args
+            .format
+            .iter()
+            .any(|format| matches!(format, ReportFormat::Csv | ReportFormat::CsvLong))
+    {
+        let path = format!("{}/sla_{}.csv", f_path, get_current_time());
+        let result = csv::save_sla(&path, &data.sla);
+        saved.record(path, result);
+    }
 
-        workbook.save(&mem_file_path).unwrap();
+    anomaly::print_dominant_frames(&anomaly::dominant_frames(&data.stacks, anomaly::TOP_FRAMES));
+    if !data.stacks.is_empty() {
+        let path = format!("{}/stacks_{}.txt", f_path, get_current_time());
+        let result = anomaly::save_stacks(&path, data.start, &data.stacks);
+        saved.record(path, result);
     }
 
+    if !data.snapshots.is_empty() {
+        let path = format!("{}/snapshots_{}", f_path, get_current_time());
+        let result = snapshot::save(&path, data.start, &data.snapshots);
+        saved.record(path, result);
+    }
+
+    let manifest_path = format!("{}/manifest_{}.json", f_path, get_current_time());
+    let manifest = manifest::manifest_json(
+        &recorded_argv,
+        &scripts,
+        &device_info,
+        &package_info,
+        &clock,
+        &data,
+    );
+    let result = manifest::save_manifest(&manifest_path, &manifest);
+    saved.record(manifest_path, result);
+
+    // The run is saved locally already, a failed upload is reported and the run goes on
+    if let (Some(id), Some(credentials)) = (&args.gsheet, &args.gsheet_credentials) {
+        let exported = gsheet::ServiceAccount::load(credentials)
+            .and_then(|account| gsheet::Spreadsheet::open(id, &account))
+            .and_then(|spreadsheet| {
+                gsheet::export(
+                    &spreadsheet,
+                    &pkg,
+                    &package_info,
+                    &device_info,
+                    &data,
+                    args.gsheet_series,
+                )
+            });
+        match exported {
+            Ok(Some(tab)) => println!("已导出到 Google Sheets: {}, {}", gsheet::RUNS_TAB, tab),
+            Ok(None) => println!("已导出到 Google Sheets: {}", gsheet::RUNS_TAB),
+            Err(e) => println!("导出到 Google Sheets 失败: {}", e),
+        }
+    }
+
+    if args.controlled {
+        let mut finished = serde_json::json!({ "event": "finished", "reports": saved.files });
+        if !data.sla.is_empty() {
+            finished["sla"] = sla::verdicts_json(&data.sla);
+        }
+        if let Some(score) = &data.score {
+            finished["score"] = score.to_json();
+        }
+        if let Some(crash_loop) = &data.crash_loop {
+            finished["crashLoop"] = crash_loop.to_json();
+        }
+        control::reply(finished);
+    }
+
+    if let Some(pipe) = &options.pipe {
+        pipe.close();
+    }
+    drop(emulator);
+
     println!("Finished!");
+    // The app was never seen, cpu and mem have no samples at all
+    if (options.samples("cpu") || options.samples("mem"))
+        && data.cpu.samples.is_empty()
+        && data.mem.samples.is_empty()
+    {
+        return Err(Error::PackageNotRunning(pkg));
+    }
+    if !saved.failed.is_empty() {
+        return Err(Error::Output(saved.failed.join(", ")));
+    }
+    if !sla::passed(&data.sla) {
+        return Err(Error::Checks("SLA failed".to_string()));
+    }
+    if let Some(crash_loop) = &data.crash_loop {
+        return Err(Error::Checks(format!(
+            "crash loop, {} crashes",
+            crash_loop.crashes
+        )));
+    }
+    Ok(())
 }
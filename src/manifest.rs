@@ -0,0 +1,221 @@
+use crate::device::DeviceInfo;
+use crate::events::now_millis;
+use crate::package::PackageInfo;
+use crate::sample::{Series, Unit};
+use crate::schema::{self, Kind};
+use crate::{RunData, adb_shell, failures, sla};
+use serde_json::{Value, json};
+
+// Bumped when the manifest layout changes
+// 2: samples are objects with their time, unit and quality instead of bare values
+pub const MANIFEST_VERSION: u64 = 2;
+
+// Clock of the device under test, event times and the host-side timeline depend on it
+pub struct ClockSettings {
+    pub auto_time: String,
+    pub timezone: String,
+    // device clock minus host clock, in seconds
+    pub offset_seconds: i64,
+}
+
+impl ClockSettings {
+    pub fn probe(device_cmd: &str) -> ClockSettings {
+        let device_now: i64 = adb_shell(device_cmd, "date +%s")
+            .trim()
+            .parse()
+            .unwrap_or(0);
+        ClockSettings {
+            auto_time: adb_shell(device_cmd, "settings get global auto_time")
+                .trim()
+                .to_string(),
+            timezone: crate::device::getprop(device_cmd, "persist.sys.timezone"),
+            offset_seconds: if device_now == 0 {
+                0
+            } else {
+                device_now - (now_millis() / 1000) as i64
+            },
+        }
+    }
+}
+
+// Everything needed to run the same scenario again: the command line, the scenario scripts
+// it refers to, and the device, app and clock it ran with, plus its samples to compare against
+pub fn manifest_json(
+    argv: &[String],
+    scripts: &[(String, String)],
+    device: &DeviceInfo,
+    package: &PackageInfo,
+    clock: &ClockSettings,
+    data: &RunData,
+) -> Value {
+    let scripts: Vec<Value> = scripts
+        .iter()
+        .map(|(path, content)| json!({ "path": path, "content": content }))
+        .collect();
+    json!({
+        "schema": Kind::Manifest.label(),
+        "version": MANIFEST_VERSION,
+        "cpureport": env!("CARGO_PKG_VERSION"),
+        "argv": argv,
+        "scripts": scripts,
+        "seed": data.seed,
+        "device": {
+            "brand": device.brand,
+            "device": device.device,
+            "model": device.model,
+            "manufacturer": device.manufacturer,
+            "fingerprint": device.fingerprint,
+            "sdk": device.sdk,
+            "cpuCores": device.cpu_cores,
+            "memTotalBytes": device.mem_total_bytes,
+        },
+        "app": {
+            "versionName": package.version_name,
+            "versionCode": package.version_code,
+            "instant": package.instant,
+            "splits": package.splits,
+        },
+        "clock": {
+            "autoTime": clock.auto_time,
+            "timezone": clock.timezone,
+            "offsetSeconds": clock.offset_seconds,
+        },
+        "run": {
+            "start": data.start,
+            "end": data.end,
+            "cpu": data.cpu.to_json(),
+            "mem": data.mem.to_json(),
+            "sla": sla::verdicts_json(&data.sla),
+            "score": data.score.as_ref().map(|score| score.to_json()),
+            "failures": failures::failures_json(&data.failures),
+            "crashLoop": data.crash_loop.as_ref().map(|crash_loop| crash_loop.to_json()),
+            "networkProfile": data.shaping.as_ref().map(|shaping| shaping.to_json()),
+        },
+    })
+}
+
+pub fn save_manifest(path: &str, manifest: &Value) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(manifest)?)
+}
+
+// A manifest read back for `replay`
+pub struct Manifest {
+    pub argv: Vec<String>,
+    pub scripts: Vec<(String, String)>,
+    pub value: Value,
+}
+
+impl Manifest {
+    pub fn load(path: &str) -> Result<Manifest, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read manifest {}: {}", path, e))?;
+        let mut value: Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse manifest {}: {}", path, e))?;
+        schema::upgrade(Kind::Manifest, &mut value)
+            .map_err(|e| format!("Failed to read manifest {}: {}", path, e))?;
+
+        let argv = value["argv"]
+            .as_array()
+            .ok_or_else(|| format!("Manifest {} has no argv", path))?
+            .iter()
+            .filter_map(|arg| arg.as_str().map(str::to_string))
+            .collect();
+        let scripts = value["scripts"]
+            .as_array()
+            .map(|scripts| {
+                scripts
+                    .iter()
+                    .filter_map(|script| {
+                        Some((
+                            script["path"].as_str()?.to_string(),
+                            script["content"].as_str()?.to_string(),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Manifest {
+            argv,
+            scripts,
+            value,
+        })
+    }
+
+    // Write the recorded scripts to temporary files and point the command line at them,
+    // so the replay runs the scripts as they were, not as they are now
+    pub fn replay_argv(&self) -> Result<Vec<String>, String> {
+        let mut argv = self.argv.clone();
+        for (i, (path, content)) in self.scripts.iter().enumerate() {
+            let file_name = std::path::Path::new(path)
+                .file_name()
+                .map_or("script".into(), |name| name.to_string_lossy());
+            let replay_path =
+                std::env::temp_dir().join(format!("cpureport_replay_{}_{}", i, file_name));
+            std::fs::write(&replay_path, content)
+                .map_err(|e| format!("Failed to write replay script: {}", e))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let _ =
+                    std::fs::set_permissions(&replay_path, std::fs::Permissions::from_mode(0o755));
+            }
+            let replay_path = replay_path.to_string_lossy().to_string();
+            for arg in argv.iter_mut().filter(|arg| *arg == path) {
+                *arg = replay_path.clone();
+            }
+        }
+        Ok(argv)
+    }
+
+    // Seed of the recorded run's random input, none in manifests from before seeds
+    pub fn seed(&self) -> Option<u64> {
+        self.value["seed"].as_u64()
+    }
+
+    // The recorded samples, to compare the replay against
+    pub fn recorded_run(&self) -> RunData {
+        let run = &self.value["run"];
+        RunData {
+            start: run["start"].as_u64().unwrap_or(0),
+            end: run["end"].as_u64().unwrap_or(0),
+            cpu: Series::from_json("cpu", Unit::Percent, &run["cpu"]),
+            mem: Series::from_json("mem", Unit::Kilobytes, &run["mem"]),
+            seed: self.seed(),
+            ..Default::default()
+        }
+    }
+
+    // Print where the device, app or clock differ from the recorded run
+    pub fn check_environment(
+        &self,
+        device: &DeviceInfo,
+        package: &PackageInfo,
+        clock: &ClockSettings,
+    ) {
+        let recorded = |section: &str, key: &str| {
+            self.value[section][key]
+                .as_str()
+                .unwrap_or_default()
+                .to_string()
+        };
+        let differences = [
+            (
+                "设备指纹",
+                recorded("device", "fingerprint"),
+                &device.fingerprint,
+            ),
+            (
+                "应用版本",
+                recorded("app", "versionCode"),
+                &package.version_code,
+            ),
+            ("自动时间", recorded("clock", "autoTime"), &clock.auto_time),
+            ("时区", recorded("clock", "timezone"), &clock.timezone),
+        ];
+        for (label, before, now) in differences {
+            if before != *now {
+                println!("{}与记录不同: {} -> {}", label, before, now);
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// Series of the sections of the `App Summary` of `dumpsys meminfo <pkg>`, PSS in KB:
+//
+//  App Summary
+//                        Pss(KB)                        Rss(KB)
+//                         ------                         ------
+//            Java Heap:     9108                          27952
+//          Native Heap:    20964                          22552
+//                 Code:    14128                          63732
+//                Stack:     1248                           1256
+//             Graphics:    11044                          11044
+pub const SECTIONS: [(&str, &str); 5] = [
+    ("Java Heap:", "mem_java"),
+    ("Native Heap:", "mem_native"),
+    ("Code:", "mem_code"),
+    ("Graphics:", "mem_graphics"),
+    ("Stack:", "mem_stack"),
+];
+
+// `(series, PSS)` of the sections a dump has
+pub type Sections = Vec<(&'static str, f64)>;
+
+// PSS of every section in `dumpsys meminfo` output, none before Android 6 which has no summary
+pub fn parse_sections(output: &str) -> Sections {
+    SECTIONS
+        .iter()
+        .filter_map(|(label, series)| {
+            let pss = output.lines().find_map(|line| {
+                line.trim()
+                    .strip_prefix(label)?
+                    .split_whitespace()
+                    .next()?
+                    .parse()
+                    .ok()
+            })?;
+            Some((*series, pss))
+        })
+        .collect()
+}
@@ -0,0 +1,349 @@
+use crate::capabilities::{self, Capability};
+use crate::sample::Unit;
+use serde_json::{Value, json};
+
+// A collector of the tool and what it needs from the device
+pub struct MetricInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    // device command it reads
+    pub source: &'static str,
+    // lowest API level it works on
+    pub min_sdk: u32,
+    // data sources the device has to support, see `capabilities`
+    pub requires: &'static [Capability],
+    // milliseconds between samples unless `--interval` is given
+    pub default_interval: u64,
+    // option that turns it on, collected on every run if none
+    pub enabled_by: Option<&'static str>,
+    // series it writes to the reports
+    pub columns: &'static [(&'static str, Unit)],
+}
+
+// Every collector, in report order
+pub static CATALOG: [MetricInfo; 17] = [
+    MetricInfo {
+        name: "cpu",
+        description: "cpu usage of the app's main process, plus its WebView renderers with --webview",
+        source: "top",
+        min_sdk: 24,
+        requires: &[Capability::Top],
+        default_interval: 1000,
+        enabled_by: None,
+        columns: &[("cpu", Unit::Percent)],
+    },
+    MetricInfo {
+        name: "mem",
+        description: "TOTAL PSS of the app's main process, plus its WebView renderers with --webview",
+        source: "dumpsys meminfo",
+        min_sdk: 24,
+        requires: &[Capability::Meminfo],
+        default_interval: 1000,
+        enabled_by: None,
+        columns: &[("mem", Unit::Kilobytes)],
+    },
+    MetricInfo {
+        name: "mem_breakdown",
+        description: "PSS of the app's java heap, native heap, code, graphics and stack, from the App Summary",
+        source: "dumpsys meminfo",
+        min_sdk: 23,
+        requires: &[Capability::Meminfo],
+        default_interval: 1000,
+        enabled_by: Some("--mem-breakdown"),
+        columns: &[
+            ("mem_java", Unit::Kilobytes),
+            ("mem_native", Unit::Kilobytes),
+            ("mem_code", Unit::Kilobytes),
+            ("mem_graphics", Unit::Kilobytes),
+            ("mem_stack", Unit::Kilobytes),
+        ],
+    },
+    MetricInfo {
+        name: "webview",
+        description: "cpu usage and PSS of the WebView renderer processes on their own",
+        source: "top, ps, dumpsys meminfo",
+        min_sdk: 24,
+        requires: &[Capability::Top, Capability::Ps, Capability::Meminfo],
+        default_interval: 1000,
+        enabled_by: Some("--webview breakout"),
+        columns: &[
+            ("webview_cpu", Unit::Percent),
+            ("webview_mem", Unit::Kilobytes),
+        ],
+    },
+    MetricInfo {
+        name: "system",
+        description: "share of all cores the whole device was busy since the sample before, what the app had left over is 100% minus it",
+        source: "/proc/stat",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--system"),
+        columns: &[("system", Unit::Percent)],
+    },
+    MetricInfo {
+        name: "threads",
+        description: "average and peak cpu of the busiest threads of the app's main process, on their own worksheet",
+        source: "top -H",
+        min_sdk: 26,
+        requires: &[Capability::Top],
+        default_interval: 1000,
+        enabled_by: Some("--threads"),
+        columns: &[],
+    },
+    MetricInfo {
+        name: "leaks",
+        description: "open file descriptors and threads of the app's main process, a warning when either only grew over the run (fds need a debuggable build or root)",
+        source: "/proc/<pid>/fd, /proc/<pid>/status",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--leaks"),
+        columns: &[("fd_count", Unit::Count), ("thread_count", Unit::Count)],
+    },
+    MetricInfo {
+        name: "pressure",
+        description: "share of time some task of the device waited for cpu or memory (PSI some avg10)",
+        source: "/proc/pressure",
+        min_sdk: 29,
+        requires: &[Capability::Pressure],
+        default_interval: 1000,
+        enabled_by: Some("--pressure, paired"),
+        columns: &[("psi_cpu", Unit::Percent), ("psi_mem", Unit::Percent)],
+    },
+    MetricInfo {
+        name: "frames",
+        description: "frames the app drew since the sample before, their rate, share of janky frames and 90th/99th percentile frame time",
+        source: "dumpsys gfxinfo framestats",
+        min_sdk: 24,
+        requires: &[Capability::Framestats],
+        default_interval: 1000,
+        enabled_by: Some("--frames"),
+        columns: &[
+            ("frames", Unit::Frames),
+            ("fps", Unit::FramesPerSecond),
+            ("jank", Unit::Percent),
+            ("frame_p90", Unit::Milliseconds),
+            ("frame_p99", Unit::Milliseconds),
+        ],
+    },
+    MetricInfo {
+        name: "battery",
+        description: "battery level, voltage, current and the power they make, plus the mAh batterystats attributes to the app over the run",
+        source: "dumpsys battery, current_now, dumpsys batterystats",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--battery"),
+        columns: &[
+            ("battery_level", Unit::Percent),
+            ("voltage", Unit::Millivolts),
+            ("current", Unit::Milliamps),
+            ("power", Unit::Milliwatts),
+        ],
+    },
+    MetricInfo {
+        name: "thermal",
+        description: "thermal status (0 none to 6 shutdown, throttling above 0) and the hottest cpu, gpu, battery and skin sensor",
+        source: "dumpsys thermalservice, /sys/class/thermal",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--thermal"),
+        columns: &[
+            ("thermal_status", Unit::Level),
+            ("temp_cpu", Unit::Celsius),
+            ("temp_gpu", Unit::Celsius),
+            ("temp_battery", Unit::Celsius),
+            ("temp_skin", Unit::Celsius),
+        ],
+    },
+    MetricInfo {
+        name: "traffic",
+        description: "bytes the app's uid received and sent per second since the sample before, and in total since the run started",
+        source: "/proc/net/xt_qtaguid/stats, dumpsys netstats",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--traffic"),
+        columns: &[
+            ("rx_rate", Unit::KilobytesPerSecond),
+            ("tx_rate", Unit::KilobytesPerSecond),
+            ("rx_total", Unit::Kilobytes),
+            ("tx_total", Unit::Kilobytes),
+        ],
+    },
+    MetricInfo {
+        name: "io",
+        description: "bytes the app's main process read from and wrote to storage per second since the sample before, and in total since the run started (a debuggable build or root)",
+        source: "/proc/<pid>/io",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--io"),
+        columns: &[
+            ("io_read_rate", Unit::KilobytesPerSecond),
+            ("io_write_rate", Unit::KilobytesPerSecond),
+            ("io_read_total", Unit::Kilobytes),
+            ("io_write_total", Unit::Kilobytes),
+        ],
+    },
+    MetricInfo {
+        name: "cores",
+        description: "share of time every core of the device was busy since the sample before, and the frequency it runs at",
+        source: "/proc/stat, cpufreq scaling_cur_freq",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--cores"),
+        columns: &[
+            ("core<N>_usage", Unit::Percent),
+            ("core<N>_freq", Unit::Megahertz),
+        ],
+    },
+    MetricInfo {
+        name: "bluetooth",
+        description: "Bluetooth LE scans the app started since the sample before and the ones it has going on",
+        source: "dumpsys bluetooth_manager",
+        min_sdk: 26,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--bluetooth"),
+        columns: &[("bt_scans", Unit::Count), ("bt_scanning", Unit::Count)],
+    },
+    MetricInfo {
+        name: "sensors",
+        description: "sensor listeners the app has registered and the sensors they listen to",
+        source: "dumpsys sensorservice",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 1000,
+        enabled_by: Some("--sensors"),
+        columns: &[
+            ("sensor_listeners", Unit::Count),
+            ("sensor_registrations", Unit::Count),
+        ],
+    },
+    MetricInfo {
+        name: "health",
+        description: "cpureport's own samples taken and failed attempts so far, tries to reconnect the device, and the 50th, 90th and 99th percentile of how long its adb commands took since the sample before",
+        source: "the sampling itself",
+        min_sdk: 24,
+        requires: &[],
+        default_interval: 5000,
+        enabled_by: Some("--health"),
+        columns: &[
+            ("health_samples", Unit::Count),
+            ("health_failures", Unit::Count),
+            ("health_retries", Unit::Count),
+            ("adb_p50", Unit::Milliseconds),
+            ("adb_p90", Unit::Milliseconds),
+            ("adb_p99", Unit::Milliseconds),
+        ],
+    },
+];
+
+// Collectors with a sampling thread of their own, chosen with `--metrics`
+pub const SELECTABLE: [&str; 14] = [
+    "cpu",
+    "mem",
+    "system",
+    "pressure",
+    "frames",
+    "battery",
+    "thermal",
+    "traffic",
+    "io",
+    "cores",
+    "bluetooth",
+    "sensors",
+    "leaks",
+    "health",
+];
+
+// `--metrics cpu,mem`, every name has to be one of `SELECTABLE`
+pub fn parse_selection(names: &[String]) -> Result<Vec<String>, String> {
+    match names
+        .iter()
+        .find(|name| !SELECTABLE.contains(&name.as_str()))
+    {
+        Some(name) => Err(format!(
+            "Failed to parse metrics: unknown collector {}, expected {}",
+            name,
+            SELECTABLE.join(", ")
+        )),
+        None => Ok(names.to_vec()),
+    }
+}
+
+// The catalog entry of a collector
+pub fn find(name: &str) -> Option<&'static MetricInfo> {
+    CATALOG.iter().find(|metric| metric.name == name)
+}
+
+// Whether `name` is a collector or one of their series, plugins cannot take it
+pub fn is_builtin(name: &str) -> bool {
+    CATALOG.iter().any(|metric| {
+        metric.name == name || metric.columns.iter().any(|(column, _)| *column == name)
+    })
+}
+
+impl MetricInfo {
+    pub fn root(&self) -> bool {
+        self.requires.contains(&Capability::Root)
+    }
+
+    fn columns_text(&self) -> String {
+        self.columns
+            .iter()
+            .map(|(name, unit)| format!("{} ({})", name, unit.label()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub fn to_json(&self) -> Value {
+        let columns: Vec<Value> = self
+            .columns
+            .iter()
+            .map(|(name, unit)| json!({ "name": name, "unit": unit.label() }))
+            .collect();
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "source": self.source,
+            "minSdk": self.min_sdk,
+            "root": self.root(),
+            "requires": self.requires.iter().map(|c| c.label()).collect::<Vec<&str>>(),
+            "defaultIntervalMs": self.default_interval,
+            "enabledBy": self.enabled_by,
+            "columns": columns,
+        })
+    }
+}
+
+// `metrics list`, a table or one JSON array for scripts
+pub fn print_catalog(as_json: bool) {
+    if as_json {
+        let catalog: Vec<Value> = CATALOG.iter().map(MetricInfo::to_json).collect();
+        println!("{}", Value::Array(catalog));
+        return;
+    }
+    println!(
+        "{:<10}{:<28}{:<8}{:<6}{:<24}{:<10}{:<20}COLUMNS",
+        "NAME", "SOURCE", "API", "ROOT", "REQUIRES", "INTERVAL", "ENABLED BY"
+    );
+    for metric in &CATALOG {
+        println!(
+            "{:<10}{:<28}{:<8}{:<6}{:<24}{:<10}{:<20}{}",
+            metric.name,
+            metric.source,
+            format!("{}+", metric.min_sdk),
+            if metric.root() { "yes" } else { "no" },
+            capabilities::labels(metric.requires),
+            format!("{}ms", metric.default_interval),
+            metric.enabled_by.unwrap_or("always"),
+            metric.columns_text()
+        );
+        println!("{:<10}{}", "", metric.description);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::platform::Platform;
+use crate::{adb_shell, scenario, window};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Seconds the window manager gets to settle the new mode before it is checked
+const SETTLE_SECONDS: u64 = 2;
+// Size of the picture-in-picture window on versions that take bounds
+const PIP_BOUNDS: &str = "0 0 540 304";
+
+// Window the app is put into during the run
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum MultiWindowMode {
+    // split-screen, the app on the primary side
+    Split,
+    // picture-in-picture, needs `android:supportsPictureInPicture`
+    Pip,
+}
+
+impl MultiWindowMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            MultiWindowMode::Split => "split-screen",
+            MultiWindowMode::Pip => "picture-in-picture",
+        }
+    }
+
+    // Windowing modes `dumpsys activity activities` shows the task in once it is there
+    fn reported_modes(self) -> &'static [&'static str] {
+        match self {
+            MultiWindowMode::Split => &[
+                "multi-window",
+                "split-screen-primary",
+                "split-screen-secondary",
+            ],
+            MultiWindowMode::Pip => &["pinned"],
+        }
+    }
+}
+
+// When and how long the app is in the mode, seconds after sampling starts
+#[derive(Clone, Debug)]
+pub struct MultiWindow {
+    pub mode: MultiWindowMode,
+    pub after: u64,
+    // back to fullscreen after this many seconds, at the end of the run if none
+    pub hold: Option<u64>,
+}
+
+// The command moving the app's task into `mode`, by version: `am stack` up to API 28 (fixed
+// stack ids, 3 is docked), `am start --windowingMode` from 29. Picture-in-picture cannot be
+// forced from API 29, leaving the app with HOME lets it enter it on its own (auto-enter or
+// `onUserLeaveHint`). None if the version has no such mode for phones.
+pub fn enter_command(
+    platform: &Platform,
+    mode: MultiWindowMode,
+    task: &str,
+    component: &str,
+) -> Option<String> {
+    let sdk = match platform.sdk {
+        0 => platform.max_sdk,
+        sdk => sdk,
+    };
+    match mode {
+        MultiWindowMode::Split if sdk <= 28 => Some(format!("am stack move-task {} 3 true", task)),
+        // split-screen-primary became the generic multi-window mode in API 31
+        MultiWindowMode::Split if sdk <= 30 => {
+            Some(format!("am start --windowingMode 3 -n {}", component))
+        }
+        MultiWindowMode::Split => Some(format!("am start --windowingMode 6 -n {}", component)),
+        MultiWindowMode::Pip if sdk <= 25 => None,
+        MultiWindowMode::Pip if sdk <= 28 => Some(format!(
+            "am stack move-top-activity-to-pinned-stack 1 {}",
+            PIP_BOUNDS
+        )),
+        MultiWindowMode::Pip => Some("input keyevent KEYCODE_HOME".to_string()),
+    }
+}
+
+// The command putting the app's task back to fullscreen
+pub fn exit_command(platform: &Platform, task: &str, component: &str) -> String {
+    match platform.sdk {
+        1..=28 => format!("am stack move-task {} 1 true", task),
+        _ => format!("am start --windowingMode 1 -n {}", component),
+    }
+}
+
+// Put the app into `mode`, `Err` if the version cannot or the window manager did not
+pub fn enter(
+    device_cmd: &str,
+    pkg: &str,
+    platform: &Platform,
+    mode: MultiWindowMode,
+) -> Result<(), String> {
+    let task = window::task(device_cmd, pkg)
+        .ok_or_else(|| format!("Failed to find the task of {}", pkg))?;
+    let component = scenario::launcher_component(device_cmd, pkg).unwrap_or_default();
+    let command = enter_command(platform, mode, &task.id, &component)
+        .ok_or_else(|| format!("{} is not supported on API {}", mode.label(), platform.sdk))?;
+    adb_shell(device_cmd, &command);
+    thread::sleep(Duration::from_secs(SETTLE_SECONDS));
+    match window::windowing_mode(device_cmd, pkg) {
+        Some(reported) if mode.reported_modes().contains(&reported.as_str()) => Ok(()),
+        reported => Err(format!(
+            "Failed to enter {}: the task is {}",
+            mode.label(),
+            reported.unwrap_or_else(|| "gone".to_string())
+        )),
+    }
+}
+
+pub fn exit(device_cmd: &str, pkg: &str, platform: &Platform) {
+    let Some(task) = window::task(device_cmd, pkg) else {
+        return;
+    };
+    let component = scenario::launcher_component(device_cmd, pkg).unwrap_or_default();
+    adb_shell(device_cmd, &exit_command(platform, &task.id, &component));
+}
+
+// Enter the mode `after` seconds into the run and leave it after `hold` or once the run is over,
+// both recorded as markers so the steps of the run can be told apart
+pub fn schedule(
+    device_cmd: &str,
+    pkg: &str,
+    platform: &Platform,
+    multi_window: &MultiWindow,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let (device_cmd, pkg) = (device_cmd.to_string(), pkg.to_string());
+    let (platform, multi_window) = (platform.clone(), multi_window.clone());
+    thread::spawn(move || {
+        let running = || now_millis() / 1000 < end_time && !stop.load(Ordering::Relaxed);
+        let wait_until = |time: u64| {
+            while now_millis() < time && running() {
+                thread::sleep(Duration::from_millis(200));
+            }
+        };
+        wait_until(events.start() + multi_window.after * 1000);
+        if !running() {
+            return;
+        }
+        let label = multi_window.mode.label();
+        println!("进入 {}", label);
+        if let Err(e) = enter(&device_cmd, &pkg, &platform, multi_window.mode) {
+            println!("{}", e);
+            return;
+        }
+        events.record(EventKind::Marker, label);
+        if let Some(hold) = multi_window.hold {
+            wait_until(now_millis() + hold * 1000);
+            exit(&device_cmd, &pkg, &platform);
+            if running() {
+                events.record(EventKind::Marker, "fullscreen");
+            }
+            return;
+        }
+        wait_until(u64::MAX);
+        exit(&device_cmd, &pkg, &platform);
+    })
+}
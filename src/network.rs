@@ -0,0 +1,167 @@
+use crate::adb_shell;
+use crate::collector::parse_interval;
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::platform::Platform;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// A radio a run can switch during sampling
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Radio {
+    Airplane,
+    Wifi,
+    Data,
+}
+
+impl Radio {
+    pub fn label(self) -> &'static str {
+        match self {
+            Radio::Airplane => "airplane",
+            Radio::Wifi => "wifi",
+            Radio::Data => "data",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Radio> {
+        match name {
+            "airplane" => Some(Radio::Airplane),
+            "wifi" => Some(Radio::Wifi),
+            "data" => Some(Radio::Data),
+            _ => None,
+        }
+    }
+
+    // The global setting the state is read from, `wifi_on` is 2 while wifi stays on in airplane
+    // mode
+    fn setting(self) -> &'static str {
+        match self {
+            Radio::Airplane => "airplane_mode_on",
+            Radio::Wifi => "wifi_on",
+            Radio::Data => "mobile_data",
+        }
+    }
+
+    // The command switching it, airplane mode has a shell command from API 30, before the
+    // setting is changed and the change broadcast
+    pub fn command(self, on: bool, platform: &Platform) -> String {
+        let enable = if on { "enable" } else { "disable" };
+        let sdk = match platform.sdk {
+            0 => platform.max_sdk,
+            sdk => sdk,
+        };
+        match self {
+            Radio::Airplane if sdk >= 30 => {
+                format!("cmd connectivity airplane-mode {}", enable)
+            }
+            Radio::Airplane => format!(
+                "settings put global airplane_mode_on {}; am broadcast -a android.intent.action.AIRPLANE_MODE --ez state {}",
+                on as u8, on
+            ),
+            Radio::Wifi => format!("svc wifi {}", enable),
+            Radio::Data => format!("svc data {}", enable),
+        }
+    }
+}
+
+// `--network wifi:off@10s`: switch a radio on or off that long after sampling starts
+#[derive(Clone, Debug)]
+pub struct Toggle {
+    pub radio: Radio,
+    pub on: bool,
+    // milliseconds after the start of the run
+    pub at: u64,
+}
+
+impl Toggle {
+    // `RADIO:on|off@TIME`, the time in seconds (`@10`) or with a unit (`@500ms`, `@30s`, `@2m`)
+    pub fn parse(text: &str) -> Result<Toggle, String> {
+        let fail = || {
+            format!(
+                "Failed to parse network toggle {}, expected airplane|wifi|data:on|off@seconds",
+                text
+            )
+        };
+        let (radio, rest) = text.split_once(':').ok_or_else(fail)?;
+        let (state, at) = rest.split_once('@').ok_or_else(fail)?;
+        let radio = Radio::parse(radio).ok_or_else(fail)?;
+        let on = match state {
+            "on" => true,
+            "off" => false,
+            _ => return Err(fail()),
+        };
+        let at = match at.parse::<u64>() {
+            Ok(seconds) => seconds * 1000,
+            Err(_) => parse_interval(at).map_err(|_| fail())?,
+        };
+        Ok(Toggle { radio, on, at })
+    }
+
+    pub fn text(&self) -> String {
+        format!(
+            "{} {}",
+            self.radio.label(),
+            if self.on { "on" } else { "off" }
+        )
+    }
+}
+
+// Whether the radio is on right now, none if the device does not say
+pub fn state(device_cmd: &str, radio: Radio) -> Option<bool> {
+    let output = adb_shell(
+        device_cmd,
+        &format!("settings get global {}", radio.setting()),
+    );
+    output.trim().parse::<u32>().ok().map(|value| value != 0)
+}
+
+fn switch(device_cmd: &str, radio: Radio, on: bool, platform: &Platform) {
+    adb_shell(device_cmd, &radio.command(on, platform));
+}
+
+// Switch the radios at their times, each switch recorded as a `network` event. Once the run is
+// over every radio that was switched is put back the way it was before, airplane mode first so
+// wifi and data come back on top of it.
+pub fn schedule(
+    device_cmd: &str,
+    platform: &Platform,
+    toggles: &[Toggle],
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let (device_cmd, platform) = (device_cmd.to_string(), platform.clone());
+    let mut toggles = toggles.to_vec();
+    toggles.sort_by_key(|toggle| toggle.at);
+    thread::spawn(move || {
+        let running = || now_millis() / 1000 < end_time && !stop.load(Ordering::Relaxed);
+        let mut before: Vec<(Radio, Option<bool>)> = Vec::new();
+        for radio in [Radio::Airplane, Radio::Wifi, Radio::Data] {
+            if toggles.iter().any(|toggle| toggle.radio == radio) {
+                before.push((radio, state(&device_cmd, radio)));
+            }
+        }
+
+        for toggle in &toggles {
+            while now_millis() < events.start() + toggle.at && running() {
+                thread::sleep(Duration::from_millis(200));
+            }
+            if !running() {
+                break;
+            }
+            println!("网络: {}", toggle.text());
+            switch(&device_cmd, toggle.radio, toggle.on, &platform);
+            events.record(EventKind::Network, toggle.text());
+        }
+        while running() {
+            thread::sleep(Duration::from_millis(200));
+        }
+
+        for (radio, on) in before {
+            if let Some(on) = on {
+                switch(&device_cmd, radio, on, &platform);
+            }
+        }
+    })
+}
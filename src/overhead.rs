@@ -0,0 +1,178 @@
+use crate::events::now_millis;
+use crate::{adb_shell, cache, now};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Clock ticks per second of `/proc/<pid>/stat`, `USER_HZ` is 100 on every Android kernel
+const CLOCK_TICKS: f64 = 100.0;
+// Seconds the overhead is measured over before the intervals are adjusted
+const WINDOW: u64 = 5;
+// Intervals are stretched to at most this many times, optional collectors are shed there
+pub const MAX_SCALE: u64 = 8;
+
+// `--overhead-budget`: `2%` or `2`, percent of the device's total cpu
+pub fn parse_budget(text: &str) -> Result<f64, String> {
+    match text.trim_end_matches('%').parse::<f64>() {
+        Ok(budget) if budget > 0.0 => Ok(budget),
+        _ => Err(format!("Failed to parse overhead budget {}", text)),
+    }
+}
+
+// Cpu time of adbd and of the shell commands it ran and reaped, in clock ticks. Every command
+// of the tool is such a child, so this is the cost of sampling on the device (the work
+// `dumpsys` has system_server do is not in it).
+fn adbd_ticks(device_cmd: &str) -> Option<u64> {
+    let pid = adb_shell(device_cmd, "pidof adbd");
+    let pid = pid.split_whitespace().next()?;
+    let stat = adb_shell(device_cmd, &format!("cat /proc/{}/stat", pid));
+    // `pid (comm) state ...`, utime, stime, cutime and cstime are fields 14 to 17
+    let (_, fields) = stat.rsplit_once(')')?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+    fields
+        .get(11..15)?
+        .iter()
+        .map(|field| field.parse::<u64>().ok())
+        .sum()
+}
+
+// Shared by the collectors of a run, stretches their intervals while sampling costs too much
+#[derive(Clone)]
+pub struct Throttle {
+    scale: Arc<AtomicU64>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle {
+            scale: Arc::new(AtomicU64::new(1)),
+        }
+    }
+}
+
+impl Throttle {
+    pub fn scale(&self) -> u64 {
+        self.scale.load(Ordering::Relaxed)
+    }
+
+    pub fn interval(&self, interval: Duration) -> Duration {
+        interval * self.scale() as u32
+    }
+
+    // Whether optional collectors should pause, stretching the intervals was not enough
+    pub fn shedding(&self) -> bool {
+        self.scale() >= MAX_SCALE
+    }
+}
+
+// What sampling cost on the device
+#[derive(Clone, Debug)]
+pub struct Overhead {
+    // percent of the device's total cpu
+    pub budget: f64,
+    pub average: f64,
+    // the most expensive measuring window
+    pub peak: f64,
+    // largest interval stretch that was needed
+    pub max_scale: u64,
+}
+
+impl Overhead {
+    pub fn within_budget(&self) -> bool {
+        self.average <= self.budget
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "budget_percent": self.budget,
+            "average_percent": self.average,
+            "peak_percent": self.peak,
+            "max_interval_scale": self.max_scale,
+            "within_budget": self.within_budget(),
+        })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "采样开销: 平均 {:.2}%, 峰值 {:.2}% (预算 {}%{}), 采样间隔最多放大 {} 倍",
+            self.average,
+            self.peak,
+            self.budget,
+            if self.within_budget() { "" } else { ", 超出" },
+            self.max_scale
+        );
+    }
+}
+
+// Measure the cost of sampling every `WINDOW` seconds until the run ends, doubling the
+// intervals while it is above `budget` and halving them again once it is well below. None if
+// adbd's cpu time cannot be read.
+pub fn govern(
+    device_cmd: &str,
+    budget: f64,
+    throttle: Throttle,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<Option<Overhead>> {
+    let device_cmd = device_cmd.to_string();
+    thread::spawn(move || {
+        let cores: f64 = cache::cached(&device_cmd, "nproc", || {
+            adb_shell(&device_cmd, "nproc").trim().to_string()
+        })
+        .parse()
+        .unwrap_or(1.0);
+        let Some(first_ticks) = adbd_ticks(&device_cmd) else {
+            println!("无法读取 adbd 的 cpu 时间, 不限制采样开销");
+            return None;
+        };
+        let percent = |ticks: u64, millis: u64| {
+            ticks as f64 / CLOCK_TICKS / (millis.max(1) as f64 / 1000.0 * cores) * 100.0
+        };
+        let first_time = now_millis();
+        let (mut last_ticks, mut last_time) = (first_ticks, first_time);
+        let (mut peak, mut max_scale) = (0.0_f64, 1);
+        let running = || now() < end_time && !stop.load(Ordering::Relaxed);
+        while running() {
+            thread::sleep(Duration::from_millis(200));
+            if now_millis() < last_time + WINDOW * 1000 {
+                continue;
+            }
+            let Some(ticks) = adbd_ticks(&device_cmd) else {
+                continue;
+            };
+            let time = now_millis();
+            let overhead = percent(ticks.saturating_sub(last_ticks), time - last_time);
+            peak = peak.max(overhead);
+            let scale = throttle.scale();
+            if overhead > budget && scale < MAX_SCALE {
+                throttle.scale.store(scale * 2, Ordering::Relaxed);
+                println!(
+                    "采样开销 {:.2}% 超过预算 {}%, 采样间隔放大到 {} 倍",
+                    overhead,
+                    budget,
+                    scale * 2
+                );
+            } else if overhead < budget / 4.0 && scale > 1 {
+                throttle.scale.store(scale / 2, Ordering::Relaxed);
+            }
+            max_scale = max_scale.max(throttle.scale());
+            (last_ticks, last_time) = (ticks, time);
+        }
+        // The part of the run after the last full window
+        if let Some(ticks) = adbd_ticks(&device_cmd) {
+            (last_ticks, last_time) = (ticks, now_millis());
+        }
+        let average = percent(
+            last_ticks.saturating_sub(first_ticks),
+            last_time - first_time,
+        );
+        Some(Overhead {
+            budget,
+            average,
+            peak: peak.max(average),
+            max_scale,
+        })
+    })
+}
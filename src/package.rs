@@ -0,0 +1,129 @@
+use crate::adb_shell;
+use crate::platform::Platform;
+use crate::webview;
+
+// Whether `pkg` can be an application id, letters, digits and `_` in parts separated by dots.
+// Anything else is refused before it is pasted into a command on the device.
+pub fn valid_name(pkg: &str) -> bool {
+    !pkg.is_empty()
+        && pkg.split('.').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+// What `dumpsys package` knows about the app under test
+#[derive(Clone, Debug, Default)]
+pub struct PackageInfo {
+    pub uid: Option<u32>,
+    pub instant: bool,
+    pub version_name: String,
+    pub version_code: String,
+    // installed apks: `base`, config splits and dynamic feature splits
+    pub splits: Vec<String>,
+    // processes currently running under the app's uid, feature splits may declare their own
+    pub processes: Vec<String>,
+}
+
+impl PackageInfo {
+    pub fn resolve(device_cmd: &str, pkg: &str) -> PackageInfo {
+        let dump = adb_shell(device_cmd, &format!("dumpsys package {}", pkg));
+        let mut info = PackageInfo::default();
+
+        // Only the first "Packages:" entry is the installed package, later ones are hidden system copies
+        for line in dump.lines().map(str::trim) {
+            if let Some(uid) = line.strip_prefix("userId=") {
+                info.uid = info.uid.or(uid.parse().ok());
+            } else if let Some(version) = line.strip_prefix("versionName=") {
+                if info.version_name.is_empty() {
+                    info.version_name = version.to_string();
+                }
+            } else if let Some(rest) = line.strip_prefix("versionCode=") {
+                if info.version_code.is_empty() {
+                    info.version_code = rest.split_whitespace().next().unwrap_or("").to_string();
+                }
+            } else if let Some(splits) = line.strip_prefix("splits=[") {
+                if info.splits.is_empty() {
+                    info.splits = splits
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(|split| split.trim().to_string())
+                        .filter(|split| !split.is_empty())
+                        .collect();
+                }
+            } else if line.starts_with("User 0:") && line.contains("instant=true") {
+                info.instant = true;
+            }
+        }
+
+        if let Some(uid) = info.uid {
+            info.processes = processes_of_uid(device_cmd, uid, &Platform::detect(device_cmd, &[]));
+        }
+        info
+    }
+
+    pub fn print(&self) {
+        if let Some(uid) = self.uid {
+            println!("应用uid: {}", uid);
+        }
+        if !self.version_name.is_empty() {
+            println!("应用版本: {} ({})", self.version_name, self.version_code);
+        }
+        if self.instant {
+            println!("免安装应用 (instant app)");
+        }
+        if !self.splits.is_empty() {
+            println!("安装的splits: {}", self.splits.join(", "));
+        }
+        if !self.processes.is_empty() {
+            println!("应用进程: {}", self.processes.join(", "));
+        }
+    }
+}
+
+// Names of the processes running with `uid`, including ones not named after the package
+pub fn processes_of_uid(device_cmd: &str, uid: u32, platform: &Platform) -> Vec<String> {
+    platform
+        .processes(device_cmd)
+        .into_iter()
+        .filter(|process| process.uid == Some(uid))
+        .map(|process| process.name)
+        .collect()
+}
+
+// Whether `process` is one of the app's: its main process or another one it declares, like
+// `com.example.app:remote`. Other packages named after it (`com.example.app.helper`) are not.
+pub fn is_app_process(process: &str, pkg: &str) -> bool {
+    process
+        .strip_prefix(pkg)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+// The lines of all the app's processes among the `top` lines naming it, WebView renderers left out,
+// or the main process line alone if none is named after the app (a `top_command` quirk with
+// another column last)
+pub fn app_process_lines<'a>(pkg: &str, lines: &[&'a str]) -> Vec<&'a str> {
+    let app_lines: Vec<&str> = lines
+        .iter()
+        .filter(|line| {
+            line.split_whitespace()
+                .last()
+                .is_some_and(|name| is_app_process(name, pkg) && !webview::is_renderer(name))
+        })
+        .copied()
+        .collect();
+    if app_lines.is_empty() {
+        main_process_line(pkg, lines).into_iter().collect()
+    } else {
+        app_lines
+    }
+}
+
+// The line of the app's main process among the `top` lines naming it, which also match
+// `pkg:feature` processes of splits that may be listed first
+pub fn main_process_line<'a>(pkg: &str, lines: &[&'a str]) -> Option<&'a str> {
+    lines
+        .iter()
+        .find(|line| line.split_whitespace().last() == Some(pkg))
+        .or(lines.first())
+        .copied()
+}
@@ -0,0 +1,67 @@
+use crate::sample::Sample;
+use serde_json::json;
+use std::io::Write;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+// Streams every sample as a JSON line into the stdin of a user command
+#[derive(Clone)]
+pub struct SamplePipe {
+    inner: Arc<Mutex<PipeState>>,
+}
+
+struct PipeState {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl SamplePipe {
+    pub fn spawn(command: &str) -> Result<SamplePipe, String> {
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut win_cmd = Command::new("cmd");
+            win_cmd.arg("/C");
+            win_cmd
+        } else {
+            let mut sh_cmd = Command::new("sh");
+            sh_cmd.arg("-c");
+            sh_cmd
+        };
+        let mut child = cmd
+            .arg(command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to execute {}: {}", command, e))?;
+        let stdin = child.stdin.take();
+
+        Ok(SamplePipe {
+            inner: Arc::new(Mutex::new(PipeState { child, stdin })),
+        })
+    }
+
+    pub fn send(&self, sample: &Sample) {
+        let line = json!({
+            "time": sample.ts,
+            "metric": sample.metric,
+            "value": sample.value,
+            "unit": sample.unit.label(),
+            "quality": sample.quality.label(),
+        });
+        let mut state = self.inner.lock().unwrap();
+        if let Some(stdin) = state.stdin.as_mut()
+            && writeln!(stdin, "{}", line)
+                .and_then(|_| stdin.flush())
+                .is_err()
+        {
+            // The consumer went away, keep sampling without it
+            println!("pipe-to command exited, no longer streaming samples");
+            state.stdin = None;
+        }
+    }
+
+    // Close stdin so the command sees EOF, then wait for it to finish
+    pub fn close(&self) {
+        let mut state = self.inner.lock().unwrap();
+        state.stdin = None;
+        let _ = state.child.wait();
+    }
+}
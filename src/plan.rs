@@ -0,0 +1,492 @@
+use crate::compress::Compression;
+use crate::platform::Platform;
+use crate::report::ReportFormat;
+use crate::screen::ScreenMode;
+use crate::transport::{self, quote};
+use crate::webview::WebviewMode;
+use crate::{CollectOptions, metrics};
+use crate::{
+    appops, battery, bluetooth, camera, cores, diskio, leaks, logcat, multiwindow, pressure,
+    scenario, sensors, sync, system, thermal, threads, traffic,
+};
+
+// Rough sizes of the outputs, for the estimate only
+const WORKBOOK_BYTES: u64 = 6 * 1024;
+const WORKBOOK_SAMPLE_BYTES: u64 = 12;
+const CSV_SAMPLE_BYTES: u64 = 50;
+const CSV_LONG_SAMPLE_BYTES: u64 = 70;
+// compressed csv of samples is about this many times smaller
+const CSV_COMPRESSION_RATIO: u64 = 6;
+const JSON_BYTES: u64 = 3 * 1024;
+const JSON_SAMPLE_BYTES: u64 = 90;
+const MANIFEST_BYTES: u64 = 2 * 1024;
+const MANIFEST_SAMPLE_BYTES: u64 = 70;
+const HTML_BYTES: u64 = 8 * 1024;
+const HTML_SAMPLE_BYTES: u64 = 16;
+const THUMBNAIL_BYTES: u64 = 6 * 1024;
+const BENCHMARK_BYTES: u64 = 2 * 1024;
+const BENCHMARK_SAMPLE_BYTES: u64 = 10;
+// screenrecord at its default bit rate, with the curves composited under it
+const VIDEO_BYTES_PER_SECOND: u64 = 500 * 1024;
+
+// A series the run is going to record
+pub struct PlannedMetric {
+    pub name: String,
+    // milliseconds between samples
+    pub interval: u64,
+    // per run, the first sample is dropped as an outlier
+    pub samples: u64,
+}
+
+// What a run is going to do, printed by `--plan` instead of running it
+pub struct Plan {
+    pub mode: String,
+    // sampled runs, e.g. baseline and measurement of `impact`
+    pub runs: u64,
+    // seconds each run samples
+    pub duration: u64,
+    pub metrics: Vec<PlannedMetric>,
+    // `--adaptive` interval after spikes, the sample counts are the least there will be
+    pub fast_interval: Option<u64>,
+    // screenshot thumbnails per run
+    pub screenshots: u64,
+    pub formats: Vec<ReportFormat>,
+    // `--compress` of the csv formats
+    pub compression: Option<Compression>,
+    pub video: bool,
+    // adb and host commands in the order they run, the ones repeated per sample marked so
+    pub commands: Vec<String>,
+}
+
+impl Plan {
+    // The sampling part of the plan, the caller adds the commands of its scenario
+    pub fn new(
+        mode: &str,
+        pkg: &str,
+        device_cmd: &str,
+        options: &CollectOptions,
+        runs: u64,
+    ) -> Plan {
+        // The device is not asked, the commands are those of the newest platform
+        let platform = Platform::for_sdk(0);
+        let samples = |interval: u64| (options.duration * 1000 / interval.max(1)).saturating_sub(1);
+
+        let mut names: Vec<String> = ["cpu", "mem"]
+            .into_iter()
+            .filter(|name| options.samples(name))
+            .map(str::to_string)
+            .collect();
+        if options.mem_breakdown && options.samples("mem") {
+            names.extend(
+                metrics::find("mem_breakdown")
+                    .map_or(&[][..], |metric| metric.columns)
+                    .iter()
+                    .map(|(column, _)| column.to_string()),
+            );
+        }
+        if options.webview == Some(WebviewMode::Breakout) {
+            names.extend(
+                metrics::find("webview")
+                    .map_or(&[][..], |metric| metric.columns)
+                    .iter()
+                    .map(|(column, _)| column.to_string()),
+            );
+        }
+        for metric in [
+            "system",
+            "pressure",
+            "frames",
+            "battery",
+            "thermal",
+            "traffic",
+            "io",
+            "cores",
+            "bluetooth",
+            "sensors",
+            "leaks",
+            "health",
+        ] {
+            if options.samples(metric) {
+                names.extend(
+                    metrics::find(metric)
+                        .map_or(&[][..], |metric| metric.columns)
+                        .iter()
+                        .map(|(column, _)| column.to_string()),
+                );
+            }
+        }
+        let mut planned: Vec<PlannedMetric> = names
+            .into_iter()
+            .map(|name| {
+                let interval = options.interval_of(&name);
+                PlannedMetric {
+                    name,
+                    interval,
+                    samples: samples(interval),
+                }
+            })
+            .collect();
+        planned.extend(options.exec.iter().map(|metric| {
+            let interval = metric.interval.unwrap_or(options.interval);
+            PlannedMetric {
+                name: metric.name.clone(),
+                interval,
+                samples: samples(interval),
+            }
+        }));
+
+        let adb = |command: String| format!("adb {} {}", device_cmd, command);
+        let mut commands = vec![
+            adb("shell getprop ro.build.version.sdk".to_string()),
+            adb("shell getprop ro.product.manufacturer".to_string()),
+            adb("shell dumpsys -l".to_string()),
+        ];
+        if options.samples("cpu") {
+            commands.push(adb(format!(
+                "shell {}    (每个样本)",
+                platform.top_command()
+            )));
+        }
+        if options.samples("mem") {
+            commands.push(adb(format!("shell dumpsys meminfo {}    (每个样本)", pkg)));
+            if !options.main_process_only {
+                commands.push(adb(
+                    "shell ps -A -o PID,UID,NAME    (每个样本, 应用的其他进程)".to_string(),
+                ));
+                commands.push(adb(
+                    "shell dumpsys meminfo <pid>    (每个样本, 每个其他进程)".to_string(),
+                ));
+            }
+        }
+        if options.threads.is_some() {
+            commands.push(adb(format!(
+                "shell {}    (每个样本)",
+                quote(&threads::command(pkg))
+            )));
+        }
+        if options.samples("system") {
+            commands.push(adb(format!("shell {}    (每个样本)", system::COMMAND)));
+        }
+        if options.samples("leaks") {
+            commands.push(adb(format!(
+                "shell {}    (每个样本)",
+                quote(&leaks::command(pkg))
+            )));
+        }
+        if options.samples("pressure") {
+            commands.push(adb(format!("shell {}    (每个样本)", pressure::COMMAND)));
+        }
+        if options.samples("battery") {
+            commands.push(adb(
+                "shell dumpsys batterystats --reset    (开始时)".to_string()
+            ));
+            commands.push(adb(format!(
+                "shell dumpsys battery; cat {}    (每个样本)",
+                battery::CURRENT_NOW
+            )));
+            commands.push(adb("shell dumpsys batterystats    (结束时)".to_string()));
+        }
+        if options.samples("thermal") {
+            commands.push(adb(format!(
+                "shell {}    (每个样本, 没有 thermalservice 时 {})",
+                thermal::COMMAND,
+                quote(thermal::SYSFS_COMMAND)
+            )));
+        }
+        if options.samples("cores") {
+            commands.push(adb(format!(
+                "shell {}    (每个样本)",
+                quote(cores::COMMAND)
+            )));
+        }
+        if options.samples("bluetooth") {
+            commands.push(adb(format!("shell {}    (每个样本)", bluetooth::COMMAND)));
+        }
+        if options.samples("sensors") {
+            commands.push(adb(format!("shell {}    (每个样本)", sensors::COMMAND)));
+        }
+        if options.samples("traffic") {
+            commands.push(adb(format!(
+                "shell cat {}    (每个样本, 没有时 {})",
+                traffic::QTAGUID,
+                quote(traffic::NETSTATS_COMMAND)
+            )));
+        }
+        if options.samples("io") {
+            commands.push(adb(format!(
+                "shell {}    (每个样本)",
+                quote(&diskio::command(pkg))
+            )));
+        }
+        for metric in &options.exec {
+            commands.push(format!("{}    (本机, 每个样本)", metric.command));
+        }
+        for plugin in &options.wasm {
+            commands.push(adb(format!(
+                "shell {}    (每个样本, wasm 插件 {})",
+                quote(&plugin.command(pkg)),
+                plugin.name
+            )));
+        }
+        if options.samples("frames") {
+            commands.push(adb(format!(
+                "shell {}    (每个样本)",
+                platform
+                    .gfxinfo_command(pkg)
+                    .unwrap_or_else(|| format!("dumpsys gfxinfo {}", pkg))
+            )));
+        }
+        if options.webview.is_some() {
+            commands.push(adb("shell ps -A -o PID,UID,NAME    (每个样本)".to_string()));
+            commands.push(adb(
+                "shell dumpsys meminfo <renderer pid>    (每个样本, 每个 renderer)".to_string(),
+            ));
+        }
+        if options.window_events {
+            commands.push(adb("shell dumpsys window    (每秒)".to_string()));
+            commands.push(adb(
+                "shell dumpsys activity activities    (每秒)".to_string()
+            ));
+        }
+        if options.cpuset {
+            commands.push(adb(format!("shell pidof {}    (每秒)", pkg)));
+            commands.push(adb("shell cat /proc/<pid>/cpuset    (每秒)".to_string()));
+        }
+        if options.appops {
+            commands.push(adb(format!("shell {}    (每 2 秒)", appops::command(pkg))));
+        }
+        if options.logcat {
+            commands.push(adb(format!(
+                "{}    (持续读取)",
+                transport::join(&logcat::ARGS)
+            )));
+        }
+        if options.camera {
+            commands.push(adb(format!("shell {}    (每 2 秒)", camera::COMMAND)));
+        }
+        if options.sync_marker {
+            let marker = "cpureport-sync <start|end> run=<run> host=<ms>";
+            commands.push(adb(format!(
+                "shell {}    (开始和结束时)",
+                quote(&sync::command(marker))
+            )));
+        }
+        if options.screen_events {
+            commands.push(adb("shell dumpsys power    (每秒)".to_string()));
+        }
+        if let Some(mode) = options.screen {
+            let (stay_on, key) = match mode {
+                ScreenMode::On => ("true", "KEYCODE_WAKEUP"),
+                ScreenMode::Off => ("false", "KEYCODE_SLEEP"),
+            };
+            commands.push(adb(format!(
+                "shell svc power stayon {}; input keyevent {}    (开始时)",
+                stay_on, key
+            )));
+            commands.push(adb(
+                "shell settings put global stay_on_while_plugged_in <原值>    (结束时)".to_string(),
+            ));
+        }
+        if let Some(events) = options.monkey {
+            let seed = options.seed.unwrap_or_default();
+            commands.push(adb(format!(
+                "shell {}    (开始时, 后台)",
+                scenario::monkey_command(pkg, seed, events)
+            )));
+        }
+        if let Some(multi_window) = &options.multi_window {
+            let enter = multiwindow::enter_command(
+                &platform,
+                multi_window.mode,
+                "<task>",
+                &format!("{}/<activity>", pkg),
+            )
+            .unwrap_or_else(|| format!("<{} 不支持>", multi_window.mode.label()));
+            commands.push(adb(format!(
+                "shell {}    (第 {} 秒)",
+                enter, multi_window.after
+            )));
+            commands.push(adb(format!(
+                "shell {}    ({})",
+                multiwindow::exit_command(&platform, "<task>", &format!("{}/<activity>", pkg)),
+                multi_window
+                    .hold
+                    .map_or("结束时".to_string(), |hold| format!("{} 秒后", hold))
+            )));
+        }
+        if let Some(shaping) = &options.shaping {
+            let (apply, restore) = shaping.commands();
+            let line = |args: Vec<String>| {
+                transport::join(&args.iter().map(String::as_str).collect::<Vec<_>>())
+            };
+            commands.extend(
+                apply
+                    .into_iter()
+                    .map(|args| adb(format!("{}    (开始时)", line(args)))),
+            );
+            commands.extend(
+                restore
+                    .into_iter()
+                    .map(|args| adb(format!("{}    (结束时)", line(args)))),
+            );
+        }
+        for toggle in &options.network {
+            commands.push(adb(format!(
+                "shell {}    (第 {} 秒)",
+                toggle.radio.command(toggle.on, &platform),
+                toggle.at as f64 / 1000.0
+            )));
+        }
+        if !options.network.is_empty() {
+            commands.push(adb(
+                "shell settings get global airplane_mode_on|wifi_on|mobile_data    (开始时, 结束时恢复)"
+                    .to_string(),
+            ));
+        }
+        if let Some(every) = options.doze {
+            commands.push(adb(
+                "shell dumpsys battery unplug; dumpsys deviceidle force-idle    (开始时)"
+                    .to_string(),
+            ));
+            commands.push(adb(format!(
+                "shell dumpsys deviceidle step    (每 {} 秒)",
+                every
+            )));
+            commands.push(adb(format!(
+                "shell {}    (维护窗口开始和结束时)",
+                traffic::NETSTATS_COMMAND
+            )
+            .to_string()));
+            commands.push(adb(
+                "shell dumpsys deviceidle unforce; dumpsys battery reset    (结束时)".to_string(),
+            ));
+        }
+        if let Some(every) = options.snapshot_every {
+            commands.push(adb(format!(
+                "shell dumpsys meminfo {}; top; cat /proc/<pid>/status /proc/<pid>/smaps_rollup    (每 {} 秒)",
+                pkg, every
+            )));
+        }
+        if let Some(critical) = options.critical_battery {
+            commands.push(adb(format!(
+                "shell dumpsys battery    (每 10 秒, 低于 {}% 时结束)",
+                critical
+            )));
+        }
+        if options.overhead_budget.is_some() {
+            commands.push(adb(
+                "shell pidof adbd; cat /proc/<adbd pid>/stat    (每 5 秒)".to_string(),
+            ));
+        }
+        if let Some(every) = options.screenshot_every {
+            commands.push(adb(format!("exec-out screencap -p    (每 {} 秒)", every)));
+        }
+        let shell = match (options.batch, options.shell_session) {
+            (true, false) => Some("每个样本, 上面每个样本的命令从 stdin 一起执行"),
+            (true, true) => Some("常驻会话, 上面每个样本的命令从 stdin 一起执行"),
+            (false, true) => Some("常驻会话, 上面每个样本的命令从 stdin 依次执行"),
+            (false, false) => None,
+        };
+        if let Some(shell) = shell {
+            commands.push(adb(format!("shell sh    ({})", shell)));
+        }
+        Plan {
+            mode: mode.to_string(),
+            runs,
+            duration: options.duration,
+            metrics: planned,
+            fast_interval: options
+                .adaptive
+                .as_ref()
+                .map(|adaptive| adaptive.fast_interval),
+            screenshots: options
+                .screenshot_every
+                .map_or(0, |every| options.duration / every.max(1)),
+            formats: Vec::new(),
+            compression: None,
+            video: false,
+            commands,
+        }
+    }
+
+    // Bytes the reports of all runs take, roughly
+    pub fn estimated_bytes(&self) -> u64 {
+        let samples: u64 = self.metrics.iter().map(|metric| metric.samples).sum();
+        let series = self.metrics.len() as u64;
+        let mut bytes = MANIFEST_BYTES + samples * MANIFEST_SAMPLE_BYTES;
+        let csv_ratio = self.compression.map_or(1, |_| CSV_COMPRESSION_RATIO);
+        for format in &self.formats {
+            bytes += match format {
+                ReportFormat::Xlsx => series * WORKBOOK_BYTES + samples * WORKBOOK_SAMPLE_BYTES,
+                ReportFormat::Workbook => WORKBOOK_BYTES + samples * WORKBOOK_SAMPLE_BYTES,
+                ReportFormat::Csv => samples * CSV_SAMPLE_BYTES / csv_ratio,
+                ReportFormat::CsvLong => samples * CSV_LONG_SAMPLE_BYTES / csv_ratio,
+                ReportFormat::Json => JSON_BYTES + samples * JSON_SAMPLE_BYTES,
+                ReportFormat::Macrobenchmark => BENCHMARK_BYTES + samples * BENCHMARK_SAMPLE_BYTES,
+                ReportFormat::Html => {
+                    HTML_BYTES + samples * HTML_SAMPLE_BYTES + self.screenshots * THUMBNAIL_BYTES
+                }
+            };
+        }
+        if self.video {
+            bytes += self.duration * VIDEO_BYTES_PER_SECOND;
+        }
+        bytes * self.runs
+    }
+
+    pub fn print(&self) {
+        println!("计划 (不会运行): {}", self.mode);
+        println!(
+            "  {} 次采样, 每次 {} 秒, 共约 {} 秒",
+            self.runs,
+            self.duration,
+            self.runs * self.duration
+        );
+        println!("  采集:");
+        for metric in &self.metrics {
+            println!(
+                "    {:<12} 每 {}ms, 约 {} 个样本",
+                metric.name, metric.interval, metric.samples
+            );
+        }
+        if let Some(fast_interval) = self.fast_interval {
+            println!("    峰值后每 {}ms 采样, 样本会更多", fast_interval);
+        }
+        if self.screenshots > 0 {
+            println!("    {:<12} 约 {} 张", "screenshots", self.screenshots);
+        }
+        let formats: Vec<String> = self
+            .formats
+            .iter()
+            .map(|format| {
+                let name = match format {
+                    ReportFormat::Xlsx => "xlsx",
+                    ReportFormat::Workbook => "workbook",
+                    ReportFormat::Csv => "csv",
+                    ReportFormat::CsvLong => "csv-long",
+                    ReportFormat::Json => "json",
+                    ReportFormat::Macrobenchmark => "macrobenchmark",
+                    ReportFormat::Html => "html",
+                };
+                match self.compression {
+                    Some(compression)
+                        if matches!(format, ReportFormat::Csv | ReportFormat::CsvLong) =>
+                    {
+                        format!("{} (.{})", name, compression.suffix())
+                    }
+                    _ => name.to_string(),
+                }
+            })
+            .collect();
+        println!(
+            "  输出: {}{}, 约 {:.1} KB",
+            formats.join(", "),
+            if self.video { ", video" } else { "" },
+            self.estimated_bytes() as f64 / 1024.0
+        );
+        println!("  命令 (API 30+ 的写法, 以设备版本为准):");
+        for command in &self.commands {
+            println!("    {}", command);
+        }
+    }
+}
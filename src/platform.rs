@@ -0,0 +1,362 @@
+use crate::batch::ShellBatch;
+use crate::cache;
+use crate::connection::Link;
+use crate::device::getprop;
+use crate::meminfo;
+use crate::quirks::{self, NO_QUIRK, Quirk};
+use crate::sample::Quality;
+use crate::shell::ShellSession;
+use crate::{adb_argv, run_adb_command_on};
+
+// Column layouts of `top` output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TopLayout {
+    // toolbox `top -n 1`: `PID PR CPU% S #THR VSS RSS PCY UID Name`
+    Toolbox,
+    // toybox `top -b -n 1`: `PID USER PR NI VIRT RES SHR S %CPU %MEM TIME+ ARGS`
+    Toybox,
+}
+
+// Column layouts of `ps` output
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PsLayout {
+    // toolbox `ps`, lists every process: `USER PID PPID VSIZE RSS WCHAN PC NAME`
+    Toolbox,
+    // toybox `ps -A -o PID,UID,NAME`
+    Toybox,
+}
+
+// A process listed by `ps`
+pub struct Process {
+    pub pid: String,
+    pub uid: Option<u32>,
+    pub name: String,
+}
+
+// The commands and output formats of one range of Android versions, adjusted by the
+// quirks of the device's manufacturer
+#[derive(Clone, Debug)]
+pub struct Platform {
+    pub sdk: u32,
+    pub min_sdk: u32,
+    pub max_sdk: u32,
+    pub top: TopLayout,
+    pub ps: PsLayout,
+    // label of the total PSS in the App Summary of `dumpsys meminfo`
+    pub total_pss_label: &'static str,
+    // `dumpsys gfxinfo <pkg> framestats`
+    pub gfxinfo_framestats: bool,
+    // `50th gpu percentile` and friends in `dumpsys gfxinfo`
+    pub gfxinfo_gpu_percentiles: bool,
+    pub quirk: Quirk,
+    // the shell reads of a tick run together, with `--batch`
+    pub batch: Option<ShellBatch>,
+    // the shell reads go to long-lived `adb shell` sessions, unless `--no-shell-session`
+    pub session: Option<ShellSession>,
+    // the connection of the run the reads are for
+    pub link: Option<Link>,
+}
+
+// Oldest to newest, API 24 (7.0) through 35 (15)
+static ADAPTERS: [Platform; 4] = [
+    Platform {
+        sdk: 24,
+        min_sdk: 24,
+        max_sdk: 25,
+        top: TopLayout::Toolbox,
+        ps: PsLayout::Toolbox,
+        total_pss_label: "TOTAL:",
+        gfxinfo_framestats: true,
+        gfxinfo_gpu_percentiles: false,
+        quirk: NO_QUIRK,
+        batch: None,
+        session: None,
+        link: None,
+    },
+    Platform {
+        sdk: 26,
+        min_sdk: 26,
+        max_sdk: 28,
+        top: TopLayout::Toybox,
+        ps: PsLayout::Toybox,
+        total_pss_label: "TOTAL:",
+        gfxinfo_framestats: true,
+        gfxinfo_gpu_percentiles: false,
+        quirk: NO_QUIRK,
+        batch: None,
+        session: None,
+        link: None,
+    },
+    Platform {
+        sdk: 29,
+        min_sdk: 29,
+        max_sdk: 29,
+        top: TopLayout::Toybox,
+        ps: PsLayout::Toybox,
+        total_pss_label: "TOTAL:",
+        gfxinfo_framestats: true,
+        gfxinfo_gpu_percentiles: true,
+        quirk: NO_QUIRK,
+        batch: None,
+        session: None,
+        link: None,
+    },
+    Platform {
+        sdk: 30,
+        min_sdk: 30,
+        max_sdk: 35,
+        top: TopLayout::Toybox,
+        ps: PsLayout::Toybox,
+        total_pss_label: "TOTAL PSS:",
+        gfxinfo_framestats: true,
+        gfxinfo_gpu_percentiles: true,
+        quirk: NO_QUIRK,
+        batch: None,
+        session: None,
+        link: None,
+    },
+];
+
+// Uid of an app user name like `u0_a101` (user 0, app 101), as printed by toolbox `ps`
+fn user_uid(user: &str) -> Option<u32> {
+    let (android_user, app) = user.strip_prefix('u')?.split_once('_')?;
+    let android_user: u32 = android_user.parse().ok()?;
+    let app_id = if let Some(id) = app.strip_prefix('a') {
+        10000 + id.parse::<u32>().ok()?
+    } else {
+        99000 + app.strip_prefix('i')?.parse::<u32>().ok()?
+    };
+    Some(android_user * 100000 + app_id)
+}
+
+impl Platform {
+    // The adapter for an API level, the closest one if it is outside the supported range
+    // (0, the version is unknown, gets the newest)
+    pub fn for_sdk(sdk: u32) -> Platform {
+        let newest = &ADAPTERS[ADAPTERS.len() - 1];
+        let adapter = match sdk {
+            0 => newest,
+            _ => ADAPTERS
+                .iter()
+                .find(|adapter| sdk <= adapter.max_sdk)
+                .unwrap_or(newest),
+        };
+        Platform {
+            sdk,
+            ..adapter.clone()
+        }
+    }
+
+    // The adapter for the device's `ro.build.version.sdk`, with the quirks of its
+    // `ro.product.manufacturer` and the user's `quirk_overrides`
+    pub fn detect(device_cmd: &str, quirk_overrides: &[Quirk]) -> Platform {
+        let sdk = getprop(device_cmd, "ro.build.version.sdk")
+            .parse()
+            .unwrap_or(0);
+        let manufacturer = getprop(device_cmd, "ro.product.manufacturer");
+        Platform {
+            quirk: quirks::resolve(&manufacturer, quirk_overrides),
+            ..Platform::for_sdk(sdk)
+        }
+    }
+
+    pub fn print(&self) {
+        if self.sdk == 0 {
+            println!(
+                "系统版本未知, 使用 API {}-{} 的命令",
+                self.min_sdk, self.max_sdk
+            );
+        } else if self.sdk < self.min_sdk || self.sdk > self.max_sdk {
+            println!(
+                "系统版本: API {} 未适配, 使用 API {}-{} 的命令",
+                self.sdk, self.min_sdk, self.max_sdk
+            );
+        } else {
+            println!("系统版本: API {}", self.sdk);
+        }
+        if !self.quirk.is_empty() {
+            println!(
+                "厂商兼容设置 ({}): {}",
+                self.quirk.manufacturer,
+                self.quirk.describe()
+            );
+        }
+    }
+
+    // `adb shell <command>`, cleaned up according to the quirks
+    pub fn shell(&self, device_cmd: &str, command: &str) -> String {
+        let output = match (&self.batch, &self.session) {
+            (Some(batch), session) => {
+                batch.shell(device_cmd, command, session.as_ref(), self.link.as_ref())
+            }
+            (None, Some(session)) => session.shell(device_cmd, command),
+            (None, None) => run_adb_command_on(
+                &adb_argv(device_cmd, &["shell", command]),
+                self.link.as_ref(),
+            ),
+        };
+        if self.quirk.strip_ansi == Some(true) {
+            quirks::strip_ansi(&output)
+        } else {
+            output
+        }
+    }
+
+    // One snapshot of all processes in `top`
+    pub fn top_command(&self) -> String {
+        match (&self.quirk.top_command, self.top) {
+            (Some(command), _) => command.clone(),
+            (None, TopLayout::Toolbox) => "top -n 1".to_string(),
+            (None, TopLayout::Toybox) => "top -b -n 1".to_string(),
+        }
+    }
+
+    // The lines of one `top` snapshot that mention the package
+    pub fn top_lines(&self, device_cmd: &str, pkg: &str) -> String {
+        self.shell(device_cmd, &self.top_command())
+            .lines()
+            .filter(|line| line.contains(pkg))
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    // CPU usage (%) column of a `top` line
+    pub fn parse_top_cpu(&self, line: &str) -> f64 {
+        let column = match (self.quirk.cpu_column, self.top) {
+            (Some(column), _) => column,
+            (None, TopLayout::Toolbox) => 2,
+            (None, TopLayout::Toybox) => 8,
+        };
+        line.split_whitespace()
+            .nth(column)
+            .unwrap_or("0")
+            .replace("%", "")
+            .parse()
+            .unwrap_or(0.0)
+    }
+
+    // TOTAL PSS (KB) of a `dumpsys meminfo <pkg|pid>` output
+    pub fn parse_total_pss(&self, mem_result: &str) -> Option<f64> {
+        // Fall back to the other labels and the `TOTAL` row of the table, the summary
+        // labels moved between releases and some builds backport them
+        for label in [self.total_pss_label, "TOTAL PSS:", "TOTAL:"] {
+            let total = mem_result.lines().find_map(|line| {
+                let (_, rest) = line.split_once(label)?;
+                rest.split_whitespace().next()?.parse().ok()
+            });
+            if total.is_some() {
+                return total;
+            }
+        }
+        mem_result.lines().find_map(|line| {
+            let mut columns = line.split_whitespace();
+            (columns.next()? == "TOTAL").then_some(())?;
+            columns.next()?.parse().ok()
+        })
+    }
+
+    // TOTAL PSS (KB) of the package's process, or of the process `pid` of the package,
+    // `Quality::Fallback` if it had to be read from smaps_rollup
+    pub fn total_pss(
+        &self,
+        device_cmd: &str,
+        pkg: &str,
+        pid: Option<&str>,
+    ) -> Option<(f64, Quality)> {
+        self.meminfo(device_cmd, pkg, pid)
+            .map(|(total, quality, _)| (total, quality))
+    }
+
+    // `total_pss` with the PSS of the sections of `meminfo::SECTIONS` it reads, none with the
+    // fallback
+    pub fn meminfo(
+        &self,
+        device_cmd: &str,
+        pkg: &str,
+        pid: Option<&str>,
+    ) -> Option<(f64, Quality, meminfo::Sections)> {
+        let target = pid.unwrap_or(pkg);
+        let mem_result = self.shell(device_cmd, &format!("dumpsys meminfo {}", target));
+        let total = self.parse_total_pss(&mem_result);
+        if total.is_some() || self.quirk.meminfo_fallback != Some(true) {
+            return total.map(|total| (total, Quality::Good, meminfo::parse_sections(&mem_result)));
+        }
+
+        // `run-as` reads the app's own /proc entries, that works for debuggable builds
+        let pss = match pid {
+            Some(pid) => self.smaps_pss(device_cmd, pkg, pid),
+            None => {
+                // a pid from before the app was restarted reads nothing, then look it up again
+                let key = format!("pid {}", pkg);
+                let pidof = || {
+                    self.shell(device_cmd, &format!("pidof {}", pkg))
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                let pid = cache::cached(device_cmd, &key, pidof);
+                if pid.is_empty() {
+                    return None;
+                }
+                self.smaps_pss(device_cmd, pkg, &pid).or_else(|| {
+                    cache::forget(device_cmd, &key);
+                    let pid = cache::cached(device_cmd, &key, pidof);
+                    self.smaps_pss(device_cmd, pkg, &pid)
+                })
+            }
+        };
+        pss.map(|pss| (pss, Quality::Fallback, Vec::new()))
+    }
+
+    // Pss (KB) in `/proc/<pid>/smaps_rollup`
+    fn smaps_pss(&self, device_cmd: &str, pkg: &str, pid: &str) -> Option<f64> {
+        if pid.is_empty() {
+            return None;
+        }
+        let smaps = self.shell(
+            device_cmd,
+            &format!("run-as {} cat /proc/{}/smaps_rollup", pkg, pid),
+        );
+        // "Pss:               52341 kB"
+        smaps.lines().find_map(|line| {
+            line.strip_prefix("Pss:")?
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()
+        })
+    }
+
+    // Every process on the device
+    pub fn processes(&self, device_cmd: &str) -> Vec<Process> {
+        let command = match self.ps {
+            PsLayout::Toolbox => "ps",
+            PsLayout::Toybox => "ps -A -o PID,UID,NAME",
+        };
+        let ps_result = self.shell(device_cmd, command);
+        ps_result
+            .lines()
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split_whitespace().collect();
+                let (pid, uid) = match self.ps {
+                    PsLayout::Toolbox => (columns.get(1)?, user_uid(columns.first()?)),
+                    PsLayout::Toybox => (columns.first()?, columns.get(1)?.parse().ok()),
+                };
+                // skip the header
+                pid.parse::<u32>().ok()?;
+                Some(Process {
+                    pid: pid.to_string(),
+                    uid,
+                    name: columns.last()?.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    // `dumpsys gfxinfo` with per-frame timings, if the version has them
+    pub fn gfxinfo_command(&self, pkg: &str) -> Option<String> {
+        self.gfxinfo_framestats
+            .then(|| format!("dumpsys gfxinfo {} framestats", pkg))
+    }
+}
@@ -0,0 +1,19 @@
+// Pressure stall information of the whole device (API 29+ kernels), the share of the last 10
+// seconds some task waited for cpu or memory:
+//
+// some avg10=4.12 avg60=2.05 avg300=0.88 total=123456
+// full avg10=0.00 avg60=0.00 avg300=0.00 total=0
+pub const COMMAND: &str = "cat /proc/pressure/cpu /proc/pressure/memory";
+
+// `(cpu, memory)` `some avg10` percentages of `COMMAND` output
+pub fn parse(output: &str) -> Option<(f64, f64)> {
+    let mut some = output
+        .lines()
+        .filter(|line| line.trim_start().starts_with("some "))
+        .map(|line| {
+            line.split_whitespace()
+                .find_map(|token| token.strip_prefix("avg10="))
+                .and_then(|value| value.parse::<f64>().ok())
+        });
+    Some((some.next()??, some.next()??))
+}
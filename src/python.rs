@@ -0,0 +1,105 @@
+use crate::session::SamplingSession;
+use crate::{CollectOptions, RunData, stats};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+// `cpureport.Session(package, device=None, interval=1000, duration=3600)`, start()/mark()/stop()
+#[pyclass(name = "Session")]
+struct PySession {
+    pkg: String,
+    device: String,
+    options: CollectOptions,
+    running: Option<SamplingSession>,
+}
+
+#[pymethods]
+impl PySession {
+    #[new]
+    #[pyo3(signature = (package, device = None, interval = 1000, duration = 3600))]
+    fn new(package: String, device: Option<String>, interval: u64, duration: u64) -> Self {
+        PySession {
+            pkg: package,
+            device: device.unwrap_or_default(),
+            options: CollectOptions {
+                duration,
+                interval,
+                ..Default::default()
+            },
+            running: None,
+        }
+    }
+
+    fn start(&mut self) -> PyResult<()> {
+        if self.running.is_some() {
+            return Err(PyRuntimeError::new_err("session already started"));
+        }
+        self.running = Some(SamplingSession::start(
+            &self.pkg,
+            &self.device,
+            &self.options,
+        ));
+        Ok(())
+    }
+
+    fn mark(&self, label: &str) -> PyResult<()> {
+        match &self.running {
+            Some(session) => {
+                session.mark(label);
+                Ok(())
+            }
+            None => Err(PyRuntimeError::new_err("session not started")),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.running
+            .as_ref()
+            .is_some_and(SamplingSession::is_running)
+    }
+
+    // Stop sampling and return the results as a dict
+    fn stop<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let session = self
+            .running
+            .take()
+            .ok_or_else(|| PyRuntimeError::new_err("session not started"))?;
+        let data = py
+            .detach(move || session.stop())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        results(py, &data)
+    }
+}
+
+fn results<'py>(py: Python<'py>, data: &RunData) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let (cpu, mem) = (data.cpu.values(), data.mem.values());
+    dict.set_item("cpu", &cpu)?;
+    dict.set_item("mem", &mem)?;
+
+    let events = PyList::empty(py);
+    for event in &data.events {
+        let item = PyDict::new(py);
+        item.set_item("time", event.time)?;
+        item.set_item("kind", event.kind.label())?;
+        item.set_item("detail", &event.detail)?;
+        events.append(item)?;
+    }
+    dict.set_item("events", events)?;
+
+    let summary = PyDict::new(py);
+    summary.set_item("cpu_average", stats::mean(&cpu))?;
+    summary.set_item("cpu_average_ci95", stats::mean_ci95(&cpu))?;
+    summary.set_item("cpu_max", stats::max(&cpu))?;
+    let mem_mb = data.mem.display_values();
+    summary.set_item("mem_average", stats::mean(&mem_mb))?;
+    summary.set_item("mem_average_ci95", stats::mean_ci95(&mem_mb))?;
+    summary.set_item("mem_max", stats::max(&mem_mb))?;
+    dict.set_item("summary", summary)?;
+    Ok(dict)
+}
+
+#[pymodule]
+fn cpureport(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySession>()
+}
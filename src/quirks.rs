@@ -0,0 +1,130 @@
+use serde::Deserialize;
+
+// Known deviations of a manufacturer's firmware from stock Android, unset fields keep the
+// platform's behaviour. The config file's `quirks` override these field by field.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Quirk {
+    // matched case-insensitively against `ro.product.manufacturer`
+    pub manufacturer: String,
+    // replaces `top -b -n 1`, e.g. to skip a busybox `top` earlier on the PATH
+    #[serde(default)]
+    pub top_command: Option<String>,
+    // 0-based column of the cpu usage in `top_command`'s process lines
+    #[serde(default)]
+    pub cpu_column: Option<usize>,
+    // remove ANSI color codes from shell output
+    #[serde(default)]
+    pub strip_ansi: Option<bool>,
+    // read PSS from `/proc/<pid>/smaps_rollup` (through `run-as`) when `dumpsys meminfo` is refused
+    #[serde(default)]
+    pub meminfo_fallback: Option<bool>,
+}
+
+pub const NO_QUIRK: Quirk = Quirk {
+    manufacturer: String::new(),
+    top_command: None,
+    cpu_column: None,
+    strip_ansi: None,
+    meminfo_fallback: None,
+};
+
+fn builtin() -> Vec<Quirk> {
+    vec![
+        // Some EMUI/HarmonyOS builds put busybox first, whose `top` has another layout
+        Quirk {
+            manufacturer: "huawei".to_string(),
+            top_command: Some("toybox top -b -n 1".to_string()),
+            ..NO_QUIRK
+        },
+        Quirk {
+            manufacturer: "honor".to_string(),
+            top_command: Some("toybox top -b -n 1".to_string()),
+            ..NO_QUIRK
+        },
+        // Samsung shells color `top` and `ps` output
+        Quirk {
+            manufacturer: "samsung".to_string(),
+            strip_ansi: Some(true),
+            ..NO_QUIRK
+        },
+        // MIUI/HyperOS refuse `dumpsys meminfo` for other apps without its developer setting
+        Quirk {
+            manufacturer: "xiaomi".to_string(),
+            meminfo_fallback: Some(true),
+            ..NO_QUIRK
+        },
+    ]
+}
+
+impl Quirk {
+    // Fields set in `other` win
+    fn merge(self, other: &Quirk) -> Quirk {
+        Quirk {
+            manufacturer: self.manufacturer,
+            top_command: other.top_command.clone().or(self.top_command),
+            cpu_column: other.cpu_column.or(self.cpu_column),
+            strip_ansi: other.strip_ansi.or(self.strip_ansi),
+            meminfo_fallback: other.meminfo_fallback.or(self.meminfo_fallback),
+        }
+    }
+
+    // The fields that are set, e.g. "strip_ansi, top_command=toybox top -b -n 1"
+    pub fn describe(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(command) = &self.top_command {
+            fields.push(format!("top_command={}", command));
+        }
+        if let Some(column) = self.cpu_column {
+            fields.push(format!("cpu_column={}", column));
+        }
+        if self.strip_ansi == Some(true) {
+            fields.push("strip_ansi".to_string());
+        }
+        if self.meminfo_fallback == Some(true) {
+            fields.push("meminfo_fallback".to_string());
+        }
+        fields.join(", ")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        Quirk {
+            manufacturer: String::new(),
+            ..self.clone()
+        } == NO_QUIRK
+    }
+}
+
+// The quirks of a manufacturer, built-in ones with the user's `overrides` applied
+pub fn resolve(manufacturer: &str, overrides: &[Quirk]) -> Quirk {
+    let matches = |quirk: &&Quirk| quirk.manufacturer.eq_ignore_ascii_case(manufacturer);
+    let initial = Quirk {
+        manufacturer: manufacturer.to_string(),
+        ..NO_QUIRK
+    };
+    builtin()
+        .iter()
+        .filter(matches)
+        .chain(overrides.iter().filter(matches))
+        .fold(initial, Quirk::merge)
+}
+
+// Remove ANSI escape sequences (`ESC [ ... letter`)
+pub fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
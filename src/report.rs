@@ -0,0 +1,663 @@
+use crate::align::Aligned;
+use crate::anomaly;
+use crate::appops;
+use crate::calibration::Calibration;
+use crate::camera;
+use crate::cpuset;
+use crate::crashloop;
+use crate::doze;
+use crate::efficiency;
+use crate::events::{self, EventKind, LinkedSheet};
+use crate::failures;
+use crate::leaks;
+use crate::logcat;
+use crate::sample::Series;
+use crate::schedule;
+use crate::screen;
+use crate::system;
+use crate::theme::Theme;
+use crate::thermal;
+use crate::threads;
+use crate::workbook::XlsxWriter;
+use crate::{RunData, get_current_time, sla, stats};
+use rust_xlsxwriter::{Chart, ChartType, ColNum, RowNum, Workbook};
+use serde_json::{Value, json};
+
+// The files that saving wrote, and the ones it could not. A file that fails does not keep the
+// others from being written.
+#[derive(Debug, Default)]
+pub struct Saved {
+    pub files: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl Saved {
+    // Whether the file was written
+    pub fn record<E: std::fmt::Display>(&mut self, path: String, result: Result<(), E>) -> bool {
+        match result {
+            Ok(()) => {
+                self.files.push(path);
+                true
+            }
+            Err(e) => {
+                println!("无法保存 {}: {}", path, e);
+                self.failed.push(path);
+                false
+            }
+        }
+    }
+
+    pub fn extend(&mut self, other: Saved) {
+        self.files.extend(other.files);
+        self.failed.extend(other.failed);
+    }
+}
+
+// Output formats of a run
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ReportFormat {
+    // cpu/mem Excel workbooks (and the events timeline)
+    Xlsx,
+    // one Excel workbook with Summary, CPU and Memory sheets
+    Workbook,
+    // one csv file per series with the raw samples
+    Csv,
+    // one csv file with the samples of every series, a row per sample and metric
+    CsvLong,
+    // one JSON document with the run's metadata, samples and statistics
+    Json,
+    // androidx.benchmark `*-benchmarkData.json`
+    Macrobenchmark,
+    // self-contained page with the curves, events and screenshot thumbnails
+    Html,
+}
+
+// Statistics of a series in the unit reports show it in
+fn series_summary(series: &Series) -> Value {
+    let values = series.display_values();
+    json!({
+        "unit": series.unit.display_label(),
+        "samples": values.len(),
+        "degraded_samples": series.degraded(),
+        "average": stats::mean(&values),
+        "average_ci95": stats::mean_ci95(&values),
+        "effective_samples": stats::effective_sample_size(&values),
+        "max": stats::max(&values),
+    })
+}
+
+// Summary of a run for machine consumers, memory in MB
+pub fn summary_json(data: &RunData) -> Value {
+    let events: Vec<Value> = data
+        .events
+        .iter()
+        .map(|event| json!({ "time": event.time, "kind": event.kind.label(), "detail": event.detail }))
+        .collect();
+    let mut summary = json!({
+        "start": data.start,
+        "cpu": series_summary(&data.cpu),
+        "mem": series_summary(&data.mem),
+        "events": events,
+    });
+    for series in &data.series {
+        summary[series.name.as_str()] = series_summary(series);
+    }
+    if !data.sla.is_empty() {
+        summary["sla"] = sla::verdicts_json(&data.sla);
+    }
+    if let Some(score) = &data.score {
+        summary["score"] = score.to_json();
+    }
+    if let Some(crash_loop) = &data.crash_loop {
+        summary["status"] = json!(crashloop::INVALID_UNSTABLE);
+        summary["crash_loop"] = crash_loop.to_json();
+    }
+    if let Some(overhead) = &data.overhead {
+        summary["overhead"] = overhead.to_json();
+    }
+    if let Some(shaping) = &data.shaping {
+        summary["network_profile"] = shaping.to_json();
+    }
+    if !data.doze.is_empty() {
+        summary["doze"] = doze::windows_json(&data.doze, &data.cpu);
+    }
+    if !data.lags.is_empty() {
+        summary["schedule"] = schedule::lags_json(&data.lags);
+    }
+    if let Some(traffic) = &data.traffic {
+        summary["traffic"] = traffic.to_json();
+    }
+    if let Some(io) = &data.io {
+        summary["io"] = io.to_json();
+    }
+    if let Some(seed) = data.seed {
+        summary["seed"] = json!(seed);
+    }
+    if data.processes.len() > 1 {
+        summary["processes"] = json!(data.processes);
+    }
+    if !data.threads.is_empty() {
+        summary["threads"] = threads::threads_json(&data.threads);
+    }
+    if let Some(mah) = data.battery_mah {
+        summary["battery_mah"] = json!(mah);
+    }
+    if let Some(level) = data.battery_low {
+        summary["battery_low"] = json!({ "level": level, "ended_at": data.end });
+    }
+    if !data.failures.is_empty() {
+        summary["failures"] = failures::failures_json(&data.failures);
+    }
+    let shares = events::shares(&data.events, EventKind::Cpuset, data.end);
+    if !shares.is_empty() {
+        summary["cpuset"] = events::shares_json(&shares);
+    }
+    let shares = events::shares(&data.events, EventKind::Screen, data.end);
+    if !shares.is_empty() {
+        summary["screen"] = events::shares_json(&shares);
+    }
+    let counts = appops::counts(&data.events);
+    if !counts.is_empty() {
+        summary["appops"] = appops::counts_json(&counts);
+    }
+    if let Some(counts) = logcat::counts_json(&data.events) {
+        summary["logcat"] = counts;
+    }
+    let sessions = camera::sessions(&data.events, data.end);
+    if !sessions.is_empty() {
+        summary["camera"] = camera::sessions_json(&sessions);
+    }
+    if let Some(thermal) = thermal::summary_json(&data.events, data.end) {
+        summary["thermal"] = thermal;
+    }
+    if let Some(headroom) = system::headroom_json(&data.series) {
+        summary["headroom"] = headroom;
+    }
+    let growth = leaks::growth(&data.series);
+    if !growth.is_empty() {
+        summary["leaks"] = leaks::growth_json(&growth);
+    }
+    if let Some(parts) = efficiency::efficiency(data) {
+        summary["efficiency"] = efficiency::efficiency_json(&parts);
+    }
+    let frames = anomaly::dominant_frames(&data.stacks, anomaly::TOP_FRAMES);
+    if !frames.is_empty() {
+        summary["anomalies"] = json!({ "dominant_frames": anomaly::frames_json(&frames) });
+    }
+    summary
+}
+
+// A finished run, for programs embedding cpureport
+pub struct Report {
+    pub data: RunData,
+}
+
+impl Report {
+    pub fn new(data: RunData) -> Report {
+        Report { data }
+    }
+
+    // Averages, maxima and events, as handed to the python and C bindings
+    pub fn summary(&self) -> Value {
+        summary_json(&self.data)
+    }
+
+    // Workbooks into `dir`, returning the written paths
+    pub fn save(&self, dir: &str, theme: &Theme) -> Vec<String> {
+        save_reports(dir, &self.data, theme).files
+    }
+}
+
+// Print the summary and save results to Excel files
+pub fn save_reports(f_path: &str, data: &RunData, theme: &Theme) -> Saved {
+    let current_time = get_current_time();
+
+    println!("current time is: {}", current_time);
+
+    let cpu_file_name = table_file_name("cpu", &current_time, "xlsx");
+    let mem_file_name = table_file_name("mem", &current_time, "xlsx");
+
+    let cpu_data = &data.cpu.values();
+    let mem_data = &data.mem.values();
+
+    let cpu_sum = cpu_data.iter().sum::<f64>();
+
+    let cpu_average: f64 = cpu_sum / cpu_data.len() as f64;
+    let cpu_max = cpu_data
+        .iter()
+        .max_by(|a, b| a.total_cmp(b))
+        .unwrap_or(&0.0);
+
+    let mem_sum = mem_data.iter().sum::<f64>();
+    let mem_average: f64 = mem_sum / (mem_data.len() as f64 * 1024.0);
+    let mem_max = mem_data
+        .iter()
+        .max_by(|a, b| a.total_cmp(b))
+        .unwrap_or(&0.0)
+        / 1024.0;
+
+    let mem_mb = data.mem.display_values();
+    println!("cpu均值: {}{}", cpu_average, stats::ci95_text(cpu_data));
+    println!("cpu峰值: {}", cpu_max);
+    println!("内存均值: {}{}", mem_average, stats::ci95_text(&mem_mb));
+    println!("内存峰值: {}", mem_max);
+
+    // Memory is sampled more slowly than cpu, the series are paired on a common time axis
+    let mut aligned_series = vec![&data.cpu, &data.mem];
+    aligned_series.extend(&data.series);
+    let aligned = Aligned::new(data.start, data.end, &aligned_series);
+    let complete = aligned.complete_columns();
+    let named: Vec<(&str, &[f64])> = aligned_series
+        .iter()
+        .zip(&complete)
+        .map(|(series, values)| (series.name.as_str(), values.as_slice()))
+        .collect();
+    for correlation in stats::correlations(&named) {
+        println!(
+            "相关系数 {} vs {}: {:.2}{}",
+            correlation.a,
+            correlation.b,
+            correlation.r,
+            if correlation.is_strong() {
+                " (强相关)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    let shares = events::shares(&data.events, EventKind::Cpuset, data.end);
+    if !shares.is_empty() {
+        cpuset::print_shares(&shares);
+    }
+    appops::print_counts(&appops::counts(&data.events));
+    camera::print_sessions(&camera::sessions(&data.events, data.end));
+    logcat::print_counts(&data.events);
+    let shares = events::shares(&data.events, EventKind::Screen, data.end);
+    if !shares.is_empty() {
+        screen::print_shares(&shares);
+    }
+    thermal::print_summary(&data.events, data.end);
+    system::print_headroom(&data.series);
+    leaks::print_growth(&leaks::growth(&data.series));
+    doze::print_windows(&data.doze, &data.cpu);
+    if let Some(parts) = efficiency::efficiency(data) {
+        efficiency::print_efficiency(&parts);
+    }
+
+    for series in &data.series {
+        // KB series are summarized in MB like the memory workbook
+        let values = series.display_values();
+        println!(
+            "{}均值: {}{}",
+            series.name,
+            stats::mean(&values),
+            stats::ci95_text(&values)
+        );
+        println!("{}峰值: {}", series.name, stats::max(&values));
+    }
+    let mut saved = save_tables(f_path, &current_time, data, &XlsxWriter { theme });
+
+    // Save the event timeline, linking into both data sheets
+    if !data.events.is_empty() {
+        let events_file_path = format!("{}/events_{}.xlsx", f_path, current_time);
+        let (cpu_sheet, mem_sheet) = (
+            data_sheet(&data.cpu, "Cpu"),
+            data_sheet(&data.mem, "Memory"),
+        );
+        let sheets = [
+            LinkedSheet {
+                title: "Cpu Sample",
+                file: Some(&cpu_file_name),
+                sheet: &cpu_sheet,
+                column: "B",
+            },
+            LinkedSheet {
+                title: "Mem Sample",
+                file: Some(&mem_file_name),
+                sheet: &mem_sheet,
+                column: "B",
+            },
+        ];
+        let result = events::save_timeline(
+            &events_file_path,
+            data.start,
+            &data.events,
+            &sheets,
+            data.warmup_dropped as usize,
+            theme,
+        );
+        saved.record(events_file_path, result);
+    }
+
+    if !aligned.is_empty() {
+        let aligned_file_path = format!("{}/aligned_{}.xlsx", f_path, current_time);
+        let result = save_aligned(
+            &aligned_file_path,
+            data.start,
+            &aligned_series,
+            &aligned,
+            theme,
+        );
+        saved.record(aligned_file_path, result);
+    }
+
+    if !data.threads.is_empty() {
+        let threads_file_path = format!("{}/threads_{}.xlsx", f_path, current_time);
+        let result = threads::save_threads(&threads_file_path, &data.threads, theme);
+        saved.record(threads_file_path, result);
+    }
+
+    if !data.failures.is_empty() {
+        let failures_file_path = format!("{}/failures_{}.xlsx", f_path, current_time);
+        let result =
+            failures::save_failures(&failures_file_path, data.start, &data.failures, theme);
+        saved.record(failures_file_path, result);
+    }
+
+    if !data.sla.is_empty() {
+        let sla_file_path = format!("{}/sla_{}.xlsx", f_path, current_time);
+        let result = save_sla(&sla_file_path, &data.sla, theme);
+        saved.record(sla_file_path, result);
+    }
+
+    saved
+}
+
+// Print how the cpu/memory of a run differ from a baseline run, and return the differences
+// as single-value metrics. With a calibration, differences are checked against the noise floor.
+// Differences in `noisy` metrics are marked informational.
+pub fn print_comparison(
+    baseline: &RunData,
+    run: &RunData,
+    calibration: Option<&Calibration>,
+    noisy: &[String],
+) -> Vec<(&'static str, f64)> {
+    let (baseline_cpu, run_cpu) = (baseline.cpu.values(), run.cpu.values());
+    let (baseline_mem, run_mem) = (baseline.mem.display_values(), run.mem.display_values());
+    let rows = [
+        (
+            "cpu均值",
+            "cpuAverageDeltaPercent",
+            calibration.map(|c| c.cpu),
+            stats::mean(&baseline_cpu),
+            stats::mean(&run_cpu),
+        ),
+        (
+            "cpu峰值",
+            "cpuMaxDeltaPercent",
+            calibration.map(|c| c.cpu),
+            stats::max(&baseline_cpu),
+            stats::max(&run_cpu),
+        ),
+        (
+            "内存均值",
+            "memoryAverageDeltaMb",
+            calibration.map(|c| c.mem),
+            stats::mean(&baseline_mem),
+            stats::mean(&run_mem),
+        ),
+        (
+            "内存峰值",
+            "memoryMaxDeltaMb",
+            calibration.map(|c| c.mem),
+            stats::max(&baseline_mem),
+            stats::max(&run_mem),
+        ),
+    ];
+
+    // Only averages have a confidence interval
+    let intervals = [
+        (stats::ci95_text(&baseline_cpu), stats::ci95_text(&run_cpu)),
+        (String::new(), String::new()),
+        (stats::ci95_text(&baseline_mem), stats::ci95_text(&run_mem)),
+        (String::new(), String::new()),
+    ];
+
+    let mut metrics = Vec::new();
+    for ((label, key, noise, before, after), (before_ci, after_ci)) in
+        rows.into_iter().zip(intervals)
+    {
+        let delta = after - before;
+        let ratio = if before == 0.0 {
+            String::new()
+        } else {
+            format!(" ({:+.1}%)", delta / before * 100.0)
+        };
+        let verdict = match noise {
+            Some(noise) if delta.abs() <= noise.tolerance() => {
+                format!(" 噪声范围内 (±{:.2})", noise.tolerance())
+            }
+            Some(noise) => format!(" 超出噪声 (±{:.2})", noise.tolerance()),
+            None => String::new(),
+        };
+        let informational = if sla::is_noisy(noisy, key) {
+            " (noisy, 仅供参考)"
+        } else {
+            ""
+        };
+        println!(
+            "{}: {:.2}{} -> {:.2}{}, 差值 {:+.2}{}{}{}",
+            label, before, before_ci, after, after_ci, delta, ratio, verdict, informational
+        );
+        metrics.push((key, delta));
+    }
+    // A run the device throttled in ran on slower cores, its cpu share does not compare
+    let (baseline_throttled, run_throttled) = (
+        thermal::throttled_millis(&baseline.events, baseline.end) > 0,
+        thermal::throttled_millis(&run.events, run.end) > 0,
+    );
+    if baseline_throttled != run_throttled {
+        println!(
+            "警告: 只有{}运行时设备降频, cpu 差值不可比",
+            if baseline_throttled {
+                "基准"
+            } else {
+                "本次"
+            }
+        );
+    }
+    // Runs with different random input did different work
+    if let (Some(baseline_seed), Some(run_seed)) = (baseline.seed, run.seed)
+        && baseline_seed != run_seed
+    {
+        println!(
+            "警告: 两次运行的随机种子不同 ({} -> {}), 随机输入不同, 差值不可比",
+            baseline_seed, run_seed
+        );
+    }
+    metrics
+}
+
+// "webview_cpu" -> "Webview Cpu"
+pub fn series_title(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// `title` as a worksheet name, without the characters Excel refuses and at most 31 long
+pub fn sheet_name(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => ' ',
+            c => c,
+        })
+        .take(31)
+        .collect()
+}
+
+// The sheet a series' table is written to, `Cpu Data` for cpu named `default`
+pub fn data_sheet(series: &Series, default: &str) -> String {
+    sheet_name(&format!("{} Data", series.title_or(default)))
+}
+
+// `cpu_data_<time>.xlsx`, the file a series' table is written to
+fn table_file_name(name: &str, current_time: &str, extension: &str) -> String {
+    format!("{}_data_{}.{}", name, current_time, extension)
+}
+
+// `(max, average)` of a series in the unit reports show it in
+pub fn max_average(series: &Series) -> (f64, f64) {
+    let values = series.display_values();
+    (stats::max(&values), stats::mean(&values))
+}
+
+// A series as one table of a report
+pub struct SeriesTable<'a> {
+    // name of the sheet it goes to, in formats that have sheets
+    pub sheet: String,
+    // title of the value column
+    pub label: String,
+    pub series: &'a Series,
+    pub data: &'a RunData,
+    // `max_average` of the series
+    pub max_average: (f64, f64),
+}
+
+// A file format the samples of a run are written in, one file per series
+pub trait ReportWriter {
+    // of the files it writes, without the dot
+    fn extension(&self) -> &'static str;
+
+    fn write_series(&self, path: &str, table: &SeriesTable) -> Result<(), String>;
+}
+
+// One file per series into `f_path`: cpu, mem, then the others, the written paths in that order
+pub fn save_tables(
+    f_path: &str,
+    current_time: &str,
+    data: &RunData,
+    writer: &dyn ReportWriter,
+) -> Saved {
+    let mut tables = vec![
+        (
+            data_sheet(&data.cpu, "Cpu"),
+            data.cpu.title_or("Cpu"),
+            &data.cpu,
+        ),
+        (
+            data_sheet(&data.mem, "Memory"),
+            data.mem.title_or("Mem"),
+            &data.mem,
+        ),
+    ];
+    for series in &data.series {
+        let title = series.title();
+        tables.push((sheet_name(&format!("{} Data", title)), title, series));
+    }
+    let mut saved = Saved::default();
+    for (sheet, label, series) in tables {
+        let name = table_file_name(&series.name, current_time, writer.extension());
+        let path = format!("{}/{}", f_path, name);
+        let table = SeriesTable {
+            sheet,
+            label,
+            series,
+            data,
+            max_average: max_average(series),
+        };
+        let result = writer.write_series(&path, &table);
+        saved.record(path, result);
+    }
+    saved
+}
+
+// The series of a run on one time axis, with a chart of cpu against the others (on the
+// secondary axis, their units differ)
+fn save_aligned(
+    path: &str,
+    start: u64,
+    series: &[&Series],
+    aligned: &Aligned,
+    theme: &Theme,
+) -> Result<(), rust_xlsxwriter::XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Aligned")?;
+    theme.brand(sheet)?;
+    let header = theme.header_format();
+    sheet.write_with_format(0, 0, "Time (s)", &header)?;
+    for (idx, series) in series.iter().enumerate() {
+        let title = format!("{} ({})", series.title(), series.unit.display_label());
+        sheet.write_with_format(0, idx as ColNum + 1, title, &header)?;
+    }
+    for (idx, time) in aligned.times.iter().enumerate() {
+        let row = idx as RowNum + 1;
+        sheet.write(row, 0, time.saturating_sub(start) as f64 / 1000.0)?;
+        for (column, values) in aligned.columns.iter().enumerate() {
+            // Blank where a series had no sample near enough, the chart shows a gap
+            if let Some(value) = values[idx] {
+                sheet.write(row, column as ColNum + 1, value)?;
+            }
+        }
+    }
+
+    let last = aligned.len() as RowNum;
+    let mut chart = Chart::new(ChartType::Line);
+    for idx in 0..series.len() {
+        let column = idx as ColNum + 1;
+        chart
+            .add_series()
+            .set_name(("Aligned", 0, column))
+            .set_categories(("Aligned", 1, 0, last, 0))
+            .set_values(("Aligned", 1, column, last, column))
+            .set_secondary_axis(idx > 0);
+    }
+    chart.x_axis().set_name("Time (s)");
+    chart.set_width(960).set_height(400);
+    sheet.insert_chart(1, series.len() as ColNum + 2, &chart)?;
+
+    workbook.save(path)
+}
+
+// One row per SLA condition, the overall verdict below
+fn save_sla(
+    path: &str,
+    verdicts: &[sla::Verdict],
+    theme: &Theme,
+) -> Result<(), rust_xlsxwriter::XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("SLA")?;
+    theme.brand(sheet)?;
+    sheet.write_row_with_format(
+        0,
+        0,
+        ["Condition", "Actual", "Unit", "Result", "Note"],
+        &theme.header_format(),
+    )?;
+    for (idx, verdict) in verdicts.iter().enumerate() {
+        let row = idx as RowNum + 1;
+        sheet.write(row, 0, verdict.condition.text())?;
+        if let Some(actual) = verdict.actual {
+            sheet.write(row, 1, actual)?;
+        }
+        sheet.write(row, 2, verdict.condition.unit)?;
+        sheet.write(row, 3, if verdict.passed { "PASS" } else { "FAIL" })?;
+        sheet.write(row, 4, verdict.note.as_deref().unwrap_or_default())?;
+    }
+    sheet.write_row(
+        verdicts.len() as RowNum + 1,
+        0,
+        [
+            "SLA",
+            "",
+            "",
+            if sla::passed(verdicts) {
+                "PASS"
+            } else {
+                "FAIL"
+            },
+        ],
+    )?;
+    workbook.save(path)
+}
@@ -0,0 +1,293 @@
+use crate::events;
+use crate::report::series_title;
+use serde_json::{Value, json};
+
+// Units samples are collected in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Percent,
+    Kilobytes,
+    Milliwatts,
+    // frames rendered since the sample before
+    Frames,
+    FramesPerSecond,
+    Milliseconds,
+    Millivolts,
+    Milliamps,
+    KilobytesPerSecond,
+    Megahertz,
+    Celsius,
+    // a step of a scale, like the thermal status from 0 (none) to 6 (shutdown)
+    Level,
+    // things counted at the sample, like the sensor listeners the app holds
+    Count,
+}
+
+impl Unit {
+    pub fn label(self) -> &'static str {
+        match self {
+            Unit::Percent => "%",
+            Unit::Kilobytes => "KB",
+            Unit::Milliwatts => "mW",
+            Unit::Frames => "frames",
+            Unit::FramesPerSecond => "fps",
+            Unit::Milliseconds => "ms",
+            Unit::Millivolts => "mV",
+            Unit::Milliamps => "mA",
+            Unit::KilobytesPerSecond => "KB/s",
+            Unit::Megahertz => "MHz",
+            Unit::Celsius => "°C",
+            Unit::Level => "level",
+            Unit::Count => "count",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Unit> {
+        match label {
+            "%" => Some(Unit::Percent),
+            "KB" => Some(Unit::Kilobytes),
+            "mW" => Some(Unit::Milliwatts),
+            "frames" => Some(Unit::Frames),
+            "fps" => Some(Unit::FramesPerSecond),
+            "ms" => Some(Unit::Milliseconds),
+            "mV" => Some(Unit::Millivolts),
+            "mA" => Some(Unit::Milliamps),
+            "KB/s" => Some(Unit::KilobytesPerSecond),
+            "MHz" => Some(Unit::Megahertz),
+            "°C" => Some(Unit::Celsius),
+            "level" => Some(Unit::Level),
+            "count" => Some(Unit::Count),
+            _ => None,
+        }
+    }
+
+    // Reports show memory in MB
+    pub fn display_label(self) -> &'static str {
+        match self {
+            Unit::Percent => "%",
+            Unit::Kilobytes => "MB",
+            Unit::Milliwatts => "mW",
+            Unit::Frames => "frames",
+            Unit::FramesPerSecond => "fps",
+            Unit::Milliseconds => "ms",
+            Unit::Millivolts => "mV",
+            Unit::Milliamps => "mA",
+            Unit::KilobytesPerSecond => "KB/s",
+            Unit::Megahertz => "MHz",
+            Unit::Celsius => "°C",
+            Unit::Level => "level",
+            Unit::Count => "count",
+        }
+    }
+
+    pub fn to_display(self, value: f64) -> f64 {
+        match self {
+            Unit::Kilobytes => value / 1024.0,
+            Unit::Percent
+            | Unit::Milliwatts
+            | Unit::Frames
+            | Unit::FramesPerSecond
+            | Unit::Milliseconds
+            | Unit::Millivolts
+            | Unit::Milliamps
+            | Unit::KilobytesPerSecond
+            | Unit::Megahertz
+            | Unit::Celsius
+            | Unit::Level
+            | Unit::Count => value,
+        }
+    }
+}
+
+// How far a sample can be trusted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    Good,
+    // read another way than usual, e.g. PSS from smaps_rollup when dumpsys meminfo was refused
+    Fallback,
+    // some of the processes it is summed over could not be read
+    Partial,
+    // the first sample of cpu, mem or a rate in `--raw` runs, kept but left out of the statistics
+    Warmup,
+}
+
+impl Quality {
+    pub fn label(self) -> &'static str {
+        match self {
+            Quality::Good => "good",
+            Quality::Fallback => "fallback",
+            Quality::Partial => "partial",
+            Quality::Warmup => "warmup",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Quality> {
+        match label {
+            "good" => Some(Quality::Good),
+            "fallback" => Some(Quality::Fallback),
+            "partial" => Some(Quality::Partial),
+            "warmup" => Some(Quality::Warmup),
+            _ => None,
+        }
+    }
+
+    // The quality of a value computed from samples of both qualities
+    pub fn worst(self, other: Quality) -> Quality {
+        match (self, other) {
+            (Quality::Warmup, Quality::Warmup) => Quality::Warmup,
+            (Quality::Good, other) => other,
+            (this, Quality::Good) => this,
+            (Quality::Partial, _) | (_, Quality::Partial) => Quality::Partial,
+            _ => Quality::Fallback,
+        }
+    }
+}
+
+// One measurement of one metric
+#[derive(Clone, Debug)]
+pub struct Sample {
+    // unix time in milliseconds
+    pub ts: u64,
+    pub metric: &'static str,
+    pub value: f64,
+    pub unit: Unit,
+    pub quality: Quality,
+}
+
+impl Sample {
+    // A good sample taken now
+    pub fn new(metric: &'static str, value: f64, unit: Unit) -> Sample {
+        Sample {
+            ts: events::now_millis(),
+            metric,
+            value,
+            unit,
+            quality: Quality::Good,
+        }
+    }
+
+    pub fn with_quality(self, quality: Quality) -> Sample {
+        Sample { quality, ..self }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "ts": self.ts,
+            "metric": self.metric,
+            "value": self.value,
+            "unit": self.unit.label(),
+            "quality": self.quality.label(),
+        })
+    }
+}
+
+// The samples of one metric of a run, in order
+#[derive(Clone, Debug)]
+pub struct Series {
+    pub name: String,
+    pub unit: Unit,
+    pub samples: Vec<Sample>,
+    // what the reports call it, from `display_names` of the config
+    pub display_name: Option<String>,
+}
+
+impl Series {
+    pub fn new(name: &str, unit: Unit) -> Series {
+        Series {
+            name: name.to_string(),
+            unit,
+            samples: Vec::new(),
+            display_name: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    // Its display name, else `default`
+    pub fn title_or(&self, default: &str) -> String {
+        self.display_name
+            .clone()
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    // Its display name, else `Psi Cpu` for `psi_cpu`
+    pub fn title(&self) -> String {
+        self.title_or(&series_title(&self.name))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    // The samples statistics are computed over, all but the warm-up sample of `--raw` runs
+    pub fn measured(&self) -> impl Iterator<Item = &Sample> {
+        self.samples
+            .iter()
+            .filter(|sample| sample.quality != Quality::Warmup)
+    }
+
+    // Values of the measured samples in the collected unit
+    pub fn values(&self) -> Vec<f64> {
+        self.measured().map(|sample| sample.value).collect()
+    }
+
+    // Values of the measured samples in the unit reports show them in
+    pub fn display_values(&self) -> Vec<f64> {
+        self.measured()
+            .map(|sample| self.unit.to_display(sample.value))
+            .collect()
+    }
+
+    // Number of samples that are read another way or only in part
+    pub fn degraded(&self) -> usize {
+        self.measured()
+            .filter(|sample| sample.quality != Quality::Good)
+            .count()
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(self.samples.iter().map(Sample::to_json).collect())
+    }
+
+    // Read back `to_json`, or a plain array of values (good samples without a time)
+    pub fn from_json(name: &'static str, unit: Unit, value: &Value) -> Series {
+        let samples = value
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| match item.as_f64() {
+                        Some(value) => Some(Sample {
+                            ts: 0,
+                            metric: name,
+                            value,
+                            unit,
+                            quality: Quality::Good,
+                        }),
+                        None => Some(Sample {
+                            ts: item["ts"].as_u64().unwrap_or(0),
+                            metric: name,
+                            value: item["value"].as_f64()?,
+                            unit: item["unit"]
+                                .as_str()
+                                .and_then(Unit::from_label)
+                                .unwrap_or(unit),
+                            quality: item["quality"]
+                                .as_str()
+                                .and_then(Quality::from_label)
+                                .unwrap_or(Quality::Good),
+                        }),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Series {
+            name: name.to_string(),
+            unit,
+            samples,
+            display_name: None,
+        }
+    }
+}
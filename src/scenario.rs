@@ -0,0 +1,200 @@
+use crate::events::now_millis;
+use crate::{adb_argv, adb_shell, run_adb_command, transport};
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Milliseconds between two events of `--monkey`
+const MONKEY_THROTTLE: u64 = 300;
+
+// Start the package's launcher activity
+pub fn launch_app(device_cmd: &str, pkg: &str) {
+    adb_shell(
+        device_cmd,
+        &format!("monkey -p {} -c android.intent.category.LAUNCHER 1", pkg),
+    );
+    // Give the app a moment to settle before sampling
+    thread::sleep(Duration::from_secs(2));
+}
+
+// A seed for runs not given one, recorded with the run so it can be repeated
+pub fn random_seed() -> u64 {
+    (now_millis() ^ (u64::from(std::process::id()) << 16)) % 1_000_000_000
+}
+
+// Monkey sending `events` random events to the app, the same ones for the same `seed`. System
+// keys are left out, home or power would end the scenario.
+pub fn monkey_command(pkg: &str, seed: u64, events: u32) -> String {
+    format!(
+        "monkey -p {} -s {} --throttle {} --pct-syskeys 0 {}",
+        pkg, seed, MONKEY_THROTTLE, events
+    )
+}
+
+// `--monkey`: random input to the app while it is sampled, in the background
+pub fn start_monkey(device_cmd: &str, pkg: &str, seed: u64, events: u32) -> JoinHandle<()> {
+    println!("随机输入: monkey {} 个事件, seed {}", events, seed);
+    let (device_cmd, command) = (device_cmd.to_string(), monkey_command(pkg, seed, events));
+    thread::spawn(move || {
+        adb_shell(&device_cmd, &command);
+    })
+}
+
+// End a monkey that outlasted the run, the device keeps running it after the adb shell is gone
+pub fn stop_monkey(device_cmd: &str, monkey: JoinHandle<()>) {
+    if !monkey.is_finished() {
+        adb_shell(device_cmd, "pkill -f com.android.commands.monkey");
+    }
+    let _ = monkey.join();
+}
+
+// Stop the package and start it again, so every measured run begins from a cold launch
+pub fn cold_launch(device_cmd: &str, pkg: &str) {
+    adb_shell(device_cmd, &format!("am force-stop {}", pkg));
+    launch_app(device_cmd, pkg);
+}
+
+// Resolve the launcher activity component (`pkg/.MainActivity`) of the package
+pub fn launcher_component(device_cmd: &str, pkg: &str) -> Option<String> {
+    let result = adb_shell(
+        device_cmd,
+        &format!(
+            "cmd package resolve-activity --brief -c android.intent.category.LAUNCHER {}",
+            pkg
+        ),
+    );
+    result
+        .lines()
+        .map(str::trim)
+        .rfind(|line| line.starts_with(pkg) && line.contains('/'))
+        .map(str::to_string)
+}
+
+// Start the launcher activity with `am start -W` and return its TotalTime in milliseconds
+pub fn start_activity_wait(device_cmd: &str, pkg: &str) -> Option<u64> {
+    let component = launcher_component(device_cmd, pkg)?;
+    let result = adb_shell(device_cmd, &format!("am start -W -n {}", component));
+    result
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("TotalTime:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+pub fn install(device_cmd: &str, apk: &str) -> Result<(), String> {
+    println!("安装: {}", apk);
+    let staged = transport::upload(apk)?;
+    let install_result = run_adb_command(&adb_argv(device_cmd, &["install", "-r", "-g", &staged]));
+    if staged != apk {
+        transport::remove_staged(&staged);
+    }
+    if install_result.contains("Success") {
+        Ok(())
+    } else {
+        Err(format!(
+            "Failed to install {}: {}",
+            apk,
+            install_result.trim()
+        ))
+    }
+}
+
+// Run a host-side script, with `ANDROID_SERIAL` pointing adb at the device under test and
+// `CPUREPORT_SEED` seeding any random input it makes
+pub fn run_host_script(
+    script: &str,
+    device: &str,
+    pkg: &str,
+    seed: Option<u64>,
+) -> Result<(), String> {
+    println!("执行脚本: {}", script);
+    let mut cmd = if cfg!(target_os = "windows") {
+        let mut win_cmd = Command::new("cmd");
+        win_cmd.arg("/C");
+        win_cmd
+    } else {
+        let mut sh_cmd = Command::new("sh");
+        sh_cmd.arg("-c");
+        sh_cmd
+    };
+    cmd.arg(script).env("CPUREPORT_PACKAGE", pkg);
+    // the script runs here, it reaches the device through the same host
+    if let Some(host) = transport::adb_host() {
+        cmd.env("CPUREPORT_ADB_HOST", host);
+    }
+    if !device.is_empty() {
+        cmd.env("ANDROID_SERIAL", device);
+    }
+    if let Some(seed) = seed {
+        cmd.env("CPUREPORT_SEED", seed.to_string());
+    }
+
+    let status = cmd
+        .status()
+        .map_err(|e| format!("Failed to execute script {}: {}", script, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("script {} failed: {}", script, status))
+    }
+}
+
+// Result of the first launch after an upgrade
+pub struct UpgradeLaunch {
+    pub total_time: Option<u64>,
+    pub migration_time: Option<u64>,
+}
+
+// Install the old apk with fresh data, let the script populate it, then upgrade to the new apk
+pub fn prepare_upgrade(
+    device_cmd: &str,
+    device: &str,
+    pkg: &str,
+    old_apk: &str,
+    data_script: Option<&str>,
+    new_apk: &str,
+    seed: Option<u64>,
+) -> Result<(), String> {
+    run_adb_command(&adb_argv(device_cmd, &["uninstall", pkg]));
+    install(device_cmd, old_apk)?;
+    launch_app(device_cmd, pkg);
+    if let Some(script) = data_script {
+        run_host_script(script, device, pkg, seed)?;
+    }
+    adb_shell(device_cmd, &format!("am force-stop {}", pkg));
+
+    install(device_cmd, new_apk)?;
+    run_adb_command(&adb_argv(device_cmd, &["logcat", "-c"]));
+    Ok(())
+}
+
+// Launch the upgraded app once, timing until `migration_marker` shows up in logcat if given
+pub fn first_launch(
+    device_cmd: &str,
+    pkg: &str,
+    migration_marker: Option<&str>,
+    timeout: Duration,
+) -> UpgradeLaunch {
+    let launched_at = Instant::now();
+    let total_time = start_activity_wait(device_cmd, pkg);
+
+    let migration_time = match migration_marker {
+        Some(marker) => {
+            let mut found = None;
+            while launched_at.elapsed() < timeout {
+                let log = run_adb_command(&adb_argv(device_cmd, &["logcat", "-d"]));
+                if log.contains(marker) {
+                    found = Some(launched_at.elapsed().as_millis() as u64);
+                    break;
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+            found
+        }
+        None => total_time,
+    };
+
+    UpgradeLaunch {
+        total_time,
+        migration_time,
+    }
+}
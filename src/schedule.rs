@@ -0,0 +1,125 @@
+use crate::collector::Priority;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+// Collectors sampling the device at the same time, one adb connection serves them all
+pub const DEFAULT_SLOTS: usize = 2;
+
+// Samples a collector took late or left out because it was behind
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lag {
+    // taken more than an interval after they were due
+    pub late: usize,
+    // not taken, a low priority collector gives up the samples it is behind on
+    pub skipped: usize,
+}
+
+#[derive(Default)]
+struct State {
+    busy: usize,
+    // (priority, deadline, ticket) of every collector waiting for its turn
+    waiting: Vec<(Priority, u64, u64)>,
+    next_ticket: u64,
+    lags: BTreeMap<&'static str, Lag>,
+}
+
+// Hands out turns on the device to the collectors of a run, at most `slots` at a time. The
+// highest priority goes first, then the earliest deadline, so cpu is read on time while a
+// heavy dumpsys waits.
+#[derive(Clone)]
+pub struct Scheduler {
+    slots: usize,
+    state: Arc<(Mutex<State>, Condvar)>,
+}
+
+// A collector's turn, the slot is free again on drop
+pub struct Turn {
+    scheduler: Scheduler,
+}
+
+impl Scheduler {
+    pub fn new(slots: usize) -> Self {
+        Scheduler {
+            slots: slots.max(1),
+            state: Arc::new((Mutex::new(State::default()), Condvar::new())),
+        }
+    }
+
+    // Wait for a turn of a collector of `priority` whose sample is due at `deadline` (unix
+    // milliseconds)
+    pub fn turn(&self, priority: Priority, deadline: u64) -> Turn {
+        let (lock, ready) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push((priority, deadline, ticket));
+        loop {
+            let first = state
+                .waiting
+                .iter()
+                .min_by_key(|(priority, deadline, ticket)| (*priority, *deadline, *ticket))
+                .map(|(_, _, ticket)| *ticket);
+            if state.busy < self.slots && first == Some(ticket) {
+                break;
+            }
+            state = ready.wait(state).unwrap();
+        }
+        state.waiting.retain(|(_, _, other)| *other != ticket);
+        state.busy += 1;
+        // the next in line may fit into another free slot
+        ready.notify_all();
+        Turn {
+            scheduler: self.clone(),
+        }
+    }
+
+    pub fn record_late(&self, collector: &'static str) {
+        let mut state = self.state.0.lock().unwrap();
+        state.lags.entry(collector).or_default().late += 1;
+    }
+
+    pub fn record_skipped(&self, collector: &'static str, samples: usize) {
+        let mut state = self.state.0.lock().unwrap();
+        state.lags.entry(collector).or_default().skipped += samples;
+    }
+
+    // Collectors that fell behind, by name
+    pub fn lags(&self) -> BTreeMap<&'static str, Lag> {
+        self.state.0.lock().unwrap().lags.clone()
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        let (lock, ready) = &*self.scheduler.state;
+        lock.lock().unwrap().busy -= 1;
+        ready.notify_all();
+    }
+}
+
+pub fn lags_json(lags: &BTreeMap<String, Lag>) -> Value {
+    let map: Map<String, Value> = lags
+        .iter()
+        .map(|(collector, lag)| {
+            (
+                collector.clone(),
+                json!({ "late": lag.late, "skipped": lag.skipped }),
+            )
+        })
+        .collect();
+    Value::Object(map)
+}
+
+pub fn print_lags(lags: &BTreeMap<String, Lag>) {
+    for (collector, lag) in lags {
+        let mut parts = Vec::new();
+        if lag.late > 0 {
+            parts.push(format!("迟到 {} 个样本", lag.late));
+        }
+        if lag.skipped > 0 {
+            parts.push(format!("跳过 {} 个样本", lag.skipped));
+        }
+        println!("调度: {} {}", collector, parts.join(", "));
+    }
+}
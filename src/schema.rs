@@ -0,0 +1,233 @@
+use crate::calibration::CALIBRATION_VERSION;
+use crate::jsonreport::JSON_REPORT_VERSION;
+use crate::manifest::MANIFEST_VERSION;
+use crate::sample::Unit;
+use crate::startup::STARTUP_VERSION;
+use crate::sweep::SWEEP_VERSION;
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+// The machine readable files cpureport writes. Every one has a `schema` field naming its kind
+// and a `version` of its layout, readers upgrade older files with `upgrade` before using them
+// and `cpureport migrate` rewrites them, so results kept for trends stay readable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Report,
+    Manifest,
+    Sweep,
+    Startup,
+    Calibration,
+}
+
+impl Kind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Report => "report",
+            Kind::Manifest => "manifest",
+            Kind::Sweep => "sweep",
+            Kind::Startup => "startup",
+            Kind::Calibration => "calibration",
+        }
+    }
+
+    fn from_label(label: &str) -> Option<Kind> {
+        [
+            Kind::Report,
+            Kind::Manifest,
+            Kind::Sweep,
+            Kind::Startup,
+            Kind::Calibration,
+        ]
+        .into_iter()
+        .find(|kind| kind.label() == label)
+    }
+
+    // Version files of this kind are written with
+    pub fn current(self) -> u64 {
+        match self {
+            Kind::Report => JSON_REPORT_VERSION,
+            Kind::Manifest => MANIFEST_VERSION,
+            Kind::Sweep => SWEEP_VERSION,
+            Kind::Startup => STARTUP_VERSION,
+            Kind::Calibration => CALIBRATION_VERSION,
+        }
+    }
+
+    // The kind of `value` by its `schema` field, or for files from before it by the fields
+    // only that kind has
+    pub fn detect(value: &Value) -> Option<Kind> {
+        if let Some(label) = value["schema"].as_str() {
+            return Kind::from_label(label);
+        }
+        let has = |key: &str| value.get(key).is_some();
+        if has("argv") && has("run") {
+            Some(Kind::Manifest)
+        } else if has("series") && has("statistics") {
+            Some(Kind::Report)
+        } else if has("sweep") && has("configurations") {
+            Some(Kind::Sweep)
+        } else if has("ttid_ms") && has("launches") {
+            Some(Kind::Startup)
+        } else if has("fingerprint") && has("metrics") {
+            Some(Kind::Calibration)
+        } else {
+            None
+        }
+    }
+}
+
+// Upgrade `value` from version `from` to the next one
+fn step(kind: Kind, from: u64, value: &mut Value) {
+    match (kind, from) {
+        // manifests from before the schema field read like the first version
+        (Kind::Manifest, 0) => {}
+        // samples were bare values, the good samples of the run without their time
+        (Kind::Manifest, 1) => {
+            for (metric, unit) in [("cpu", Unit::Percent), ("mem", Unit::Kilobytes)] {
+                let Some(samples) = value["run"][metric].as_array_mut() else {
+                    continue;
+                };
+                for sample in samples.iter_mut() {
+                    if let Some(number) = sample.as_f64() {
+                        *sample = json!({
+                            "ts": 0,
+                            "metric": metric,
+                            "value": number,
+                            "unit": unit.label(),
+                            "quality": "good",
+                        });
+                    }
+                }
+            }
+        }
+        // sweeps, startups and calibrations had no version, the first one has the same layout
+        _ => {}
+    }
+}
+
+// Bring `value` to the current layout of `kind`, the version it had before
+pub fn upgrade(kind: Kind, value: &mut Value) -> Result<u64, String> {
+    if !value.is_object() {
+        return Err(format!("A {} is a JSON object", kind.label()));
+    }
+    let version = value["version"].as_u64().unwrap_or(0);
+    if version > kind.current() {
+        return Err(format!(
+            "{} version {} is newer than this cpureport supports ({})",
+            kind.label(),
+            version,
+            kind.current()
+        ));
+    }
+    for from in version..kind.current() {
+        step(kind, from, value);
+    }
+    value["schema"] = json!(kind.label());
+    value["version"] = json!(kind.current());
+    Ok(version)
+}
+
+// Every `.json` file in `path` and the directories under it, or `path` itself for a file
+fn json_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            json_files(&entry, files)?;
+        } else if entry
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+// `cpureport migrate`: rewrite the files of older versions in `paths` in the current layout.
+// Files of a directory that are not cpureport's (benchmark data, anything else) are left alone.
+pub fn migrate(paths: &[String], dry_run: bool) -> Result<(), String> {
+    let mut files = Vec::new();
+    for path in paths {
+        json_files(Path::new(path), &mut files)
+            .map_err(|e| format!("Failed to list {}: {}", path, e))?;
+    }
+    let (mut upgraded, mut current, mut failed) = (0, 0, 0);
+    for file in &files {
+        let path = file.display();
+        let mut value: Value = match std::fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|content| serde_json::from_str(&content).map_err(|e| e.to_string()))
+        {
+            Ok(value) => value,
+            Err(e) => {
+                println!("无法读取 {}: {}", path, e);
+                failed += 1;
+                continue;
+            }
+        };
+        let Some(kind) = Kind::detect(&value) else {
+            if paths.iter().any(|named| Path::new(named) == file) {
+                println!("不是 cpureport 的结果文件, 跳过: {}", path);
+            }
+            continue;
+        };
+        let before = value.clone();
+        let version = match upgrade(kind, &mut value) {
+            Ok(version) => version,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                failed += 1;
+                continue;
+            }
+        };
+        if value == before {
+            current += 1;
+            continue;
+        }
+        if !dry_run {
+            // written next to it and renamed over it, an interrupted migration leaves the old file
+            let temporary = file.with_extension("json.migrating");
+            let written = serde_json::to_string_pretty(&value)
+                .map_err(std::io::Error::from)
+                .and_then(|content| std::fs::write(&temporary, content))
+                .and_then(|_| std::fs::rename(&temporary, file));
+            if let Err(e) = written {
+                let _ = std::fs::remove_file(&temporary);
+                println!("无法写入 {}: {}", path, e);
+                failed += 1;
+                continue;
+            }
+        }
+        let change = if version == kind.current() {
+            // the layout is current, only the `schema` field is new
+            "加上 schema 字段".to_string()
+        } else {
+            format!("版本 {} -> {}", version, kind.current())
+        };
+        println!(
+            "{} {} ({}): {}",
+            if dry_run { "需要升级" } else { "已升级" },
+            path,
+            kind.label(),
+            change
+        );
+        upgraded += 1;
+    }
+    println!(
+        "迁移: {} 个文件{}, {} 个已是最新",
+        upgraded,
+        if dry_run { "需要升级" } else { "已升级" },
+        current
+    );
+    if failed > 0 {
+        return Err(format!("{} of the files could not be migrated", failed));
+    }
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use crate::RunData;
+use crate::sla;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+// `score:` of the config file, how a run is boiled down to one 0-100 number
+//
+// score:
+//   components:
+//     - { metric: cpu_avg, weight: 0.4, best: 5, worst: 60 }     # %
+//     - { metric: mem_p95, weight: 0.3, best: 100, worst: 500 }  # MB
+//     - { metric: jank_avg, weight: 0.3, best: 0, worst: 20 }    # %
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreModel {
+    pub components: Vec<Component>,
+}
+
+// A metric as in `--sla` (`cpu_avg`, `mem_max`, `timeToInitialDisplayMs`...) scored linearly, 100 at
+// `best` and 0 at `worst`, in the unit reports show it in
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Component {
+    pub metric: String,
+    pub weight: f64,
+    pub best: f64,
+    pub worst: f64,
+}
+
+impl Default for ScoreModel {
+    fn default() -> Self {
+        let component = |metric: &str, weight, best, worst| Component {
+            metric: metric.to_string(),
+            weight,
+            best,
+            worst,
+        };
+        ScoreModel {
+            components: vec![
+                component("cpu_avg", 0.35, 5.0, 60.0),
+                component("mem_avg", 0.25, 100.0, 500.0),
+                component("jank_avg", 0.2, 0.0, 20.0),
+                component("power_avg", 0.2, 200.0, 2000.0),
+            ],
+        }
+    }
+}
+
+impl ScoreModel {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.components.is_empty() {
+            return Err("score has no components".to_string());
+        }
+        for component in &self.components {
+            if component.weight.is_nan() || component.weight <= 0.0 {
+                return Err(format!(
+                    "score weight of {} must be positive",
+                    component.metric
+                ));
+            }
+            if component.best == component.worst {
+                return Err(format!(
+                    "score best and worst of {} are the same",
+                    component.metric
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Weighted mean of the components the run measured, None if it measured none of them.
+    // `scalars` are the run's single-value metrics, as for `Sla::evaluate`.
+    pub fn score(&self, data: &RunData, scalars: &[(&str, f64)]) -> Option<Score> {
+        let parts: Vec<Part> = self
+            .components
+            .iter()
+            .map(|component| {
+                let actual = sla::metric_value(data, scalars, &component.metric)
+                    .filter(|actual| !actual.is_nan());
+                let points = actual.map(|actual| {
+                    let share = (actual - component.worst) / (component.best - component.worst);
+                    share.clamp(0.0, 1.0) * 100.0
+                });
+                Part {
+                    metric: component.metric.clone(),
+                    weight: component.weight,
+                    actual,
+                    points,
+                }
+            })
+            .collect();
+        let weight: f64 = parts
+            .iter()
+            .filter(|part| part.points.is_some())
+            .map(|part| part.weight)
+            .sum();
+        if weight == 0.0 {
+            return None;
+        }
+        let value = parts
+            .iter()
+            .filter_map(|part| Some(part.points? * part.weight))
+            .sum::<f64>()
+            / weight;
+        Some(Score { value, parts })
+    }
+}
+
+// One component's share of a score
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub metric: String,
+    pub weight: f64,
+    // none if the run did not measure the metric, it is left out of the score then
+    pub actual: Option<f64>,
+    pub points: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Score {
+    // 0 to 100, higher is better
+    pub value: f64,
+    pub parts: Vec<Part>,
+}
+
+impl Score {
+    pub fn to_json(&self) -> Value {
+        let parts: Vec<Value> = self
+            .parts
+            .iter()
+            .map(|part| {
+                json!({
+                    "metric": part.metric,
+                    "weight": part.weight,
+                    "actual": part.actual,
+                    "points": part.points,
+                })
+            })
+            .collect();
+        json!({ "value": self.value, "components": parts })
+    }
+
+    // Components the run did not measure
+    pub fn missing(&self) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter(|part| part.points.is_none())
+            .map(|part| part.metric.as_str())
+            .collect()
+    }
+
+    pub fn print(&self) {
+        let parts: Vec<String> = self
+            .parts
+            .iter()
+            .filter_map(|part| Some(format!("{} {:.0}", part.metric, part.points?)))
+            .collect();
+        println!("性能得分: {:.1} / 100 ({})", self.value, parts.join(", "));
+        let missing = self.missing();
+        if !missing.is_empty() {
+            println!("  未采集, 不计入得分: {}", missing.join(", "));
+        }
+    }
+}
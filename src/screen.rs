@@ -0,0 +1,125 @@
+use crate::events::{self, Event, EventKind, EventLog, now_millis};
+use crate::{adb_shell, now};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Milliseconds between two looks at the screen
+const POLL_INTERVAL: u64 = 1000;
+// Seconds the device gets to turn the screen on or off before sampling starts
+const SETTLE_SECONDS: u64 = 1;
+
+// Screen the run is sampled with
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ScreenMode {
+    // woken up and kept on for the whole run (`svc power stayon true`)
+    On,
+    // turned off when sampling starts, like a locked phone in a pocket
+    Off,
+}
+
+// `on`, `off`, `doze` (always-on display) or `dream` (screen saver), from `mWakefulness=Awake`
+// of `dumpsys power`, none if the device does not say
+pub fn state(device_cmd: &str) -> Option<&'static str> {
+    let output = adb_shell(device_cmd, "dumpsys power");
+    let wakefulness = output
+        .split_whitespace()
+        .find_map(|token| token.strip_prefix("mWakefulness="))?;
+    match wakefulness {
+        "Awake" => Some("on"),
+        "Asleep" => Some("off"),
+        "Dozing" => Some("doze"),
+        "Dreaming" => Some("dream"),
+        _ => None,
+    }
+}
+
+// What `apply` changed, to be put back by `restore`
+pub struct ScreenControl {
+    mode: ScreenMode,
+    // `stay_on_while_plugged_in` before the run, a bit mask of the chargers keeping it on
+    stay_on: Option<String>,
+}
+
+// Turn the screen on and keep it on, or turn it off, before sampling. Staying on is switched off
+// for `off` too, else the device may wake up again while charging.
+pub fn apply(device_cmd: &str, mode: ScreenMode) -> ScreenControl {
+    let shell = |command: &str| adb_shell(device_cmd, command);
+    let stay_on = shell("settings get global stay_on_while_plugged_in");
+    let stay_on = Some(stay_on.trim().to_string()).filter(|value| value.parse::<u32>().is_ok());
+    match mode {
+        ScreenMode::On => {
+            shell("svc power stayon true");
+            shell("input keyevent KEYCODE_WAKEUP");
+            println!("屏幕常亮");
+        }
+        ScreenMode::Off => {
+            shell("svc power stayon false");
+            shell("input keyevent KEYCODE_SLEEP");
+            println!("屏幕已关闭");
+        }
+    }
+    thread::sleep(Duration::from_secs(SETTLE_SECONDS));
+    ScreenControl { mode, stay_on }
+}
+
+impl ScreenControl {
+    // Put staying on back as it was, and wake the device up after an `off` run so it is usable
+    pub fn restore(self, device_cmd: &str) {
+        let shell = |command: &str| adb_shell(device_cmd, command);
+        match &self.stay_on {
+            Some(value) => shell(&format!(
+                "settings put global stay_on_while_plugged_in {}",
+                value
+            )),
+            None => shell("svc power stayon false"),
+        };
+        if self.mode == ScreenMode::Off {
+            shell("input keyevent KEYCODE_WAKEUP");
+        }
+    }
+}
+
+// Record the screen state at the start and at every change as `screen` events, screens
+// turning off or dozing explain most steps in the curves nothing else does
+pub fn watch(
+    device_cmd: &str,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let device_cmd = device_cmd.to_string();
+    thread::spawn(move || {
+        let mut last: Option<&str> = None;
+        let mut next = now_millis();
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                if let Some(current) = state(&device_cmd).filter(|current| last != Some(*current)) {
+                    let detail = match last {
+                        Some(last) => format!("{} -> {}", last, current),
+                        None => current.to_string(),
+                    };
+                    events.record(EventKind::Screen, detail);
+                    last = Some(current);
+                }
+                next = now_millis() + POLL_INTERVAL;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    })
+}
+
+// The screen state at every time of `times`, from the run's `screen` events. None before the
+// first one, and for every time if the screen was not watched.
+pub fn states_at(events: &[Event], times: &[u64]) -> Vec<Option<String>> {
+    events::states_at(events, EventKind::Screen, times)
+}
+
+pub fn print_shares(shares: &[(String, f64)]) {
+    let text: Vec<String> = shares
+        .iter()
+        .map(|(state, share)| format!("{} {:.1}%", state, share * 100.0))
+        .collect();
+    println!("屏幕: {}", text.join(", "));
+}
@@ -0,0 +1,56 @@
+use crate::events::now_millis;
+use crate::{adb_argv, now, transport};
+use image::ImageFormat;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Width (px) screenshots are scaled down to for the html report
+const THUMBNAIL_WIDTH: u32 = 160;
+
+// A thumbnail of the screen at `time` (unix milliseconds), JPEG encoded
+pub struct Screenshot {
+    pub time: u64,
+    pub jpeg: Vec<u8>,
+}
+
+// `adb exec-out screencap -p` scaled down to a thumbnail
+pub fn capture_thumbnail(device_cmd: &str) -> Option<Vec<u8>> {
+    let argv = adb_argv(device_cmd, &["exec-out", "screencap", "-p"]);
+    let output = transport::command(&argv).output().ok()?;
+
+    let screen = image::load_from_memory_with_format(&output.stdout, ImageFormat::Png).ok()?;
+    let height = screen.height() * THUMBNAIL_WIDTH / screen.width().max(1);
+    let thumbnail = screen.thumbnail(THUMBNAIL_WIDTH, height.max(1)).to_rgb8();
+    let mut jpeg = Cursor::new(Vec::new());
+    thumbnail.write_to(&mut jpeg, ImageFormat::Jpeg).ok()?;
+    Some(jpeg.into_inner())
+}
+
+// Take a thumbnail every `every` seconds until the run ends
+pub fn watch(
+    device_cmd: &str,
+    every: u64,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<Vec<Screenshot>> {
+    let device_cmd = device_cmd.to_string();
+    thread::spawn(move || {
+        let mut screenshots = Vec::new();
+        let mut next = now_millis();
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                let time = now_millis();
+                match capture_thumbnail(&device_cmd) {
+                    Some(jpeg) => screenshots.push(Screenshot { time, jpeg }),
+                    None => println!("Failed to capture screenshot"),
+                }
+                next = time + every * 1000;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        screenshots
+    })
+}
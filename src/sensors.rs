@@ -0,0 +1,46 @@
+// The sensor service's connections, one per registered listener, with the sensors each is
+// registered for:
+//
+// 2 active connections
+// Connection Number: 0
+// 	Operating Mode: NORMAL
+// 	 com.example.app.StepListener | WakeLockRefCount 0 | uid 10123 | cache size 0 | max cache size 0
+// 	 Step Counter 0x00000013 | status: active | pending flush events 0
+pub const COMMAND: &str = "dumpsys sensorservice";
+
+// `(listeners, sensor registrations)` the app holds in `COMMAND` output. A connection is named
+// after the listener's class, so it is told apart by the app's uid, or by the package if the
+// uid is not known.
+pub fn parse(output: &str, pkg: &str, uid: Option<u32>) -> Result<(u64, u64), String> {
+    if !output.contains("active connections") {
+        return Err("no active connections in dumpsys sensorservice".to_string());
+    }
+    let owned = |line: &str| match uid {
+        Some(uid) => line
+            .split('|')
+            .any(|field| field.trim() == format!("uid {}", uid)),
+        None => line.trim().starts_with(pkg),
+    };
+    let mut listeners = 0;
+    let mut registrations = 0;
+    let mut in_connection = false;
+    let mut mine = false;
+    for line in output.lines() {
+        if line.trim_start().starts_with("Connection Number") {
+            in_connection = true;
+            mine = false;
+        } else if !line.starts_with([' ', '\t']) {
+            // the next section, like the direct connections
+            in_connection = false;
+            mine = false;
+        } else if in_connection && line.contains("WakeLockRefCount") {
+            mine = owned(line);
+            if mine {
+                listeners += 1;
+            }
+        } else if mine && line.contains("| status:") {
+            registrations += 1;
+        }
+    }
+    Ok((listeners, registrations))
+}
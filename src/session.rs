@@ -0,0 +1,131 @@
+use crate::error::Error;
+use crate::events::{EventKind, EventLog};
+use crate::failures;
+use crate::report::Report;
+use crate::{CollectOptions, Run, RunData, StdinCommands};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+// What to sample and how, for programs embedding cpureport
+#[derive(Clone)]
+pub struct Collector {
+    pkg: String,
+    device: String,
+    options: CollectOptions,
+}
+
+impl Collector {
+    // `pkg` on the only device connected over usb, with the default options
+    pub fn new(pkg: &str) -> Collector {
+        Collector {
+            pkg: pkg.to_string(),
+            device: String::new(),
+            options: CollectOptions::default(),
+        }
+    }
+
+    // Serial of the device to sample, as for `adb -s`
+    pub fn device(mut self, serial: &str) -> Collector {
+        self.device = serial.to_string();
+        self
+    }
+
+    pub fn options(mut self, options: CollectOptions) -> Collector {
+        self.options = options;
+        self
+    }
+
+    // Milliseconds between two samples
+    pub fn interval(mut self, interval: u64) -> Collector {
+        self.options.interval = interval;
+        self
+    }
+
+    // Seconds the run lasts unless stopped earlier
+    pub fn duration(mut self, duration: u64) -> Collector {
+        self.options.duration = duration;
+        self
+    }
+
+    // Sample in the background until the session is stopped or the duration has passed
+    pub fn start(&self) -> SamplingSession {
+        SamplingSession::start(&self.pkg, &self.device, &self.options)
+    }
+
+    // Sample for the whole duration, `Err` if a `strict` run cannot start
+    pub fn collect(&self) -> Result<Report, String> {
+        let options = CollectOptions {
+            stdin: StdinCommands::Ignore,
+            ..self.options.clone()
+        };
+        Run::new(&options)
+            .try_collect(&self.pkg, &device_cmd(&self.device))
+            .map(Report::new)
+    }
+}
+
+// `adb` arguments selecting `device`, `-d` if it is empty
+fn device_cmd(device: &str) -> String {
+    if device.is_empty() {
+        String::from("-d")
+    } else {
+        format!("-s {}", device)
+    }
+}
+
+// A run collecting in the background, for programs embedding cpureport
+pub struct SamplingSession {
+    events: EventLog,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<RunData, String>>,
+}
+
+impl SamplingSession {
+    // Start sampling `pkg` on `device` (empty for `adb -d`); stdin is left alone
+    pub fn start(pkg: &str, device: &str, options: &CollectOptions) -> SamplingSession {
+        let options = CollectOptions {
+            stdin: StdinCommands::Ignore,
+            ..options.clone()
+        };
+        let device_cmd = device_cmd(device);
+
+        let run = Run::new(&options);
+        let events = run.events();
+        let stop = run.stopper();
+        let pkg = pkg.to_string();
+        let thread = thread::spawn(move || run.try_collect(&pkg, &device_cmd));
+
+        SamplingSession {
+            events,
+            stop,
+            thread,
+        }
+    }
+
+    pub fn mark(&self, label: &str) {
+        self.events.record(EventKind::Marker, label);
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    // End the run (if its duration has not passed yet) and hand out the samples, `Err` if a
+    // `strict` run could not start or the run's thread panicked
+    pub fn stop(self) -> Result<RunData, Error> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.join() {
+            Ok(collected) => collected.map_err(Error::Collectors),
+            Err(panic) => Err(Error::failed(
+                "Failed to collect",
+                failures::panic_reason(panic.as_ref()),
+            )),
+        }
+    }
+
+    // `stop`, with the samples wrapped for summaries and saving
+    pub fn finish(self) -> Result<Report, Error> {
+        self.stop().map(Report::new)
+    }
+}
@@ -0,0 +1,215 @@
+use crate::transport::quote;
+use crate::{adb_argv, run_adb_command};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+// Latency, bandwidth and loss a run is sampled under, built in or from `network_profiles:` of
+// the config file:
+//
+// network_profiles:
+//   - { name: hotel-wifi, delay_ms: 120, jitter_ms: 40, rate_kbit: 2000, loss_percent: 1 }
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkProfile {
+    pub name: String,
+    #[serde(default)]
+    pub delay_ms: u32,
+    #[serde(default)]
+    pub jitter_ms: u32,
+    // unlimited if none
+    #[serde(default)]
+    pub rate_kbit: Option<u32>,
+    #[serde(default)]
+    pub loss_percent: f64,
+}
+
+// Rough figures of common networks, the delay is added to every packet each way
+pub fn builtin() -> Vec<NetworkProfile> {
+    let profile = |name: &str, delay_ms, jitter_ms, rate_kbit, loss_percent| NetworkProfile {
+        name: name.to_string(),
+        delay_ms,
+        jitter_ms,
+        rate_kbit: Some(rate_kbit),
+        loss_percent,
+    };
+    vec![
+        profile("2g", 300, 50, 50, 1.0),
+        profile("edge", 200, 40, 200, 0.5),
+        profile("3g", 100, 20, 750, 0.2),
+        profile("lte", 25, 5, 12_000, 0.0),
+        profile("lossy", 50, 20, 2_000, 5.0),
+    ]
+}
+
+// `--network-profile`, from the config file first so it can override a built-in one
+pub fn find(name: &str, configured: &[NetworkProfile]) -> Result<NetworkProfile, String> {
+    let profiles: Vec<NetworkProfile> = configured.iter().cloned().chain(builtin()).collect();
+    match profiles.iter().find(|profile| profile.name == name) {
+        Some(profile) => Ok(profile.clone()),
+        None => {
+            let names: Vec<&str> = profiles
+                .iter()
+                .map(|profile| profile.name.as_str())
+                .collect();
+            Err(format!(
+                "Failed to find network profile {}, expected one of {}",
+                name,
+                names.join(", ")
+            ))
+        }
+    }
+}
+
+// How the profile is put in place
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ShapingBackend {
+    // `tc qdisc ... netem` on the device's interface, needs root
+    Tc,
+    // the emulator console's `network delay` and `network speed`, no loss
+    Emulator,
+    // the device's global http proxy pointed at a throttling proxy on the host, which enforces
+    // the profile itself
+    Proxy,
+}
+
+impl ShapingBackend {
+    pub fn label(self) -> &'static str {
+        match self {
+            ShapingBackend::Tc => "tc",
+            ShapingBackend::Emulator => "emulator",
+            ShapingBackend::Proxy => "proxy",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Shaping {
+    pub profile: NetworkProfile,
+    pub backend: ShapingBackend,
+    // device interface `tc` shapes
+    pub interface: String,
+    // `host:port` of the throttling proxy
+    pub proxy: Option<String>,
+}
+
+impl Shaping {
+    // The emulator console of emulators, `tc` elsewhere
+    pub fn default_backend(emulator: bool) -> ShapingBackend {
+        if emulator {
+            ShapingBackend::Emulator
+        } else {
+            ShapingBackend::Tc
+        }
+    }
+
+    // The `netem` arguments of the profile, `delay 100ms 20ms rate 750kbit loss 0.2%`
+    fn netem(&self) -> String {
+        let profile = &self.profile;
+        let mut netem = format!("delay {}ms", profile.delay_ms);
+        if profile.jitter_ms > 0 {
+            netem.push_str(&format!(" {}ms", profile.jitter_ms));
+        }
+        if let Some(rate) = profile.rate_kbit {
+            netem.push_str(&format!(" rate {}kbit", rate));
+        }
+        if profile.loss_percent > 0.0 {
+            netem.push_str(&format!(" loss {}%", profile.loss_percent));
+        }
+        netem
+    }
+
+    // The arguments of the adb commands putting the profile in place, and of the ones taking
+    // it away again, after those picking the device
+    pub fn commands(&self) -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+        let profile = &self.profile;
+        let shell = |command: String| vec!["shell".to_string(), command];
+        let as_root = |command: String| shell(format!("su -c {}", quote(&command)));
+        let emu = |args: &[&str]| {
+            std::iter::once("emu")
+                .chain(args.iter().copied())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        match self.backend {
+            ShapingBackend::Tc => (
+                vec![as_root(format!(
+                    "tc qdisc replace dev {} root netem {}",
+                    quote(&self.interface),
+                    self.netem()
+                ))],
+                vec![as_root(format!(
+                    "tc qdisc del dev {} root",
+                    quote(&self.interface)
+                ))],
+            ),
+            ShapingBackend::Emulator => {
+                let delay = format!(
+                    "{}:{}",
+                    profile.delay_ms,
+                    profile.delay_ms + profile.jitter_ms
+                );
+                let speed = profile
+                    .rate_kbit
+                    .map_or("full".to_string(), |rate| format!("{}:{}", rate, rate));
+                (
+                    vec![
+                        emu(&["network", "delay", &delay]),
+                        emu(&["network", "speed", &speed]),
+                    ],
+                    vec![
+                        emu(&["network", "delay", "none"]),
+                        emu(&["network", "speed", "full"]),
+                    ],
+                )
+            }
+            ShapingBackend::Proxy => (
+                vec![shell(format!(
+                    "settings put global http_proxy {}",
+                    quote(self.proxy.as_deref().unwrap_or_default())
+                ))],
+                vec![shell("settings put global http_proxy :0".to_string())],
+            ),
+        }
+    }
+
+    // Put the profile in place before sampling. `tc` and `settings` say nothing when they
+    // succeed, the emulator console answers `OK`.
+    pub fn apply(&self, device_cmd: &str) -> Result<(), String> {
+        if self.backend == ShapingBackend::Emulator && self.profile.loss_percent > 0.0 {
+            println!("模拟器不支持丢包, 忽略 loss_percent");
+        }
+        for args in self.commands().0 {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let output = run_adb_command(&adb_argv(device_cmd, &args));
+            let output = output.trim();
+            if !output.is_empty() && output != "OK" {
+                return Err(format!(
+                    "Failed to apply network profile {}: {}",
+                    self.profile.name, output
+                ));
+            }
+        }
+        println!("网络配置: {} ({})", self.profile.name, self.backend.label());
+        Ok(())
+    }
+
+    pub fn restore(&self, device_cmd: &str) {
+        for args in self.commands().1 {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            run_adb_command(&adb_argv(device_cmd, &args));
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let profile = &self.profile;
+        json!({
+            "name": profile.name,
+            "backend": self.backend.label(),
+            "delay_ms": profile.delay_ms,
+            "jitter_ms": profile.jitter_ms,
+            "rate_kbit": profile.rate_kbit,
+            "loss_percent": profile.loss_percent,
+            "proxy": self.proxy,
+        })
+    }
+}
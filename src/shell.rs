@@ -0,0 +1,227 @@
+use crate::connection::Link;
+use crate::transport;
+use crate::{adb_argv, run_adb_command_on};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Line after the output of every command, with its number
+const DONE: &str = "===cpureport done";
+// Time a command may take before its shell is given up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Why a command did not get to its done line. What it printed before is cut off, so it is
+// thrown away instead of being read as a whole sample.
+enum Failure {
+    // the shell ended, `true` if the command printed something before
+    Closed(bool),
+    TimedOut,
+}
+
+// A running `adb shell sh`, the lines it prints come over `lines`
+#[derive(Debug)]
+struct Live {
+    device_cmd: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    // commands sent so far, numbering the done lines
+    sent: u64,
+}
+
+impl Live {
+    fn spawn(device_cmd: &str) -> Option<Live> {
+        let mut child = transport::command(&adb_argv(device_cmd, &["shell", "sh"]))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let stdin = child.stdin.take()?;
+        let mut stdout = BufReader::new(child.stdout.take()?);
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let mut line = Vec::new();
+            while stdout.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+                if sender
+                    .send(String::from_utf8_lossy(&line).into_owned())
+                    .is_err()
+                {
+                    break;
+                }
+                line.clear();
+            }
+        });
+        Some(Live {
+            device_cmd: device_cmd.to_string(),
+            child,
+            stdin,
+            lines,
+            sent: 0,
+        })
+    }
+
+    // The output of `script`, run with nothing on its stdin so it cannot read the commands after
+    // it. A command whose output does not end with a newline has the done line right after it.
+    fn run(&mut self, script: &str) -> Result<String, Failure> {
+        self.sent += 1;
+        let done = format!("{} {}", DONE, self.sent);
+        let sent = write!(
+            self.stdin,
+            "{{ {}\n}} </dev/null\necho '{}'\n",
+            script.trim_end(),
+            done
+        )
+        .and_then(|_| self.stdin.flush());
+        if sent.is_err() {
+            return Err(Failure::Closed(false));
+        }
+        let deadline = Instant::now() + TIMEOUT;
+        let mut output = String::new();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(left) {
+                Ok(line) => match line.trim_end().strip_suffix(&done) {
+                    Some(rest) => {
+                        output.push_str(rest);
+                        return Ok(output);
+                    }
+                    None => output.push_str(&line),
+                },
+                Err(RecvTimeoutError::Timeout) => return Err(Failure::TimedOut),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Failure::Closed(!output.is_empty()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Live {
+    // Let the shell exit, and kill it if it is still busy with a command
+    fn drop(&mut self) {
+        let _ = self.stdin.write_all(b"exit\n");
+        let _ = self.stdin.flush();
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // shells no collector is using right now
+    idle: Vec<Live>,
+    started: usize,
+    commands: usize,
+    // a shell ended before running anything, every command spawns its own `adb shell` again
+    unusable: bool,
+}
+
+// The shell reads of a run written to long-lived `adb shell sh` sessions instead of spawning a
+// new `adb shell` for each, which costs a few hundred milliseconds per sample. Collectors that
+// read at the same time get a session each, so there are as many as they need at once.
+#[derive(Clone, Debug, Default)]
+pub struct ShellSession {
+    state: Arc<Mutex<State>>,
+    // the connection of the run the sessions are for
+    link: Link,
+}
+
+impl ShellSession {
+    pub fn new(link: Link) -> Self {
+        ShellSession {
+            state: Arc::default(),
+            link,
+        }
+    }
+
+    // The output of `adb shell <command>`, quoted and evaluated so a command that does not
+    // parse does not take the session with it
+    pub fn shell(&self, device_cmd: &str, command: &str) -> String {
+        self.run(device_cmd, &format!("eval {}", transport::quote(command)))
+            .unwrap_or_else(|| {
+                run_adb_command_on(&adb_argv(device_cmd, &["shell", command]), Some(&self.link))
+            })
+    }
+
+    // The output of the lines of `script`, none if there is no session to run it in or it did not
+    // run to its end
+    pub fn run(&self, device_cmd: &str, script: &str) -> Option<String> {
+        let idle = {
+            let mut state = self.state.lock().unwrap();
+            if state.unusable {
+                return None;
+            }
+            let index = state
+                .idle
+                .iter()
+                .position(|live| live.device_cmd == device_cmd);
+            index.map(|index| state.idle.swap_remove(index))
+        };
+        let fresh = idle.is_none();
+        let mut live = match idle {
+            Some(live) => live,
+            None => {
+                let live = Live::spawn(device_cmd)?;
+                self.state.lock().unwrap().started += 1;
+                live
+            }
+        };
+        let started = Instant::now();
+        let result = live.run(script);
+        self.link
+            .record_latency(started.elapsed().as_millis() as u64);
+        match result {
+            Ok(output) => {
+                let mut state = self.state.lock().unwrap();
+                state.commands += 1;
+                state.idle.push(live);
+                Some(output)
+            }
+            Err(Failure::TimedOut) => {
+                println!("adb shell 会话 {} 秒无响应, 重新启动", TIMEOUT.as_secs());
+                None
+            }
+            // the device went away or the command ended the shell, the next one starts another
+            Err(Failure::Closed(true)) => None,
+            Err(Failure::Closed(false)) => {
+                let mut state = self.state.lock().unwrap();
+                // a device that went away ends it too, sessions work again once it is back
+                if fresh && !state.unusable && self.link.probe(device_cmd) {
+                    println!("adb shell 会话不可用, 每条命令单独执行");
+                    state.unusable = true;
+                }
+                None
+            }
+        }
+    }
+
+    // End the sessions, the samples are read
+    pub fn close(&self) {
+        let idle = std::mem::take(&mut self.state.lock().unwrap().idle);
+        drop(idle);
+    }
+
+    // `(sessions started, commands run in them)` so far
+    pub fn counts(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.started, state.commands)
+    }
+
+    pub fn print(&self) {
+        let (started, commands) = self.counts();
+        if commands > 0 {
+            println!("常驻 adb shell: {} 条命令, {} 个会话", commands, started);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::sample::Series;
+use crate::{RunData, stats};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+// `--sla` file, YAML (or JSON)
+//
+// conditions:
+//   - cpu_p95 < 35%
+//   - mem_max < 400MB AND jank% < 5%
+// noisy:
+//   - jank
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SlaFile {
+    conditions: Vec<String>,
+    #[serde(default)]
+    noisy: Vec<String>,
+}
+
+// Whether `metric` is one of `noisy`, a series (`jank`) covers all its statistics (`jank_p95`)
+pub fn is_noisy(noisy: &[String], metric: &str) -> bool {
+    noisy.iter().any(|name| {
+        metric == name
+            || metric
+                .rsplit_once('_')
+                .is_some_and(|(series, _)| series == name)
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn holds(self, actual: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Less => actual < threshold,
+            Comparison::LessOrEqual => actual <= threshold,
+            Comparison::Greater => actual > threshold,
+            Comparison::GreaterOrEqual => actual >= threshold,
+        }
+    }
+}
+
+// One `metric op threshold` term, memory thresholds are kept in MB
+#[derive(Clone, Debug)]
+pub struct Condition {
+    pub metric: String,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    // unit the threshold was given in after normalizing, empty if it had none
+    pub unit: &'static str,
+}
+
+impl Condition {
+    fn parse(term: &str) -> Result<Condition, String> {
+        let comparisons = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ];
+        let (metric, comparison, threshold) = comparisons
+            .iter()
+            .find_map(|(symbol, comparison)| {
+                let (metric, threshold) = term.split_once(symbol)?;
+                Some((metric.trim(), *comparison, threshold.trim()))
+            })
+            .ok_or_else(|| format!("Failed to parse SLA condition {}: no comparison", term))?;
+        let split = threshold
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+            .unwrap_or(threshold.len());
+        let (number, unit) = threshold.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("Failed to parse SLA condition {}: bad threshold", term))?;
+        let (threshold, unit) = match unit.trim() {
+            "" => (number, ""),
+            "%" => (number, "%"),
+            "ms" => (number, "ms"),
+            "s" => (number * 1000.0, "ms"),
+            "KB" => (number / 1024.0, "MB"),
+            "MB" => (number, "MB"),
+            "GB" => (number * 1024.0, "MB"),
+            unit => {
+                return Err(format!(
+                    "Failed to parse SLA condition {}: unknown unit {}",
+                    term, unit
+                ));
+            }
+        };
+        Ok(Condition {
+            metric: metric.to_string(),
+            comparison,
+            threshold,
+            unit,
+        })
+    }
+
+    pub fn text(&self) -> String {
+        format!(
+            "{} {} {}{}",
+            self.metric,
+            self.comparison.symbol(),
+            self.threshold,
+            self.unit
+        )
+    }
+}
+
+// The outcome of one condition at the end of the run
+#[derive(Clone, Debug)]
+pub struct Verdict {
+    pub condition: Condition,
+    // value of the metric in the condition's unit, none if the run did not measure it
+    pub actual: Option<f64>,
+    pub passed: bool,
+    pub note: Option<String>,
+    // on a noisy metric, reported but not failing the run
+    pub informational: bool,
+}
+
+impl Verdict {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "condition": self.condition.text(),
+            "metric": self.condition.metric,
+            "comparison": self.condition.comparison.symbol(),
+            "threshold": self.condition.threshold,
+            "unit": self.condition.unit,
+            "actual": self.actual,
+            "passed": self.passed,
+            "note": self.note,
+            "informational": self.informational,
+        })
+    }
+}
+
+// `{ "passed": .., "conditions": [..] }`
+pub fn verdicts_json(verdicts: &[Verdict]) -> Value {
+    json!({
+        "passed": passed(verdicts),
+        "conditions": verdicts.iter().map(Verdict::to_json).collect::<Vec<Value>>(),
+    })
+}
+
+// Informational verdicts do not count
+pub fn passed(verdicts: &[Verdict]) -> bool {
+    verdicts
+        .iter()
+        .all(|verdict| verdict.passed || verdict.informational)
+}
+
+pub fn print_verdicts(verdicts: &[Verdict]) {
+    if verdicts.is_empty() {
+        return;
+    }
+    println!(
+        "SLA: {}",
+        if passed(verdicts) {
+            "通过"
+        } else {
+            "未通过"
+        }
+    );
+    for verdict in verdicts {
+        let actual = verdict.actual.map_or("-".to_string(), |actual| {
+            format!("{:.2}{}", actual, verdict.condition.unit)
+        });
+        println!(
+            "  [{}] {}: {}{}{}",
+            if verdict.passed { "PASS" } else { "FAIL" },
+            verdict.condition.text(),
+            actual,
+            verdict
+                .note
+                .as_ref()
+                .map_or(String::new(), |note| format!(" ({})", note)),
+            if verdict.informational {
+                " (noisy, 仅供参考)"
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+// Conditions every run has to meet
+pub struct Sla {
+    pub conditions: Vec<Condition>,
+    // metrics whose conditions are informational only, see `is_noisy`
+    pub noisy: Vec<String>,
+}
+
+impl Sla {
+    pub fn load(path: &str) -> Result<Sla, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read SLA {}: {}", path, e))?;
+        let file: SlaFile = serde_yaml::from_str(&content)
+            .map_err(|e| format!("Failed to parse SLA {}: {}", path, e))?;
+        let mut sla = Sla::parse(&file.conditions)?;
+        sla.noisy = file.noisy;
+        Ok(sla)
+    }
+
+    // `AND` (or `&&`) joins terms, every term is a condition of its own
+    pub fn parse(expressions: &[String]) -> Result<Sla, String> {
+        let mut conditions = Vec::new();
+        for expression in expressions {
+            for term in expression
+                .split("&&")
+                .flat_map(|part| part.split(" AND "))
+                .flat_map(|part| part.split(" and "))
+            {
+                conditions.push(Condition::parse(term.trim())?);
+            }
+        }
+        Ok(Sla {
+            conditions,
+            noisy: Vec::new(),
+        })
+    }
+
+    // `scalars` are the run's single-value metrics, e.g. launch times
+    pub fn evaluate(&self, data: &RunData, scalars: &[(&str, f64)]) -> Vec<Verdict> {
+        self.conditions
+            .iter()
+            .map(|condition| Verdict {
+                informational: is_noisy(&self.noisy, &condition.metric),
+                ..evaluate(condition, data, scalars)
+            })
+            .collect()
+    }
+}
+
+// `<series>_<avg|mean|median|min|max|pNN>` over the series in the unit reports show it in
+fn series_statistic(data: &RunData, metric: &str) -> Option<(f64, &'static str)> {
+    let (name, statistic) = metric.rsplit_once('_')?;
+    let series: &Series = [&data.cpu, &data.mem]
+        .into_iter()
+        .chain(&data.series)
+        .find(|series| series.name == name)?;
+    let values = series.display_values();
+    let unit = series.unit.display_label();
+    if values.is_empty() {
+        return Some((f64::NAN, unit));
+    }
+    let value = match statistic {
+        "avg" | "mean" => stats::mean(&values),
+        "median" => stats::median(&values),
+        "min" => stats::min(&values),
+        "max" => stats::max(&values),
+        percentile => stats::percentile(&values, percentile.strip_prefix('p')?.parse().ok()?),
+    };
+    Some((value, unit))
+}
+
+// Value of `metric` in the unit reports show it in, a series statistic or one of `scalars`.
+// NaN if its series has no samples.
+pub fn metric_value(data: &RunData, scalars: &[(&str, f64)], metric: &str) -> Option<f64> {
+    series_statistic(data, metric)
+        .map(|(value, _)| value)
+        .or_else(|| {
+            scalars
+                .iter()
+                .find(|(name, _)| *name == metric)
+                .map(|(_, value)| *value)
+        })
+}
+
+fn evaluate(condition: &Condition, data: &RunData, scalars: &[(&str, f64)]) -> Verdict {
+    let found = series_statistic(data, &condition.metric).or_else(|| {
+        scalars
+            .iter()
+            .find(|(name, _)| *name == condition.metric)
+            .map(|(_, value)| (*value, condition.unit))
+    });
+    let verdict = |actual, passed, note: Option<&str>| Verdict {
+        condition: condition.clone(),
+        actual,
+        passed,
+        note: note.map(str::to_string),
+        informational: false,
+    };
+    match found {
+        // An SLA that cannot be checked is not met
+        None => verdict(None, false, Some("未采集")),
+        Some((actual, _)) if actual.is_nan() => verdict(None, false, Some("没有样本")),
+        Some((_, unit)) if !condition.unit.is_empty() && unit != condition.unit => {
+            verdict(None, false, Some(&format!("单位应为 {}", unit)))
+        }
+        Some((actual, _)) => verdict(
+            Some(actual),
+            condition.comparison.holds(actual, condition.threshold),
+            None,
+        ),
+    }
+}
@@ -0,0 +1,103 @@
+use crate::events::now_millis;
+use crate::now;
+use crate::platform::Platform;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Raw device output at one moment of the run, kept to investigate odd results afterwards
+pub struct Snapshot {
+    // unix milliseconds
+    pub time: u64,
+    // `(name, output)`, e.g. `("meminfo", "...")`
+    pub files: Vec<(&'static str, String)>,
+}
+
+// `--snapshot-every`: `300`, `30s`, `5m` or `1h`, in seconds
+pub fn parse_every(text: &str) -> Result<u64, String> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => text.split_at(idx),
+        None => (text, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(format!("Failed to parse snapshot interval {}", text)),
+    };
+    match number.parse::<u64>() {
+        Ok(value) if value > 0 => Ok(value * scale),
+        _ => Err(format!("Failed to parse snapshot interval {}", text)),
+    }
+}
+
+// meminfo, status, smaps_rollup of the main process and a full top
+pub fn take(device_cmd: &str, pkg: &str, platform: &Platform) -> Snapshot {
+    let time = now_millis();
+    let mut files = vec![
+        (
+            "meminfo",
+            platform.shell(device_cmd, &format!("dumpsys meminfo {}", pkg)),
+        ),
+        ("top", platform.shell(device_cmd, &platform.top_command())),
+    ];
+    let pids = platform.shell(device_cmd, &format!("pidof {}", pkg));
+    if let Some(pid) = pids.split_whitespace().next() {
+        files.push((
+            "status",
+            platform.shell(device_cmd, &format!("cat /proc/{}/status", pid)),
+        ));
+        // other apps' smaps are only readable as the app (debuggable builds) or root
+        let rollup = format!("cat /proc/{}/smaps_rollup", pid);
+        let mut smaps = platform.shell(device_cmd, &rollup);
+        if !smaps.contains("Pss:") {
+            smaps = platform.shell(device_cmd, &format!("run-as {} {}", pkg, rollup));
+        }
+        files.push(("smaps_rollup", smaps));
+    }
+    Snapshot { time, files }
+}
+
+// Take a snapshot at the start and then every `every` seconds until the run ends
+pub fn watch(
+    device_cmd: &str,
+    pkg: &str,
+    platform: &Platform,
+    every: u64,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<Vec<Snapshot>> {
+    let (device_cmd, pkg, platform) = (device_cmd.to_string(), pkg.to_string(), platform.clone());
+    thread::spawn(move || {
+        let mut snapshots = Vec::new();
+        let mut next = now_millis();
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                let snapshot = take(&device_cmd, &pkg, &platform);
+                println!("保存快照 ({} 个文件)", snapshot.files.len());
+                next = snapshot.time + every * 1000;
+                snapshots.push(snapshot);
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        snapshots
+    })
+}
+
+// One directory per snapshot named by its offset into the run (`0042s/meminfo.txt`)
+pub fn save(dir: &str, start: u64, snapshots: &[Snapshot]) -> Result<(), String> {
+    for snapshot in snapshots {
+        let offset = snapshot.time.saturating_sub(start) / 1000;
+        let path = Path::new(dir).join(format!("{:04}s", offset));
+        std::fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        for (name, output) in &snapshot.files {
+            let file = path.join(format!("{}.txt", name));
+            std::fs::write(&file, output)
+                .map_err(|e| format!("Failed to write {}: {}", file.display(), e))?;
+        }
+    }
+    Ok(())
+}
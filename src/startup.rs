@@ -0,0 +1,173 @@
+use crate::schema::Kind;
+use crate::{RunData, adb_shell, stats};
+use serde_json::{Value, json};
+use std::thread;
+use std::time::Duration;
+
+// Bumped when the startup result layout changes
+pub const STARTUP_VERSION: u64 = 1;
+
+// How the app is started for `startup`
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum LaunchKind {
+    // force-stopped before every launch, the process and the activity are created
+    Cold,
+    // left with back before every launch, the process stays and the activity is created again
+    Warm,
+}
+
+impl LaunchKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            LaunchKind::Cold => "cold",
+            LaunchKind::Warm => "warm",
+        }
+    }
+}
+
+// `am start -W` output of a launch:
+//
+// Status: ok
+// LaunchState: COLD
+// Activity: com.example.app/.MainActivity
+// TotalTime: 612
+// WaitTime: 630
+pub fn start_command(component: &str) -> String {
+    format!("am start -W -n {}", component)
+}
+
+// `(TotalTime, LaunchState)` of `start_command` output, the state is only printed on Android 10
+// and later
+fn parse(output: &str) -> Option<(u64, Option<String>)> {
+    let field = |key: &str| {
+        output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(key).map(str::trim))
+    };
+    let total_time = field("TotalTime:")?.parse().ok()?;
+    let state = field("LaunchState:").map(str::to_lowercase);
+    Some((total_time, state))
+}
+
+// Bring the device to the state a launch of `kind` starts from
+pub fn prepare(device_cmd: &str, pkg: &str, kind: LaunchKind) {
+    match kind {
+        LaunchKind::Cold => {
+            adb_shell(device_cmd, &format!("am force-stop {}", pkg));
+        }
+        LaunchKind::Warm => {
+            adb_shell(device_cmd, "input keyevent 4");
+            // let the activity be destroyed before it is started again
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+// Start `component` and wait until its first frame, the TotalTime (time to initial display)
+// and launch state reported. Err if the activity did not start.
+pub fn launch(device_cmd: &str, component: &str) -> Result<(u64, Option<String>), String> {
+    let output = adb_shell(device_cmd, &start_command(component));
+    parse(&output).ok_or_else(|| format!("Failed to launch {}: {}", component, output.trim()))
+}
+
+// One measured launch and the app sampled from just before it
+pub struct Launch {
+    pub ttid: u64,
+    // `cold`, `warm` or `hot` as the system saw it, a warm launch that came out cold means the
+    // process was killed in between
+    pub state: Option<String>,
+    pub data: RunData,
+}
+
+impl Launch {
+    // `(cpu average, cpu peak, memory average, memory peak)` while it started
+    fn profile(&self) -> (f64, f64, f64, f64) {
+        let cpu = self.data.cpu.values();
+        let mem = self.data.mem.display_values();
+        (
+            stats::mean(&cpu),
+            stats::max(&cpu),
+            stats::mean(&mem),
+            stats::max(&mem),
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        let (cpu_avg, cpu_peak, mem_avg, mem_peak) = self.profile();
+        json!({
+            "ttid_ms": self.ttid,
+            "launch_state": self.state,
+            "cpu_avg": cpu_avg,
+            "cpu_peak": cpu_peak,
+            "mem_avg_mb": mem_avg,
+            "mem_peak_mb": mem_peak,
+        })
+    }
+}
+
+fn ttids(launches: &[Launch]) -> Vec<f64> {
+    launches.iter().map(|launch| launch.ttid as f64).collect()
+}
+
+// `(min, avg, p90)` time to initial display of the launches
+pub fn ttid(launches: &[Launch]) -> (f64, f64, f64) {
+    let ttids = ttids(launches);
+    (
+        stats::min(&ttids),
+        stats::mean(&ttids),
+        stats::percentile(&ttids, 90.0),
+    )
+}
+
+pub fn startup_json(kind: LaunchKind, launches: &[Launch]) -> Value {
+    let (min, avg, p90) = ttid(launches);
+    let profiles: Vec<(f64, f64, f64, f64)> = launches.iter().map(Launch::profile).collect();
+    let average = |pick: fn(&(f64, f64, f64, f64)) -> f64| {
+        stats::mean(&profiles.iter().map(pick).collect::<Vec<f64>>())
+    };
+    json!({
+        "schema": Kind::Startup.label(),
+        "version": STARTUP_VERSION,
+        "kind": kind.label(),
+        "ttid_ms": { "min": min, "avg": avg, "p90": p90 },
+        "cpu_avg": average(|profile| profile.0),
+        "cpu_peak": average(|profile| profile.1),
+        "mem_avg_mb": average(|profile| profile.2),
+        "mem_peak_mb": average(|profile| profile.3),
+        "launches": launches.iter().map(Launch::to_json).collect::<Vec<Value>>(),
+    })
+}
+
+pub fn print_startup(kind: LaunchKind, launches: &[Launch]) {
+    if launches.is_empty() {
+        return;
+    }
+    let (min, avg, p90) = ttid(launches);
+    println!(
+        "启动 ({}, {} 次): TTID 最小 {:.0}ms 平均 {:.0}ms P90 {:.0}ms",
+        kind.label(),
+        launches.len(),
+        min,
+        avg,
+        p90
+    );
+    for (index, launch) in launches.iter().enumerate() {
+        let (cpu_avg, cpu_peak, mem_avg, mem_peak) = launch.profile();
+        println!(
+            "  #{:<3} {:>6}ms {:<5} cpu均值 {:.2}% cpu峰值 {:.2}% 内存均值 {:.2}MB 内存峰值 {:.2}MB",
+            index + 1,
+            launch.ttid,
+            launch.state.as_deref().unwrap_or("-"),
+            cpu_avg,
+            cpu_peak,
+            mem_avg,
+            mem_peak
+        );
+    }
+}
+
+pub fn save_startup(path: &str, kind: LaunchKind, launches: &[Launch]) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&startup_json(kind, launches))
+        .map_err(|e| format!("Failed to serialize startup: {}", e))?;
+    std::fs::write(path, json).map_err(|e| format!("Failed to write startup {}: {}", path, e))
+}
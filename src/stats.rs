@@ -0,0 +1,159 @@
+// |r| from which a correlation is called out in the summary
+pub const STRONG_CORRELATION: f64 = 0.7;
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Sample variance (n - 1), 0 for fewer than two values
+pub fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(values);
+    values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / (values.len() - 1) as f64
+}
+
+pub fn stddev(values: &[f64]) -> f64 {
+    variance(values).sqrt()
+}
+
+// Lag-1 autocorrelation, 0 for fewer than three values or a constant series
+pub fn autocorrelation(values: &[f64]) -> f64 {
+    if values.len() < 3 {
+        return 0.0;
+    }
+    let mean = mean(values);
+    let variance: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+    if variance == 0.0 {
+        return 0.0;
+    }
+    let covariance: f64 = values
+        .windows(2)
+        .map(|pair| (pair[0] - mean) * (pair[1] - mean))
+        .sum();
+    covariance / variance
+}
+
+// Number of independent samples the series is worth: consecutive samples of a slowly changing
+// metric are correlated and carry less information than independent ones (AR(1) approximation)
+pub fn effective_sample_size(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    if n < 1.0 {
+        return 0.0;
+    }
+    let r = autocorrelation(values).clamp(0.0, 0.99);
+    (n * (1.0 - r) / (1.0 + r)).clamp(1.0, n)
+}
+
+// Two-sided 95% critical value of Student's t with `df` degrees of freedom
+fn t_critical(df: f64) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    let df = df.floor() as usize;
+    match df {
+        0..=30 => TABLE[df.max(1) - 1],
+        31..=60 => 2.000,
+        61..=120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+// Half width of the 95% confidence interval of the mean, based on the effective sample size.
+// `None` when the samples are worth fewer than two independent ones.
+pub fn mean_ci95(values: &[f64]) -> Option<f64> {
+    let n_eff = effective_sample_size(values);
+    if n_eff < 2.0 {
+        return None;
+    }
+    Some(t_critical(n_eff - 1.0) * stddev(values) / n_eff.sqrt())
+}
+
+pub fn max(values: &[f64]) -> f64 {
+    *values.iter().max_by(|a, b| a.total_cmp(b)).unwrap_or(&0.0)
+}
+
+pub fn min(values: &[f64]) -> f64 {
+    *values.iter().min_by(|a, b| a.total_cmp(b)).unwrap_or(&0.0)
+}
+
+pub fn median(values: &[f64]) -> f64 {
+    percentile(values, 50.0)
+}
+
+// Percentile `p` (0-100) with linear interpolation between the closest ranks
+pub fn percentile(values: &[f64], p: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+// " (95% CI ±x)" for printing next to an average, empty if there are too few samples
+pub fn ci95_text(values: &[f64]) -> String {
+    mean_ci95(values).map_or(String::new(), |ci| format!(" (95% CI ±{:.2})", ci))
+}
+
+// Pearson correlation coefficient of two series paired by sample index,
+// `None` if there are too few pairs or either series is constant
+pub fn pearson(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len().min(b.len());
+    if n < 3 {
+        return None;
+    }
+    let (a, b) = (&a[..n], &b[..n]);
+    let (mean_a, mean_b) = (mean(a), mean(b));
+
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return None;
+    }
+    Some(cov / (var_a.sqrt() * var_b.sqrt()))
+}
+
+pub struct Correlation {
+    pub a: String,
+    pub b: String,
+    pub r: f64,
+}
+
+impl Correlation {
+    pub fn is_strong(&self) -> bool {
+        self.r.abs() >= STRONG_CORRELATION
+    }
+}
+
+// Correlation of every pair of the named series
+pub fn correlations(series: &[(&str, &[f64])]) -> Vec<Correlation> {
+    let mut result = Vec::new();
+    for (i, (name_a, a)) in series.iter().enumerate() {
+        for (name_b, b) in &series[i + 1..] {
+            if let Some(r) = pearson(a, b) {
+                result.push(Correlation {
+                    a: name_a.to_string(),
+                    b: name_b.to_string(),
+                    r,
+                });
+            }
+        }
+    }
+    result
+}
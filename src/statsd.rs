@@ -0,0 +1,98 @@
+use crate::sample::Sample;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Prefix of every gauge, e.g. `cpureport.cpu`
+const PREFIX: &str = "cpureport";
+
+// How tags are sent
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum StatsdFlavor {
+    // DogStatsD `|#tag:value` tags, also understood by Telegraf and the Datadog agent
+    Dogstatsd,
+    // plain StatsD has no tags, they become part of the name: `cpureport.<package>.<device>.cpu`
+    Plain,
+}
+
+// Emits every sample as a gauge over UDP, a collector that is down loses nothing but gauges
+#[derive(Clone)]
+pub struct StatsdExporter {
+    socket: Arc<UdpSocket>,
+    flavor: StatsdFlavor,
+    // `(name, value)` pairs, device and package of the run
+    tags: Vec<(String, String)>,
+    failed: Arc<AtomicBool>,
+}
+
+// Characters StatsD uses as separators
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| if "|:#,@ ".contains(c) { '_' } else { c })
+        .collect()
+}
+
+impl StatsdExporter {
+    // `address` is `host:port`, 8125 being the usual port
+    pub fn connect(
+        address: &str,
+        flavor: StatsdFlavor,
+        tags: &[(&str, &str)],
+    ) -> Result<StatsdExporter, String> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .and_then(|socket| socket.connect(address).map(|_| socket))
+            .map_err(|e| format!("Failed to connect to statsd {}: {}", address, e))?;
+        Ok(StatsdExporter {
+            socket: Arc::new(socket),
+            flavor,
+            tags: tags
+                .iter()
+                .map(|(name, value)| (sanitize(name), sanitize(value)))
+                .collect(),
+            failed: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    // `cpureport.mem:412.5|g|#device:emulator-5554,package:com.example.app,unit:MB,quality:good`
+    fn line(&self, sample: &Sample) -> String {
+        let value = sample.unit.to_display(sample.value);
+        match self.flavor {
+            StatsdFlavor::Dogstatsd => {
+                let tags: Vec<String> = self
+                    .tags
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", name, value))
+                    .chain([
+                        format!(
+                            "unit:{}",
+                            sample.unit.display_label().replace('%', "percent")
+                        ),
+                        format!("quality:{}", sample.quality.label()),
+                    ])
+                    .collect();
+                format!(
+                    "{}.{}:{}|g|#{}",
+                    PREFIX,
+                    sample.metric,
+                    value,
+                    tags.join(",")
+                )
+            }
+            StatsdFlavor::Plain => {
+                let mut name = vec![PREFIX.to_string()];
+                name.extend(self.tags.iter().map(|(_, value)| value.replace('.', "_")));
+                name.push(sample.metric.to_string());
+                format!("{}:{}|g", name.join("."), value)
+            }
+        }
+    }
+
+    pub fn send(&self, sample: &Sample) {
+        if let Err(e) = self.socket.send(self.line(sample).as_bytes())
+            && !self.failed.swap(true, Ordering::Relaxed)
+        {
+            // said once, nobody listening is common while a lab agent restarts
+            println!("statsd 发送失败: {}", e);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use serde_json::{Value, json};
+
+// Client for the STF/OpenSTF user API (`/api/v1/user/devices`)
+pub struct StfClient {
+    base_url: String,
+    token: String,
+}
+
+// A device reserved on the farm; released again when dropped
+pub struct StfLease {
+    client: StfClient,
+    serial: String,
+    pub remote_url: String,
+}
+
+impl StfClient {
+    pub fn new(base_url: &str, token: &str) -> Self {
+        StfClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    fn devices_url(&self, path: &str) -> String {
+        format!("{}/api/v1/user/devices{}", self.base_url, path)
+    }
+
+    fn auth(&self) -> String {
+        format!("Bearer {}", self.token)
+    }
+
+    // Reserve the device for the current user and open a remote adb endpoint for it
+    pub fn acquire(self, serial: &str) -> Result<StfLease, String> {
+        let mut response = ureq::post(self.devices_url(""))
+            .header("Authorization", self.auth())
+            .send_json(json!({ "serial": serial }))
+            .map_err(|e| format!("STF: failed to reserve device {}: {}", serial, e))?;
+        check_success(response.body_mut().read_json::<Value>())?;
+
+        // From here on the device is ours, so let the lease release it on any failure
+        let mut lease = StfLease {
+            client: self,
+            serial: serial.to_string(),
+            remote_url: String::new(),
+        };
+
+        let mut response = ureq::post(
+            lease
+                .client
+                .devices_url(&format!("/{}/remoteConnect", serial)),
+        )
+        .header("Authorization", lease.client.auth())
+        .send_empty()
+        .map_err(|e| format!("STF: failed to remote connect {}: {}", serial, e))?;
+        let body = check_success(response.body_mut().read_json::<Value>())?;
+        lease.remote_url = body
+            .get("remoteConnectUrl")
+            .and_then(Value::as_str)
+            .ok_or("STF: response has no remoteConnectUrl")?
+            .to_string();
+
+        Ok(lease)
+    }
+}
+
+impl Drop for StfLease {
+    fn drop(&mut self) {
+        println!("STF: releasing device {}", self.serial);
+        let remote = self
+            .client
+            .devices_url(&format!("/{}/remoteConnect", self.serial));
+        let device = self.client.devices_url(&format!("/{}", self.serial));
+        for url in [remote, device] {
+            if let Err(e) = ureq::delete(&url)
+                .header("Authorization", self.client.auth())
+                .call()
+            {
+                println!("STF: release request {} failed: {}", url, e);
+            }
+        }
+    }
+}
+
+fn check_success(body: Result<Value, ureq::Error>) -> Result<Value, String> {
+    let body = body.map_err(|e| format!("STF: invalid response: {}", e))?;
+    if body.get("success").and_then(Value::as_bool) == Some(true) {
+        Ok(body)
+    } else {
+        Err(format!(
+            "STF: request rejected: {}",
+            body.get("description")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error")
+        ))
+    }
+}
@@ -0,0 +1,70 @@
+use crate::report::summary_json;
+use crate::schema::Kind;
+use crate::{RunData, stats};
+use serde_json::{Value, json};
+
+// Bumped when the sweep layout changes
+pub const SWEEP_VERSION: u64 = 1;
+
+// One configuration of a sweep and the run measured under it
+pub struct SweepRun {
+    pub label: String,
+    pub data: RunData,
+}
+
+// The averages of the first configuration that the others are compared with
+fn averages(data: &RunData) -> (f64, f64) {
+    (
+        stats::mean(&data.cpu.values()),
+        stats::mean(&data.mem.display_values()),
+    )
+}
+
+fn relative(value: f64, reference: f64) -> String {
+    if reference == 0.0 {
+        String::new()
+    } else {
+        format!(" ({:+.1}%)", (value - reference) / reference * 100.0)
+    }
+}
+
+// One line per configuration, averages relative to the first configuration
+pub fn print_sweep(kind: &str, runs: &[SweepRun]) {
+    let Some(first) = runs.first() else {
+        return;
+    };
+    let (first_cpu, first_mem) = averages(&first.data);
+    println!("{} 对比 (相对 {}):", kind, first.label);
+    for run in runs {
+        let (cpu, mem) = averages(&run.data);
+        println!(
+            "  {:<20} cpu均值 {:.2}%{} cpu峰值 {:.2}% 内存均值 {:.2}MB{} 内存峰值 {:.2}MB",
+            run.label,
+            cpu,
+            relative(cpu, first_cpu),
+            stats::max(&run.data.cpu.values()),
+            mem,
+            relative(mem, first_mem),
+            stats::max(&run.data.mem.display_values()),
+        );
+    }
+}
+
+pub fn sweep_json(kind: &str, runs: &[SweepRun]) -> Value {
+    let configurations: Vec<Value> = runs
+        .iter()
+        .map(|run| json!({ "label": run.label, "summary": summary_json(&run.data) }))
+        .collect();
+    json!({
+        "schema": Kind::Sweep.label(),
+        "version": SWEEP_VERSION,
+        "sweep": kind,
+        "configurations": configurations,
+    })
+}
+
+pub fn save_sweep(path: &str, kind: &str, runs: &[SweepRun]) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&sweep_json(kind, runs))
+        .map_err(|e| format!("Failed to serialize sweep: {}", e))?;
+    std::fs::write(path, json).map_err(|e| format!("Failed to write sweep {}: {}", path, e))
+}
@@ -0,0 +1,48 @@
+use crate::adb_shell;
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::transport::quote;
+
+// Tag of the markers in logcat
+pub const TAG: &str = "cpureport";
+
+// Where the kernel takes text for the ftrace buffer, so a Perfetto or systrace trace recorded
+// at the same time has the markers too, older kernels only have it under debugfs
+const TRACE_MARKERS: [&str; 2] = [
+    "/sys/kernel/tracing/trace_marker",
+    "/sys/kernel/debug/tracing/trace_marker",
+];
+
+// A marker, the same text in logcat, the trace and the run's events:
+//
+//   cpureport-sync start run=1760421462974 host=1760421462980
+//
+// `run` is the start of the run and tells runs apart, `host` the host's unix time in
+// milliseconds when the marker was sent. Logcat and the trace stamp it with the device's
+// clock, the difference to `host` is what the other tools' data is shifted by.
+pub fn text(phase: &str, run: u64, host: u64) -> String {
+    format!("cpureport-sync {} run={} host={}", phase, run, host)
+}
+
+// The device's shell writing `text` to logcat and to the trace marker of either kernel, a
+// device that does not let the shell write to the trace marker still logs it
+pub fn command(text: &str) -> String {
+    let text = quote(text);
+    format!(
+        "log -t {} {}; echo {} 2>/dev/null > {} || echo {} 2>/dev/null > {}",
+        TAG, text, text, TRACE_MARKERS[0], text, TRACE_MARKERS[1]
+    )
+}
+
+// Send the marker of `phase` (`start`, `end`) of the run to the device and record it as a
+// `sync` event. Its round trip is how far off the device's stamp can be.
+pub fn emit(device_cmd: &str, events: &EventLog, phase: &str) {
+    let host = now_millis();
+    let text = text(phase, events.start(), host);
+    events.record(EventKind::Sync, text.clone());
+    adb_shell(device_cmd, &command(&text));
+    println!(
+        "同步标记 {}: adb 往返 {}ms",
+        phase,
+        now_millis().saturating_sub(host)
+    );
+}
@@ -0,0 +1,59 @@
+use crate::cores;
+use crate::sample::Series;
+use crate::stats;
+use serde_json::{Value, json};
+
+// Jiffies of the whole device since boot, the first line of `/proc/stat`:
+//
+// cpu  92307 3089 87535 4564121 11160 0 4130 0 0 0
+pub const COMMAND: &str = "head -n 1 /proc/stat";
+
+// Series of the device's cpu load, its sheet is `System`
+pub const SERIES: &str = "system";
+
+// Jiffies of the last read, one look at the device's load needs two
+#[derive(Default)]
+pub struct SystemTracker {
+    last: Option<(u64, u64)>,
+}
+
+impl SystemTracker {
+    // Percent of all cores the device was busy since the last read, none for the first read
+    pub fn update(&mut self, output: &str) -> Result<Option<f64>, String> {
+        let mut fields = output
+            .lines()
+            .map(str::split_whitespace)
+            .find_map(|mut fields| (fields.next() == Some("cpu")).then_some(fields))
+            .ok_or("no cpu line in /proc/stat")?;
+        let (busy, total) = cores::busy_total(&mut fields);
+        let Some((last_busy, last_total)) = self.last.replace((busy, total)) else {
+            return Ok(None);
+        };
+        let Some(elapsed) = total.checked_sub(last_total).filter(|elapsed| *elapsed > 0) else {
+            return Ok(None);
+        };
+        let busy = busy.saturating_sub(last_busy).min(elapsed);
+        Ok(Some(busy as f64 * 100.0 / elapsed as f64))
+    }
+}
+
+// `(average, lowest)` percent of the device's cpu that was left over while the app ran, none
+// if the load was not sampled
+pub fn headroom(series: &[Series]) -> Option<(f64, f64)> {
+    let values = series.iter().find(|series| series.name == SERIES)?.values();
+    if values.is_empty() {
+        return None;
+    }
+    Some((100.0 - stats::mean(&values), 100.0 - stats::max(&values)))
+}
+
+pub fn headroom_json(series: &[Series]) -> Option<Value> {
+    let (average, lowest) = headroom(series)?;
+    Some(json!({ "average": average, "min": lowest }))
+}
+
+pub fn print_headroom(series: &[Series]) {
+    if let Some((average, lowest)) = headroom(series) {
+        println!("系统 cpu 余量: 均值 {:.1}%, 最低 {:.1}%", average, lowest);
+    }
+}
@@ -0,0 +1,137 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rust_xlsxwriter::{Color, Format, HeaderImagePosition, Image, Worksheet, XlsxError};
+use serde::Deserialize;
+
+// `theme` of the config file, the look of the html and xlsx reports
+//
+// theme:
+//   logo: brand/logo.png
+//   colors: { primary: "#0b5394", accent: "#e69138" }
+//   footer: "Example Corp - confidential"
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    // png, jpeg or svg at the top of the html report and in the page header of the workbooks
+    #[serde(default)]
+    pub logo: Option<String>,
+    #[serde(default)]
+    pub colors: Palette,
+    // at the bottom of the html report and in the page footer of the workbooks
+    #[serde(default)]
+    pub footer: Option<String>,
+}
+
+// `#rrggbb` colors, unset ones keep the default look
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Palette {
+    // curves, headings and the header rows of the workbooks
+    #[serde(default)]
+    pub primary: Option<String>,
+    // event lines on the charts
+    #[serde(default)]
+    pub accent: Option<String>,
+    #[serde(default)]
+    pub pass: Option<String>,
+    #[serde(default)]
+    pub fail: Option<String>,
+}
+
+fn parse_color(color: &str) -> Option<u32> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+impl Theme {
+    pub fn validate(&self) -> Result<(), String> {
+        let colors = [
+            ("primary", &self.colors.primary),
+            ("accent", &self.colors.accent),
+            ("pass", &self.colors.pass),
+            ("fail", &self.colors.fail),
+        ];
+        for (name, color) in colors {
+            if let Some(color) = color
+                && parse_color(color).is_none()
+            {
+                return Err(format!("theme color {} {} is not #rrggbb", name, color));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn primary(&self) -> &str {
+        self.colors.primary.as_deref().unwrap_or("#1a73e8")
+    }
+
+    pub fn accent(&self) -> &str {
+        self.colors.accent.as_deref().unwrap_or("#d93025")
+    }
+
+    pub fn pass(&self) -> &str {
+        self.colors.pass.as_deref().unwrap_or("#188038")
+    }
+
+    pub fn fail(&self) -> &str {
+        self.colors.fail.as_deref().unwrap_or("#d93025")
+    }
+
+    // The logo as a `data:` url for the html report, none if there is none or it is unreadable
+    pub fn logo_data_url(&self) -> Option<String> {
+        let path = self.logo.as_deref()?;
+        let mime = match path.rsplit('.').next()?.to_ascii_lowercase().as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "svg" => "image/svg+xml",
+            _ => {
+                println!("不支持的 logo 格式: {}", path);
+                return None;
+            }
+        };
+        match std::fs::read(path) {
+            Ok(bytes) => Some(format!("data:{};base64,{}", mime, STANDARD.encode(bytes))),
+            Err(e) => {
+                println!("无法读取 logo {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    // Bold, and white on the primary color if the theme sets one
+    pub fn header_format(&self) -> Format {
+        let format = Format::new().set_bold();
+        match self.colors.primary.as_deref().and_then(parse_color) {
+            Some(primary) => format
+                .set_background_color(Color::RGB(primary))
+                .set_font_color(Color::White),
+            None => format,
+        }
+    }
+
+    // The logo in the page header and the footer text of a worksheet, seen when it is printed
+    // or in the page layout view
+    pub fn brand(&self, sheet: &mut Worksheet) -> Result<(), XlsxError> {
+        if let Some(logo) = &self.logo {
+            // svg cannot go into a worksheet
+            match Image::new(logo) {
+                Ok(image) => {
+                    sheet.set_header("&L&[Picture]");
+                    sheet.set_header_image(&image, HeaderImagePosition::Left)?;
+                }
+                Err(e) => println!("无法使用 logo {}: {}", logo, e),
+            }
+        }
+        if let Some(footer) = &self.footer {
+            // `&` starts a control code in header and footer strings
+            sheet.set_footer(format!("&C{}", footer.replace('&', "&&")));
+        }
+        if let Some(primary) = self.colors.primary.as_deref().and_then(parse_color) {
+            sheet.set_tab_color(Color::RGB(primary));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::events::{self, Event, EventKind};
+use serde_json::{Value, json};
+
+// Temperatures and throttling status of the thermal HAL (API 29+):
+//
+// Thermal Status: 2
+// Current temperatures from HAL:
+//     Temperature{mValue=41.0, mType=0, mName=cpu0-silver-usr, mStatus=0}
+//     Temperature{mValue=33.0, mType=2, mName=battery, mStatus=0}
+//     Temperature{mValue=34.2, mType=3, mName=skin, mStatus=0}
+pub const COMMAND: &str = "dumpsys thermalservice";
+// Devices without the service, `<zone type> <millidegrees>` per thermal zone
+pub const SYSFS_COMMAND: &str = "for zone in /sys/class/thermal/thermal_zone*; do echo $(cat $zone/type) $(cat $zone/temp); done";
+
+// Series of a reading, by `mType` of the HAL
+const SERIES: [(u32, &str); 4] = [
+    (0, "temp_cpu"),
+    (1, "temp_gpu"),
+    (2, "temp_battery"),
+    (3, "temp_skin"),
+];
+
+// One look at the device's temperatures
+pub struct Reading {
+    // `Thermal Status`, 0 (none) to 6 (shutdown), none without the thermal service
+    pub status: Option<u32>,
+    // hottest sensor of every type the device has, in degrees Celsius
+    pub temperatures: Vec<(&'static str, f64)>,
+}
+
+// `PowerManager.THERMAL_STATUS_*` names, from `light` on the device throttles
+pub fn status_label(status: u32) -> &'static str {
+    match status {
+        0 => "none",
+        1 => "light",
+        2 => "moderate",
+        3 => "severe",
+        4 => "critical",
+        5 => "emergency",
+        _ => "shutdown",
+    }
+}
+
+fn hottest(readings: impl Iterator<Item = (u32, f64)>) -> Vec<(&'static str, f64)> {
+    let readings: Vec<(u32, f64)> = readings.collect();
+    SERIES
+        .iter()
+        .filter_map(|(kind, name)| {
+            readings
+                .iter()
+                .filter(|(other, _)| other == kind)
+                .map(|(_, value)| *value)
+                .reduce(f64::max)
+                .map(|value| (*name, value))
+        })
+        .collect()
+}
+
+// The reading of `COMMAND` output, the temperatures read from the HAL right now if it has
+// them, else the cached ones
+pub fn parse_service(output: &str) -> Option<Reading> {
+    let status = output.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("Thermal Status:")
+            .and_then(|status| status.trim().parse().ok())
+    })?;
+    let section = |title: &str| -> Vec<(u32, f64)> {
+        output
+            .lines()
+            .skip_while(|line| line.trim() != title)
+            .skip(1)
+            .map_while(|line| line.trim().strip_prefix("Temperature{"))
+            .filter_map(|fields| {
+                let field = |key: &str| {
+                    fields
+                        .split(", ")
+                        .find_map(|field| field.strip_prefix(key))
+                        .map(|value| value.trim_end_matches('}'))
+                };
+                Some((
+                    field("mType=")?.parse().ok()?,
+                    field("mValue=")?.parse().ok()?,
+                ))
+            })
+            .collect()
+    };
+    let mut temperatures = section("Current temperatures from HAL:");
+    if temperatures.is_empty() {
+        temperatures = section("Cached temperatures:");
+    }
+    Some(Reading {
+        status: Some(status),
+        temperatures: hottest(temperatures.into_iter()),
+    })
+}
+
+// The reading of `SYSFS_COMMAND` output, zones are told apart by their type (`cpu-0-0-usr`,
+// `gpu0-usr`, `battery`, `skin-therm`) and read in millidegrees on most kernels
+pub fn parse_sysfs(output: &str) -> Reading {
+    let readings = output.lines().filter_map(|line| {
+        let (zone, value) = line.trim().rsplit_once(' ')?;
+        let value: f64 = value.parse().ok()?;
+        let zone = zone.to_lowercase();
+        let kind = if zone.contains("cpu") {
+            0
+        } else if zone.contains("gpu") {
+            1
+        } else if zone.contains("battery") {
+            2
+        } else if zone.contains("skin") {
+            3
+        } else {
+            return None;
+        };
+        let celsius = if value.abs() > 1000.0 {
+            value / 1000.0
+        } else {
+            value
+        };
+        Some((kind, celsius))
+    });
+    Reading {
+        status: None,
+        temperatures: hottest(readings),
+    }
+}
+
+// Milliseconds of `events` the device spent throttling, from the first `thermal` event to `end`
+pub fn throttled_millis(events: &[Event], end: u64) -> u64 {
+    let changes = events::changes(events, EventKind::Thermal);
+    changes
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, state))| *state != status_label(0))
+        .map(|(idx, (time, _))| {
+            let until = changes.get(idx + 1).map_or(end, |(next, _)| *next);
+            until.saturating_sub(*time)
+        })
+        .sum()
+}
+
+// Share of the run spent in every status and the seconds throttled, none if the status was not
+// sampled
+pub fn summary_json(events: &[Event], end: u64) -> Option<Value> {
+    let shares = events::shares(events, EventKind::Thermal, end);
+    if shares.is_empty() {
+        return None;
+    }
+    let throttled = throttled_millis(events, end);
+    Some(json!({
+        "status": events::shares_json(&shares),
+        "throttled": throttled > 0,
+        "throttled_s": throttled as f64 / 1000.0,
+    }))
+}
+
+pub fn print_summary(events: &[Event], end: u64) {
+    let shares = events::shares(events, EventKind::Thermal, end);
+    if shares.is_empty() {
+        return;
+    }
+    let text: Vec<String> = shares
+        .iter()
+        .map(|(state, share)| format!("{} {:.1}%", state, share * 100.0))
+        .collect();
+    let throttled = throttled_millis(events, end);
+    if throttled > 0 {
+        println!(
+            "温控: {}, 降频 {:.1}s",
+            text.join(", "),
+            throttled as f64 / 1000.0
+        );
+    } else {
+        println!("温控: {}", text.join(", "));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::theme::Theme;
+use rust_xlsxwriter::{RowNum, Workbook, Worksheet, XlsxError};
+use serde_json::{Value, json};
+
+// Every thread of the app's main process with its cpu share, on the scale of the app's cpu.
+// The device's shell looks up the pid:
+//
+//   TID %CPU CMD
+// 12345 12.0 RenderThread
+// 12351  3.4 OkHttp Dispatch
+pub fn command(pkg: &str) -> String {
+    format!("top -H -b -n 1 -p $(pidof {}) -o TID,%CPU,CMD", pkg)
+}
+
+// `(tid, cpu, name)` of every thread in `command` output, names can have spaces
+fn parse(output: &str) -> Vec<(u32, f64, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let tid = fields.next()?.parse().ok()?;
+            let cpu = fields.next()?.trim_end_matches('%').parse().ok()?;
+            let name: Vec<&str> = fields.collect();
+            Some((tid, cpu, name.join(" ")))
+        })
+        .collect()
+}
+
+// cpu of one thread over the run
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThreadUsage {
+    pub tid: u32,
+    pub name: String,
+    // over every read of the run, reads the thread was not there in count as idle
+    pub average: f64,
+    pub max: f64,
+    // reads the thread was there in
+    pub samples: usize,
+}
+
+impl ThreadUsage {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "tid": self.tid,
+            "name": self.name,
+            "average": self.average,
+            "max": self.max,
+            "samples": self.samples,
+        })
+    }
+}
+
+// Running totals of every thread seen, a thread is told apart by its tid and name as the
+// kernel reuses tids
+pub struct ThreadTracker {
+    // the first read is left out like the first sample of cpu, unless `--raw`
+    warmup: bool,
+    reads: usize,
+    // (tid, name, cpu summed over the reads, max, reads seen in)
+    threads: Vec<(u32, String, f64, f64, usize)>,
+}
+
+impl ThreadTracker {
+    pub fn new(raw: bool) -> Self {
+        ThreadTracker {
+            warmup: !raw,
+            reads: 0,
+            threads: Vec::new(),
+        }
+    }
+
+    // Add a read of `command`, false if it had no threads (the app was not running)
+    pub fn update(&mut self, output: &str) -> bool {
+        let threads = parse(output);
+        if threads.is_empty() {
+            return false;
+        }
+        if std::mem::take(&mut self.warmup) {
+            return true;
+        }
+        self.reads += 1;
+        for (tid, cpu, name) in threads {
+            match self
+                .threads
+                .iter_mut()
+                .find(|(other, other_name, ..)| *other == tid && *other_name == name)
+            {
+                Some((_, _, sum, max, seen)) => {
+                    *sum += cpu;
+                    *max = max.max(cpu);
+                    *seen += 1;
+                }
+                None => self.threads.push((tid, name, cpu, cpu, 1)),
+            }
+        }
+        true
+    }
+
+    // The `top` threads with the highest average cpu, busiest first
+    pub fn busiest(&self, top: usize) -> Vec<ThreadUsage> {
+        let mut threads: Vec<ThreadUsage> = self
+            .threads
+            .iter()
+            .map(|(tid, name, sum, max, seen)| ThreadUsage {
+                tid: *tid,
+                name: name.clone(),
+                average: sum / self.reads.max(1) as f64,
+                max: *max,
+                samples: *seen,
+            })
+            .collect();
+        threads.sort_by(|a, b| b.average.total_cmp(&a.average));
+        threads.truncate(top);
+        threads
+    }
+}
+
+pub fn threads_json(threads: &[ThreadUsage]) -> Value {
+    Value::Array(threads.iter().map(ThreadUsage::to_json).collect())
+}
+
+pub fn print_threads(threads: &[ThreadUsage]) {
+    if threads.is_empty() {
+        return;
+    }
+    println!("最忙的 {} 个线程:", threads.len());
+    for thread in threads {
+        println!(
+            "  {} (tid {}): 均值 {:.1}%, 峰值 {:.1}%",
+            thread.name, thread.tid, thread.average, thread.max
+        );
+    }
+}
+
+pub fn save_threads(path: &str, threads: &[ThreadUsage], theme: &Theme) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Threads")?;
+    theme.brand(sheet)?;
+    write_threads(sheet, threads, theme)?;
+    workbook.save(path)
+}
+
+// One row per thread, busiest first
+pub fn write_threads(
+    sheet: &mut Worksheet,
+    threads: &[ThreadUsage],
+    theme: &Theme,
+) -> Result<(), XlsxError> {
+    sheet.write_row_with_format(
+        0,
+        0,
+        ["Thread", "TID", "Average CPU (%)", "Max CPU (%)", "Samples"],
+        &theme.header_format(),
+    )?;
+    for (idx, thread) in threads.iter().enumerate() {
+        let row = idx as RowNum + 1;
+        sheet.write(row, 0, &thread.name)?;
+        sheet.write(row, 1, thread.tid)?;
+        sheet.write(row, 2, thread.average)?;
+        sheet.write(row, 3, thread.max)?;
+        sheet.write(row, 4, thread.samples as f64)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+// How times are written into the reports
+#[derive(Clone, Debug)]
+enum Format {
+    // `2026-01-31 14:05:09.120`
+    Plain,
+    // `2026-01-31T14:05:09.120+08:00`, `2026-01-31T06:05:09.120Z` in UTC
+    Iso8601,
+    // a strftime pattern of `--timestamp-format`
+    Pattern(String),
+}
+
+#[derive(Clone, Debug)]
+struct Timestamps {
+    utc: bool,
+    format: Format,
+}
+
+static TIMESTAMPS: OnceLock<Timestamps> = OnceLock::new();
+
+// `--utc` and `--timestamp-format`, set once before the first time is written. `format` is
+// `iso8601` or a strftime pattern like `%d.%m.%Y %H:%M:%S`, the host's time zone and
+// `2026-01-31 14:05:09.120` if not set.
+pub fn configure(utc: bool, format: Option<&str>) -> Result<(), String> {
+    let format = match format {
+        None => Format::Plain,
+        Some("iso8601") => Format::Iso8601,
+        Some(pattern) => {
+            if StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+                return Err(format!(
+                    "Failed to parse timestamp format {}, expected iso8601 or a strftime pattern",
+                    pattern
+                ));
+            }
+            Format::Pattern(pattern.to_string())
+        }
+    };
+    if TIMESTAMPS.set(Timestamps { utc, format }).is_err() {
+        println!("时间格式已设置, 忽略");
+    }
+    Ok(())
+}
+
+fn timestamps() -> Timestamps {
+    TIMESTAMPS.get().cloned().unwrap_or(Timestamps {
+        utc: false,
+        format: Format::Plain,
+    })
+}
+
+fn render<Tz: TimeZone>(time: DateTime<Tz>, format: &Format, utc: bool) -> String
+where
+    Tz::Offset: Display,
+{
+    match format {
+        Format::Plain => time.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+        Format::Iso8601 => time.to_rfc3339_opts(SecondsFormat::Millis, utc),
+        Format::Pattern(pattern) => time.format(pattern).to_string(),
+    }
+}
+
+// A unix millisecond time as the reports show it, empty if it is out of range
+pub fn format(millis: u64) -> String {
+    let Timestamps { utc, format } = timestamps();
+    if utc {
+        Utc.timestamp_millis_opt(millis as i64)
+            .single()
+            .map(|time| render(time, &format, true))
+    } else {
+        Local
+            .timestamp_millis_opt(millis as i64)
+            .single()
+            .map(|time| render(time, &format, false))
+    }
+    .unwrap_or_default()
+}
+
+// The current time for file names, `20260131_140509`. With iso8601 it is the basic format
+// with its offset, `20260131T140509+0800`, and UTC times end in `Z` either way.
+pub fn file_time() -> String {
+    let Timestamps { utc, format } = timestamps();
+    match (format, utc) {
+        (Format::Iso8601, true) => Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+        (Format::Iso8601, false) => Local::now().format("%Y%m%dT%H%M%S%z").to_string(),
+        (_, true) => Utc::now().format("%Y%m%d_%H%M%SZ").to_string(),
+        (_, false) => Local::now().format("%Y%m%d_%H%M%S").to_string(),
+    }
+}
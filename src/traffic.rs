@@ -0,0 +1,87 @@
+use crate::adb_shell;
+use serde_json::{Value, json};
+
+// Byte counters of every socket tag of every uid, up to Android 9:
+//
+// idx iface acct_tag_hex uid_tag_int cnt_set rx_bytes rx_packets tx_bytes tx_packets ...
+// 2 wlan0 0x0 10101 0 1531392 1121 84211 903 ...
+pub const QTAGUID: &str = "/proc/net/xt_qtaguid/stats";
+// Android 10 on, the counters are only read from the kernel every half hour without `--poll`
+pub const NETSTATS_COMMAND: &str = "dumpsys netstats --poll >/dev/null; dumpsys netstats detail";
+
+// What the app's uid sent and received over the run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Traffic {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+impl Traffic {
+    pub fn to_json(&self) -> Value {
+        json!({ "rx_bytes": self.rx_bytes, "tx_bytes": self.tx_bytes })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "流量: 接收 {:.1} KB, 发送 {:.1} KB",
+            self.rx_bytes as f64 / 1024.0,
+            self.tx_bytes as f64 / 1024.0
+        );
+    }
+}
+
+// Bytes received and sent by `uid` in `QTAGUID` output over every interface but loopback, none
+// without the file
+pub fn parse_qtaguid(output: &str, uid: u32) -> Option<(u64, u64)> {
+    let mut lines = output.lines();
+    if !lines.next()?.starts_with("idx iface") {
+        return None;
+    }
+    let uid = uid.to_string();
+    let (mut rx, mut tx) = (0, 0);
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // tagged rows count the same bytes again under a socket tag
+        if fields.len() < 8 || fields[1] == "lo" || fields[2] != "0x0" || fields[3] != uid {
+            continue;
+        }
+        rx += fields[5].parse::<u64>().unwrap_or(0);
+        tx += fields[7].parse::<u64>().unwrap_or(0);
+    }
+    Some((rx, tx))
+}
+
+// Bytes received and sent by `uid` since boot, summed over the `NetworkStatsHistory` buckets
+// of its untagged entries in `dumpsys netstats detail`, none if it has no entry:
+//
+//   ident=[{type=WIFI, ...}] uid=10123 set=DEFAULT tag=0x0
+//     NetworkStatsHistory: bucketDuration=7200
+//       st=1760414400 rb=18234 rp=31 tb=4410 tp=29 op=0
+pub fn parse_netstats(output: &str, uid: u32) -> Option<(u64, u64)> {
+    let entry = format!("uid={} ", uid);
+    let mut counting = false;
+    let mut found = false;
+    let (mut rx, mut tx) = (0, 0);
+    for line in output.lines().map(str::trim) {
+        if line.starts_with("ident=") {
+            counting = line.contains(&entry) && line.contains("tag=0x0");
+            found |= counting;
+        } else if counting && line.starts_with("st=") {
+            let counter = |key: &str| -> u64 {
+                line.split_whitespace()
+                    .find_map(|token| token.strip_prefix(key))
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0)
+            };
+            rx += counter("rb=");
+            tx += counter("tb=");
+        }
+    }
+    found.then_some((rx, tx))
+}
+
+// Bytes received and sent by `uid` since boot from `dumpsys netstats`
+pub fn netstats_bytes(device_cmd: &str, uid: u32) -> Option<(u64, u64)> {
+    let output = adb_shell(device_cmd, NETSTATS_COMMAND);
+    parse_netstats(&output, uid)
+}
@@ -0,0 +1,113 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+// `user@host` adb runs on, over SSH, when the device is attached to another machine
+static ADB_HOST: OnceLock<String> = OnceLock::new();
+
+// Run every adb command on `host` from now on, set once before the first command
+pub fn set_adb_host(host: &str) {
+    if ADB_HOST.set(host.to_string()).is_err() {
+        println!("adb host 已设置, 忽略 {}", host);
+    }
+}
+
+pub fn adb_host() -> Option<&'static str> {
+    ADB_HOST.get().map(String::as_str)
+}
+
+// `word` quoted for a POSIX shell, as it is if nothing in it is special
+pub fn quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+// `argv` as one line of a POSIX shell, each word quoted on its own
+pub fn join(argv: &[&str]) -> String {
+    argv.iter()
+        .map(|word| quote(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// A program and its arguments like `["adb", "-s", "emulator-5554", "shell", "dumpsys meminfo"]`,
+// run without a shell in between, or over SSH on the adb host, the only place they are quoted
+// for a shell so the one there runs the same program with the same words
+pub fn command(argv: &[&str]) -> Command {
+    match adb_host() {
+        Some(host) => {
+            let mut ssh_cmd = Command::new("ssh");
+            // never stop at a password prompt, CI has nobody to answer it
+            ssh_cmd
+                .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10", host])
+                .arg(join(argv));
+            ssh_cmd
+        }
+        None => {
+            let mut cmd = Command::new(argv.first().copied().unwrap_or_default());
+            cmd.args(argv.iter().skip(1));
+            cmd
+        }
+    }
+}
+
+// Where a local file is staged on the adb host, e.g. `/tmp/cpureport-1234-app.apk`
+fn staged_path(local: &str) -> String {
+    let name = Path::new(local)
+        .file_name()
+        .map_or("file".to_string(), |name| {
+            name.to_string_lossy().to_string()
+        });
+    format!("/tmp/cpureport-{}-{}", std::process::id(), name)
+}
+
+fn scp(from: &str, to: &str) -> Result<(), String> {
+    let status = Command::new("scp")
+        .args(["-q", "-o", "BatchMode=yes", from, to])
+        .status()
+        .map_err(|e| format!("Failed to execute scp: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("scp {} {} failed: {}", from, to, status))
+    }
+}
+
+// The path adb can read `local` at, copied to the adb host first if there is one
+pub fn upload(local: &str) -> Result<String, String> {
+    let Some(host) = adb_host() else {
+        return Ok(local.to_string());
+    };
+    let remote = staged_path(local);
+    scp(local, &format!("{}:{}", host, remote))?;
+    Ok(remote)
+}
+
+// The path adb should write a file meant for `local` to, hand it to `download` afterwards
+pub fn download_path(local: &str) -> String {
+    match adb_host() {
+        Some(_) => staged_path(local),
+        None => local.to_string(),
+    }
+}
+
+// Bring a file adb wrote to `download_path(local)` over to `local`
+pub fn download(staged: &str, local: &str) -> Result<(), String> {
+    let Some(host) = adb_host() else {
+        return Ok(());
+    };
+    let copied = scp(&format!("{}:{}", host, staged), local);
+    remove_staged(staged);
+    copied
+}
+
+// Remove a file `upload` or `download_path` put on the adb host
+pub fn remove_staged(staged: &str) {
+    if adb_host().is_some() {
+        let _ = command(&["rm", "-f", staged]).output();
+    }
+}
@@ -0,0 +1,452 @@
+// `--tray`: an icon in the system tray showing the app's current cpu and memory, with menu
+// items to start a run, add a marker, stop the run and quit, for testers who keep cpureport
+// going all day next to the app. Every run is a `--controlled` cpureport with the rest of the
+// command line, so it writes its reports like any other run. Runs until Quit.
+pub fn run(pkg: &str, argv: Vec<String>) -> Result<(), String> {
+    #[cfg(all(
+        feature = "tray",
+        any(target_os = "linux", windows, target_os = "macos")
+    ))]
+    {
+        icon::run(companion::Controller::new(pkg, argv)?)
+    }
+    #[cfg(not(all(
+        feature = "tray",
+        any(target_os = "linux", windows, target_os = "macos")
+    )))]
+    {
+        let _ = (pkg, argv);
+        Err(if cfg!(feature = "tray") {
+            "--tray is not supported on this system".to_string()
+        } else {
+            "--tray needs cpureport built with the tray feature".to_string()
+        })
+    }
+}
+
+#[cfg(feature = "tray")]
+mod companion {
+    use serde_json::{Value, json};
+    use std::io::{BufRead, BufReader, Write};
+    use std::path::PathBuf;
+    use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    // Time between two status requests to the running run
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    // What the icon shows
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Status {
+        pub running: bool,
+        // latest cpu (%) and memory (MB) of the run
+        pub cpu: Option<f64>,
+        pub mem: Option<f64>,
+        // seconds since sampling started
+        pub elapsed: f64,
+        pub markers: usize,
+        // reports of the last run that finished
+        pub reports: Vec<String>,
+        // why the last run failed, if it did
+        pub error: Option<String>,
+    }
+
+    impl Status {
+        // One line with the values, e.g. `CPU 12.3%  MEM 234.5MB  00:12:03`
+        pub fn summary(&self) -> String {
+            if !self.running {
+                return match (&self.error, self.reports.first()) {
+                    (Some(error), _) => format!("Idle, last run failed: {}", error),
+                    (None, Some(report)) => format!("Idle, last report {}", report),
+                    (None, None) => "Idle".to_string(),
+                };
+            }
+            let value = |value: Option<f64>, unit: &str| {
+                value.map_or("-".to_string(), |value| format!("{:.1}{}", value, unit))
+            };
+            let elapsed = self.elapsed as u64;
+            format!(
+                "CPU {}  MEM {}  {:02}:{:02}:{:02}",
+                value(self.cpu, "%"),
+                value(self.mem, "MB"),
+                elapsed / 3600,
+                elapsed / 60 % 60,
+                elapsed % 60
+            )
+        }
+    }
+
+    // Starts runs as child processes and talks to the one running over the control protocol
+    #[derive(Clone)]
+    pub struct Controller {
+        pub pkg: String,
+        exe: PathBuf,
+        // the command line of a run, `--controlled` is added
+        argv: Vec<String>,
+        status: Arc<Mutex<Status>>,
+        stdin: Arc<Mutex<Option<ChildStdin>>>,
+    }
+
+    impl Controller {
+        pub fn new(pkg: &str, argv: Vec<String>) -> Result<Controller, String> {
+            let exe = std::env::current_exe()
+                .map_err(|e| format!("Failed to locate cpureport: {}", e))?;
+            Ok(Controller {
+                pkg: pkg.to_string(),
+                exe,
+                argv,
+                status: Arc::new(Mutex::new(Status::default())),
+                stdin: Arc::new(Mutex::new(None)),
+            })
+        }
+
+        pub fn status(&self) -> Status {
+            self.status.lock().unwrap().clone()
+        }
+
+        fn send(&self, command: Value) {
+            if let Some(stdin) = self.stdin.lock().unwrap().as_mut() {
+                let _ = writeln!(stdin, "{}", command).and_then(|_| stdin.flush());
+            }
+        }
+
+        // Start a run, unless one is going
+        pub fn start(&self) {
+            let mut stdin = self.stdin.lock().unwrap();
+            if self.status().running {
+                return;
+            }
+            let child = Command::new(&self.exe)
+                .args(&self.argv)
+                .arg("--controlled")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn();
+            let mut child = match child {
+                Ok(child) => child,
+                Err(e) => {
+                    println!("托盘: 未能开始采集: {}", e);
+                    return;
+                }
+            };
+            *stdin = child.stdin.take();
+            let Some(stdout) = child.stdout.take() else {
+                return;
+            };
+            {
+                let mut status = self.status.lock().unwrap();
+                *status = Status {
+                    running: true,
+                    reports: std::mem::take(&mut status.reports),
+                    ..Status::default()
+                };
+            }
+            println!("托盘: 开始采集");
+            let controller = self.clone();
+            thread::spawn(move || controller.follow(child, stdout));
+            let controller = self.clone();
+            thread::spawn(move || {
+                while controller.status().running {
+                    controller.send(json!({ "cmd": "status" }));
+                    thread::sleep(POLL_INTERVAL);
+                }
+            });
+        }
+
+        // Read the run's replies until it exits, its log is printed as it comes
+        fn follow(&self, mut child: Child, stdout: ChildStdout) {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let reply: Option<Value> = serde_json::from_str(&line).ok();
+                let event = reply
+                    .as_ref()
+                    .and_then(|reply| reply.get("event"))
+                    .and_then(Value::as_str);
+                let reply = reply.as_ref().unwrap_or(&Value::Null);
+                match event {
+                    Some("ready") => self.send(json!({ "cmd": "start" })),
+                    Some("status") => {
+                        let mut status = self.status.lock().unwrap();
+                        status.cpu = reply["current"]["cpu"].as_f64().or(status.cpu);
+                        status.mem = reply["current"]["mem"].as_f64().or(status.mem);
+                        status.elapsed = reply["elapsed"].as_f64().unwrap_or(status.elapsed);
+                    }
+                    Some("marked") => {
+                        self.status.lock().unwrap().markers += 1;
+                        println!("托盘: 标记 {}", reply["label"].as_str().unwrap_or("mark"));
+                    }
+                    Some("finished") => {
+                        let reports: Vec<String> = reply["reports"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|report| report.as_str().map(str::to_string))
+                            .collect();
+                        println!("托盘: 报告 {}", reports.join(", "));
+                        self.status.lock().unwrap().reports = reports;
+                    }
+                    Some("failed") => {
+                        let message = reply["message"].as_str().unwrap_or_default();
+                        println!("托盘: 采集失败: {}", message);
+                        self.status.lock().unwrap().error = Some(message.to_string());
+                    }
+                    Some(_) => {}
+                    None => println!("{}", line),
+                }
+            }
+            let exit = child.wait();
+            *self.stdin.lock().unwrap() = None;
+            self.status.lock().unwrap().running = false;
+            match exit {
+                Ok(exit) if !exit.success() => println!("托盘: 采集结束 ({})", exit),
+                _ => println!("托盘: 采集结束"),
+            }
+        }
+
+        pub fn mark(&self) {
+            let label = format!("tray mark {}", self.status().markers + 1);
+            self.send(json!({ "cmd": "mark", "label": label }));
+        }
+
+        // End the run early, it writes its reports with what it sampled
+        pub fn stop(&self) {
+            self.send(json!({ "cmd": "stop" }));
+        }
+
+        // Stop the run, wait for its reports and exit
+        pub fn quit(&self) -> ! {
+            self.stop();
+            while self.status().running {
+                thread::sleep(Duration::from_millis(200));
+            }
+            std::process::exit(0)
+        }
+    }
+
+    // The icon, RGBA: four bars of a chart, blue while a run is going and gray otherwise
+    pub const ICON_SIZE: u32 = 32;
+
+    pub fn icon_rgba(running: bool) -> Vec<u8> {
+        let color: [u8; 4] = if running {
+            [0x0b, 0x53, 0x94, 0xff]
+        } else {
+            [0x80, 0x80, 0x80, 0xff]
+        };
+        let heights = [12, 22, 16, 28];
+        let mut rgba = vec![0; (ICON_SIZE * ICON_SIZE * 4) as usize];
+        for y in 0..ICON_SIZE {
+            for x in 0..ICON_SIZE {
+                let bar = (x / 8) as usize;
+                let inside = x % 8 >= 1 && x % 8 <= 6 && ICON_SIZE - y <= heights[bar];
+                if inside {
+                    let offset = ((y * ICON_SIZE + x) * 4) as usize;
+                    rgba[offset..offset + 4].copy_from_slice(&color);
+                }
+            }
+        }
+        rgba
+    }
+}
+
+// StatusNotifierItem over D-Bus, shown by KDE, XFCE and most other desktops (GNOME with the
+// AppIndicator extension)
+#[cfg(all(feature = "tray", target_os = "linux"))]
+mod icon {
+    use super::companion::{Controller, ICON_SIZE, Status, icon_rgba};
+    use ksni::blocking::TrayMethods;
+    use ksni::menu::{MenuItem, StandardItem};
+    use std::thread;
+    use std::time::Duration;
+
+    // Time between two refreshes of the icon
+    const REFRESH: Duration = Duration::from_millis(500);
+
+    struct Sni {
+        controller: Controller,
+        status: Status,
+    }
+
+    impl ksni::Tray for Sni {
+        fn id(&self) -> String {
+            "cpureport".to_string()
+        }
+
+        fn title(&self) -> String {
+            format!("cpureport {}", self.controller.pkg)
+        }
+
+        fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+            // ARGB in network byte order
+            let mut data = icon_rgba(self.status.running);
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.rotate_right(1);
+            }
+            vec![ksni::Icon {
+                width: ICON_SIZE as i32,
+                height: ICON_SIZE as i32,
+                data,
+            }]
+        }
+
+        fn tool_tip(&self) -> ksni::ToolTip {
+            ksni::ToolTip {
+                title: self.title(),
+                description: self.status.summary(),
+                ..Default::default()
+            }
+        }
+
+        fn menu(&self) -> Vec<MenuItem<Self>> {
+            let running = self.status.running;
+            let item = |label: &str, enabled: bool, activate: fn(&Controller)| {
+                StandardItem {
+                    label: label.to_string(),
+                    enabled,
+                    activate: Box::new(move |tray: &mut Sni| activate(&tray.controller)),
+                    ..Default::default()
+                }
+                .into()
+            };
+            vec![
+                StandardItem {
+                    label: self.status.summary(),
+                    enabled: false,
+                    ..Default::default()
+                }
+                .into(),
+                MenuItem::Separator,
+                item("Start run", !running, Controller::start),
+                item("Add marker", running, Controller::mark),
+                item("Stop run", running, Controller::stop),
+                MenuItem::Separator,
+                item("Quit", true, |controller| {
+                    let controller = controller.clone();
+                    thread::spawn(move || controller.quit());
+                }),
+            ]
+        }
+    }
+
+    pub fn run(controller: Controller) -> Result<(), String> {
+        let tray = Sni {
+            controller: controller.clone(),
+            status: controller.status(),
+        };
+        let handle = tray.spawn().map_err(|e| {
+            format!(
+                "Failed to show the tray icon, the desktop needs StatusNotifierItem support: {}",
+                e
+            )
+        })?;
+        println!("托盘图标已显示");
+        loop {
+            thread::sleep(REFRESH);
+            let status = controller.status();
+            let changed = handle.update(|tray| {
+                let changed = tray.status != status;
+                tray.status = status;
+                changed
+            });
+            if changed.is_none() {
+                return Err("The tray icon went away".to_string());
+            }
+        }
+    }
+}
+
+// The tray-icon crate, on a tao event loop that owns the main thread
+#[cfg(all(feature = "tray", any(windows, target_os = "macos")))]
+mod icon {
+    use super::companion::{Controller, ICON_SIZE, icon_rgba};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tao::event::{Event, StartCause};
+    use tao::event_loop::{ControlFlow, EventLoopBuilder};
+    use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+    use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+
+    // Time between two refreshes of the icon
+    const REFRESH: Duration = Duration::from_millis(500);
+
+    fn icon(running: bool) -> Option<Icon> {
+        Icon::from_rgba(icon_rgba(running), ICON_SIZE, ICON_SIZE).ok()
+    }
+
+    pub fn run(controller: Controller) -> Result<(), String> {
+        let failed = |e: &dyn std::fmt::Display| format!("Failed to show the tray icon: {}", e);
+        let event_loop = EventLoopBuilder::new().build();
+        let summary = MenuItem::new("Idle", false, None);
+        let start = MenuItem::new("Start run", true, None);
+        let mark = MenuItem::new("Add marker", false, None);
+        let stop = MenuItem::new("Stop run", false, None);
+        let quit = MenuItem::new("Quit", true, None);
+        let menu = Menu::new();
+        menu.append_items(&[
+            &summary,
+            &PredefinedMenuItem::separator(),
+            &start,
+            &mark,
+            &stop,
+            &PredefinedMenuItem::separator(),
+            &quit,
+        ])
+        .map_err(|e| failed(&e))?;
+
+        let mut tray: Option<TrayIcon> = None;
+        let mut shown = None;
+        event_loop.run(move |event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(Instant::now() + REFRESH);
+            // macOS wants the icon made once the loop runs
+            if let Event::NewEvents(StartCause::Init) = event {
+                let built = TrayIconBuilder::new()
+                    .with_menu(Box::new(menu.clone()))
+                    .with_tooltip(format!("cpureport {}", controller.pkg))
+                    .with_icon(icon(false).expect("Failed to draw the tray icon"))
+                    .build();
+                match built {
+                    Ok(built) => {
+                        println!("托盘图标已显示");
+                        tray = Some(built);
+                    }
+                    Err(e) => {
+                        println!("{}", failed(&e));
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            while let Ok(event) = MenuEvent::receiver().try_recv() {
+                if event.id == *start.id() {
+                    controller.start();
+                } else if event.id == *mark.id() {
+                    controller.mark();
+                } else if event.id == *stop.id() {
+                    controller.stop();
+                } else if event.id == *quit.id() {
+                    let controller = controller.clone();
+                    thread::spawn(move || controller.quit());
+                }
+            }
+
+            let status = controller.status();
+            if shown.as_ref() == Some(&status) {
+                return;
+            }
+            let text = status.summary();
+            summary.set_text(&text);
+            start.set_enabled(!status.running);
+            mark.set_enabled(status.running);
+            stop.set_enabled(status.running);
+            if let Some(tray) = &tray {
+                let _ = tray.set_tooltip(Some(format!("cpureport {}\n{}", controller.pkg, text)));
+                // next to the icon in the macOS menu bar, ignored on Windows
+                tray.set_title(status.running.then(|| text.clone()));
+                if shown.as_ref().map(|shown| shown.running) != Some(status.running) {
+                    let _ = tray.set_icon(icon(status.running));
+                }
+            }
+            shown = Some(status);
+        })
+    }
+}
@@ -0,0 +1,154 @@
+use crate::events::now_millis;
+use crate::{RunData, adb_argv, adb_shell, run_adb_command, stats, transport};
+use image::{Rgb, RgbImage};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+// `screenrecord` stops by itself after 3 minutes
+const MAX_RECORDING_SECONDS: u64 = 180;
+const STRIP_WIDTH: u32 = 720;
+const STRIP_HEIGHT: u32 = 160;
+const CPU_COLOR: Rgb<u8> = Rgb([66, 133, 244]);
+const MEM_COLOR: Rgb<u8> = Rgb([251, 140, 0]);
+
+// `adb shell screenrecord` running on the device while the run is sampled
+pub struct Recording {
+    device_cmd: String,
+    remote_path: String,
+    start: u64,
+    child: Child,
+}
+
+impl Recording {
+    pub fn start(device_cmd: &str, duration: u64) -> Result<Recording, String> {
+        let remote_path = "/sdcard/cpureport_screenrecord.mp4".to_string();
+        let time_limit = duration.clamp(1, MAX_RECORDING_SECONDS);
+        if duration > MAX_RECORDING_SECONDS {
+            println!(
+                "screenrecord最长录制{}秒, 之后的部分没有视频",
+                MAX_RECORDING_SECONDS
+            );
+        }
+        let command = format!("screenrecord --time-limit {} {}", time_limit, remote_path);
+        let child = transport::command(&adb_argv(device_cmd, &["shell", &command]))
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start screenrecord: {}", e))?;
+        Ok(Recording {
+            device_cmd: device_cmd.to_string(),
+            remote_path,
+            start: now_millis(),
+            child,
+        })
+    }
+
+    // Stop the recording and pull it to `local_path`, returns when the recording started (unix ms)
+    pub fn finish(mut self, local_path: &str) -> Result<u64, String> {
+        // Interrupt it like Ctrl-C so the mp4 is finalized, killing adb would leave it running
+        adb_shell(&self.device_cmd, "pkill -INT screenrecord");
+        let _ = self.child.wait();
+        thread::sleep(Duration::from_secs(1));
+
+        let pulled_path = transport::download_path(local_path);
+        let pull_result = run_adb_command(&adb_argv(
+            &self.device_cmd,
+            &["pull", &self.remote_path, &pulled_path],
+        ));
+        adb_shell(&self.device_cmd, &format!("rm {}", self.remote_path));
+        transport::download(&pulled_path, local_path)?;
+        if std::path::Path::new(local_path).exists() {
+            Ok(self.start)
+        } else {
+            Err(format!(
+                "Failed to pull screen recording: {}",
+                pull_result.trim()
+            ))
+        }
+    }
+}
+
+// Draw `values` scaled to the strip height as a line
+fn draw_line(strip: &mut RgbImage, values: &[f64], color: Rgb<u8>) {
+    if values.is_empty() {
+        return;
+    }
+    let top = stats::max(values).max(1.0);
+    let y = |value: f64| {
+        let margin = 4.0;
+        let height = (STRIP_HEIGHT - 1) as f64 - 2.0 * margin;
+        (margin + height - value / top * height).round() as i64
+    };
+    let step = (STRIP_WIDTH - 1) as f64 / (values.len().max(2) - 1) as f64;
+    // Linear interpolation between the neighbouring samples
+    let value_at = |x: u32| {
+        let position = x as f64 / step;
+        let i = (position.floor() as usize).min(values.len() - 1);
+        let next = (i + 1).min(values.len() - 1);
+        values[i] + (values[next] - values[i]) * (position - i as f64)
+    };
+    for x in 0..STRIP_WIDTH {
+        // Fill vertically to the previous column so steep changes stay connected
+        let previous = y(value_at(x.saturating_sub(1)));
+        let current = y(value_at(x));
+        for row in previous.min(current)..=previous.max(current) {
+            let row = row.clamp(0, STRIP_HEIGHT as i64 - 1) as u32;
+            strip.put_pixel(x, row, color);
+            strip.put_pixel((x + 1).min(STRIP_WIDTH - 1), row, color);
+        }
+    }
+}
+
+// The cpu (blue) and memory (orange) curves of the run as a strip image
+pub fn render_strip(data: &RunData, path: &str) -> Result<(), String> {
+    let mut strip = RgbImage::from_pixel(STRIP_WIDTH, STRIP_HEIGHT, Rgb([24, 24, 24]));
+    draw_line(&mut strip, &data.mem.values(), MEM_COLOR);
+    draw_line(&mut strip, &data.cpu.values(), CPU_COLOR);
+    strip
+        .save(path)
+        .map_err(|e| format!("Failed to save graph strip: {}", e))
+}
+
+// Burn the strip under the recording with ffmpeg, a cursor on the strip follows the video time.
+// `offset` is how long (ms) the recording started before the run
+pub fn composite(
+    video: &str,
+    strip: &str,
+    data: &RunData,
+    offset: u64,
+    output: &str,
+) -> Result<(), String> {
+    let run_seconds = (data.end.saturating_sub(data.start).max(1)) as f64 / 1000.0;
+    let offset_seconds = offset as f64 / 1000.0;
+    let filter = format!(
+        "[0:v]scale={w}:-2,format=yuv420p[video];[1:v]scale={w}:{h},format=yuv420p[strip];[video][strip]vstack[stacked];\
+         [stacked][2:v]overlay=x='(t-{offset})/{run}*{w}':y=H-{h}:eval=frame[out]",
+        w = STRIP_WIDTH,
+        h = STRIP_HEIGHT,
+        offset = offset_seconds,
+        run = run_seconds
+    );
+    let status = Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-loglevel",
+            "error",
+            "-i",
+            video,
+            "-loop",
+            "1",
+            "-i",
+            strip,
+        ])
+        .args(["-f", "lavfi", "-i"])
+        .arg(format!("color=c=white:s=2x{}", STRIP_HEIGHT))
+        .args(["-filter_complex", &filter, "-map", "[out]", "-shortest"])
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p", output])
+        .status()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("ffmpeg exited with {}", status))
+    }
+}
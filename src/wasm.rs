@@ -0,0 +1,224 @@
+use crate::metrics;
+use crate::transport::quote;
+use serde::Deserialize;
+
+// A plugin of the config's `wasm` list: a WebAssembly module parsing the output of a device
+// command into samples, run sandboxed with no access to the host but the output it is given:
+//
+// wasm:
+//   - name: vendor_gpu
+//     plugin: plugins/vendor_gpu.wasm
+//     command: dumpsys vendor.gpu {package}
+//     interval: 2000
+//
+// The module exports `memory`, `alloc(len: i32) -> i32` returning where the host writes the
+// output, and `collect(ptr: i32, len: i32) -> i32` returning 0 if it parsed the output. It may
+// import `cpureport.sample(name_ptr, name_len, value: f64, unit_ptr, unit_len)` to add a
+// sample of a series, the unit a label like `ms` or `count`, and `cpureport.error(ptr, len)`
+// to say why it could not parse it.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WasmPlugin {
+    // name in the partial results
+    pub name: String,
+    // `.wasm` file, or `.wat` text
+    pub plugin: String,
+    // run in the device's shell every interval, `{package}` is the app under test, quoted
+    pub command: String,
+    // milliseconds between two runs, the run's interval if not given
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+impl WasmPlugin {
+    pub fn command(&self, pkg: &str) -> String {
+        self.command.replace("{package}", &quote(pkg))
+    }
+}
+
+// Every plugin has a name of its own and loads
+pub fn validate(plugins: &[WasmPlugin]) -> Result<(), String> {
+    #[cfg(not(feature = "wasm"))]
+    if let Some(plugin) = plugins.first() {
+        return Err(format!(
+            "wasm plugin {} needs cpureport built with the wasm feature",
+            plugin.name
+        ));
+    }
+    for (index, plugin) in plugins.iter().enumerate() {
+        if metrics::is_builtin(&plugin.name) {
+            return Err(format!("wasm plugin {} is a built-in metric", plugin.name));
+        }
+        if plugins[..index]
+            .iter()
+            .any(|other| other.name == plugin.name)
+        {
+            return Err(format!("wasm plugin {} is listed twice", plugin.name));
+        }
+        if plugin.interval == Some(0) {
+            return Err(format!("wasm plugin {} has an interval of 0", plugin.name));
+        }
+        #[cfg(feature = "wasm")]
+        runtime::Plugin::load(&plugin.plugin)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "wasm")]
+pub use runtime::Plugin;
+
+#[cfg(feature = "wasm")]
+mod runtime {
+    use crate::sample::Unit;
+    use wasmi::{
+        Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits,
+        StoreLimitsBuilder, TypedFunc,
+    };
+
+    // Instructions (roughly) a plugin may run per sample before it is stopped
+    const FUEL: u64 = 10_000_000;
+    // Bytes of memory a plugin may grow to
+    const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+    // Bytes of a series name or unit, and of the reason it could not parse the output
+    const MAX_NAME: usize = 256;
+    const MAX_REASON: usize = 4096;
+
+    struct HostState {
+        samples: Vec<(String, f64, Unit)>,
+        error: Option<String>,
+        limits: StoreLimits,
+    }
+
+    // Text at `ptr` in the plugin's memory, none if out of bounds or longer than `max`, before
+    // anything is allocated for it
+    fn read_text(caller: &Caller<'_, HostState>, ptr: i32, len: i32, max: usize) -> Option<String> {
+        let memory = caller.get_export("memory").and_then(Extern::into_memory)?;
+        let (ptr, len) = (usize::try_from(ptr).ok()?, usize::try_from(len).ok()?);
+        if len > max || ptr.checked_add(len)? > memory.data_size(caller) {
+            return None;
+        }
+        let mut buffer = vec![0; len];
+        memory.read(caller, ptr, &mut buffer).ok()?;
+        Some(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    // A loaded plugin, it keeps its memory from one sample to the next
+    pub struct Plugin {
+        store: Store<HostState>,
+        memory: Memory,
+        alloc: TypedFunc<i32, i32>,
+        collect: TypedFunc<(i32, i32), i32>,
+    }
+
+    impl Plugin {
+        // Compile and instantiate the module at `path`, Err if it lacks an export
+        pub fn load(path: &str) -> Result<Plugin, String> {
+            let failed =
+                |e: &dyn std::fmt::Display| format!("Failed to load plugin {}: {}", path, e);
+            let bytes = std::fs::read(path).map_err(|e| failed(&e))?;
+            let mut config = Config::default();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config);
+            let module = Module::new(&engine, bytes).map_err(|e| failed(&e))?;
+            let state = HostState {
+                samples: Vec::new(),
+                error: None,
+                limits: StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build(),
+            };
+            let mut store = Store::new(&engine, state);
+            store.limiter(|state| &mut state.limits);
+            store.set_fuel(FUEL).map_err(|e| failed(&e))?;
+
+            let mut linker = <Linker<HostState>>::new(&engine);
+            linker
+                .func_wrap(
+                    "cpureport",
+                    "sample",
+                    |mut caller: Caller<'_, HostState>,
+                     name_ptr: i32,
+                     name_len: i32,
+                     value: f64,
+                     unit_ptr: i32,
+                     unit_len: i32| {
+                        let name = read_text(&caller, name_ptr, name_len, MAX_NAME);
+                        let unit = read_text(&caller, unit_ptr, unit_len, MAX_NAME);
+                        let state = caller.data_mut();
+                        match name {
+                            Some(name) if value.is_finite() => {
+                                let unit = unit.as_deref().and_then(Unit::from_label);
+                                state
+                                    .samples
+                                    .push((name, value, unit.unwrap_or(Unit::Count)));
+                            }
+                            _ => {
+                                state.error = Some(
+                                    "sample out of bounds, too long or not a number".to_string(),
+                                )
+                            }
+                        }
+                    },
+                )
+                .map_err(|e| failed(&e))?;
+            linker
+                .func_wrap(
+                    "cpureport",
+                    "error",
+                    |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+                        let reason = read_text(&caller, ptr, len, MAX_REASON);
+                        caller.data_mut().error = reason;
+                    },
+                )
+                .map_err(|e| failed(&e))?;
+            let instance = linker
+                .instantiate_and_start(&mut store, &module)
+                .map_err(|e| failed(&e))?;
+            let memory = instance
+                .get_memory(&store, "memory")
+                .ok_or_else(|| failed(&"no memory export"))?;
+            let alloc = instance
+                .get_typed_func::<i32, i32>(&store, "alloc")
+                .map_err(|e| failed(&e))?;
+            let collect = instance
+                .get_typed_func::<(i32, i32), i32>(&store, "collect")
+                .map_err(|e| failed(&e))?;
+            Ok(Plugin {
+                store,
+                memory,
+                alloc,
+                collect,
+            })
+        }
+
+        // The samples `(series, value, unit)` the plugin makes of `output`
+        pub fn collect(&mut self, output: &str) -> Result<Vec<(String, f64, Unit)>, String> {
+            let state = self.store.data_mut();
+            state.samples.clear();
+            state.error = None;
+            self.store.set_fuel(FUEL).map_err(|e| e.to_string())?;
+
+            let len = i32::try_from(output.len()).map_err(|_| "output too long".to_string())?;
+            let ptr = self
+                .alloc
+                .call(&mut self.store, len)
+                .map_err(|e| format!("alloc trapped: {}", e))?;
+            self.memory
+                .write(
+                    &mut self.store,
+                    usize::try_from(ptr).unwrap_or(usize::MAX),
+                    output.as_bytes(),
+                )
+                .map_err(|e| format!("alloc returned {}: {}", ptr, e))?;
+            let status = self
+                .collect
+                .call(&mut self.store, (ptr, len))
+                .map_err(|e| format!("collect trapped: {}", e))?;
+
+            let state = self.store.data_mut();
+            match (status, state.error.take()) {
+                (0, None) => Ok(std::mem::take(&mut state.samples)),
+                (_, Some(reason)) => Err(reason),
+                (status, None) => Err(format!("collect returned {}", status)),
+            }
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::sample::{Sample, Unit};
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Points of every series the page shows, the older ones scroll out
+const MAX_POINTS: usize = 600;
+// Events the page lists, the latest ones
+const MAX_EVENTS: usize = 20;
+// Bytes of a marker label, a longer body is cut
+const MAX_BODY: usize = 4096;
+// Seconds a browser has to send its request, a connection that sends nothing is dropped
+const READ_TIMEOUT: u64 = 5;
+// Requests served at the same time, connections beyond are closed right away
+const MAX_CONNECTIONS: usize = 8;
+
+struct LiveSeries {
+    name: &'static str,
+    unit: Unit,
+    // `(unix milliseconds, value in the unit reports show it in)`
+    points: VecDeque<(u64, f64)>,
+}
+
+// The run the buttons act on
+struct LiveRun {
+    events: EventLog,
+    stop: Arc<AtomicBool>,
+    // unix seconds
+    end_time: u64,
+}
+
+#[derive(Default)]
+struct LiveState {
+    series: Vec<LiveSeries>,
+    run: Option<LiveRun>,
+}
+
+// `--web-ui`: a page with the live curves of the run, its current values and events, and
+// buttons to add a marker or stop the run. The data and the buttons need the token of the url
+// printed when it starts, another page open in the same browser can neither read nor press
+// them.
+#[derive(Clone)]
+pub struct WebUi {
+    pkg: String,
+    token: String,
+    // the `Host` a request of this host alone comes with, `localhost:<port>` and
+    // `127.0.0.1:<port>`, so a name of another site resolving here is refused. Empty when
+    // serving every interface, any address of this host is fine then.
+    hosts: Vec<String>,
+    state: Arc<Mutex<LiveState>>,
+}
+
+// 128 random bits in hex
+fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Failed to make web ui token: {}", e))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+impl WebUi {
+    // Serve on `port` of this host alone, or of every interface with `all_interfaces`, until the
+    // program exits
+    pub fn serve(port: u16, pkg: &str, all_interfaces: bool) -> Result<WebUi, String> {
+        let address = if all_interfaces {
+            "0.0.0.0"
+        } else {
+            "127.0.0.1"
+        };
+        let listener = TcpListener::bind((address, port))
+            .map_err(|e| format!("Failed to serve web ui on port {}: {}", port, e))?;
+        let hosts = if all_interfaces {
+            Vec::new()
+        } else {
+            vec![format!("localhost:{}", port), format!("127.0.0.1:{}", port)]
+        };
+        let web_ui = WebUi {
+            pkg: pkg.to_string(),
+            token: new_token()?,
+            hosts,
+            state: Arc::new(Mutex::new(LiveState::default())),
+        };
+        let server = web_ui.clone();
+        let serving = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if serving.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    serving.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                let (server, serving) = (server.clone(), serving.clone());
+                thread::spawn(move || {
+                    server.handle(stream);
+                    serving.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        println!(
+            "实时页面: http://localhost:{}/?token={}",
+            port, web_ui.token
+        );
+        if all_interfaces {
+            println!("实时页面监听所有网卡, 其他机器用本机地址代替 localhost");
+        }
+        Ok(web_ui)
+    }
+
+    // Show the run starting now, the curves of the one before are cleared
+    pub fn attach(&self, events: EventLog, stop: Arc<AtomicBool>, end_time: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.series.clear();
+        state.run = Some(LiveRun {
+            events,
+            stop,
+            end_time,
+        });
+    }
+
+    pub fn send(&self, sample: &Sample) {
+        let mut state = self.state.lock().unwrap();
+        let index = match state
+            .series
+            .iter()
+            .position(|series| series.name == sample.metric)
+        {
+            Some(index) => index,
+            None => {
+                state.series.push(LiveSeries {
+                    name: sample.metric,
+                    unit: sample.unit,
+                    points: VecDeque::new(),
+                });
+                state.series.len() - 1
+            }
+        };
+        let series = &mut state.series[index];
+        if series.points.len() == MAX_POINTS {
+            series.points.pop_front();
+        }
+        series
+            .points
+            .push_back((sample.ts, sample.unit.to_display(sample.value)));
+    }
+
+    fn data(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        let series: Vec<Value> = state
+            .series
+            .iter()
+            .map(|series| {
+                json!({
+                    "name": series.name,
+                    "unit": series.unit.display_label(),
+                    "points": series.points.iter().map(|(time, value)| json!([time, value])).collect::<Vec<Value>>(),
+                })
+            })
+            .collect();
+        let (running, remaining, events) = match &state.run {
+            Some(run) => {
+                let now = now_millis() / 1000;
+                let running = now < run.end_time && !run.stop.load(Ordering::Relaxed);
+                let events: Vec<Value> = run
+                    .events
+                    .recent(MAX_EVENTS)
+                    .into_iter()
+                    .map(|(time, kind, detail)| {
+                        json!({ "time": time, "kind": kind.label(), "detail": detail })
+                    })
+                    .collect();
+                (running, run.end_time.saturating_sub(now), events)
+            }
+            None => (false, 0, Vec::new()),
+        };
+        json!({
+            "package": self.pkg,
+            "running": running,
+            "remaining": remaining,
+            "series": series,
+            "events": events,
+        })
+    }
+
+    fn mark(&self, label: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        let Some(run) = &state.run else {
+            return false;
+        };
+        let label = label.trim();
+        run.events.record(
+            EventKind::Marker,
+            if label.is_empty() { "mark" } else { label },
+        );
+        true
+    }
+
+    fn stop(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        let Some(run) = &state.run else {
+            return false;
+        };
+        println!("实时页面请求停止采集");
+        run.stop.store(true, Ordering::Relaxed);
+        true
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT)));
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+        let (mut length, mut host, mut origin, mut token) = (0, None, None, None);
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+                break;
+            }
+            let Some((name, value)) = header.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => length = value.parse().unwrap_or(0),
+                "host" => host = Some(value),
+                "origin" => origin = Some(value),
+                "x-cpureport-token" => token = Some(value),
+                _ => {}
+            }
+        }
+        let mut body = vec![0; length.min(MAX_BODY)];
+        let _ = reader.read_exact(&mut body);
+        let body = String::from_utf8_lossy(&body);
+
+        let mut words = request_line.split_whitespace();
+        let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+        let path = target.split_once('?').map_or(target, |(path, _)| path);
+        // a page of another site the browser has open sends its own origin, or its own name
+        // as the host if that resolves here
+        let foreign =
+            !self.hosts.is_empty() && !host.as_ref().is_some_and(|host| self.hosts.contains(host));
+        let foreign = foreign
+            || origin
+                .is_some_and(|origin| Some(origin) != host.map(|host| format!("http://{}", host)));
+        let authorized = token.as_deref() == Some(self.token.as_str());
+        let (status, content_type, content) = match (method, path) {
+            _ if foreign => ("403 Forbidden", "text/plain", "foreign origin".to_string()),
+            // the page itself has nothing of the run, it gets the token from its url
+            ("GET", "/") => ("200 OK", "text/html; charset=utf-8", PAGE.to_string()),
+            _ if !authorized => ("403 Forbidden", "text/plain", "bad token".to_string()),
+            ("GET", "/data") => ("200 OK", "application/json", self.data().to_string()),
+            ("POST", "/mark") if self.mark(&body) => ("200 OK", "text/plain", String::new()),
+            ("POST", "/stop") if self.stop() => ("200 OK", "text/plain", String::new()),
+            ("POST", "/mark") | ("POST", "/stop") => {
+                ("409 Conflict", "text/plain", "no run".to_string())
+            }
+            _ => ("404 Not Found", "text/plain", "not found".to_string()),
+        };
+        let _ = write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            content.len(),
+            content
+        );
+    }
+}
+
+// The page polls `/data` every second and draws every series on a canvas of its own
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>cpureport live</title>
+<style>
+body { font-family: sans-serif; margin: 1em 2em; color: #222; }
+header { display: flex; gap: 1em; align-items: center; flex-wrap: wrap; }
+.status { font-weight: bold; }
+.series { display: inline-block; margin: 0.5em 1em 0.5em 0; vertical-align: top; }
+.series h2 { font-size: 1em; margin: 0; }
+.value { font-size: 1.6em; }
+canvas { border: 1px solid #ccc; width: 420px; height: 140px; }
+button { font-size: 1em; padding: 0.3em 1em; }
+#stop { background: #cc0000; color: white; border: none; }
+table { border-collapse: collapse; }
+td { padding: 0.1em 0.8em 0.1em 0; }
+</style>
+</head>
+<body>
+<header>
+<h1>cpureport <span id="package"></span></h1>
+<span class="status" id="status"></span>
+<input id="label" placeholder="marker label">
+<button id="mark">Add marker</button>
+<button id="stop">Stop run</button>
+</header>
+<div id="charts"></div>
+<h2>Events</h2>
+<table id="events"></table>
+<script>
+const token = new URLSearchParams(location.search).get('token') || '';
+const headers = { 'X-Cpureport-Token': token };
+const charts = {};
+function chart(series) {
+  if (!charts[series.name]) {
+    const div = document.createElement('div');
+    div.className = 'series';
+    div.innerHTML = '<h2></h2><div class="value"></div><canvas width="420" height="140"></canvas>';
+    div.querySelector('h2').textContent = series.name + ' (' + series.unit + ')';
+    document.getElementById('charts').appendChild(div);
+    charts[series.name] = div;
+  }
+  return charts[series.name];
+}
+function draw(canvas, points) {
+  const ctx = canvas.getContext('2d');
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  if (points.length < 2) return;
+  const times = points.map(p => p[0]), values = points.map(p => p[1]);
+  const t0 = Math.min(...times), t1 = Math.max(...times);
+  const low = Math.min(0, ...values), high = Math.max(...values) || 1;
+  ctx.strokeStyle = '#0b5394';
+  ctx.beginPath();
+  points.forEach(([t, v], i) => {
+    const x = (t - t0) / Math.max(1, t1 - t0) * canvas.width;
+    const y = canvas.height - (v - low) / Math.max(1e-9, high - low) * (canvas.height - 4) - 2;
+    i ? ctx.lineTo(x, y) : ctx.moveTo(x, y);
+  });
+  ctx.stroke();
+}
+async function refresh() {
+  try {
+    const data = await (await fetch('/data', { headers })).json();
+    document.getElementById('package').textContent = data.package;
+    document.getElementById('status').textContent =
+      data.running ? 'running, ' + data.remaining + 's left' : 'not running';
+    for (const series of data.series) {
+      const div = chart(series);
+      const last = series.points[series.points.length - 1];
+      div.querySelector('.value').textContent = last ? last[1].toFixed(2) : '-';
+      draw(div.querySelector('canvas'), series.points);
+    }
+    const rows = data.events.map(e => {
+      const tr = document.createElement('tr');
+      for (const text of [new Date(e.time).toLocaleTimeString(), e.kind, e.detail]) {
+        const td = document.createElement('td');
+        td.textContent = text;
+        tr.appendChild(td);
+      }
+      return tr;
+    });
+    document.getElementById('events').replaceChildren(...rows.reverse());
+  } catch (e) {
+    document.getElementById('status').textContent = 'cpureport is not reachable';
+  }
+}
+document.getElementById('mark').onclick = () => {
+  const label = document.getElementById('label');
+  fetch('/mark', { method: 'POST', headers, body: label.value }).then(refresh);
+  label.value = '';
+};
+document.getElementById('stop').onclick = () => {
+  if (confirm('Stop the run now? The reports are written with what was sampled so far.')) {
+    fetch('/stop', { method: 'POST', headers }).then(refresh);
+  }
+};
+refresh();
+setInterval(refresh, 1000);
+</script>
+</body>
+</html>
+"#;
@@ -0,0 +1,34 @@
+use crate::platform::Platform;
+
+// How WebView renderer (`:sandboxed_process`) processes of the app are accounted
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum WebviewMode {
+    // added to the app's cpu and memory
+    Aggregate,
+    // added to the app's cpu and memory, and also recorded as their own series
+    Breakout,
+}
+
+// Renderers of hybrid apps run as isolated processes named after the app,
+// e.g. `com.example.app:sandboxed_process0:org.chromium.content.app.SandboxedProcessService0:0`
+pub fn is_renderer(process_name: &str) -> bool {
+    process_name.contains(":sandboxed_process") || process_name.contains("org.chromium")
+}
+
+// Split the app's `top` lines into the app's process lines and its renderer lines
+pub fn split_top_lines(top_result: &str) -> (Vec<&str>, Vec<&str>) {
+    let (renderers, others): (Vec<&str>, Vec<&str>) = top_result
+        .lines()
+        .partition(|line| line.split_whitespace().last().is_some_and(is_renderer));
+    (others, renderers)
+}
+
+// Pids of the renderer processes currently running for the package
+pub fn renderer_pids(device: &str, pkg: &str, platform: &Platform) -> Vec<String> {
+    platform
+        .processes(device)
+        .into_iter()
+        .filter(|process| process.name.starts_with(pkg) && is_renderer(&process.name))
+        .map(|process| process.pid)
+        .collect()
+}
@@ -0,0 +1,105 @@
+use crate::events::{EventKind, EventLog, now_millis};
+use crate::{adb_shell, now};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Milliseconds between two looks at the window manager
+const POLL_INTERVAL: u64 = 1000;
+
+// Display rotation in degrees, from `mRotation=1` (API 24+) of `dumpsys window`
+pub fn rotation(device_cmd: &str) -> Option<u32> {
+    let output = adb_shell(device_cmd, "dumpsys window");
+    output
+        .split_whitespace()
+        .find_map(|token| token.strip_prefix("mRotation="))
+        .and_then(|rotation| rotation.parse::<u32>().ok())
+        .map(|quarter| quarter * 90)
+}
+
+// The app's task in `dumpsys activity activities`, its windowing mode (`fullscreen`,
+// `multi-window`, `pinned`, `freeform`...) is on the task line from API 30 (`* Task{6ea7b1a #18
+// type=standard A=10282:com.example.app U=0 visible=true mode=fullscreen ...}`), before it is on
+// the stack line above the task (`Stack #18: type=standard mode=fullscreen` then
+// `* TaskRecord{45b7c1e #18 A=com.example.app U=0 StackId=18 sz=1}`)
+pub struct Task {
+    pub id: String,
+    pub mode: Option<String>,
+}
+
+pub fn task(device_cmd: &str, pkg: &str) -> Option<Task> {
+    let output = adb_shell(device_cmd, "dumpsys activity activities");
+    let mut mode = None;
+    let lines = output
+        .lines()
+        .filter(|line| line.contains("mode=") || line.contains(" A="));
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if let Some(line_mode) = tokens.iter().find_map(|token| token.strip_prefix("mode=")) {
+            mode = Some(line_mode.trim_end_matches('}').to_string());
+        }
+        let affinity = tokens.iter().find_map(|token| token.strip_prefix("A="));
+        // `A=` is the uid and the affinity, or only the affinity before API 30
+        if affinity.is_some_and(|affinity| affinity.rsplit(':').next() == Some(pkg)) {
+            let id = tokens.iter().find_map(|token| token.strip_prefix('#'))?;
+            return Some(Task {
+                id: id.to_string(),
+                mode,
+            });
+        }
+    }
+    None
+}
+
+pub fn windowing_mode(device_cmd: &str, pkg: &str) -> Option<String> {
+    task(device_cmd, pkg)?.mode
+}
+
+// Record rotations of the display and windowing mode changes of the app (split-screen,
+// picture-in-picture) until the run ends, they often explain steps in the curves
+pub fn watch(
+    device_cmd: &str,
+    pkg: &str,
+    events: EventLog,
+    end_time: u64,
+    stop: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let (device_cmd, pkg) = (device_cmd.to_string(), pkg.to_string());
+    thread::spawn(move || {
+        let mut last_rotation = rotation(&device_cmd);
+        let mut last_mode = windowing_mode(&device_cmd, &pkg);
+        let mut next = now_millis() + POLL_INTERVAL;
+        while now() < end_time && !stop.load(Ordering::Relaxed) {
+            if now_millis() >= next {
+                let rotation = rotation(&device_cmd);
+                if rotation.is_some() && rotation != last_rotation {
+                    events.record(
+                        EventKind::Rotation,
+                        format!(
+                            "{}° -> {}°",
+                            last_rotation.map_or("?".to_string(), |r| r.to_string()),
+                            rotation.unwrap_or_default()
+                        ),
+                    );
+                    last_rotation = rotation;
+                }
+                // none while the app is not running, that is a death and not a mode change
+                let mode = windowing_mode(&device_cmd, &pkg);
+                if mode.is_some() && mode != last_mode {
+                    events.record(
+                        EventKind::WindowMode,
+                        format!(
+                            "{} -> {}",
+                            last_mode.as_deref().unwrap_or("?"),
+                            mode.as_deref().unwrap_or_default()
+                        ),
+                    );
+                    last_mode = mode;
+                }
+                next = now_millis() + POLL_INTERVAL;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    })
+}
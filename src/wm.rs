@@ -0,0 +1,88 @@
+use crate::adb_shell;
+
+// A simulated screen, `1080x1920`, `@420` or `1080x1920@420` (size in pixels, density in dpi)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenConfig {
+    pub size: Option<(u32, u32)>,
+    pub density: Option<u32>,
+}
+
+impl ScreenConfig {
+    pub fn parse(text: &str) -> Result<ScreenConfig, String> {
+        let invalid = || {
+            format!(
+                "Failed to parse screen {}: expected WIDTHxHEIGHT, @DENSITY or WIDTHxHEIGHT@DENSITY",
+                text
+            )
+        };
+        let (size, density) = match text.split_once('@') {
+            Some((size, density)) => (size, Some(density)),
+            None => (text, None),
+        };
+        let size = match size {
+            "" => None,
+            size => {
+                let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+                Some((
+                    width.parse().map_err(|_| invalid())?,
+                    height.parse().map_err(|_| invalid())?,
+                ))
+            }
+        };
+        let density = density
+            .map(|density| density.parse().map_err(|_| invalid()))
+            .transpose()?;
+        if size.is_none() && density.is_none() {
+            return Err(invalid());
+        }
+        Ok(ScreenConfig { size, density })
+    }
+
+    // `1080x1920@420`, also the name of the directory its reports go to
+    pub fn label(&self) -> String {
+        let mut label = self.size.map_or(String::new(), |(width, height)| {
+            format!("{}x{}", width, height)
+        });
+        if let Some(density) = self.density {
+            label.push_str(&format!("@{}", density));
+        }
+        label
+    }
+}
+
+// The screen size and density overridden for a run, both are reset to the physical ones on drop
+pub struct ScreenOverride {
+    device_cmd: String,
+}
+
+impl ScreenOverride {
+    pub fn apply(device_cmd: &str, config: &ScreenConfig) -> Result<ScreenOverride, String> {
+        println!("模拟屏幕: {}", config.label());
+        // Dropped on failure too, a half applied config is reset
+        let screen = ScreenOverride {
+            device_cmd: device_cmd.to_string(),
+        };
+        let mut commands = Vec::new();
+        if let Some((width, height)) = config.size {
+            commands.push(format!("wm size {}x{}", width, height));
+        }
+        if let Some(density) = config.density {
+            commands.push(format!("wm density {}", density));
+        }
+        for command in commands {
+            // `wm` prints nothing on success
+            let output = adb_shell(device_cmd, &command);
+            if !output.trim().is_empty() {
+                return Err(format!("Failed to run {}: {}", command, output.trim()));
+            }
+        }
+        Ok(screen)
+    }
+}
+
+impl Drop for ScreenOverride {
+    fn drop(&mut self) {
+        adb_shell(&self.device_cmd, "wm size reset");
+        adb_shell(&self.device_cmd, "wm density reset");
+    }
+}
@@ -0,0 +1,215 @@
+use crate::events::{self, LinkedSheet};
+use crate::report::{ReportWriter, SeriesTable, max_average, sheet_name};
+use crate::sample::Series;
+use crate::theme::Theme;
+use crate::timestamp;
+use crate::{RunData, crashloop, screen, sla, threads};
+use rust_xlsxwriter::{Chart, ChartLine, ChartType, RowNum, Workbook, Worksheet, XlsxError};
+
+// Sheets of the combined workbook holding the cpu and memory samples
+const CPU_SHEET: &str = "CPU";
+const MEM_SHEET: &str = "Memory";
+
+// A row per sample with its time (`timestamp::format`), its value in the collected unit and the seconds since
+// the start of the run, followed by the max and average rows. Samples read back without a time
+// (old manifests) have the time columns blank. Runs that watched the screen get its state at
+// every sample in a fourth column, and a line chart of the values over the elapsed time is put
+// next to them. `sheet` is already named and branded.
+pub fn write_series(
+    sheet: &mut Worksheet,
+    label: &str,
+    series: &Series,
+    data: &RunData,
+    (max, average): (f64, f64),
+    theme: &Theme,
+) -> Result<(), XlsxError> {
+    let times: Vec<u64> = series.samples.iter().map(|sample| sample.ts).collect();
+    let screen_states = screen::states_at(&data.events, &times);
+    let mut header = vec!["Time", label, "Elapsed (s)"];
+    if screen_states.iter().any(Option::is_some) {
+        header.push("Screen");
+    }
+    sheet.write_row_with_format(0, 0, header, &theme.header_format())?;
+    for (idx, sample) in series.samples.iter().enumerate() {
+        let row = idx as RowNum + 1;
+        sheet.write(row, 1, sample.value)?;
+        if sample.ts > 0 {
+            sheet.write(row, 0, timestamp::format(sample.ts))?;
+            sheet.write(row, 2, sample.ts.saturating_sub(data.start) as f64 / 1000.0)?;
+        }
+        if let Some(state) = &screen_states[idx] {
+            sheet.write(row, 3, state)?;
+        }
+    }
+
+    let last = series.len() as RowNum + 1;
+    sheet.write_row(last, 0, [format!("{} Max", label), max.to_string()])?;
+    sheet.write_row(
+        last + 1,
+        0,
+        [format!("{} Average", label), average.to_string()],
+    )?;
+
+    if !series.is_empty() {
+        let name = sheet.name();
+        let mut chart = Chart::new(ChartType::Line);
+        chart
+            .add_series()
+            .set_name(label)
+            .set_categories((name.as_str(), 1, 2, last - 1, 2))
+            .set_values((name.as_str(), 1, 1, last - 1, 1))
+            .set_format(ChartLine::new().set_color(theme.primary()));
+        chart.title().set_name(&format!("{} over time", label));
+        chart.x_axis().set_name("Elapsed (s)");
+        chart
+            .y_axis()
+            .set_name(&format!("{} ({})", label, series.unit.label()));
+        chart.legend().set_hidden();
+        chart.set_width(720).set_height(360);
+        sheet.insert_chart(1, 5, &chart)?;
+    }
+    Ok(())
+}
+
+// The per-series workbooks, `cpu_data_<time>.xlsx`...
+pub struct XlsxWriter<'a> {
+    pub theme: &'a Theme,
+}
+
+impl ReportWriter for XlsxWriter<'_> {
+    fn extension(&self) -> &'static str {
+        "xlsx"
+    }
+
+    fn write_series(&self, path: &str, table: &SeriesTable) -> Result<(), String> {
+        let fail = |e: XlsxError| format!("Failed to save {}: {}", path, e);
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(&table.sheet).map_err(fail)?;
+        self.theme.brand(sheet).map_err(fail)?;
+        write_series(
+            sheet,
+            &table.label,
+            table.series,
+            table.data,
+            table.max_average,
+            self.theme,
+        )
+        .map_err(fail)?;
+        workbook.save(path).map_err(fail)
+    }
+}
+
+// The run at a glance: when and how long, average and peak of every series, and the verdicts
+fn write_summary(sheet: &mut Worksheet, data: &RunData, theme: &Theme) -> Result<(), XlsxError> {
+    sheet.write_row_with_format(0, 0, ["Metric", "Value"], &theme.header_format())?;
+    let mut row = 1;
+    let mut text = |sheet: &mut Worksheet, name: &str, value: String| -> Result<(), XlsxError> {
+        sheet.write_row(row, 0, [name, value.as_str()])?;
+        row += 1;
+        Ok(())
+    };
+    text(sheet, "Start", timestamp::format(data.start))?;
+    text(
+        sheet,
+        "Duration (s)",
+        (data.end.saturating_sub(data.start) as f64 / 1000.0).to_string(),
+    )?;
+    for series in [&data.cpu, &data.mem].into_iter().chain(&data.series) {
+        let (max, average) = max_average(series);
+        let title = series.title();
+        let unit = series.unit.display_label();
+        text(
+            sheet,
+            &format!("{} Average ({})", title, unit),
+            average.to_string(),
+        )?;
+        text(sheet, &format!("{} Max ({})", title, unit), max.to_string())?;
+    }
+    if let Some(score) = &data.score {
+        text(sheet, "Score", format!("{:.1}", score.value))?;
+    }
+    if !data.sla.is_empty() {
+        let verdict = if sla::passed(&data.sla) {
+            "PASS"
+        } else {
+            "FAIL"
+        };
+        text(sheet, "SLA", verdict.to_string())?;
+    }
+    if data.crash_loop.is_some() {
+        text(sheet, "Status", crashloop::INVALID_UNSTABLE.to_string())?;
+    }
+    if let Some(level) = data.battery_low {
+        text(sheet, "Battery Low (%)", level.to_string())?;
+    }
+    if !data.failures.is_empty() {
+        text(sheet, "Failures", data.failures.len().to_string())?;
+    }
+    sheet.set_column_width(0, 24)?;
+    Ok(())
+}
+
+// One workbook with a Summary, a CPU and a Memory sheet, a sheet per further series and the
+// event timeline linking into the CPU and Memory sheets
+pub fn save_combined(path: &str, data: &RunData, theme: &Theme) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Summary")?;
+    theme.brand(sheet)?;
+    write_summary(sheet, data, theme)?;
+
+    // (sheet, column label, series), the further series are named after their title
+    let mut sheets: Vec<(String, String, &Series)> = vec![
+        (CPU_SHEET.to_string(), data.cpu.title_or("Cpu"), &data.cpu),
+        (MEM_SHEET.to_string(), data.mem.title_or("Mem"), &data.mem),
+    ];
+    sheets.extend(
+        data.series
+            .iter()
+            .map(|series| (sheet_name(&series.title()), series.title(), series)),
+    );
+    for (name, label, series) in sheets {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(&name)?;
+        theme.brand(sheet)?;
+        write_series(sheet, &label, series, data, max_average(series), theme)?;
+    }
+
+    if !data.events.is_empty() {
+        let linked = [
+            LinkedSheet {
+                title: "Cpu Sample",
+                file: None,
+                sheet: CPU_SHEET,
+                column: "B",
+            },
+            LinkedSheet {
+                title: "Mem Sample",
+                file: None,
+                sheet: MEM_SHEET,
+                column: "B",
+            },
+        ];
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("Events")?;
+        theme.brand(sheet)?;
+        events::write_timeline(
+            sheet,
+            data.start,
+            &data.events,
+            &linked,
+            data.warmup_dropped as usize,
+            theme,
+        )?;
+    }
+
+    if !data.threads.is_empty() {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("Threads")?;
+        theme.brand(sheet)?;
+        threads::write_threads(sheet, &data.threads, theme)?;
+    }
+
+    workbook.save(path)
+}
@@ -0,0 +1,181 @@
+use crate::pipe::SamplePipe;
+use crate::sample::{Quality, Sample, Series, Unit};
+use crate::statsd::StatsdExporter;
+use crate::webui::WebUi;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+// Samples a collector can get ahead of the writer before it has to wait for it
+const CHANNEL_CAPACITY: usize = 256;
+
+// Number of samples the writer has stored per metric and the latest value of each, readable
+// while the run goes on
+#[derive(Clone)]
+pub struct SampleCounts {
+    metrics: Vec<&'static str>,
+    counts: Arc<Vec<AtomicUsize>>,
+    // bits of the value in the unit reports show it in, NaN before the first sample
+    latest: Arc<Vec<AtomicU64>>,
+}
+
+impl SampleCounts {
+    // Only `metrics` are counted, in this order
+    pub fn new(metrics: &[&'static str]) -> SampleCounts {
+        SampleCounts {
+            metrics: metrics.to_vec(),
+            counts: Arc::new(metrics.iter().map(|_| AtomicUsize::new(0)).collect()),
+            latest: Arc::new(
+                metrics
+                    .iter()
+                    .map(|_| AtomicU64::new(f64::NAN.to_bits()))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn get(&self) -> Vec<usize> {
+        self.counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    // `(metric, latest value)` of the metrics sampled so far
+    pub fn latest(&self) -> Vec<(&'static str, f64)> {
+        self.metrics
+            .iter()
+            .zip(self.latest.iter())
+            .map(|(metric, value)| (*metric, f64::from_bits(value.load(Ordering::Relaxed))))
+            .filter(|(_, value)| !value.is_nan())
+            .collect()
+    }
+
+    fn record(&self, sample: &Sample) {
+        if let Some(index) = self.metrics.iter().position(|name| *name == sample.metric) {
+            self.counts[index].fetch_add(1, Ordering::Relaxed);
+            let value = sample.unit.to_display(sample.value);
+            self.latest[index].store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+// The sending side a collector thread owns
+#[derive(Clone)]
+pub struct SampleSender {
+    sender: SyncSender<Sample>,
+    // whether the slow writer was already reported
+    stalled: Arc<AtomicBool>,
+}
+
+impl SampleSender {
+    // Blocks while the writer is behind, so slow exporters slow sampling down instead of
+    // queueing samples without bound
+    pub fn send(&self, sample: Sample) {
+        match self.sender.try_send(sample) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+            Err(TrySendError::Full(sample)) => {
+                if !self.stalled.swap(true, Ordering::Relaxed) {
+                    println!("样本写入跟不上采样, 采样将等待导出完成");
+                }
+                let _ = self.sender.send(sample);
+            }
+        }
+    }
+}
+
+// Samples of every metric in arrival order, as stored by the writer
+#[derive(Default)]
+pub struct SampleStore {
+    series: Vec<Series>,
+}
+
+impl SampleStore {
+    fn push(&mut self, sample: Sample) {
+        match self
+            .series
+            .iter_mut()
+            .find(|series| series.name == sample.metric)
+        {
+            Some(series) => series.samples.push(sample),
+            None => {
+                let mut series = Series::new(sample.metric, sample.unit);
+                series.samples.push(sample);
+                self.series.push(series);
+            }
+        }
+    }
+
+    // The samples the collectors marked as their warm-up, for runs without `--raw`
+    pub fn drop_warmup(&mut self) {
+        for series in &mut self.series {
+            series
+                .samples
+                .retain(|sample| sample.quality != Quality::Warmup);
+        }
+    }
+
+    // Every series not taken yet, in the order their first samples arrived
+    pub fn take_rest(&mut self) -> Vec<Series> {
+        std::mem::take(&mut self.series)
+    }
+
+    // The series of `metric`, empty if it got no samples
+    pub fn take(&mut self, metric: &str, unit: Unit) -> Series {
+        match self.series.iter().position(|series| series.name == metric) {
+            Some(index) => self.series.remove(index),
+            None => Series::new(metric, unit),
+        }
+    }
+}
+
+// Start the single writer of a run. It stores the samples, counts them and streams them to
+// the pipe, statsd and the web ui, and returns the store once every `SampleSender` has been
+// dropped.
+pub fn spawn(
+    counts: SampleCounts,
+    pipe: Option<SamplePipe>,
+    statsd: Option<StatsdExporter>,
+    web_ui: Option<WebUi>,
+) -> (SampleSender, JoinHandle<SampleStore>) {
+    let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    let writer = thread::spawn(move || {
+        write_samples(
+            receiver,
+            &counts,
+            pipe.as_ref(),
+            statsd.as_ref(),
+            web_ui.as_ref(),
+        )
+    });
+    let sender = SampleSender {
+        sender,
+        stalled: Arc::new(AtomicBool::new(false)),
+    };
+    (sender, writer)
+}
+
+fn write_samples(
+    receiver: Receiver<Sample>,
+    counts: &SampleCounts,
+    pipe: Option<&SamplePipe>,
+    statsd: Option<&StatsdExporter>,
+    web_ui: Option<&WebUi>,
+) -> SampleStore {
+    let mut store = SampleStore::default();
+    for sample in receiver {
+        counts.record(&sample);
+        if let Some(pipe) = pipe {
+            pipe.send(&sample);
+        }
+        if let Some(statsd) = statsd {
+            statsd.send(&sample);
+        }
+        if let Some(web_ui) = web_ui {
+            web_ui.send(&sample);
+        }
+        store.push(sample);
+    }
+    store
+}